@@ -11,6 +11,7 @@ import os
 import re
 import sys
 from dataclasses import dataclass
+from datetime import date
 from enum import StrEnum
 from urllib.parse import unquote
 
@@ -367,6 +368,7 @@ def render(downloads: list[PythonDownload]):
         "// To regenerate, run `rye run find-downloads > rye/src/sources/generated/python_downloads.inc` from the root of the repository."
     )
     print("use std::borrow::Cow;")
+    print(f'pub const PYTHON_VERSIONS_DATE: &str = "{date.today().isoformat()}";')
     print("pub const PYTHON_VERSIONS: &[(PythonVersion, &str, Option<&str>)] = &[")
 
     for download in downloads:
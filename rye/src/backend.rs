@@ -0,0 +1,427 @@
+//! The backend that resolves and installs dependencies.
+//!
+//! With `behavior.use-uv` (the default) locking, syncing and installing go
+//! through uv, which rye downloads into its home.  Otherwise pip-tools are
+//! used from a virtualenv of their own.  Platforms that uv has no build for
+//! fall back to pip-tools.
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::{env, fmt, fs};
+
+use anyhow::{anyhow, bail, Context, Error};
+use once_cell::sync::Lazy;
+use pep508_rs::Requirement;
+
+use crate::config::Config;
+use crate::lock::{KeyringProvider, LockOptions};
+use crate::piptools::{
+    create_venv_with_pip, find_latest_with_unearth, pip_compile, pip_freeze, pip_install,
+    pip_install_lockfile, pip_install_requirements, pip_sync,
+};
+use crate::pyproject::{ExpandedSources, PyProject};
+use crate::sources::py::PythonVersion;
+use crate::sources::uv::{UvDownload, UvRequest};
+use crate::sync::{sync, SyncOptions};
+use crate::utils::{get_venv_python_bin, prepend_path_to_path_env, CommandOutput, IoPathContext};
+use crate::uv::{UvBuilder, UvInstallOptions, UvPackageUpgrade, UvWithVenv};
+
+/// Resolves lockfiles and installs them into virtualenvs.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Backend {
+    /// uv, the fast backend.
+    Uv,
+    /// `pip-compile` and `pip-sync` with pip.
+    PipTools,
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Backend::Uv => "uv",
+            Backend::PipTools => "pip-tools",
+        })
+    }
+}
+
+/// Options for installing packages into a virtualenv.
+pub struct InstallOptions {
+    /// The package sources to install from.
+    pub sources: ExpandedSources,
+    /// The directory that relative paths in requirements are resolved against.
+    pub workdir: Option<PathBuf>,
+    /// Byte-compile the installed packages.  `None` leaves it to the installer.
+    pub compile_bytecode: Option<bool>,
+    /// Install `importlib-metadata` alongside, which Python 3.7 needs.
+    pub importlib_workaround: bool,
+    /// Refetch the packages instead of using cached ones.
+    pub refresh: bool,
+}
+
+impl InstallOptions {
+    pub fn new(sources: ExpandedSources) -> InstallOptions {
+        InstallOptions {
+            sources,
+            workdir: None,
+            compile_bytecode: None,
+            importlib_workaround: false,
+            refresh: false,
+        }
+    }
+}
+
+/// What is resolved into a lockfile.
+pub struct ResolveRequest<'a> {
+    /// The Python version to resolve for.
+    pub py_ver: &'a PythonVersion,
+    /// The directory that relative paths are resolved against.
+    pub workspace_path: &'a Path,
+    /// The requirements to resolve.
+    pub requirements_file_in: &'a Path,
+    /// The resolution is written here.  A prior resolution in it is kept
+    /// as far as possible.
+    pub requirements_file: &'a Path,
+    pub sources: &'a ExpandedSources,
+    pub lock_options: &'a LockOptions,
+    /// The platform to resolve for.
+    pub python_platform: Option<String>,
+    /// A file with constraints for the resolution.
+    pub constraints: Option<&'a Path>,
+    pub no_deps: bool,
+    pub keyring_provider: KeyringProvider,
+}
+
+static UV_AVAILABLE: Lazy<bool> = Lazy::new(|| UvDownload::try_from(UvRequest::default()).is_ok());
+
+impl Backend {
+    /// Returns the backend selected by the current config.
+    pub fn current() -> Backend {
+        static CURRENT: Lazy<Backend> = Lazy::new(|| {
+            let use_uv = Config::current().use_uv();
+            if use_uv && !*UV_AVAILABLE {
+                warn!("uv is not available for this platform, falling back to pip-tools");
+            }
+            select_backend(use_uv, *UV_AVAILABLE)
+        });
+        *CURRENT
+    }
+
+    /// Returns the backend selected by the given config.
+    ///
+    /// Unlike [`Backend::current`] this does not warn about the fallback.
+    pub fn for_config(config: &Config) -> Backend {
+        select_backend(config.use_uv(), *UV_AVAILABLE)
+    }
+
+    /// Is this the uv backend?
+    pub fn is_uv(self) -> bool {
+        self == Backend::Uv
+    }
+
+    /// Fails if the backend cannot look up credentials with the keyring.
+    pub fn check_keyring_provider(self, keyring_provider: KeyringProvider) -> Result<(), Error> {
+        if self == Backend::PipTools && keyring_provider != KeyringProvider::Disabled {
+            bail!("`--keyring-provider` option requires the uv backend");
+        }
+        Ok(())
+    }
+
+    /// Fails if the backend does not support the lock options.
+    pub fn check_lock_options(
+        self,
+        lock_options: &LockOptions,
+        keyring_provider: KeyringProvider,
+    ) -> Result<(), Error> {
+        self.check_keyring_provider(keyring_provider)?;
+        if self == Backend::PipTools
+            && (lock_options.python_version.is_some() || lock_options.python_platform.is_some())
+        {
+            bail!("`--python-version` and `--platform` options require the uv backend");
+        }
+        Ok(())
+    }
+
+    /// Creates a virtualenv for rye's own use, like the self venv.
+    ///
+    /// An existing folder at the path is replaced.  The marker and pip are
+    /// left to the caller.
+    pub fn create_internal_venv(
+        self,
+        venv_dir: &Path,
+        py_bin: &Path,
+        py_ver: &PythonVersion,
+        output: CommandOutput,
+    ) -> Result<(), Error> {
+        if venv_dir.is_dir() {
+            fs::remove_dir_all(venv_dir)
+                .path_context(venv_dir, "unable to remove outdated virtualenv")?;
+        }
+        match self {
+            Backend::Uv => {
+                UvBuilder::new()
+                    .with_output(output.quieter())
+                    .ensure_exists()?
+                    .venv(venv_dir, py_bin, py_ver, None)?;
+            }
+            Backend::PipTools => create_venv_with_pip(py_bin, venv_dir)?,
+        }
+        Ok(())
+    }
+
+    /// Installs or upgrades requirements in a virtualenv for rye's own use.
+    ///
+    /// `requirements` has the format of a requirements file.
+    pub fn install_internal_requirements(
+        self,
+        venv_dir: &Path,
+        py_ver: &PythonVersion,
+        requirements: &str,
+        output: CommandOutput,
+    ) -> Result<(), Error> {
+        match self {
+            Backend::Uv => {
+                let uv = UvBuilder::new()
+                    .with_output(output.quieter())
+                    .ensure_exists()?;
+                UvWithVenv::new(uv, venv_dir, py_ver).update_requirements(requirements)
+            }
+            Backend::PipTools => pip_install_requirements(venv_dir, requirements, output),
+        }
+    }
+
+    /// Installs requirements with their dependencies into a virtualenv.
+    pub fn install(
+        self,
+        venv: &Path,
+        py_ver: &PythonVersion,
+        requirements: &[Requirement],
+        options: InstallOptions,
+        output: CommandOutput,
+    ) -> Result<(), Error> {
+        let Some((first, rest)) = requirements.split_first() else {
+            return Ok(());
+        };
+        match self {
+            Backend::Uv => uv_for_venv(venv, py_ver, &options, output)?.install(
+                first,
+                UvInstallOptions {
+                    importlib_workaround: options.importlib_workaround,
+                    extras: rest.to_vec(),
+                    refresh: options.refresh,
+                },
+            ),
+            Backend::PipTools => pip_install(venv, requirements, &options, output),
+        }
+    }
+
+    /// Installs the packages of a lockfile into a fresh virtualenv.
+    ///
+    /// Unlike [`Backend::sync`] nothing is removed from the virtualenv.
+    pub fn install_lockfile(
+        self,
+        venv: &Path,
+        py_ver: &PythonVersion,
+        lockfile: &Path,
+        options: InstallOptions,
+        output: CommandOutput,
+    ) -> Result<(), Error> {
+        match self {
+            Backend::Uv => uv_for_venv(venv, py_ver, &options, output)?.sync(lockfile),
+            Backend::PipTools => pip_install_lockfile(venv, lockfile, &options, output),
+        }
+    }
+
+    /// Syncs a virtualenv to a lockfile.
+    ///
+    /// Everything that is not in the lockfile is removed from the virtualenv.
+    pub fn sync(
+        self,
+        venv: &Path,
+        py_ver: &PythonVersion,
+        lockfile: &Path,
+        options: InstallOptions,
+        output: CommandOutput,
+    ) -> Result<(), Error> {
+        match self {
+            Backend::Uv => uv_for_venv(venv, py_ver, &options, output)?.sync(lockfile),
+            Backend::PipTools => pip_sync(venv, py_ver, lockfile, &options, output),
+        }
+    }
+
+    /// Resolves requirements into a lockfile.
+    pub fn resolve(self, request: &ResolveRequest, output: CommandOutput) -> Result<(), Error> {
+        self.check_keyring_provider(request.keyring_provider)?;
+        match self {
+            Backend::Uv => {
+                let lock_options = request.lock_options;
+                let upgrade = if lock_options.update_all {
+                    UvPackageUpgrade::All
+                } else if !lock_options.update.is_empty() {
+                    UvPackageUpgrade::Packages(lock_options.update.clone())
+                } else {
+                    UvPackageUpgrade::Nothing
+                };
+                UvBuilder::new()
+                    .with_output(output.quieter())
+                    .with_sources(request.sources.clone())
+                    .with_workdir(request.workspace_path)
+                    .ensure_exists()?
+                    .lockfile(
+                        request.py_ver,
+                        request.requirements_file_in,
+                        request.requirements_file,
+                        lock_options.pre,
+                        env::var("__RYE_UV_EXCLUDE_NEWER").ok(),
+                        upgrade,
+                        request.keyring_provider,
+                        lock_options.generate_hashes,
+                        request.python_platform.clone(),
+                        request.constraints,
+                    )
+            }
+            Backend::PipTools => pip_compile(request, output),
+        }
+    }
+
+    /// Finds the latest versions of requirements for a project.
+    ///
+    /// Every returned requirement has the name as it is on the index and
+    /// pins the version that was found.  Markers have to be removed from the
+    /// requirements beforehand.
+    pub fn find_latest(
+        self,
+        pyproject: &PyProject,
+        py_ver: &PythonVersion,
+        requirements: &[Requirement],
+        pre: bool,
+        keyring_provider: KeyringProvider,
+        output: CommandOutput,
+    ) -> Result<Vec<Requirement>, Error> {
+        self.check_keyring_provider(keyring_provider)?;
+        match self {
+            Backend::Uv => {
+                // uv resolves within the virtualenv of the project
+                sync(SyncOptions::python_only().pyproject(Some(pyproject.toml_path().into())))
+                    .context("failed to sync ahead of add")?;
+                let venv = pyproject.venv_path();
+                let sources = ExpandedSources::from_sources(&pyproject.sources()?)?;
+                let uv = UvBuilder::new()
+                    .with_output(output.quieter())
+                    .with_sources(sources)
+                    .ensure_exists()?
+                    .venv(&venv, &get_venv_python_bin(&venv), py_ver, None)?;
+                requirements
+                    .iter()
+                    .map(|req| {
+                        uv.resolve(
+                            py_ver,
+                            req,
+                            pre,
+                            env::var("__RYE_UV_EXCLUDE_NEWER").ok(),
+                            keyring_provider,
+                        )
+                    })
+                    .collect()
+            }
+            Backend::PipTools => requirements
+                .iter()
+                .map(|req| find_latest_with_unearth(pyproject, py_ver, req, pre, output))
+                .collect(),
+        }
+    }
+
+    /// Prints the packages installed in a virtualenv.
+    pub fn freeze(
+        self,
+        venv: &Path,
+        py_ver: &PythonVersion,
+        output: CommandOutput,
+    ) -> Result<(), Error> {
+        match self {
+            Backend::Uv => {
+                let uv = UvBuilder::new().with_output(output).ensure_exists()?;
+                UvWithVenv::new(uv, venv, py_ver).freeze()
+            }
+            Backend::PipTools => pip_freeze(venv, output),
+        }
+    }
+
+    /// Makes the `build` frontend install build dependencies with this backend.
+    pub fn configure_build_frontend(
+        self,
+        cmd: &mut Command,
+        output: CommandOutput,
+    ) -> Result<(), Error> {
+        if self == Backend::Uv {
+            // uv is made available to the frontend without installing it into the venv
+            let uv = UvBuilder::new().with_output(output).ensure_exists()?;
+            let uv_dir = uv
+                .uv_bin()
+                .parent()
+                .ok_or_else(|| anyhow!("Could not find uv binary in self venv: empty path"))?;
+            cmd.env("PATH", prepend_path_to_path_env(uv_dir)?);
+            cmd.arg("--installer=uv");
+        }
+        Ok(())
+    }
+}
+
+/// Returns uv for installing into a virtualenv, which is created if needed.
+fn uv_for_venv(
+    venv: &Path,
+    py_ver: &PythonVersion,
+    options: &InstallOptions,
+    output: CommandOutput,
+) -> Result<UvWithVenv, Error> {
+    let mut builder = UvBuilder::new()
+        .with_output(output.quieter())
+        .with_sources(options.sources.clone());
+    if let Some(ref workdir) = options.workdir {
+        builder = builder.with_workdir(workdir);
+    }
+    Ok(builder
+        .ensure_exists()?
+        .venv(venv, &get_venv_python_bin(venv), py_ver, None)?
+        .with_output(output))
+}
+
+fn select_backend(use_uv: bool, uv_available: bool) -> Backend {
+    if use_uv && uv_available {
+        Backend::Uv
+    } else {
+        Backend::PipTools
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{select_backend, Backend};
+    use crate::lock::{KeyringProvider, LockOptions};
+
+    #[test]
+    fn test_select_backend() {
+        assert_eq!(select_backend(true, true), Backend::Uv);
+        assert_eq!(select_backend(true, false), Backend::PipTools);
+        assert_eq!(select_backend(false, true), Backend::PipTools);
+        assert_eq!(Backend::PipTools.to_string(), "pip-tools");
+    }
+
+    #[test]
+    fn test_check_lock_options() {
+        let lock_options = LockOptions {
+            python_platform: Some("linux".into()),
+            ..Default::default()
+        };
+        assert!(Backend::Uv
+            .check_lock_options(&lock_options, KeyringProvider::Subprocess)
+            .is_ok());
+        assert!(Backend::PipTools
+            .check_lock_options(&LockOptions::default(), KeyringProvider::Disabled)
+            .is_ok());
+        assert!(Backend::PipTools
+            .check_lock_options(&lock_options, KeyringProvider::Disabled)
+            .is_err());
+        assert!(Backend::PipTools
+            .check_keyring_provider(KeyringProvider::Subprocess)
+            .is_err());
+    }
+}
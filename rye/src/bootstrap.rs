@@ -1,26 +1,43 @@
 use std::borrow::Cow;
-use std::env::consts::EXE_EXTENSION;
+use std::cell::Cell;
+use std::env::consts::{ARCH, EXE_EXTENSION, OS};
 use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::sync::atomic::{self, AtomicBool};
-use std::{env, fs};
+use std::time::{Duration, Instant};
+use std::{env, fmt, fs, thread};
 
 use anyhow::{anyhow, bail, Context, Error};
 use console::style;
-use indicatif::{ProgressBar, ProgressStyle};
+use fslock::LockFile;
+use indicatif::{HumanBytes, ProgressBar};
 use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use tempfile::tempdir_in;
 
+use crate::backend::Backend;
 use crate::config::Config;
+use crate::consts::VENV_BIN;
 use crate::piptools::LATEST_PIP;
 use crate::platform::{
     get_app_dir, get_canonical_py_path, get_python_bin_within, get_toolchain_python_bin,
     list_known_toolchains,
 };
-use crate::pyproject::latest_available_python_version;
-use crate::sources::py::{get_download_url, PythonVersion, PythonVersionRequest};
-use crate::utils::{check_checksum, symlink_file, unpack_archive, CommandOutput, IoPathContext};
-use crate::uv::UvBuilder;
+use crate::prefetch::wait_for_job;
+use crate::pyproject::{latest_available_python_version, read_venv_marker, write_venv_marker};
+use crate::shims::{refresh_core_shims, refresh_versioned_shims};
+use crate::sources::py::{
+    find_checksum_in_manifest, get_download_url, get_mirror_urls, PythonVersion,
+    PythonVersionRequest,
+};
+use crate::tui::{ci_timestamp, is_ci_mode, CI_PROGRESS_INTERVAL};
+use crate::utils::atomic::write_atomic;
+use crate::utils::cleanup::RemoveOnInterrupt;
+use crate::utils::{
+    check_checksum, get_venv_python_bin, internal_command, unpack_archive, CommandOutput,
+    IoPathContext,
+};
 
 /// this is the target version that we want to fetch
 pub const SELF_PYTHON_TARGET_VERSION: PythonVersionRequest = PythonVersionRequest {
@@ -33,7 +50,7 @@ pub const SELF_PYTHON_TARGET_VERSION: PythonVersionRequest = PythonVersionReques
     suffix: None,
 };
 
-const SELF_VERSION: u64 = 18;
+const SELF_VERSION: u64 = 19;
 
 const SELF_REQUIREMENTS: &str = r#"
 build==1.2.1
@@ -48,20 +65,167 @@ platformdirs==4.0.0
 pyproject_hooks==1.0.0
 requests==2.31.0
 tomli==2.0.1
-twine==4.0.2
-unearth==0.14.0
 urllib3==2.0.7
 virtualenv==20.25.0
 ruff==0.3.0
 "#;
 
+/// Returns the pinned build frontend of the self venv.
+///
+/// The same pin is installed into the build environments of other Python
+/// versions.
+pub fn get_build_requirement() -> &'static str {
+    SELF_REQUIREMENTS
+        .lines()
+        .find(|x| x.starts_with("build=="))
+        .expect("build is pinned in the self requirements")
+}
+
+/// Requirements that are only installed up front if uv is not used.
+///
+/// With uv, unearth is never needed and twine is installed on first publish.
+const SELF_PIP_REQUIREMENTS: &str = r#"
+twine==4.0.2
+unearth==0.14.0
+"#;
+
+/// The requirement installed on demand for `rye publish`.
+const SELF_TWINE_REQUIREMENT: &str = "twine==4.0.2";
+
+/// The requirement installed on demand for `rye test --coverage`.
+const SELF_COVERAGE_REQUIREMENT: &str = "coverage==7.5.1";
+
+/// Suffix of the tool version for self venvs bootstrapped for uv only.
+const UV_FLAVOR_SUFFIX: &str = "+uv";
+
+/// The probe run with freshly downloaded toolchains.
+const TOOLCHAIN_PROBE: &str = "import ssl, zlib, sqlite3";
+
 static FORCED_TO_UPDATE: AtomicBool = AtomicBool::new(false);
 
+static OFFLINE: AtomicBool = AtomicBool::new(false);
+
+/// Disallows network access for downloads for the rest of the process.
+pub fn enable_offline() {
+    OFFLINE.store(true, atomic::Ordering::Relaxed);
+}
+
+/// Is offline mode enabled with the flag or the environment?
+pub fn is_offline() -> bool {
+    OFFLINE.load(atomic::Ordering::Relaxed)
+        || env::var("RYE_OFFLINE").map_or(false, |x| {
+            !x.is_empty() && x != "0" && !x.eq_ignore_ascii_case("false")
+        })
+}
+
+/// In-place migrations of the self venv keyed by the version they migrate to.
+///
+/// Every entry lists the requirements that are installed or upgraded in place
+/// to bring a venv of the previous version up to date.  When bumping
+/// `SELF_VERSION`, add an entry here.  If a version has no entry, venvs older
+/// than it are rebuilt from scratch.
+const SELF_MIGRATIONS: &[(u64, &str)] = &[
+    // twine and unearth became optional.  Existing venvs keep them.
+    (19, ""),
+];
+
+/// Reads the version of the self venv.
+///
+/// The second value indicates if the venv was bootstrapped for uv only.
+fn read_self_version(venv_dir: &Path) -> Option<(u64, bool)> {
+    parse_self_version(&fs::read_to_string(venv_dir.join("tool-version.txt")).ok()?)
+}
+
+fn parse_self_version(contents: &str) -> Option<(u64, bool)> {
+    let contents = contents.trim();
+    let (version, uv_only) = match contents.strip_suffix(UV_FLAVOR_SUFFIX) {
+        Some(version) => (version, true),
+        None => (contents, false),
+    };
+    Some((version.parse().ok()?, uv_only))
+}
+
+/// Caches that the self venv was found up to date.
+///
+/// Reading this single file replaces probing the self venv on every
+/// invocation.  It lives within the self venv so that it goes away with it,
+/// it is removed before the self venv is migrated or rebuilt, and it is only
+/// trusted by the rye version that wrote it.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct SelfVenvOk {
+    rye_version: String,
+    tool_version: String,
+    python: PythonVersion,
+}
+
+fn get_self_venv_ok_path() -> PathBuf {
+    get_app_dir().join("self").join("rye-self-ok.json")
+}
+
+fn read_self_venv_ok() -> Option<SelfVenvOk> {
+    static SELF_VENV_OK: Lazy<Option<SelfVenvOk>> = Lazy::new(|| {
+        let contents = fs::read(get_self_venv_ok_path()).ok()?;
+        serde_json::from_slice::<SelfVenvOk>(&contents)
+            .ok()
+            .filter(|x| x.rye_version == env!("CARGO_PKG_VERSION"))
+    });
+    SELF_VENV_OK.clone()
+}
+
+/// Remembers that the self venv is up to date.  This is best effort.
+fn write_self_venv_ok(venv_dir: &Path) {
+    let (Some(tool_version), Some(marker)) = (
+        fs::read_to_string(venv_dir.join("tool-version.txt")).ok(),
+        read_venv_marker(venv_dir),
+    ) else {
+        return;
+    };
+    if let Ok(contents) = serde_json::to_vec(&SelfVenvOk {
+        rye_version: env!("CARGO_PKG_VERSION").to_string(),
+        tool_version: tool_version.trim().to_string(),
+        python: marker.python,
+    }) {
+        write_atomic(get_self_venv_ok_path(), contents).ok();
+    }
+}
+
+/// Forgets that the self venv is up to date, before it is modified.
+pub fn remove_self_venv_ok() -> Result<(), Error> {
+    let path = get_self_venv_ok_path();
+    if path.is_file() {
+        fs::remove_file(&path).path_context(&path, "unable to remove self venv marker")?;
+    }
+    Ok(())
+}
+
+/// Returns the interpreter of the self venv if it was bootstrapped.
+pub fn get_self_venv_python() -> Option<PythonVersion> {
+    match read_self_venv_ok() {
+        Some(marker) => Some(marker.python),
+        None => read_venv_marker(&get_app_dir().join("self")).map(|x| x.python),
+    }
+}
+
+/// Returns the contents of the `tool-version.txt` of the self venv.
+pub fn get_self_tool_version() -> Option<String> {
+    let contents = fs::read_to_string(get_app_dir().join("self").join("tool-version.txt")).ok()?;
+    Some(contents.trim().to_string())
+}
+
+fn is_current_self_version((version, uv_only): (u64, bool)) -> bool {
+    // a uv-only self venv lacks the pip requirements, so it's
+    // outdated once uv is turned off.  The reverse is fine.
+    version == SELF_VERSION && (!uv_only || Backend::current().is_uv())
+}
+
 fn is_up_to_date() -> bool {
     static UP_TO_UPDATE: Lazy<bool> = Lazy::new(|| {
-        fs::read_to_string(get_app_dir().join("self").join("tool-version.txt"))
-            .ok()
-            .map_or(false, |x| x.parse() == Ok(SELF_VERSION))
+        let venv_dir = get_app_dir().join("self");
+        let rv = read_self_version(&venv_dir).map_or(false, is_current_self_version);
+        if rv {
+            write_self_venv_ok(&venv_dir);
+        }
+        rv
     });
     *UP_TO_UPDATE || FORCED_TO_UPDATE.load(atomic::Ordering::Relaxed)
 }
@@ -77,6 +241,15 @@ pub fn get_self_venv_status() -> Result<PathBuf, (PathBuf, SelfVenvStatus)> {
     let app_dir = get_app_dir();
     let venv_dir = app_dir.join("self");
 
+    // fast path: the self venv was found up to date before.  The marker
+    // lives within the self venv so reading it is the only check needed.
+    if read_self_venv_ok()
+        .and_then(|x| parse_self_version(&x.tool_version))
+        .map_or(false, is_current_self_version)
+    {
+        return Ok(venv_dir);
+    }
+
     if venv_dir.is_dir() {
         if is_up_to_date() {
             Ok(venv_dir)
@@ -102,8 +275,29 @@ pub fn ensure_self_venv_with_toolchain(
 
     let venv_dir = match get_self_venv_status() {
         Ok(venv_dir) => return Ok(venv_dir),
+        Err((_, status)) if is_offline() => bail!(
+            "rye internals {}, which requires network access but rye is in offline mode.  \
+             Run rye once with network access to bootstrap them.",
+            match status {
+                SelfVenvStatus::DoesNotExist => "are not bootstrapped yet",
+                SelfVenvStatus::NotUpToDate => "are outdated and need to be refreshed",
+            }
+        ),
         Err((venv_dir, SelfVenvStatus::DoesNotExist)) => venv_dir,
         Err((venv_dir, SelfVenvStatus::NotUpToDate)) => {
+            remove_self_venv_ok()?;
+            if toolchain_version_request.is_none() {
+                match migrate_self_venv(&venv_dir, output) {
+                    Ok(true) => {
+                        FORCED_TO_UPDATE.store(true, atomic::Ordering::Relaxed);
+                        return Ok(venv_dir);
+                    }
+                    Ok(false) => {}
+                    Err(err) => {
+                        echo!(if verbose output, "could not migrate rye internals: {:?}", err);
+                    }
+                }
+            }
             echo!(if output, "Detected outdated rye internals. Refreshing");
             fs::remove_dir_all(&venv_dir)
                 .path_context(&venv_dir, "could not remove self-venv for update")?;
@@ -120,11 +314,6 @@ pub fn ensure_self_venv_with_toolchain(
 
     echo!(if output, "Bootstrapping rye internals");
 
-    // Ensure we have uv
-    let uv = UvBuilder::new()
-        .with_output(CommandOutput::Quiet)
-        .ensure_exists()?;
-
     let version = match toolchain_version_request {
         Some(ref version_request) => ensure_specific_self_toolchain(output, version_request)
             .with_context(|| {
@@ -149,92 +338,141 @@ pub fn ensure_self_venv_with_toolchain(
         validate_shared_libraries(&py_bin)?;
     }
 
-    // initialize the virtualenv
-    {
-        let uv_venv = uv.venv(&venv_dir, &py_bin, &version, None)?;
-        // write our marker
-        uv_venv.write_marker()?;
-        // update pip and our requirements
-        uv_venv.update(LATEST_PIP, SELF_REQUIREMENTS)?;
-
-        // Update the shims
-        let shims = app_dir.join("shims");
-        if !shims.is_dir() {
-            fs::create_dir_all(&shims).path_context(&shims, "tried to create shim folder")?;
-        }
-
-        // if rye is itself installed into the shims folder, we want to
-        // use that.  Otherwise we fall back to the current executable
-        let mut this = shims.join("rye").with_extension(EXE_EXTENSION);
-        if !this.is_file() {
-            this = env::current_exe()?;
-        }
-
-        update_core_shims(&shims, &this)?;
-
-        uv_venv.write_tool_version(SELF_VERSION)?;
-    }
+    // initialize the virtualenv.  Without uv the pip requirements are needed.
+    let backend = Backend::current();
+    let use_uv = backend.is_uv();
+    backend.create_internal_venv(&venv_dir, &py_bin, &version, output)?;
+    write_venv_marker(&venv_dir, &version)?;
+    install_self_requirements(&venv_dir, &version, LATEST_PIP, output)?;
+    install_self_requirements(
+        &venv_dir,
+        &version,
+        &if use_uv {
+            SELF_REQUIREMENTS.to_string()
+        } else {
+            format!("{}{}", SELF_REQUIREMENTS, SELF_PIP_REQUIREMENTS)
+        },
+        output,
+    )?;
+    refresh_core_shims()?;
+    write_self_version(&venv_dir, use_uv)?;
 
     FORCED_TO_UPDATE.store(true, atomic::Ordering::Relaxed);
 
     Ok(venv_dir)
 }
 
-pub fn update_core_shims(shims: &Path, this: &Path) -> Result<(), Error> {
-    #[cfg(unix)]
+/// Brings an outdated self venv up to date by running the migration steps.
+///
+/// Returns `false` if the venv cannot be migrated and needs to be rebuilt.
+fn migrate_self_venv(venv_dir: &Path, output: CommandOutput) -> Result<bool, Error> {
+    let (from_version, uv_only) = match read_self_version(venv_dir) {
+        Some(rv) => rv,
+        None => return Ok(false),
+    };
+    if from_version > SELF_VERSION {
+        return Ok(false);
+    }
+    let requirements = match ((from_version + 1)..=SELF_VERSION)
+        .map(|version| SELF_MIGRATIONS.iter().find(|x| x.0 == version).map(|x| x.1))
+        .collect::<Option<Vec<_>>>()
     {
-        let py_shim = shims.join("python");
-        let py3_shim = shims.join("python3");
-
-        // on linux we cannot symlink at all, as this will misreport.  We will try to do
-        // hardlinks and if that fails, we fall back to copying the entire file over.  This
-        // for instance is needed when the rye executable is placed on a different volume
-        // than ~/.rye/shims
-        if cfg!(target_os = "linux") {
-            fs::remove_file(&py_shim).ok();
-            if fs::hard_link(this, &py_shim).is_err() {
-                fs::copy(this, &py_shim).path_context(&py_shim, "tried to copy python shim")?;
-            }
-            fs::remove_file(&py3_shim).ok();
-            if fs::hard_link(this, &py3_shim).is_err() {
-                fs::copy(this, &py3_shim).path_context(&py_shim, "tried to copy python3 shim")?;
-            }
+        Some(requirements) => requirements,
+        None => return Ok(false),
+    };
 
-        // on other unices we always use symlinks
-        } else {
-            fs::remove_file(&py_shim).ok();
-            symlink_file(this, &py_shim).path_context(&py_shim, "tried to symlink python shim")?;
-            fs::remove_file(&py3_shim).ok();
-            symlink_file(this, &py3_shim)
-                .path_context(&py3_shim, "tried to symlink python3 shim")?;
-        }
+    // the interpreter of the venv needs to still be around and be the one
+    // we would bootstrap with today.
+    let marker = match read_venv_marker(venv_dir) {
+        Some(marker) => marker,
+        None => return Ok(false),
+    };
+    if marker.python.minor != SELF_PYTHON_TARGET_VERSION.minor
+        || !get_venv_python_bin(venv_dir).is_file()
+    {
+        return Ok(false);
     }
 
-    #[cfg(windows)]
-    {
-        let py_shim = shims.join("python.exe");
-        let pyw_shim = shims.join("pythonw.exe");
-        let py3_shim = shims.join("python3.exe");
-
-        // on windows we need privileges to symlink.  Not everyone might have that, so we
-        // fall back to hardlinks.
-        fs::remove_file(&py_shim).ok();
-        if symlink_file(this, &py_shim).is_err() {
-            fs::hard_link(this, &py_shim).path_context(&py_shim, "tried to symlink python shim")?;
-        }
-        fs::remove_file(&py3_shim).ok();
-        if symlink_file(this, &py3_shim).is_err() {
-            fs::hard_link(this, &py3_shim)
-                .path_context(&py3_shim, "tried to symlink python3 shim")?;
-        }
-        fs::remove_file(&pyw_shim).ok();
-        if symlink_file(this, &pyw_shim).is_err() {
-            fs::hard_link(this, &pyw_shim)
-                .path_context(&pyw_shim, "tried to symlink pythonw shim")?;
+    echo!(
+        if output,
+        "Migrating rye internals from version {} to {}",
+        from_version,
+        SELF_VERSION
+    );
+    for requirements in requirements {
+        if !requirements.is_empty() {
+            install_self_requirements(venv_dir, &marker.python, requirements, output)?;
         }
     }
+    refresh_core_shims()?;
 
-    Ok(())
+    // a uv-only venv needs the pip requirements once uv is turned off
+    let use_uv = Backend::current().is_uv();
+    if uv_only && !use_uv {
+        install_self_requirements(venv_dir, &marker.python, SELF_PIP_REQUIREMENTS, output)?;
+    }
+    write_self_version(venv_dir, uv_only && use_uv)?;
+
+    Ok(true)
+}
+
+fn write_self_version(venv_dir: &Path, uv_only: bool) -> Result<(), Error> {
+    let version = if uv_only {
+        format!("{}{}", SELF_VERSION, UV_FLAVOR_SUFFIX)
+    } else {
+        SELF_VERSION.to_string()
+    };
+    let path = venv_dir.join("tool-version.txt");
+    fs::write(&path, version).path_context(&path, "could not write tool version")
+}
+
+/// Installs or upgrades requirements in the self venv.
+fn install_self_requirements(
+    venv_dir: &Path,
+    py_ver: &PythonVersion,
+    requirements: &str,
+    output: CommandOutput,
+) -> Result<(), Error> {
+    Backend::current().install_internal_requirements(venv_dir, py_ver, requirements, output)
+}
+
+/// Bootstraps the venv for rye itself and ensures twine is installed.
+///
+/// Self venvs bootstrapped for uv do not come with twine, so it gets
+/// installed the first time it's needed.
+pub fn ensure_self_venv_with_twine(output: CommandOutput) -> Result<PathBuf, Error> {
+    ensure_self_venv_with_tool(output, "twine", SELF_TWINE_REQUIREMENT)
+}
+
+/// Bootstraps the venv for rye itself and ensures coverage is installed.
+///
+/// This is only used to combine and report coverage data, so it's installed
+/// the first time it's needed.
+pub fn ensure_self_venv_with_coverage(output: CommandOutput) -> Result<PathBuf, Error> {
+    ensure_self_venv_with_tool(output, "coverage", SELF_COVERAGE_REQUIREMENT)
+}
+
+fn ensure_self_venv_with_tool(
+    output: CommandOutput,
+    tool: &str,
+    requirement: &str,
+) -> Result<PathBuf, Error> {
+    let venv_dir = ensure_self_venv(output)?;
+    let bin = venv_dir
+        .join(VENV_BIN)
+        .join(tool)
+        .with_extension(EXE_EXTENSION);
+    if bin.is_file() {
+        return Ok(venv_dir);
+    }
+
+    echo!(if output, "Installing {} into rye internals", tool);
+    let marker =
+        read_venv_marker(&venv_dir).ok_or_else(|| anyhow!("self-venv is missing its marker"))?;
+    install_self_requirements(&venv_dir, &marker.python, requirement, output)
+        .with_context(|| format!("failed to install {}", tool))?;
+
+    Ok(venv_dir)
 }
 
 /// Returns the pip runner for the self venv
@@ -244,6 +482,11 @@ pub fn get_pip_runner(venv: &Path) -> Result<PathBuf, Error> {
 
 /// Returns the pip module for the self venv
 pub fn get_pip_module(venv: &Path) -> Result<PathBuf, Error> {
+    Ok(get_site_packages_dir(venv)?.join("pip"))
+}
+
+/// Returns the site-packages folder of a virtualenv.
+pub fn get_site_packages_dir(venv: &Path) -> Result<PathBuf, Error> {
     let mut rv = venv.to_path_buf();
     rv.push("lib");
     #[cfg(windows)]
@@ -279,7 +522,6 @@ pub fn get_pip_module(venv: &Path) -> Result<PathBuf, Error> {
             bail!("no site-packages in venv");
         }
     }
-    rv.push("pip");
     Ok(rv)
 }
 
@@ -367,7 +609,7 @@ impl FetchOptions {
 impl Default for FetchOptions {
     fn default() -> Self {
         Self {
-            output: CommandOutput::Normal,
+            output: CommandOutput::NORMAL,
             force: false,
             target_path: None,
             build_info: None,
@@ -394,8 +636,56 @@ pub fn fetch(
         Some(result) => result,
         None => bail!("unknown version {}", version),
     };
+    if options.target_path.is_none() {
+        wait_for_job(&version, options.output);
+    }
 
-    let target_dir = match options.target_path {
+    let Some(target_dir) = prepare_target_dir(&version, &options)? else {
+        return Ok(version);
+    };
+
+    echo!(if verbose options.output, "download url: {}", url);
+    let archive_buffer = match find_cached_download(url) {
+        Some(path) => {
+            echo!(if options.output, "{} {} from download cache", style("Using").cyan(), version);
+            fs::read(&path).path_context(&path, "unable to read cached download")?
+        }
+        None => {
+            echo!(if options.output, "{} {}", style("Downloading").cyan(), version);
+            download_toolchain(url, options.output)?
+        }
+    };
+
+    let sha256 = match get_manifest_checksum(url, options.output) {
+        Some(sha256) => Some(Cow::Owned(sha256)),
+        None => sha256.map(Cow::Borrowed),
+    };
+    install_archive(
+        &version,
+        &archive_buffer,
+        url,
+        sha256.as_deref(),
+        &target_dir,
+        &options,
+    )?;
+
+    echo!(if options.output, "{} {}", style("Downloaded").green(), version);
+    if options.target_path.is_none() {
+        refresh_versioned_shims()?;
+    }
+
+    Ok(version)
+}
+
+/// Resolves the folder a toolchain is installed into.
+///
+/// Returns `None` if the toolchain is already installed and should not be
+/// replaced.
+fn prepare_target_dir<'a>(
+    version: &PythonVersion,
+    options: &'a FetchOptions,
+) -> Result<Option<Cow<'a, Path>>, Error> {
+    Ok(Some(match options.target_path {
         Some(ref target_dir) => {
             if target_dir.is_file() {
                 bail!("target directory '{}' is a file", target_dir.display());
@@ -421,12 +711,12 @@ pub fn fetch(
             Cow::Borrowed(target_dir.as_path())
         }
         None => {
-            let target_dir = get_canonical_py_path(&version)?;
-            let target_py_bin = get_toolchain_python_bin(&version)?;
+            let target_dir = get_canonical_py_path(version)?;
+            let target_py_bin = get_toolchain_python_bin(version)?;
             if target_py_bin.is_file() {
                 if !options.force {
                     echo!(if verbose options.output, "Python version already downloaded. Skipping.");
-                    return Ok(version);
+                    return Ok(None);
                 }
                 echo!(if options.output, "Removing the existing Python version");
                 fs::remove_dir_all(&target_dir).with_context(|| {
@@ -436,16 +726,24 @@ pub fn fetch(
             echo!(if verbose options.output, "target dir: {}", target_dir.display());
             Cow::Owned(target_dir)
         }
-    };
-
-    echo!(if verbose options.output, "download url: {}", url);
-    echo!(if options.output, "{} {}", style("Downloading").cyan(), version);
-    let archive_buffer = download_url(url, options.output)?;
+    }))
+}
 
+/// Verifies, unpacks and smoke tests a toolchain archive into its target folder.
+///
+/// `source` names the archive in error messages.
+fn install_archive(
+    version: &PythonVersion,
+    archive_buffer: &[u8],
+    source: &str,
+    sha256: Option<&str>,
+    target_dir: &Path,
+    options: &FetchOptions,
+) -> Result<(), Error> {
     if let Some(sha256) = sha256 {
         echo!(if options.output, "{} {}", style("Checking").cyan(), "checksum");
-        check_checksum(&archive_buffer, sha256)
-            .with_context(|| format!("Checksum check of {} failed", &url))?;
+        check_checksum(archive_buffer, sha256)
+            .with_context(|| format!("Checksum check of {} failed", source))?;
     } else {
         echo!(if options.output, "Checksum check skipped (no hash available)");
     }
@@ -456,40 +754,84 @@ pub fn fetch(
         .parent()
         .ok_or_else(|| anyhow!("cannot unpack to root"))?;
     if !parent.exists() {
-        fs::create_dir_all(parent).path_context(&target_dir, "failed to create target folder")?;
+        fs::create_dir_all(parent).path_context(target_dir, "failed to create target folder")?;
     }
 
     let with_build_info = options
         .build_info
         .unwrap_or_else(|| Config::current().fetch_with_build_info());
     let temp_dir = tempdir_in(parent).context("temporary unpack location")?;
+    let _temp_guard = RemoveOnInterrupt::new(temp_dir.path());
 
-    unpack_archive(&archive_buffer, temp_dir.path(), 1).with_context(|| {
+    unpack_archive(archive_buffer, temp_dir.path(), 1).with_context(|| {
         format!(
-            "unpacking of downloaded tarball {} to '{}' failed",
-            &url,
+            "unpacking of {} to '{}' failed",
+            source,
             temp_dir.path().display(),
         )
     })?;
+    #[cfg(target_os = "macos")]
+    {
+        prepare_macos_toolchain(temp_dir.path(), options.output)?;
+    }
+    smoke_test_toolchain(version, temp_dir.path(), options.output)?;
 
     // if we want to retain build infos or the installation has no build infos, then move
     // the folder into the permanent location
     if with_build_info || !installation_has_build_info(temp_dir.path()) {
         let temp_dir = temp_dir.into_path();
-        fs::rename(&temp_dir, &target_dir).map_err(|err| {
+        fs::rename(&temp_dir, target_dir).map_err(|err| {
             fs::remove_dir_all(&temp_dir).ok();
             err
         })
 
     // otherwise move the contents of the `install` folder over.
     } else {
-        fs::rename(temp_dir.path().join("install"), &target_dir)
+        fs::rename(temp_dir.path().join("install"), target_dir)
     }
-    .path_context(&target_dir, "unable to persist download")?;
+    .path_context(target_dir, "unable to persist download")?;
 
-    echo!(if options.output, "{} {}", style("Downloaded").green(), version);
+    Ok(())
+}
 
-    Ok(version)
+/// Installs a toolchain from a local archive.
+///
+/// The archive goes through the same checks as a download.  Without an
+/// explicit checksum the builtin one is used if the archive has the file name
+/// of the official download.
+pub fn install_from_archive(
+    version: &PythonVersion,
+    archive: &Path,
+    sha256: Option<&str>,
+    options: FetchOptions,
+) -> Result<PythonVersion, Error> {
+    let Some(target_dir) = prepare_target_dir(version, &options)? else {
+        return Ok(version.clone());
+    };
+    let archive_buffer = fs::read(archive).path_context(archive, "unable to read archive")?;
+    let sha256 = sha256.map(|x| x.to_ascii_lowercase()).or_else(|| {
+        let (_, url, sha256) = get_download_url(&version.clone().into())?;
+        let filename = archive.file_name()?.to_str()?;
+        let official = url.rsplit('/').next()?;
+        if official == filename || official.replace("%2B", "+") == filename {
+            sha256.map(|x| x.to_string())
+        } else {
+            None
+        }
+    });
+    install_archive(
+        version,
+        &archive_buffer,
+        &archive.display().to_string(),
+        sha256.as_deref(),
+        &target_dir,
+        &options,
+    )?;
+    echo!(if options.output, "{} {}", style("Installed").green(), version);
+    if options.target_path.is_none() {
+        refresh_versioned_shims()?;
+    }
+    Ok(version.clone())
 }
 
 fn installation_has_build_info(p: &Path) -> bool {
@@ -507,6 +849,166 @@ fn installation_has_build_info(p: &Path) -> bool {
     has_install && has_build
 }
 
+/// How often a download is attempted before giving up.
+const DOWNLOAD_ATTEMPTS: u32 = 5;
+
+/// The delay before the first retry of a download, doubled for every further one.
+const DOWNLOAD_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Partial downloads older than this are started over, the resource might
+/// have changed in the meantime.
+const PARTIAL_DOWNLOAD_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Classifies a failed download.
+#[derive(Debug)]
+pub enum DownloadError {
+    /// The server responded with a non successful status code.
+    Http(u32),
+    /// The transfer ended before all announced bytes arrived.
+    Truncated { expected: u64, received: u64 },
+    /// More bytes arrived than the server announced.
+    Corrupted { expected: u64, received: u64 },
+}
+
+impl fmt::Display for DownloadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DownloadError::Http(code) => write!(f, "Failed to download: {}", code),
+            DownloadError::Truncated { expected, received } => write!(
+                f,
+                "download truncated: received {} of {} bytes",
+                received, expected
+            ),
+            DownloadError::Corrupted { expected, received } => write!(
+                f,
+                "download corrupted: received {} bytes but expected {}",
+                received, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DownloadError {}
+
+impl DownloadError {
+    /// Can another attempt succeed?
+    fn is_transient(&self) -> bool {
+        match self {
+            // 416 means the partial download was discarded and starts over.
+            DownloadError::Http(code) => matches!(code, 408 | 416 | 429 | 500..=599),
+            DownloadError::Truncated { .. } => true,
+            DownloadError::Corrupted { .. } => false,
+        }
+    }
+}
+
+/// Is a failed download worth another attempt?
+fn is_transient_download_error(err: &Error) -> bool {
+    if let Some(err) = err.downcast_ref::<DownloadError>() {
+        err.is_transient()
+    } else if let Some(err) = err.downcast_ref::<curl::Error>() {
+        err.is_couldnt_connect()
+            || err.is_couldnt_resolve_host()
+            || err.is_operation_timedout()
+            || err.is_partial_file()
+            || err.is_recv_error()
+            || err.is_send_error()
+            || err.is_got_nothing()
+            || err.is_ssl_connect_error()
+    } else {
+        false
+    }
+}
+
+/// Returns the file a download of the URL is streamed to.
+///
+/// It stays behind if the download fails so that the next attempt, also of
+/// a later command, resumes it.
+fn get_partial_download_path(url: &str) -> PathBuf {
+    get_download_cache_dir()
+        .join("partial")
+        .join(hex::encode(Sha256::digest(url.as_bytes())))
+}
+
+/// Locks the partial download of a URL.
+///
+/// Concurrent downloads of the same URL (eg: parallel `rye sync` runs in CI)
+/// wait for each other instead of writing to the same file.  The lock is
+/// released when the returned file is dropped.
+fn lock_partial_download(partial: &Path, output: CommandOutput) -> Result<LockFile, Error> {
+    if let Some(parent) = partial.parent() {
+        fs::create_dir_all(parent).path_context(parent, "unable to create download cache")?;
+    }
+    let mut lock_path = partial.as_os_str().to_owned();
+    lock_path.push(".lock");
+    let lock_path = PathBuf::from(lock_path);
+    let mut lock =
+        LockFile::open(&lock_path).path_context(&lock_path, "unable to open download lock")?;
+    if !lock
+        .try_lock()
+        .path_context(&lock_path, "unable to lock download")?
+    {
+        echo!(if verbose output, "waiting for another download of the same file");
+        lock.lock()
+            .path_context(&lock_path, "unable to lock download")?;
+    }
+    Ok(lock)
+}
+
+/// Parses the status code from an HTTP status line.
+fn parse_status_line(header: &[u8]) -> Option<u32> {
+    let header = std::str::from_utf8(header).ok()?;
+    if !header.starts_with("HTTP/") {
+        return None;
+    }
+    header.split_whitespace().nth(1)?.parse().ok()
+}
+
+/// Returns the folder where pre-fetched toolchain archives are picked up.
+pub fn get_download_cache_dir() -> PathBuf {
+    get_app_dir().join("cache").join("downloads")
+}
+
+/// Looks up a pre-fetched archive for a download URL.
+///
+/// Archives are matched by file name, both with the `+` of the URL encoded
+/// and decoded.  Their checksums are verified like those of downloads.
+fn find_cached_download(url: &str) -> Option<PathBuf> {
+    find_archive_in_dir(&get_download_cache_dir(), url)
+}
+
+fn find_archive_in_dir(dir: &Path, url: &str) -> Option<PathBuf> {
+    let filename = url.rsplit('/').next()?;
+    [filename.to_string(), filename.replace("%2B", "+")]
+        .into_iter()
+        .map(|x| dir.join(x))
+        .find(|x| x.is_file())
+}
+
+/// Looks up the checksum of a download in the configured checksum manifest.
+///
+/// Returns `None` if no manifest is configured, it cannot be fetched or it
+/// does not list the archive, in which case the builtin hash is used.
+fn get_manifest_checksum(url: &str, output: CommandOutput) -> Option<String> {
+    let manifest_url = Config::current().python_checksum_url()?;
+    echo!(if verbose output, "checksum manifest: {}", manifest_url);
+    let manifest = match download_url(&manifest_url, output.quieter()) {
+        Ok(manifest) => manifest,
+        Err(err) => {
+            warn!(
+                "could not fetch checksum manifest {}: {}",
+                manifest_url, err
+            );
+            return None;
+        }
+    };
+    let rv = find_checksum_in_manifest(&String::from_utf8_lossy(&manifest), url);
+    if rv.is_none() {
+        echo!(if verbose output, "archive not listed in checksum manifest, using builtin hash");
+    }
+    rv
+}
+
 pub fn download_url(url: &str, output: CommandOutput) -> Result<Vec<u8>, Error> {
     match download_url_ignore_404(url, output)? {
         Some(result) => Ok(result),
@@ -515,22 +1017,153 @@ pub fn download_url(url: &str, output: CommandOutput) -> Result<Vec<u8>, Error>
 }
 
 pub fn download_url_ignore_404(url: &str, output: CommandOutput) -> Result<Option<Vec<u8>>, Error> {
+    download_url_with_timeout(url, output, None, false)
+}
+
+/// Downloads a toolchain archive from the first configured mirror that has it.
+///
+/// Mirrors in local folders are read directly.
+pub fn download_toolchain(url: &str, output: CommandOutput) -> Result<Vec<u8>, Error> {
+    let candidates = get_mirror_urls(url, &Config::current().python_download_mirrors()?);
+    if candidates == [(url.to_string(), None)] {
+        return download_url(url, output);
+    }
+    let mut failures = Vec::new();
+    for (idx, (candidate, timeout)) in candidates.iter().enumerate() {
+        echo!(if verbose output, "trying mirror: {}", candidate);
+        let rv = if candidate.starts_with("https://") {
+            // retrying an unreachable mirror would defeat its timeout, only
+            // the last one is retried.
+            let single_attempt = idx + 1 < candidates.len();
+            download_url_with_timeout(candidate, output, *timeout, single_attempt)
+        } else {
+            read_local_archive(candidate)
+        };
+        match rv {
+            Ok(Some(rv)) => return Ok(rv),
+            Ok(None) => failures.push(format!("{}: not found", candidate)),
+            Err(err) => failures.push(format!("{}: {:#}", candidate, err)),
+        }
+        if !output.is_quiet() && failures.len() < candidates.len() {
+            warn!("{}, trying the next mirror", failures.last().unwrap());
+        }
+    }
+    bail!(
+        "Failed to download from any mirror:\n  {}",
+        failures.join("\n  ")
+    );
+}
+
+/// Reads a toolchain archive from a mirror in a local folder.
+///
+/// The path can be given as `file://` URL and the archive be stored with a
+/// decoded `+` in its name.
+fn read_local_archive(path: &str) -> Result<Option<Vec<u8>>, Error> {
+    let path = Path::new(path.strip_prefix("file://").unwrap_or(path));
+    let (Some(dir), Some(filename)) = (path.parent(), path.file_name()) else {
+        return Ok(None);
+    };
+    match find_archive_in_dir(dir, &filename.to_string_lossy()) {
+        Some(path) => fs::read(&path)
+            .path_context(&path, "unable to read archive from mirror")
+            .map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Downloads a URL, overriding the connect timeout if given.
+///
+/// Transient failures are retried unless `single_attempt` is set.
+fn download_url_with_timeout(
+    url: &str,
+    output: CommandOutput,
+    connect_timeout: Option<Duration>,
+    single_attempt: bool,
+) -> Result<Option<Vec<u8>>, Error> {
     // for now we only allow HTTPS downloads.
     if !url.starts_with("https://") {
         bail!("Refusing insecure download");
     }
+    if is_offline() {
+        bail!("cannot download {} in offline mode", url);
+    }
+
+    let partial = get_partial_download_path(url);
+    let _lock = lock_partial_download(&partial, output)?;
+    let mut attempt = 1;
+    loop {
+        match download_url_attempt(url, &partial, output, connect_timeout) {
+            Err(err)
+                if !single_attempt
+                    && attempt < DOWNLOAD_ATTEMPTS
+                    && is_transient_download_error(&err) =>
+            {
+                let delay = DOWNLOAD_BACKOFF * 2u32.pow(attempt - 1);
+                if !output.is_quiet() {
+                    warn!(
+                        "{:#}, retrying in {}s ({}/{})",
+                        err,
+                        delay.as_secs(),
+                        attempt,
+                        DOWNLOAD_ATTEMPTS - 1
+                    );
+                }
+                thread::sleep(delay);
+                attempt += 1;
+            }
+            rv => return rv,
+        }
+    }
+}
 
+/// Downloads a URL into the partial download, resuming what is already there.
+///
+/// The contents are returned and the partial download is removed once
+/// complete.
+fn download_url_attempt(
+    url: &str,
+    partial: &Path,
+    output: CommandOutput,
+    connect_timeout: Option<Duration>,
+) -> Result<Option<Vec<u8>>, Error> {
     let config = Config::current();
-    let mut archive_buffer = Vec::new();
+    let is_stale = fs::metadata(partial)
+        .and_then(|x| x.modified())
+        .ok()
+        .and_then(|x| x.elapsed().ok())
+        .map_or(false, |age| age > PARTIAL_DOWNLOAD_MAX_AGE);
+    if is_stale {
+        fs::remove_file(partial).ok();
+    }
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(partial)
+        .path_context(partial, "unable to open partial download")?;
+    let offset = file
+        .metadata()
+        .path_context(partial, "unable to open partial download")?
+        .len();
+
     let mut handle = curl::easy::Easy::new();
     handle.url(url)?;
     handle.progress(true)?;
     handle.follow_location(true)?;
+    if offset > 0 {
+        echo!(if verbose output, "resuming download at {}", HumanBytes(offset));
+        handle.resume_from(offset)?;
+    }
 
     // we only do https requests here, so we always set an https proxy
     if let Some(proxy) = config.https_proxy_url() {
         handle.proxy(&proxy)?;
     }
+    if let Some(timeout) = connect_timeout.or_else(|| config.network_connect_timeout()) {
+        handle.connect_timeout(timeout)?;
+    }
+    if let Some(timeout) = config.network_timeout() {
+        handle.timeout(timeout)?;
+    }
 
     // on windows we want to disable revocation checks.  The reason is that MITM proxies
     // will otherwise not work.  This is a schannel specific behavior anyways.
@@ -540,24 +1173,48 @@ pub fn download_url_ignore_404(url: &str, output: CommandOutput) -> Result<Optio
         handle.ssl_options(curl::easy::SslOpt::new().no_revoke(true))?;
     }
 
-    let write_archive = &mut archive_buffer;
-    {
+    // the status of the latest response, redirects send more than one.
+    let status = Cell::new(0);
+    let mut restart = offset > 0;
+    let mut write_error = None;
+    let result = {
         let mut transfer = handle.transfer();
         let mut pb = None;
+        let mut last_report = Instant::now();
+        transfer.header_function(|header| {
+            if let Some(code) = parse_status_line(header) {
+                status.set(code);
+            }
+            true
+        })?;
+        let status = &status;
         transfer.progress_function(move |a, b, _, _| {
-            if output == CommandOutput::Quiet {
+            if output.is_quiet() {
                 return true;
             }
 
-            let (down_len, down_pos) = (a as u64, b as u64);
-            if down_len > 0 {
+            // a resumed download reports the remaining bytes only
+            let base = if status.get() == 206 { offset } else { 0 };
+            let (down_len, down_pos) = (a as u64 + base, b as u64 + base);
+            if is_ci_mode() {
+                if down_len > 0
+                    && down_pos < down_len
+                    && last_report.elapsed() >= CI_PROGRESS_INTERVAL
+                {
+                    elog!(
+                        "[{}] downloading {}/{} ({}%)",
+                        ci_timestamp(),
+                        HumanBytes(down_pos),
+                        HumanBytes(down_len),
+                        down_pos * 100 / down_len
+                    );
+                    last_report = Instant::now();
+                }
+            } else if down_len > base {
                 if down_pos < down_len {
                     if pb.is_none() {
                         let pb_config = ProgressBar::new(down_len);
-                        pb_config.set_style(
-                            ProgressStyle::with_template("{wide_bar} {bytes:>7}/{total_bytes:7}")
-                                .unwrap(),
-                        );
+                        pb_config.set_style(crate::tui::progress_style());
                         pb = Some(pb_config);
                     }
                     pb.as_ref().unwrap().set_position(down_pos);
@@ -567,28 +1224,171 @@ pub fn download_url_ignore_404(url: &str, output: CommandOutput) -> Result<Optio
             }
             true
         })?;
-        transfer.write_function(move |data| {
-            write_archive.write_all(data).unwrap();
+        transfer.write_function(|data| {
+            match status.get() {
+                206 => {}
+                // the server ignored the range and sends everything
+                200..=299 if restart => {
+                    restart = false;
+                    if let Err(err) = file.set_len(0) {
+                        write_error = Some(err);
+                        return Ok(0);
+                    }
+                }
+                200..=299 => {}
+                // error pages are not part of the download
+                _ => return Ok(data.len()),
+            }
+            if let Err(err) = file.write_all(data) {
+                write_error = Some(err);
+                return Ok(0);
+            }
             Ok(data.len())
         })?;
-        transfer
-            .perform()
-            .with_context(|| format!("download of {} failed", &url))?;
+        transfer.perform()
+    };
+    if let Some(err) = write_error {
+        return Err(err).path_context(partial, "unable to write partial download");
     }
+    drop(file);
+
+    // a negative content length means that the server did not announce one.
+    // For a resumed download it only covers the remaining bytes.
     let code = handle.response_code()?;
+    let content_length = handle.content_length_download()?;
+    let expected = (content_length >= 0.0)
+        .then(|| content_length as u64 + if code == 206 { offset } else { 0 });
+    let received = fs::metadata(partial).map_or(0, |x| x.len());
+    if let Err(err) = result {
+        if let (true, Some(expected)) = (err.is_partial_file(), expected) {
+            return Err(Error::new(DownloadError::Truncated { expected, received })
+                .context(format!("download of {} failed", &url)));
+        }
+        return Err(Error::new(err).context(format!("download of {} failed", &url)));
+    }
+
+    if code == 404 || code == 416 {
+        fs::remove_file(partial).ok();
+    }
     if code == 404 {
         Ok(None)
     } else if !(200..300).contains(&code) {
-        bail!("Failed to download: {}", code)
+        Err(DownloadError::Http(code).into())
+    } else if let Some(expected) = expected.filter(|x| received < *x) {
+        Err(DownloadError::Truncated { expected, received }.into())
+    } else if let Some(expected) = expected.filter(|x| received > *x) {
+        fs::remove_file(partial).ok();
+        Err(DownloadError::Corrupted { expected, received }.into())
     } else {
-        Ok(Some(archive_buffer))
+        let rv = fs::read(partial).path_context(partial, "unable to read download")?;
+        fs::remove_file(partial).ok();
+        Ok(Some(rv))
     }
 }
 
+/// Makes an unpacked toolchain launch without Gatekeeper prompts.
+///
+/// Files that were downloaded or moved out of a quarantined folder can carry
+/// the quarantine attribute, which makes macOS ask before running them.  The
+/// attribute is removed from all files and, if enabled, the binaries are
+/// signed again with an ad-hoc signature.
+#[cfg(target_os = "macos")]
+fn prepare_macos_toolchain(root: &Path, output: CommandOutput) -> Result<(), Error> {
+    let config = Config::current();
+    let clear_quarantine = config.clear_quarantine();
+    let codesign = config.codesign_toolchains();
+    if !clear_quarantine && !codesign {
+        return Ok(());
+    }
+
+    for entry in walkdir::WalkDir::new(root) {
+        let entry = entry?;
+        let path = entry.path();
+        if clear_quarantine {
+            xattr::remove(path, "com.apple.quarantine").ok();
+        }
+        if codesign && entry.file_type().is_file() && is_mach_o(path) {
+            echo!(if verbose output, "signing {}", path.display());
+            let status = internal_command("/usr/bin/codesign")
+                .arg("--force")
+                .arg("--sign")
+                .arg("-")
+                .arg(path)
+                .stdout(std::process::Stdio::null())
+                .status()
+                .context("unable to run codesign")?;
+            if !status.success() {
+                bail!("failed to sign {}", path.display());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Checks if a file starts with one of the Mach-O magic numbers.
+#[cfg(target_os = "macos")]
+fn is_mach_o(path: &Path) -> bool {
+    use std::io::Read;
+    let mut magic = [0u8; 4];
+    if fs::File::open(path)
+        .and_then(|mut f| f.read_exact(&mut magic))
+        .is_err()
+    {
+        return false;
+    }
+    matches!(
+        u32::from_be_bytes(magic),
+        0xfeedface | 0xfeedfacf | 0xcefaedfe | 0xcffaedfe | 0xcafebabe
+    )
+}
+
+/// Runs a freshly unpacked interpreter to make sure it works on this machine.
+///
+/// This catches broken toolchains at install time rather than during the
+/// first sync.
+fn smoke_test_toolchain(
+    version: &PythonVersion,
+    root: &Path,
+    output: CommandOutput,
+) -> Result<(), Error> {
+    // toolchains for other platforms cannot be run here
+    if version.os != OS || version.arch != ARCH {
+        return Ok(());
+    }
+
+    let py = get_python_bin_within(root);
+    echo!(if verbose output, "running smoke test with {}", py.display());
+
+    #[cfg(target_os = "linux")]
+    {
+        validate_shared_libraries(&py)?;
+    }
+
+    let out = internal_command(&py)
+        .arg("-c")
+        .arg(TOOLCHAIN_PROBE)
+        .output()
+        .path_context(&py, "unable to run downloaded python")?;
+    if !out.status.success() {
+        bail!(
+            "Python installation failed the smoke test ({}):\n{}",
+            TOOLCHAIN_PROBE,
+            String::from_utf8_lossy(&out.stderr).trim_end()
+        );
+    }
+
+    Ok(())
+}
+
 #[cfg(target_os = "linux")]
 fn validate_shared_libraries(py: &Path) -> Result<(), Error> {
-    use std::process::Command;
-    let out = Command::new("ldd")
+    // prefer the system ldd over whatever is first on the PATH
+    let ldd = ["/usr/bin/ldd", "/bin/ldd"]
+        .into_iter()
+        .map(Path::new)
+        .find(|x| x.is_file())
+        .unwrap_or(Path::new("ldd"));
+    let out = std::process::Command::new(ldd)
         .arg(py)
         .output()
         .context("unable to invoke ldd on downloaded python binary")?;
@@ -621,3 +1421,38 @@ fn validate_shared_libraries(py: &Path) -> Result<(), Error> {
         Visit https://rye-up.com/guide/faq/#missing-shared-libraries-on-linux for next steps."
     );
 }
+
+#[cfg(test)]
+mod test {
+    use super::{parse_status_line, DownloadError};
+
+    #[test]
+    fn test_parse_status_line() {
+        assert_eq!(
+            parse_status_line(b"HTTP/1.1 206 Partial Content\r\n"),
+            Some(206)
+        );
+        assert_eq!(parse_status_line(b"HTTP/2 404\r\n"), Some(404));
+        assert_eq!(parse_status_line(b"content-length: 200\r\n"), None);
+        assert_eq!(parse_status_line(b"HTTP/1.1\r\n"), None);
+        assert_eq!(parse_status_line(b"\xff\xfe"), None);
+    }
+
+    #[test]
+    fn test_is_transient() {
+        assert!(DownloadError::Http(503).is_transient());
+        assert!(DownloadError::Http(429).is_transient());
+        assert!(DownloadError::Http(416).is_transient());
+        assert!(!DownloadError::Http(403).is_transient());
+        assert!(DownloadError::Truncated {
+            expected: 10,
+            received: 5
+        }
+        .is_transient());
+        assert!(!DownloadError::Corrupted {
+            expected: 5,
+            received: 10
+        }
+        .is_transient());
+    }
+}
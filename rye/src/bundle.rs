@@ -0,0 +1,454 @@
+//! Project bundles for handing off a reproducible environment.
+//!
+//! A bundle is a zstd compressed tarball.  It holds a `rye-bundle.json`
+//! manifest, the project sources and lockfiles under `project/`, optionally
+//! the distributions of all locked dependencies under `wheels/` and optionally
+//! an archive of the toolchain under `toolchain/`.  With both included a
+//! bundle can be restored without network access.
+use std::ffi::OsStr;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context, Error};
+use console::style;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tempfile::{tempdir, tempdir_in, NamedTempFile};
+
+use crate::bootstrap::{ensure_self_venv, install_from_archive, FetchOptions};
+use crate::consts::VENV_BIN;
+use crate::platform::get_canonical_py_path;
+use crate::pyproject::{ExpandedSources, PyProject};
+use crate::sources::py::PythonVersion;
+use crate::sync::{sync, SyncMode, SyncOptions};
+use crate::utils::cleanup::{run_interruptible, RemoveOnInterrupt};
+use crate::utils::{
+    internal_command, set_network_timeouts, set_proxy_variables, CommandOutput, IoPathContext,
+    Verbosity,
+};
+
+/// The name of the manifest within a bundle.
+const MANIFEST_NAME: &str = "rye-bundle.json";
+
+/// Bumped whenever the layout of bundles changes incompatibly.
+const BUNDLE_FORMAT: u32 = 1;
+
+/// Describes the contents of a bundle.
+#[derive(Serialize, Deserialize, Debug)]
+struct BundleManifest {
+    format: u32,
+    rye_version: String,
+    name: Option<String>,
+    python: PythonVersion,
+    wheels: bool,
+    toolchain: Option<BundledToolchain>,
+}
+
+/// The toolchain archive within a bundle.
+#[derive(Serialize, Deserialize, Debug)]
+struct BundledToolchain {
+    path: String,
+    sha256: String,
+}
+
+/// Controls what goes into a bundle.
+#[derive(Debug, Default)]
+pub struct ExportOptions {
+    pub output: CommandOutput,
+    /// Include the distributions of all locked dependencies.
+    pub wheels: bool,
+    /// Include the toolchain the project is pinned to.
+    pub toolchain: bool,
+}
+
+/// Writes a bundle of the project to the given archive path.
+pub fn export_bundle(
+    project: &PyProject,
+    archive: &Path,
+    options: ExportOptions,
+) -> Result<(), Error> {
+    let output = options.output;
+    let root = project.workspace_path();
+    let py_ver = project.venv_python_version()?;
+    let lockfiles = find_lockfiles(&root)?;
+    if lockfiles.is_empty() {
+        bail!(
+            "no lockfiles found in {}, run `rye lock` first",
+            root.display()
+        );
+    }
+
+    let scratch = tempdir()?;
+    let _scratch_guard = RemoveOnInterrupt::new(scratch.path());
+
+    let toolchain = if options.toolchain {
+        echo!(if output, "Archiving toolchain {}", style(&py_ver).cyan());
+        let path = format!("toolchain/{}.tar.zst", py_ver);
+        let sha256 = archive_toolchain(&py_ver, &scratch.path().join("toolchain.tar.zst"))?;
+        Some(BundledToolchain { path, sha256 })
+    } else {
+        None
+    };
+
+    let wheels_dir = scratch.path().join("wheels");
+    if options.wheels {
+        echo!(if output, "Downloading dependencies");
+        fs::create_dir_all(&wheels_dir).path_context(&wheels_dir, "unable to create folder")?;
+        download_wheels(project, &py_ver, &lockfiles, &wheels_dir, output)?;
+    }
+
+    let manifest = BundleManifest {
+        format: BUNDLE_FORMAT,
+        rye_version: env!("CARGO_PKG_VERSION").to_string(),
+        name: project.name().map(|x| x.to_string()),
+        python: py_ver.clone(),
+        wheels: options.wheels,
+        toolchain,
+    };
+
+    echo!(if output, "Writing {}", style(archive.display()).cyan());
+    let _archive_guard = RemoveOnInterrupt::new(archive);
+    write_bundle(
+        project,
+        archive,
+        &manifest,
+        scratch.path(),
+        options.wheels.then_some(wheels_dir.as_path()),
+    )
+    .map_err(|err| {
+        fs::remove_file(archive).ok();
+        err
+    })
+    .with_context(|| format!("unable to write bundle {}", archive.display()))?;
+
+    Ok(())
+}
+
+fn write_bundle(
+    project: &PyProject,
+    archive: &Path,
+    manifest: &BundleManifest,
+    scratch: &Path,
+    wheels_dir: Option<&Path>,
+) -> Result<(), Error> {
+    let root = project.workspace_path();
+    let venv = project.venv_path();
+    let file = fs::File::create(archive).path_context(archive, "unable to create archive")?;
+    let mut builder = tar::Builder::new(zstd::Encoder::new(file, 0)?);
+    builder.follow_symlinks(false);
+
+    let manifest = serde_json::to_vec_pretty(manifest)?;
+    let mut header = tar::Header::new_gnu();
+    header.set_size(manifest.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder.append_data(&mut header, MANIFEST_NAME, &manifest[..])?;
+
+    // the archive might be written into the project itself
+    let archive = archive.canonicalize()?;
+    for entry in walkdir::WalkDir::new(&*root)
+        .min_depth(1)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|entry| {
+            !(entry.file_type().is_dir()
+                && (skip_bundle_dir(entry.file_name()) || entry.path() == venv))
+        })
+    {
+        let entry = entry?;
+        if entry.file_type().is_dir() || entry.path().canonicalize().ok() == Some(archive.clone()) {
+            continue;
+        }
+        let rel = entry.path().strip_prefix(&*root)?;
+        builder.append_path_with_name(entry.path(), Path::new("project").join(rel))?;
+    }
+
+    if let Some(ref toolchain) = manifest.toolchain {
+        builder.append_path_with_name(scratch.join("toolchain.tar.zst"), &toolchain.path)?;
+    }
+    if let Some(wheels_dir) = wheels_dir {
+        builder.append_dir_all("wheels", wheels_dir)?;
+    }
+
+    let mut encoder = builder.into_inner()?;
+    encoder.flush()?;
+    encoder.finish()?;
+    Ok(())
+}
+
+/// Hidden folders like `.venv` and `.git` as well as caches are not bundled.
+fn skip_bundle_dir(name: &OsStr) -> bool {
+    name.to_str()
+        .map_or(false, |x| x.starts_with('.') || x == "__pycache__")
+}
+
+/// Finds the lockfiles of a workspace.
+pub fn find_lockfiles(root: &Path) -> Result<Vec<PathBuf>, Error> {
+    let mut rv = Vec::new();
+    for entry in fs::read_dir(root).path_context(root, "unable to read project folder")? {
+        let path = entry?.path();
+        if path.is_file()
+            && path
+                .file_name()
+                .and_then(|x| x.to_str())
+                .map_or(false, |x| {
+                    x.starts_with("requirements") && x.ends_with(".lock")
+                })
+        {
+            rv.push(path);
+        }
+    }
+    rv.sort();
+    Ok(rv)
+}
+
+/// Writes an archive of the toolchain that [`install_from_archive`] accepts
+/// and returns its checksum.
+fn archive_toolchain(py_ver: &PythonVersion, dst: &Path) -> Result<String, Error> {
+    let toolchain_dir = get_canonical_py_path(py_ver)?;
+    if toolchain_dir.is_file() {
+        bail!("toolchain {} is registered and cannot be bundled", py_ver);
+    } else if !toolchain_dir.is_dir() {
+        bail!(
+            "toolchain {} is not installed, fetch it with `rye fetch {}`",
+            py_ver,
+            py_ver
+        );
+    }
+
+    let file = fs::File::create(dst).path_context(dst, "unable to create toolchain archive")?;
+    let mut builder = tar::Builder::new(zstd::Encoder::new(file, 0)?);
+    builder.follow_symlinks(false);
+    builder
+        .append_dir_all("python", &toolchain_dir)
+        .path_context(&toolchain_dir, "unable to archive toolchain")?;
+    builder.into_inner()?.finish()?;
+
+    let mut hasher = Sha256::new();
+    io::copy(
+        &mut fs::File::open(dst).path_context(dst, "unable to read toolchain archive")?,
+        &mut hasher,
+    )?;
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// Downloads the distributions of all locked packages and of the build
+/// backends of the workspace projects.
+pub fn download_wheels(
+    project: &PyProject,
+    py_ver: &PythonVersion,
+    lockfiles: &[PathBuf],
+    dst: &Path,
+    output: CommandOutput,
+) -> Result<(), Error> {
+    let self_venv = ensure_self_venv(output)?;
+    let sources = ExpandedSources::from_sources(&project.sources()?)?;
+    let requirements = NamedTempFile::new()?;
+    let mut contents = String::new();
+    for lockfile in lockfiles {
+        let lock =
+            fs::read_to_string(lockfile).path_context(lockfile, "unable to read lockfile")?;
+        contents.push_str(&filter_local_requirements(&lock));
+    }
+    fs::write(requirements.path(), contents)
+        .path_context(requirements.path(), "unable to write requirements")?;
+
+    let mut build_requires = Vec::new();
+    match project.workspace() {
+        Some(workspace) => {
+            for member in workspace.iter_projects() {
+                build_requires.extend(member?.build_requires());
+            }
+        }
+        None => build_requires.extend(project.build_requires()),
+    }
+    build_requires.sort();
+    build_requires.dedup();
+
+    let download = |extra_args: &mut dyn FnMut(&mut std::process::Command)| {
+        let mut cmd = internal_command(self_venv.join(VENV_BIN).join("pip"));
+        cmd.arg("download")
+            .arg("--dest")
+            .arg(dst)
+            .arg("--python-version")
+            .arg(format!("{}.{}", py_ver.major, py_ver.minor))
+            .arg("--implementation")
+            .arg(if py_ver.name == "pypy" { "pp" } else { "cp" });
+        if output.child() == Verbosity::Verbose {
+            cmd.arg("--verbose");
+        } else {
+            cmd.arg("-q");
+        }
+        sources.add_as_pip_args(&mut cmd);
+        set_proxy_variables(&mut cmd);
+        set_network_timeouts(&mut cmd);
+        extra_args(&mut cmd);
+        let status = run_interruptible(&mut cmd).context("unable to run pip download")?;
+        if !status.success() {
+            bail!("failed to download dependencies");
+        }
+        Ok(())
+    };
+
+    // the lockfile is fully resolved, but build requirements are not
+    download(&mut |cmd| {
+        cmd.arg("--no-deps").arg("-r").arg(requirements.path());
+    })?;
+    if !build_requires.is_empty() {
+        download(&mut |cmd| {
+            cmd.arg("--only-binary").arg(":all:").args(&build_requires);
+        })?;
+    }
+    Ok(())
+}
+
+/// Removes editable and local path requirements from a lockfile.
+///
+/// These refer to projects within the workspace which are bundled as
+/// sources anyways.
+fn filter_local_requirements(lockfile: &str) -> String {
+    let mut rv = String::new();
+    let mut skip_continuation = false;
+    for line in lockfile.lines() {
+        let continued = line.trim_end().ends_with('\\');
+        if skip_continuation {
+            skip_continuation = continued;
+            continue;
+        }
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("-e ") || trimmed.contains("file:") {
+            skip_continuation = continued;
+            continue;
+        }
+        rv.push_str(line);
+        rv.push('\n');
+    }
+    rv
+}
+
+/// Controls how a bundle is restored.
+#[derive(Debug, Default)]
+pub struct ImportOptions {
+    pub output: CommandOutput,
+}
+
+/// Restores a bundle into the given folder and syncs the virtualenv.
+///
+/// The folder must not exist or be empty.  If the bundle carries wheels the
+/// virtualenv is installed from those only, without consulting any index.
+pub fn import_bundle(archive: &Path, target: &Path, options: ImportOptions) -> Result<(), Error> {
+    let output = options.output;
+    if target.is_file()
+        || (target.is_dir()
+            && target
+                .read_dir()
+                .path_context(target, "unable to read target folder")?
+                .next()
+                .is_some())
+    {
+        bail!("target folder {} is not empty", target.display());
+    }
+    let parent = match target.parent() {
+        Some(parent) if parent != Path::new("") => parent.to_path_buf(),
+        _ => std::env::current_dir()?,
+    };
+    fs::create_dir_all(&parent).path_context(&parent, "unable to create folder")?;
+
+    echo!(if output, "Unpacking {}", style(archive.display()).cyan());
+    // unpacked next to the target so that the project can be moved in place
+    let scratch = tempdir_in(&parent)?;
+    let _scratch_guard = RemoveOnInterrupt::new(scratch.path());
+    unpack_bundle(archive, scratch.path())
+        .with_context(|| format!("unable to unpack bundle {}", archive.display()))?;
+
+    let manifest_path = scratch.path().join(MANIFEST_NAME);
+    let manifest: BundleManifest = serde_json::from_slice(
+        &fs::read(&manifest_path)
+            .map_err(|_| anyhow!("{} is not a rye bundle", archive.display()))?,
+    )
+    .context("invalid bundle manifest")?;
+    if manifest.format != BUNDLE_FORMAT {
+        bail!(
+            "bundle was created by rye {} and uses an unsupported format",
+            manifest.rye_version
+        );
+    }
+
+    if let Some(ref toolchain) = manifest.toolchain {
+        install_from_archive(
+            &manifest.python,
+            &scratch.path().join(&toolchain.path),
+            Some(&toolchain.sha256),
+            FetchOptions::with_output(output),
+        )?;
+    }
+
+    if target.is_dir() {
+        fs::remove_dir(target).path_context(target, "unable to replace target folder")?;
+    }
+    let _target_guard = RemoveOnInterrupt::new(target);
+    fs::rename(scratch.path().join("project"), target)
+        .path_context(target, "unable to move project into place")?;
+    echo!(
+        if output,
+        "Restored {} into {}",
+        style(manifest.name.as_deref().unwrap_or("project")).cyan(),
+        style(target.display()).cyan()
+    );
+
+    sync(SyncOptions {
+        output,
+        dev: true,
+        mode: SyncMode::Regular,
+        no_lock: true,
+        pyproject: Some(target.join("pyproject.toml")),
+        offline_wheels: manifest.wheels.then(|| scratch.path().join("wheels")),
+        ..SyncOptions::default()
+    })
+    .context("unable to sync the restored project")?;
+
+    Ok(())
+}
+
+/// Unpacks a bundle by streaming it from disk, bundles with wheels and a
+/// toolchain are too large to be read into memory.
+fn unpack_bundle(archive: &Path, dst: &Path) -> Result<(), Error> {
+    let file = fs::File::open(archive).path_context(archive, "unable to read bundle")?;
+    // entries that would end up outside of the folder are refused
+    tar::Archive::new(zstd::Decoder::new(file)?).unpack(dst)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::filter_local_requirements;
+
+    #[test]
+    fn test_filter_local_requirements() {
+        let lockfile = "\
+# generated by rye
+--index-url https://pypi.org/simple/
+
+-e file:.
+anyio==4.3.0 \\
+    --hash=sha256:048e05d0f6caeed70d731f3db756d35dcc1f35747c8c403364a8332c630441b8
+    # via httpx
+helper @ file:///${PROJECT_ROOT}/../helper \\
+    --hash=sha256:abc
+idna==3.7
+";
+        assert_eq!(
+            filter_local_requirements(lockfile),
+            "\
+# generated by rye
+--index-url https://pypi.org/simple/
+
+anyio==4.3.0 \\
+    --hash=sha256:048e05d0f6caeed70d731f3db756d35dcc1f35747c8c403364a8332c630441b8
+    # via httpx
+idna==3.7
+"
+        );
+    }
+}
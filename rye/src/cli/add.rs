@@ -1,62 +1,20 @@
 use std::env;
 use std::path::{Path, PathBuf};
-use std::process::{Command, Stdio};
 use std::str::FromStr;
 
 use anyhow::{anyhow, bail, Context, Error};
 use clap::{Parser, ValueEnum};
 use pep440_rs::{Operator, Version, VersionSpecifier, VersionSpecifiers};
-use pep508_rs::{Requirement, VersionOrUrl};
-use serde::Deserialize;
+use pep508_rs::{MarkerTree, Requirement, VersionOrUrl};
 use url::Url;
 
-use crate::bootstrap::ensure_self_venv;
+use crate::backend::Backend;
 use crate::config::Config;
-use crate::consts::VENV_BIN;
 use crate::lock::KeyringProvider;
-use crate::pyproject::{BuildSystem, DependencyKind, ExpandedSources, PyProject};
-use crate::sources::py::PythonVersion;
-use crate::sync::{autosync, sync, SyncOptions};
-use crate::utils::{format_requirement, get_venv_python_bin, set_proxy_variables, CommandOutput};
-use crate::uv::UvBuilder;
-
-const PACKAGE_FINDER_SCRIPT: &str = r#"
-import sys
-import json
-from unearth.finder import PackageFinder
-from unearth.session import PyPISession
-from packaging.version import Version
-
-py_ver = sys.argv[1]
-package = sys.argv[2]
-sources = json.loads(sys.argv[3])
-pre = len(sys.argv) > 4 and sys.argv[4] == "--pre"
-
-finder = PackageFinder(
-    index_urls=[x[0] for x in sources["index_urls"]],
-    find_links=sources["find_links"],
-    trusted_hosts=sources["trusted_hosts"],
-)
-if py_ver:
-    finder.target_python.py_ver = tuple(map(int, py_ver.split('.')))
-choices = iter(finder.find_matches(package))
-if not pre:
-    choices = (m for m in choices if not(m.version and Version(m.version).is_prerelease))
-
-print(json.dumps([x.as_json() for x in choices]))
-"#;
-
-#[derive(Deserialize, Debug)]
-struct Match {
-    name: String,
-    version: Option<String>,
-    link: Option<Link>,
-}
-
-#[derive(Deserialize, Debug)]
-struct Link {
-    requires_python: Option<String>,
-}
+use crate::policy::Operation;
+use crate::pyproject::{BuildSystem, DependencyKind, PyProject};
+use crate::sync::autosync;
+use crate::utils::{format_requirement, override_network_timeout, CommandOutput};
 
 #[derive(Parser, Debug)]
 pub struct ReqExtras {
@@ -101,6 +59,26 @@ enum Pin {
     GreaterThanEqual,
 }
 
+/// A platform a dependency can be restricted to.
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq)]
+enum Platform {
+    #[value(alias = "win32", alias = "win")]
+    Windows,
+    Linux,
+    #[value(alias = "darwin", alias = "mac")]
+    Macos,
+}
+
+impl Platform {
+    fn sys_platform(self) -> &'static str {
+        match self {
+            Platform::Windows => "win32",
+            Platform::Linux => "linux",
+            Platform::Macos => "darwin",
+        }
+    }
+}
+
 impl From<Pin> for Operator {
     fn from(value: Pin) -> Self {
         match value {
@@ -214,6 +192,15 @@ pub struct Args {
     /// Overrides the pin operator
     #[arg(long)]
     pin: Option<Pin>,
+    /// Adds an environment marker, e.g. "sys_platform == 'win32'".
+    #[arg(long)]
+    marker: Option<String>,
+    /// Only install the dependency on this platform (can be repeated).
+    #[arg(long, value_enum)]
+    platform: Vec<Platform>,
+    /// Keep the dependencies sorted by name.
+    #[arg(long)]
+    sort: bool,
     /// Attempt to use `keyring` for authentication for index URLs.
     #[arg(long, value_enum, default_value_t)]
     keyring_provider: KeyringProvider,
@@ -223,6 +210,9 @@ pub struct Args {
     /// Does not run `sync` even if auto-sync is enabled.
     #[arg(long, conflicts_with = "sync")]
     no_sync: bool,
+    /// Overall network timeout in seconds.
+    #[arg(long, value_name = "SECONDS")]
+    timeout: Option<u64>,
     /// Enables verbose diagnostics.
     #[arg(short, long)]
     verbose: bool,
@@ -233,11 +223,13 @@ pub struct Args {
 
 pub fn execute(cmd: Args) -> Result<(), Error> {
     let output = CommandOutput::from_quiet_and_verbose(cmd.quiet, cmd.verbose);
-    let self_venv = ensure_self_venv(output).context("error bootstrapping venv")?;
-    let python_path = self_venv.join(VENV_BIN).join("python");
+    override_network_timeout(cmd.timeout);
     let cfg = Config::current();
 
     let mut pyproject_toml = PyProject::discover()?;
+    pyproject_toml
+        .policy()
+        .check(Operation::Add, &pyproject_toml.policy_path())?;
     let py_ver = pyproject_toml.venv_python_version()?;
     let dep_kind = if cmd.dev {
         DependencyKind::Dev
@@ -261,49 +253,46 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
     for str_requirement in &cmd.requirements {
         let mut requirement = Requirement::from_str(str_requirement)?;
         cmd.req_extras.apply_to_requirement(&mut requirement)?;
+        apply_markers(&mut requirement, cmd.marker.as_deref(), &cmd.platform)?;
         requirements.push(requirement);
     }
 
     let keyring_provider = cmd.keyring_provider;
 
     if !cmd.excluded {
-        if cfg.use_uv() {
-            sync(SyncOptions::python_only().pyproject(None))
-                .context("failed to sync ahead of add")?;
-            resolve_requirements_with_uv(
-                &pyproject_toml,
-                &py_ver,
-                &mut requirements,
-                cmd.pre,
-                output,
-                &default_operator,
-                keyring_provider,
-            )?;
-        } else {
-            if keyring_provider != KeyringProvider::Disabled {
-                bail!("`--keyring-provider` option requires the uv backend");
-            }
-            for requirement in &mut requirements {
-                resolve_requirements_with_unearth(
-                    &pyproject_toml,
-                    &python_path,
-                    &py_ver,
-                    requirement,
-                    cmd.pre,
-                    output,
-                    &default_operator,
-                )?;
-            }
+        // markers only decide where a dependency is installed, nothing would
+        // be found for a marker that does not apply to this machine.
+        let unmarked = requirements
+            .iter()
+            .map(|req| {
+                let mut req = req.clone();
+                req.marker = None;
+                req
+            })
+            .collect::<Vec<_>>();
+        let found = Backend::current().find_latest(
+            &pyproject_toml,
+            &py_ver,
+            &unmarked,
+            cmd.pre,
+            keyring_provider,
+            output,
+        )?;
+        for (req, found) in requirements.iter_mut().zip(found) {
+            apply_found_requirement(req, found, &default_operator)?;
         }
     }
 
     for requirement in &requirements {
         pyproject_toml.add_dependency(requirement, &dep_kind)?;
     }
+    if cmd.sort || pyproject_toml.sort_dependencies() {
+        pyproject_toml.normalize_dependencies(&dep_kind);
+    }
 
     pyproject_toml.save()?;
 
-    if output != CommandOutput::Quiet {
+    if !output.is_quiet() {
         for ref requirement in requirements {
             echo!(
                 "Added {} as {} dependency",
@@ -320,199 +309,109 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
     Ok(())
 }
 
-fn resolve_requirements_with_unearth(
-    pyproject_toml: &PyProject,
-    python_path: &PathBuf,
-    py_ver: &PythonVersion,
-    requirement: &mut Requirement,
-    pre: bool,
-    output: CommandOutput,
-    default_operator: &Operator,
+/// Combines the marker of a requirement with the `--marker` and `--platform` flags.
+///
+/// All markers have to apply, multiple platforms are alternatives.
+fn apply_markers(
+    req: &mut Requirement,
+    marker: Option<&str>,
+    platforms: &[Platform],
 ) -> Result<(), Error> {
-    let matches = find_best_matches_with_unearth(
-        pyproject_toml,
-        python_path,
-        Some(py_ver),
-        requirement,
-        pre,
-    )?;
-    if matches.is_empty() {
-        let all_matches =
-            find_best_matches_with_unearth(pyproject_toml, python_path, None, requirement, pre)
-                .unwrap_or_default();
-        if all_matches.is_empty() {
-            // if we did not consider pre-releases, maybe we could find it by doing so.  In
-            // that case give the user a helpful warning before erroring.
-            if !pre {
-                let all_pre_matches = find_best_matches_with_unearth(
-                    pyproject_toml,
-                    python_path,
-                    None,
-                    requirement,
-                    true,
-                )
-                .unwrap_or_default();
-                if let Some(pre) = all_pre_matches.into_iter().next() {
-                    warn!(
-                        "{} ({}) was found considering pre-releases.  Pass --pre to allow use.",
-                        pre.name,
-                        pre.version.unwrap_or_default()
-                    );
-                }
-                bail!(
-                    "did not find package '{}' without using pre-releases.",
-                    format_requirement(requirement)
-                );
-            } else {
-                bail!("did not find package '{}'", format_requirement(requirement));
-            }
-        } else {
-            if output != CommandOutput::Quiet {
-                echo!("Available package versions:");
-                for pkg in all_matches {
-                    echo!(
-                        "  {} ({}) requires Python {}",
-                        pkg.name,
-                        pkg.version.unwrap_or_default(),
-                        pkg.link
-                            .as_ref()
-                            .and_then(|x| x.requires_python.as_ref())
-                            .map_or("unknown", |x| x as &str)
-                    );
-                }
-                echo!("A possible solution is to raise the version in `requires-python` in `pyproject.toml`.");
-            }
-            bail!(
-                "did not find a version of package '{}' compatible with this version of Python.",
-                format_requirement(requirement)
-            );
-        }
+    if marker.is_none() && platforms.is_empty() {
+        return Ok(());
     }
-    let m = matches.into_iter().next().unwrap();
-    if m.version.is_some() && requirement.version_or_url.is_none() {
-        let version = Version::from_str(m.version.as_ref().unwrap())
-            .map_err(|msg| anyhow!("invalid version: {}", msg))?;
-        requirement.version_or_url = Some(VersionOrUrl::VersionSpecifier(
-            VersionSpecifiers::from_iter(Some(
-                VersionSpecifier::new(
-                    // local versions or versions with only one component cannot
-                    // use ~= but need to use ==.
-                    match *default_operator {
-                        _ if version.is_local() => Operator::Equal,
-                        Operator::TildeEqual if version.release.len() < 2 => {
-                            Operator::GreaterThanEqual
-                        }
-                        ref other => other.clone(),
-                    },
-                    Version::from_str(m.version.as_ref().unwrap())
-                        .map_err(|msg| anyhow!("invalid version: {}", msg))?,
-                    false,
-                )
-                .map_err(|msg| anyhow!("invalid version specifier: {}", msg))?,
-            )),
-        ));
+    let mut parts = Vec::new();
+    if let Some(ref existing) = req.marker {
+        parts.push(existing.to_string());
     }
-    requirement.name = m.name;
-    Ok(())
-}
-
-fn find_best_matches_with_unearth(
-    pyproject: &PyProject,
-    python_path: &PathBuf,
-    py_ver: Option<&PythonVersion>,
-    requirement: &Requirement,
-    pre: bool,
-) -> Result<Vec<Match>, Error> {
-    let mut unearth = Command::new(python_path);
-    let sources = ExpandedSources::from_sources(&pyproject.sources()?)?;
-
-    unearth
-        .arg("-c")
-        .arg(PACKAGE_FINDER_SCRIPT)
-        .arg(match py_ver {
-            Some(ver) => ver.format_simple(),
-            None => "".into(),
-        })
-        .arg(&format_requirement(requirement).to_string())
-        .arg(serde_json::to_string(&sources)?);
-    if pre {
-        unearth.arg("--pre");
+    if let Some(marker) = marker {
+        parts.push(marker.trim().to_string());
     }
-    set_proxy_variables(&mut unearth);
-    let unearth = unearth.stdout(Stdio::piped()).output()?;
-    if unearth.status.success() {
-        Ok(serde_json::from_slice(&unearth.stdout)?)
-    } else {
-        let log = String::from_utf8_lossy(&unearth.stderr);
-        bail!(
-            "failed to resolve package {}\n{}",
-            format_requirement(requirement),
-            log
+    if !platforms.is_empty() {
+        parts.push(
+            platforms
+                .iter()
+                .map(|x| format!("sys_platform == '{}'", x.sys_platform()))
+                .collect::<Vec<_>>()
+                .join(" or "),
         );
     }
+    let combined = if parts.len() == 1 {
+        parts.remove(0)
+    } else {
+        parts
+            .iter()
+            .map(|x| {
+                if x.contains(" or ") {
+                    format!("({})", x)
+                } else {
+                    x.clone()
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" and ")
+    };
+    req.marker = Some(
+        MarkerTree::from_str(&combined)
+            .map_err(|err| anyhow!("invalid marker '{}': {}", combined, err))?,
+    );
+    Ok(())
 }
 
-fn resolve_requirements_with_uv(
-    pyproject_toml: &PyProject,
-    py_ver: &PythonVersion,
-    requirements: &mut [Requirement],
-    pre: bool,
-    output: CommandOutput,
+/// Takes the name and version of a requirement found by the backend.
+///
+/// Requirements that already carry a version or URL only take the name, the
+/// found version is pinned with the default operator otherwise.
+fn apply_found_requirement(
+    req: &mut Requirement,
+    found: Requirement,
     default_operator: &Operator,
-    keyring_provider: KeyringProvider,
 ) -> Result<(), Error> {
-    let venv_path = pyproject_toml.venv_path();
-    let py_bin = get_venv_python_bin(&venv_path);
-    let sources = ExpandedSources::from_sources(&pyproject_toml.sources()?)?;
-
-    let uv = UvBuilder::new()
-        .with_output(output.quieter())
-        .with_sources(sources)
-        .ensure_exists()?
-        .venv(&venv_path, &py_bin, py_ver, None)?;
-
-    for req in requirements {
-        let mut new_req = uv.resolve(
-            py_ver,
-            req,
-            pre,
-            env::var("__RYE_UV_EXCLUDE_NEWER").ok(),
-            keyring_provider,
-        )?;
-
-        // if a version or URL is already provided we just use the normalized package name but
-        // retain all old information.
-        if req.version_or_url.is_some() {
-            req.name = new_req.name;
-            continue;
-        }
-
-        if let Some(ref mut version_or_url) = new_req.version_or_url {
-            if let VersionOrUrl::VersionSpecifier(ref mut specs) = version_or_url {
-                *version_or_url = VersionOrUrl::VersionSpecifier(VersionSpecifiers::from_iter({
-                    let mut new_specs = Vec::new();
-                    for spec in specs.iter() {
-                        let op = match *default_operator {
-                            _ if spec.version().is_local() => Operator::Equal,
-                            Operator::TildeEqual if spec.version().release.len() < 2 => {
-                                Operator::GreaterThanEqual
-                            }
-                            ref other => other.clone(),
-                        };
-                        new_specs.push(
-                            VersionSpecifier::new(op, spec.version().clone(), false)
-                                .map_err(|msg| anyhow!("invalid version specifier: {}", msg))?,
-                        );
-                    }
-                    new_specs
-                }));
+    req.name = found.name;
+    if req.version_or_url.is_some() {
+        return Ok(());
+    }
+    req.version_or_url = match found.version_or_url {
+        Some(VersionOrUrl::VersionSpecifier(specs)) => {
+            let mut new_specs = Vec::new();
+            for spec in specs.iter() {
+                new_specs.extend(make_version_specifiers(spec.version(), default_operator)?);
             }
+            Some(VersionOrUrl::VersionSpecifier(
+                VersionSpecifiers::from_iter(new_specs),
+            ))
         }
-        if let Some(old_extras) = &req.extras {
-            new_req.extras = Some(old_extras.clone());
-        }
-        *req = new_req;
-    }
-
+        other => other,
+    };
     Ok(())
 }
+
+/// Returns the specifiers for a dependency that resolved to the given version.
+///
+/// If the dependency is added with `>=`, the `behavior.add-bound` config
+/// decides if an upper bound is added as well.
+fn make_version_specifiers(
+    version: &Version,
+    default_operator: &Operator,
+) -> Result<Vec<VersionSpecifier>, Error> {
+    // local versions or versions with only one component cannot
+    // use ~= but need to use ==.
+    let op = match *default_operator {
+        _ if version.is_local() => Operator::Equal,
+        Operator::TildeEqual if version.release.len() < 2 => Operator::GreaterThanEqual,
+        ref other => other.clone(),
+    };
+    let upper_bound = match op {
+        Operator::GreaterThanEqual => Config::current().add_bound().upper_bound(version),
+        _ => None,
+    };
+    let mut rv = vec![VersionSpecifier::new(op, version.clone(), false)
+        .map_err(|msg| anyhow!("invalid version specifier: {}", msg))?];
+    if let Some(bound) = upper_bound {
+        rv.push(
+            VersionSpecifier::new(Operator::LessThan, bound, false)
+                .map_err(|msg| anyhow!("invalid version specifier: {}", msg))?,
+        );
+    }
+    Ok(rv)
+}
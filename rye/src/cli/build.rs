@@ -1,16 +1,19 @@
 use std::fs;
-use std::path::PathBuf;
-use std::process::{Command, Stdio};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
 
-use anyhow::{anyhow, bail, Error};
+use anyhow::{bail, Context, Error};
 use clap::Parser;
 use console::style;
 
-use crate::bootstrap::ensure_self_venv;
-use crate::config::Config;
-use crate::pyproject::{locate_projects, PyProject};
-use crate::utils::{get_venv_python_bin, prepend_path_to_path_env, CommandOutput, IoPathContext};
-use crate::uv::UvBuilder;
+use crate::backend::Backend;
+use crate::bootstrap::{ensure_self_venv, fetch, get_build_requirement, FetchOptions};
+use crate::platform::{get_app_dir, get_toolchain_python_bin};
+use crate::pyproject::{locate_projects, read_venv_marker, write_venv_marker, PyProject};
+use crate::sources::py::{PythonVersion, PythonVersionRequest};
+use crate::utils::{
+    get_venv_python_bin, internal_command, CommandOutput, IoPathContext, Verbosity,
+};
 
 /// Builds a package for distribution.
 #[derive(Parser, Debug)]
@@ -36,6 +39,9 @@ pub struct Args {
     /// Clean the output directory first
     #[arg(short, long)]
     clean: bool,
+    /// Build wheels for these Python versions (comma separated)
+    #[arg(long, value_name = "VERSIONS", value_delimiter = ',')]
+    python: Vec<String>,
     /// Enables verbose diagnostics.
     #[arg(short, long)]
     verbose: bool,
@@ -63,9 +69,20 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
         }
     }
 
-    let use_uv = Config::current().use_uv();
     let projects = locate_projects(project, cmd.all, &cmd.package[..])?;
 
+    // one wheel per requested version, the sdist is the same for all of them.
+    let mut wheel_pythons = Vec::new();
+    if !cmd.python.is_empty() && (cmd.wheel || !cmd.sdist) {
+        for version in &cmd.python {
+            let request: PythonVersionRequest = version
+                .trim()
+                .parse()
+                .with_context(|| format!("invalid Python version '{}'", version))?;
+            wheel_pythons.push(ensure_build_venv(&request, output)?);
+        }
+    }
+
     for project in projects {
         // skip over virtual packages on build
         if project.is_virtual() {
@@ -78,46 +95,91 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
             style(project.normalized_name()?).cyan()
         );
 
-        let mut build_cmd = Command::new(get_venv_python_bin(&self_venv));
-        build_cmd
-            .arg("-mbuild")
-            .env("NO_COLOR", "1")
-            .arg("--outdir")
-            .arg(&out)
-            .arg(&*project.root_path());
-
-        if use_uv {
-            // we need to ensure uv is available to use without installing it into self_venv
-            let uv = UvBuilder::new().with_output(output).ensure_exists()?;
-            let uv_dir = uv
-                .uv_bin()
-                .parent()
-                .ok_or_else(|| anyhow!("Could not find uv binary in self venv: empty path"))?;
-            build_cmd.env("PATH", prepend_path_to_path_env(uv_dir)?);
-            build_cmd.arg("--installer=uv");
+        if cmd.python.is_empty() {
+            let kinds = [(cmd.wheel, "--wheel"), (cmd.sdist, "--sdist")];
+            run_build(&self_venv, &project, &out, &kinds, output)?;
+            continue;
         }
 
-        if cmd.wheel {
-            build_cmd.arg("--wheel");
+        if cmd.sdist || !cmd.wheel {
+            run_build(&self_venv, &project, &out, &[(true, "--sdist")], output)?;
         }
-        if cmd.sdist {
-            build_cmd.arg("--sdist");
+        for (version, venv) in &wheel_pythons {
+            echo!(if output, "building wheel for {}", style(version).cyan());
+            run_build(venv, &project, &out, &[(true, "--wheel")], output)?;
         }
+    }
 
-        if output == CommandOutput::Verbose {
-            build_cmd.arg("--verbose");
-        }
+    Ok(())
+}
 
-        if output == CommandOutput::Quiet {
-            build_cmd.stdout(Stdio::null());
-            build_cmd.stderr(Stdio::null());
+/// Runs the build frontend of a virtualenv for a project.
+///
+/// `kinds` are the distribution flags passed if enabled.  Without any the
+/// frontend builds both.
+fn run_build(
+    venv: &Path,
+    project: &PyProject,
+    out: &Path,
+    kinds: &[(bool, &str)],
+    output: CommandOutput,
+) -> Result<(), Error> {
+    let mut build_cmd = internal_command(get_venv_python_bin(venv));
+    build_cmd
+        .arg("-mbuild")
+        .env("NO_COLOR", "1")
+        .arg("--outdir")
+        .arg(out)
+        .arg(&*project.root_path());
+
+    Backend::current().configure_build_frontend(&mut build_cmd, output)?;
+
+    for (enabled, flag) in kinds {
+        if *enabled {
+            build_cmd.arg(flag);
         }
+    }
 
-        let status = build_cmd.status()?;
-        if !status.success() {
-            bail!("failed to build dist");
-        }
+    if output.child() == Verbosity::Verbose {
+        build_cmd.arg("--verbose");
+    }
+
+    // errors on stderr stay visible, they explain why the build failed.
+    if output.child() == Verbosity::Quiet {
+        build_cmd.stdout(Stdio::null());
     }
 
+    let status = build_cmd.status()?;
+    if !status.success() {
+        bail!("failed to build dist");
+    }
     Ok(())
 }
+
+/// Provisions a toolchain and a virtualenv with the build frontend for it.
+///
+/// The isolated build environments are created with the interpreter that
+/// runs the frontend, so every target version needs its own.
+fn ensure_build_venv(
+    request: &PythonVersionRequest,
+    output: CommandOutput,
+) -> Result<(PythonVersion, PathBuf), Error> {
+    let version = fetch(request, FetchOptions::with_output(output))
+        .with_context(|| format!("failed to provision toolchain {}", request))?;
+    let py_bin = get_toolchain_python_bin(&version)?;
+    let venv_dir = get_app_dir().join("build-venvs").join(format!(
+        "{}@{}.{}",
+        version.name, version.major, version.minor
+    ));
+    if read_venv_marker(&venv_dir).map_or(false, |x| x.is_compatible(&version)) {
+        return Ok((version, venv_dir));
+    }
+
+    echo!(if output, "Installing build frontend for {}", version);
+    let backend = Backend::current();
+    backend.create_internal_venv(&venv_dir, &py_bin, &version, output)?;
+    backend.install_internal_requirements(&venv_dir, &version, get_build_requirement(), output)?;
+    // the marker goes last so that a failed install is retried
+    write_venv_marker(&venv_dir, &version)?;
+    Ok((version, venv_dir))
+}
@@ -0,0 +1,98 @@
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::Error;
+use clap::Parser;
+use indicatif::HumanBytes;
+
+use crate::index::get_metadata_cache_dir;
+use crate::lock::get_lock_cache_dir;
+use crate::platform::get_app_dir;
+use crate::utils::cleanup::remove_orphaned_temp_files;
+use crate::utils::{CommandOutput, IoPathContext};
+
+/// `--tmp` leaves temporary files alone that could belong to a running rye.
+const TMP_MIN_AGE: Duration = Duration::from_secs(60 * 60);
+
+/// Helper utility to manage the caches of rye.
+#[derive(Parser, Debug)]
+pub struct Args {
+    #[command(subcommand)]
+    command: SubCommand,
+}
+
+/// Removes cached data.
+#[derive(Parser, Debug)]
+pub struct CleanCommand {
+    /// Only remove the cached package index metadata.
+    #[arg(long)]
+    metadata: bool,
+    /// Only remove the cached resolver results.
+    #[arg(long, conflicts_with = "metadata")]
+    lock: bool,
+    /// Only remove temporary files left behind by interrupted operations.
+    #[arg(long, conflicts_with_all = ["metadata", "lock"])]
+    tmp: bool,
+    /// Enables verbose diagnostics.
+    #[arg(short, long)]
+    verbose: bool,
+    /// Turns off all output.
+    #[arg(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+}
+
+#[derive(Parser, Debug)]
+enum SubCommand {
+    Clean(CleanCommand),
+}
+
+pub fn execute(cmd: Args) -> Result<(), Error> {
+    match cmd.command {
+        SubCommand::Clean(args) => clean(args),
+    }
+}
+
+fn clean(cmd: CleanCommand) -> Result<(), Error> {
+    let output = CommandOutput::from_quiet_and_verbose(cmd.quiet, cmd.verbose);
+    if cmd.tmp {
+        // temporary files of operations that are still running are younger.
+        let (count, size) = remove_orphaned_temp_files(get_app_dir(), TMP_MIN_AGE);
+        if count > 0 {
+            echo!(
+                if output,
+                "Removed {} temporary files ({})",
+                count,
+                HumanBytes(size)
+            );
+        } else {
+            echo!(if output, "Nothing to remove");
+        }
+        return Ok(());
+    }
+
+    let (path, what) = if cmd.metadata {
+        (get_metadata_cache_dir(), "metadata cache")
+    } else if cmd.lock {
+        (get_lock_cache_dir(), "lock cache")
+    } else {
+        (get_app_dir().join("cache"), "cache")
+    };
+
+    if remove_dir(&path)? {
+        echo!(if output, "Removed {}", what);
+    } else {
+        echo!(if output, "Nothing to remove");
+    }
+    echo!(if verbose output, "cache folder: {}", path.display());
+
+    Ok(())
+}
+
+fn remove_dir(path: &Path) -> Result<bool, Error> {
+    if !path.is_dir() {
+        return Ok(false);
+    }
+    fs::remove_dir_all(path).path_context(path, "unable to remove cache")?;
+    Ok(true)
+}
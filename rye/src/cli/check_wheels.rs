@@ -0,0 +1,228 @@
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use anyhow::Error;
+use clap::Parser;
+use console::style;
+use pep440_rs::Version;
+
+use crate::bootstrap::get_site_packages_dir;
+use crate::index::{is_platform_tag_compatible, IndexClient};
+use crate::pyproject::{read_venv_marker, ExpandedSources, PyProject};
+use crate::sources::py::PythonVersion;
+use crate::sync::find_installed_dists;
+use crate::utils::{CommandOutput, QuietExit};
+
+/// Audits the wheels installed into the virtualenv.
+///
+/// This reports packages that were built from source because no wheel for
+/// the platform existed, as well as wheels with tags that do not match the
+/// virtualenv or claim to be more portable than they are.
+#[derive(Parser, Debug)]
+pub struct Args {
+    /// Only inspect the virtualenv and do not consult the package index.
+    #[arg(long)]
+    offline: bool,
+    /// Use this pyproject.toml file
+    #[arg(long, value_name = "PYPROJECT_TOML")]
+    pyproject: Option<PathBuf>,
+    /// Enables verbose diagnostics.
+    #[arg(short, long)]
+    verbose: bool,
+    /// Turns off all output.
+    #[arg(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+}
+
+/// A problem found with an installed distribution.
+#[derive(Debug, PartialEq, Eq)]
+enum Finding {
+    /// The wheel was built on this machine.
+    BuiltLocally(String),
+    /// The index has no wheel for this platform.
+    SdistOnly,
+    /// The wheel tag does not match the virtualenv.
+    IncompatibleTag(String),
+    /// A wheel with compiled code claims to work everywhere.
+    BroadTag(String),
+}
+
+impl Finding {
+    fn describe(&self) -> String {
+        match self {
+            Finding::BuiltLocally(tag) => format!("built from source on this machine ({})", tag),
+            Finding::SdistOnly => "no wheel for this platform on the index".into(),
+            Finding::IncompatibleTag(tag) => format!("incompatible wheel tag ({})", tag),
+            Finding::BroadTag(tag) => {
+                format!("binary wheel is tagged for every platform ({})", tag)
+            }
+        }
+    }
+}
+
+pub fn execute(cmd: Args) -> Result<(), Error> {
+    let output = CommandOutput::from_quiet_and_verbose(cmd.quiet, cmd.verbose);
+    let project = PyProject::load_or_discover(cmd.pyproject.as_deref())?;
+    let venv = project.venv_path();
+    let py_ver = match read_venv_marker(&venv) {
+        Some(marker) => marker.python,
+        None => project.venv_python_version()?,
+    };
+    // direct URL and editable installs do not come from the index
+    let dists = find_installed_dists(&get_site_packages_dir(&venv)?)?
+        .into_iter()
+        .filter(|x| !x.direct_url)
+        .collect::<Vec<_>>();
+
+    let mut index_client = if cmd.offline {
+        None
+    } else {
+        Some(IndexClient::from_sources(
+            &ExpandedSources::from_sources(&project.sources()?)?,
+            output,
+        ))
+    };
+
+    let mut problems = 0;
+    for dist in &dists {
+        let mut findings = audit_tags(&dist.tags, dist.purelib, &py_ver);
+        if let Some(ref client) = index_client {
+            if !dist.purelib && findings.is_empty() {
+                match client.get_project(&dist.name) {
+                    Ok(Some(metadata)) => {
+                        let version = Version::from_str(&dist.version).ok();
+                        let files = metadata
+                            .files
+                            .iter()
+                            .filter(|x| version.is_some() && x.version() == version)
+                            .collect::<Vec<_>>();
+                        if !files.is_empty()
+                            && !files
+                                .iter()
+                                .any(|x| x.filename.ends_with(".whl") && x.is_compatible(&py_ver))
+                        {
+                            findings.push(Finding::SdistOnly);
+                        }
+                    }
+                    Ok(None) => {}
+                    Err(err) => {
+                        warn!(
+                            "unable to consult the package index, continuing offline: {}",
+                            err
+                        );
+                        index_client = None;
+                    }
+                }
+            }
+        }
+        for finding in &findings {
+            echo!(
+                if output,
+                "{} {}: {}",
+                style(&dist.name).cyan(),
+                dist.version,
+                style(finding.describe()).yellow()
+            );
+        }
+        problems += findings.len();
+    }
+
+    echo!(
+        if verbose output,
+        "audited {} packages in {}",
+        dists.len(),
+        venv.display()
+    );
+    if problems == 0 {
+        echo!(if output, "No problems found in {} packages", dists.len());
+        Ok(())
+    } else {
+        Err(QuietExit(1).into())
+    }
+}
+
+/// Checks the tags of an installed wheel against the virtualenv's python.
+fn audit_tags(tags: &[String], purelib: bool, py_ver: &PythonVersion) -> Vec<Finding> {
+    let mut rv = Vec::new();
+    if !tags.is_empty() && !tags.iter().any(|tag| is_tag_compatible(tag, py_ver)) {
+        rv.push(Finding::IncompatibleTag(tags.join(", ")));
+    }
+    for tag in tags {
+        let Some(platform) = tag.rsplit('-').next() else {
+            continue;
+        };
+        // published linux wheels are manylinux or musllinux, plain linux
+        // tags are only produced by local builds.
+        if platform.starts_with("linux_") {
+            rv.push(Finding::BuiltLocally(tag.clone()));
+        } else if !purelib && platform == "any" {
+            rv.push(Finding::BroadTag(tag.clone()));
+        }
+    }
+    rv
+}
+
+fn is_tag_compatible(tag: &str, py_ver: &PythonVersion) -> bool {
+    let mut parts = tag.splitn(3, '-');
+    let (Some(python), Some(abi), Some(platform)) = (parts.next(), parts.next(), parts.next())
+    else {
+        return false;
+    };
+    let python_ok = python.split('.').any(|python| {
+        if python == format!("py{}", py_ver.major)
+            || python == format!("py{}{}", py_ver.major, py_ver.minor)
+        {
+            return true;
+        }
+        let interpreter = if py_ver.name == "pypy" { "pp" } else { "cp" };
+        match python
+            .strip_prefix(interpreter)
+            .and_then(|x| x.strip_prefix(&py_ver.major.to_string()))
+            .and_then(|x| x.parse::<u8>().ok())
+        {
+            // the stable abi works with all later versions
+            Some(minor) if abi == "abi3" => minor <= py_ver.minor,
+            Some(minor) => minor == py_ver.minor,
+            None => false,
+        }
+    });
+    python_ok && platform.split('.').any(is_platform_tag_compatible)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{audit_tags, Finding};
+    use crate::sources::py::PythonVersion;
+
+    #[test]
+    fn test_audit_tags() {
+        let py_ver: PythonVersion = "cpython@3.12.3".parse().unwrap();
+        let tags = |x: &[&str]| x.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(audit_tags(&tags(&["py3-none-any"]), true, &py_ver), vec![]);
+        assert_eq!(audit_tags(&tags(&["cp38-abi3-any"]), true, &py_ver), vec![]);
+        assert_eq!(
+            audit_tags(&tags(&["cp311-cp311-any"]), true, &py_ver),
+            vec![Finding::IncompatibleTag("cp311-cp311-any".into())]
+        );
+        assert_eq!(
+            audit_tags(&tags(&["py3-none-any"]), false, &py_ver),
+            vec![Finding::BroadTag("py3-none-any".into())]
+        );
+        #[cfg(target_os = "linux")]
+        {
+            assert_eq!(
+                audit_tags(&tags(&["cp312-cp312-linux_x86_64"]), false, &py_ver),
+                vec![Finding::BuiltLocally("cp312-cp312-linux_x86_64".into())]
+            );
+            assert_eq!(
+                audit_tags(
+                    &tags(&["cp312-cp312-manylinux_2_17_x86_64.manylinux2014_x86_64"]),
+                    false,
+                    &py_ver
+                ),
+                vec![]
+            );
+        }
+    }
+}
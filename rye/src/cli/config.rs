@@ -8,11 +8,14 @@ use clap::Parser;
 use clap::ValueEnum;
 use serde::Serialize;
 use toml_edit::value;
+use toml_edit::DocumentMut;
 use toml_edit::Item;
 use toml_edit::Table;
 use toml_edit::Value;
 
 use crate::config::Config;
+use crate::prefetch::schedule_pinned_toolchain;
+use crate::pyproject::PyProject;
 
 #[derive(ValueEnum, Copy, Clone, Serialize, Debug, PartialEq)]
 #[value(rename_all = "snake_case")]
@@ -62,7 +65,12 @@ pub struct Action {
 }
 
 pub fn execute(cmd: Args) -> Result<(), Error> {
+    if let Ok(project) = PyProject::discover() {
+        schedule_pinned_toolchain(&project);
+    }
+
     let mut config = Config::current();
+    let machine_defaults = config.machine_defaults().cloned();
     let doc = Arc::make_mut(&mut config).doc_mut();
 
     if cmd.show_path {
@@ -75,12 +83,17 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
     let reads = !cmd.action.get.is_empty();
 
     for item in cmd.action.get {
-        let mut ptr = Some(doc.as_item());
-        for piece in item.split('.') {
-            ptr = ptr.as_ref().and_then(|x| x.get(piece));
-        }
+        let lookup = |doc: &DocumentMut| {
+            let mut ptr = Some(doc.as_item());
+            for piece in item.split('.') {
+                ptr = ptr.and_then(|x| x.get(piece));
+            }
+            ptr.cloned()
+        };
+        // fall back to the machine-level defaults for keys the user did not set
+        let ptr = lookup(doc).or_else(|| machine_defaults.as_ref().and_then(lookup));
 
-        let val = ptr.and_then(|x| x.as_value());
+        let val = ptr.as_ref().and_then(|x| x.as_value());
         match cmd.action.format {
             None => {
                 read_as_string.push(value_to_string(val));
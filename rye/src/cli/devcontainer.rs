@@ -0,0 +1,118 @@
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{bail, Error};
+use clap::Parser;
+use console::style;
+use minijinja::{context, Environment};
+
+use crate::pyproject::PyProject;
+use crate::utils::{CommandOutput, IoPathContext};
+
+/// Template for the devcontainer configuration.
+const DEVCONTAINER_TEMPLATE: &str = include_str!("../templates/devcontainer/devcontainer.json.j2");
+
+/// Template for the optional devcontainer image.
+const DOCKERFILE_TEMPLATE: &str = include_str!("../templates/devcontainer/Dockerfile.j2");
+
+/// Manage the development container of the project.
+#[derive(Parser, Debug)]
+pub struct Args {
+    #[command(subcommand)]
+    command: SubCommand,
+}
+
+/// Writes a `.devcontainer` configuration for the project.
+///
+/// The container installs rye and the pinned toolchain and syncs the
+/// virtualenv from the lockfile once it was created.
+#[derive(Parser, Debug)]
+pub struct InitCommand {
+    /// Also write a Dockerfile that installs rye and the toolchain into the image.
+    #[arg(long)]
+    dockerfile: bool,
+    /// Overwrite an existing configuration.
+    #[arg(short, long)]
+    force: bool,
+    /// Use this pyproject.toml file
+    #[arg(long, value_name = "PYPROJECT_TOML")]
+    pyproject: Option<PathBuf>,
+    /// Enables verbose diagnostics.
+    #[arg(short, long)]
+    verbose: bool,
+    /// Turns off all output.
+    #[arg(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+}
+
+#[derive(Parser, Debug)]
+enum SubCommand {
+    Init(InitCommand),
+}
+
+pub fn execute(cmd: Args) -> Result<(), Error> {
+    match cmd.command {
+        SubCommand::Init(cmd) => init(cmd),
+    }
+}
+
+fn init(cmd: InitCommand) -> Result<(), Error> {
+    let output = CommandOutput::from_quiet_and_verbose(cmd.quiet, cmd.verbose);
+    let project = PyProject::load_or_discover(cmd.pyproject.as_deref())?;
+    let root = project.workspace_path();
+    let folder = root.join(".devcontainer");
+    let config_path = folder.join("devcontainer.json");
+    let dockerfile_path = folder.join("Dockerfile");
+
+    let mut targets = vec![&config_path];
+    if cmd.dockerfile {
+        targets.push(&dockerfile_path);
+    }
+    if !cmd.force {
+        if let Some(path) = targets.iter().find(|x| x.is_file()) {
+            bail!(
+                "{} already exists (use --force to overwrite)",
+                path.display()
+            );
+        }
+    }
+
+    let py_ver = project.venv_python_version()?;
+    let venv = project.venv_path();
+    let venv = venv.strip_prefix(&*root).unwrap_or(&venv);
+    // the container always runs linux, even if the host does not
+    let venv_python = venv
+        .join("bin")
+        .join("python")
+        .to_string_lossy()
+        .replace('\\', "/");
+    let name = project
+        .name()
+        .map(|x| x.to_string())
+        .or_else(|| root.file_name().map(|x| x.to_string_lossy().into_owned()))
+        .unwrap_or_else(|| "rye".into());
+
+    let mut env = Environment::new();
+    env.set_keep_trailing_newline(true);
+    let ctx = context! {
+        name,
+        python => py_ver.to_string(),
+        rye_version => env!("CARGO_PKG_VERSION"),
+        venv_python,
+        dockerfile => cmd.dockerfile,
+    };
+
+    fs::create_dir_all(&folder).path_context(&folder, "failed to create .devcontainer folder")?;
+    let rv = env.render_named_str("devcontainer.json", DEVCONTAINER_TEMPLATE, &ctx)?;
+    fs::write(&config_path, rv).path_context(&config_path, "failed to write devcontainer.json")?;
+    echo!(if output, "Wrote {}", style(config_path.display()).cyan());
+    if cmd.dockerfile {
+        let rv = env.render_named_str("Dockerfile", DOCKERFILE_TEMPLATE, &ctx)?;
+        fs::write(&dockerfile_path, rv)
+            .path_context(&dockerfile_path, "failed to write Dockerfile")?;
+        echo!(if output, "Wrote {}", style(dockerfile_path.display()).cyan());
+    }
+    echo!(if verbose output, "pinned toolchain: {}", py_ver);
+
+    Ok(())
+}
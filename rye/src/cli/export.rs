@@ -0,0 +1,177 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Error};
+use clap::{Parser, ValueEnum};
+use console::style;
+
+use crate::bootstrap::get_site_packages_dir;
+use crate::bundle::{export_bundle, ExportOptions};
+use crate::lock::{get_locked_platform, parse_locked_packages, LockMode};
+use crate::notices::{collect_notices, get_marker_environment, render_notices};
+use crate::pyproject::{ExpandedSources, PyProject};
+use crate::sync::{get_lockfile_path, get_sync_variant};
+use crate::utils::atomic::write_atomic;
+use crate::utils::{CommandOutput, IoPathContext};
+use crate::wasm::{fetch_pyodide_lock, make_pyodide_lock, WasmTarget, DEFAULT_PYODIDE_VERSION};
+
+/// Exports the project for a hand-off to another machine.
+///
+/// The bundle contains the sources and lockfiles of the project and can be
+/// restored with `rye import`.  With `--format pyodide-lock` a
+/// `pyodide-lock.json` for a web deployment is written instead and with
+/// `--format third-party-notices` the license texts of the dependencies.
+#[derive(Parser, Debug)]
+pub struct Args {
+    /// The format to export to.
+    #[arg(long, value_enum, default_value_t)]
+    format: ExportFormat,
+    /// Write a bundle to this path (.tar.zst).
+    #[arg(long, value_name = "PATH")]
+    archive: Option<PathBuf>,
+    /// Write the export to this path (defaults to `pyodide-lock.json` or `THIRD_PARTY_NOTICES`).
+    #[arg(short, long, value_name = "PATH", conflicts_with = "archive")]
+    output: Option<PathBuf>,
+    /// Include the distributions of all locked dependencies.
+    #[arg(long)]
+    include_wheels: bool,
+    /// Include the toolchain the project is pinned to.
+    #[arg(long)]
+    include_toolchain: bool,
+    /// Use this pyproject.toml file
+    #[arg(long, value_name = "PYPROJECT_TOML")]
+    pyproject: Option<PathBuf>,
+    /// Enables verbose diagnostics.
+    #[arg(short, long)]
+    verbose: bool,
+    /// Turns off all output.
+    #[arg(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+}
+
+#[derive(ValueEnum, Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[value(rename_all = "kebab-case")]
+enum ExportFormat {
+    /// A bundle that can be restored with `rye import`.
+    #[default]
+    Bundle,
+    /// A `pyodide-lock.json` of a lockfile locked with `--platform emscripten`.
+    PyodideLock,
+    /// The license texts of the locked dependencies from the synced virtualenv.
+    ThirdPartyNotices,
+}
+
+pub fn execute(cmd: Args) -> Result<(), Error> {
+    let output = CommandOutput::from_quiet_and_verbose(cmd.quiet, cmd.verbose);
+    let project = PyProject::load_or_discover(cmd.pyproject.as_deref())?;
+    match cmd.format {
+        ExportFormat::Bundle => {
+            let Some(archive) = cmd.archive.or(cmd.output) else {
+                bail!("--archive is required to export a bundle");
+            };
+            export_bundle(
+                &project,
+                &archive,
+                ExportOptions {
+                    output,
+                    wheels: cmd.include_wheels,
+                    toolchain: cmd.include_toolchain,
+                },
+            )?;
+        }
+        ExportFormat::PyodideLock => {
+            let path = cmd
+                .output
+                .unwrap_or_else(|| PathBuf::from("pyodide-lock.json"));
+            export_pyodide_lock(&project, &path, output)?;
+        }
+        ExportFormat::ThirdPartyNotices => {
+            let path = cmd
+                .output
+                .unwrap_or_else(|| PathBuf::from("THIRD_PARTY_NOTICES"));
+            export_third_party_notices(&project, &path, output)?;
+        }
+    }
+    echo!(if output, "Done!");
+    Ok(())
+}
+
+fn export_pyodide_lock(
+    project: &PyProject,
+    path: &Path,
+    output: CommandOutput,
+) -> Result<(), Error> {
+    let workspace_path = project.workspace_path();
+    let variant = get_sync_variant(project, None)?;
+    let lockfile = get_lockfile_path(project, &LockMode::Production, variant.as_deref());
+    let contents =
+        fs::read_to_string(&lockfile).path_context(&lockfile, "unable to read lockfile")?;
+    if get_locked_platform(&contents).and_then(|x| WasmTarget::from_platform(&x))
+        != Some(WasmTarget::Emscripten)
+    {
+        bail!(
+            "{} was not locked for emscripten, run `rye lock --platform emscripten` first",
+            lockfile.display()
+        );
+    }
+
+    let pyodide_version = project
+        .pyodide_version()
+        .unwrap_or_else(|| DEFAULT_PYODIDE_VERSION.to_string());
+    let pyodide = fetch_pyodide_lock(&pyodide_version, output)?;
+    let sources = ExpandedSources::from_sources(&project.sources()?)?;
+    let locked = parse_locked_packages(&contents, &workspace_path);
+    let rv = make_pyodide_lock(&locked, &pyodide, &sources, output)?;
+    write_atomic(path, serde_json::to_string_pretty(&rv)? + "\n")
+        .path_context(path, "unable to write pyodide lock")?;
+    echo!(
+        if output,
+        "Exported {} packages for Pyodide {} to {}",
+        rv.packages.len(),
+        pyodide_version,
+        style(path.display()).cyan()
+    );
+    Ok(())
+}
+
+fn export_third_party_notices(
+    project: &PyProject,
+    path: &Path,
+    output: CommandOutput,
+) -> Result<(), Error> {
+    let workspace_path = project.workspace_path();
+    let variant = get_sync_variant(project, None)?;
+    let lockfile = get_lockfile_path(project, &LockMode::Production, variant.as_deref());
+    let contents =
+        fs::read_to_string(&lockfile).path_context(&lockfile, "unable to read lockfile")?;
+    let venv = project.venv_path();
+    if !venv.is_dir() {
+        bail!("the virtualenv of the project does not exist, run `rye sync` first");
+    }
+
+    let locked = parse_locked_packages(&contents, &workspace_path);
+    let env = get_marker_environment(&venv)?;
+    let (notices, missing) = collect_notices(&get_site_packages_dir(&venv)?, &locked, &env)?;
+    if !missing.is_empty() {
+        bail!(
+            "locked packages are not installed in the virtualenv, run `rye sync` first: {}",
+            missing.join(", ")
+        );
+    }
+    for notice in notices.iter().filter(|x| x.texts.is_empty()) {
+        warn!(
+            "{} {} has no license text in its distribution",
+            notice.name, notice.version
+        );
+    }
+    let name = project.name().unwrap_or("<unknown>");
+    write_atomic(path, render_notices(name, &notices))
+        .path_context(path, "unable to write third party notices")?;
+    echo!(
+        if output,
+        "Exported notices of {} packages to {}",
+        notices.len(),
+        style(path.display()).cyan()
+    );
+    Ok(())
+}
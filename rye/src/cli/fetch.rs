@@ -1,22 +1,22 @@
 use std::path::PathBuf;
 
-use anyhow::{Context, Error};
+use anyhow::{bail, Context, Error};
 use clap::Parser;
 
 use crate::bootstrap::{fetch, FetchOptions};
-use crate::config::Config;
-use crate::platform::get_python_version_request_from_pyenv_pin;
 use crate::pyproject::PyProject;
-use crate::sources::py::PythonVersionRequest;
-use crate::utils::CommandOutput;
+use crate::pyversion::resolve_python_version_request;
+use crate::sources::py::{get_download_url, PythonVersionRequest};
+use crate::utils::{override_network_timeout, CommandOutput};
 
 /// Fetches a Python interpreter for the local machine. This is an alias of `rye toolchain fetch`.
 #[derive(Parser, Debug)]
 pub struct Args {
-    /// The version of Python to fetch.
+    /// The versions of Python to fetch.
     ///
     /// If no version is provided, the requested version from local project or `.python-version` will be fetched.
-    version: Option<String>,
+    #[arg(value_name = "VERSION")]
+    versions: Vec<String>,
     /// Fetch the Python toolchain even if it is already installed.
     #[arg(short, long)]
     force: bool,
@@ -29,6 +29,16 @@ pub struct Args {
     /// Fetches without build info.
     #[arg(long, conflicts_with = "build_info")]
     no_build_info: bool,
+    /// Print the download URLs and sha256 checksums instead of fetching.
+    ///
+    /// This is useful to pre-fetch archives for machines without network
+    /// access.  Placed in the download cache they are used instead of
+    /// downloading.
+    #[arg(long, conflicts_with_all = ["force", "target_path"])]
+    list_urls: bool,
+    /// Overall network timeout in seconds.
+    #[arg(long, value_name = "SECONDS")]
+    timeout: Option<u64>,
     /// Enables verbose diagnostics.
     #[arg(short, long)]
     verbose: bool,
@@ -39,36 +49,51 @@ pub struct Args {
 
 pub fn execute(cmd: Args) -> Result<(), Error> {
     let output = CommandOutput::from_quiet_and_verbose(cmd.quiet, cmd.verbose);
+    override_network_timeout(cmd.timeout);
 
-    let version: PythonVersionRequest = match cmd.version {
-        Some(version) => version.parse()?,
-        None => {
-            if let Ok(pyproject) = PyProject::discover() {
-                pyproject.venv_python_version()?.into()
-            } else {
-                match get_python_version_request_from_pyenv_pin(&std::env::current_dir()?) {
-                    Some(version) => version,
-                    None => Config::current().default_toolchain()?,
-                }
-            }
-        }
+    let versions: Vec<PythonVersionRequest> = if cmd.versions.is_empty() {
+        vec![if let Ok(pyproject) = PyProject::discover() {
+            pyproject.venv_python_version()?.into()
+        } else {
+            resolve_python_version_request(None, &std::env::current_dir()?, None)?.0
+        }]
+    } else {
+        cmd.versions
+            .iter()
+            .map(|x| x.parse())
+            .collect::<Result<_, _>>()?
     };
 
-    fetch(
-        &version,
-        FetchOptions {
-            output,
-            force: cmd.force,
-            target_path: cmd.target_path,
-            build_info: if cmd.build_info {
-                Some(true)
-            } else if cmd.no_build_info {
-                Some(false)
-            } else {
-                None
+    if cmd.list_urls {
+        for version in &versions {
+            let Some((version, url, sha256)) = get_download_url(version) else {
+                bail!("unknown version {}", version);
+            };
+            echo!("{} {} {}", version, url, sha256.unwrap_or("-"));
+        }
+        return Ok(());
+    }
+    if versions.len() > 1 && cmd.target_path.is_some() {
+        bail!("--target-path can only be used with a single version");
+    }
+
+    for version in &versions {
+        fetch(
+            version,
+            FetchOptions {
+                output,
+                force: cmd.force,
+                target_path: cmd.target_path.clone(),
+                build_info: if cmd.build_info {
+                    Some(true)
+                } else if cmd.no_build_info {
+                    Some(false)
+                } else {
+                    None
+                },
             },
-        },
-    )
-    .context("error while fetching Python installation")?;
+        )
+        .context("error while fetching Python installation")?;
+    }
     Ok(())
 }
@@ -0,0 +1,60 @@
+use std::path::PathBuf;
+
+use anyhow::Error;
+use clap::Parser;
+use console::style;
+
+use crate::pyproject::PyProject;
+use crate::utils::{CommandOutput, QuietExit};
+
+/// Sorts and normalizes the dependency lists in pyproject.toml.
+///
+/// Dependencies are sorted by name and formatted the same way `rye add`
+/// writes them.  Comments stay with the dependency they precede.
+#[derive(Parser, Debug)]
+pub struct Args {
+    /// Normalize all projects in the workspace.
+    #[arg(short, long)]
+    all: bool,
+    /// Only check if the files are normalized and fail if not.
+    #[arg(long)]
+    check: bool,
+    /// Use this pyproject.toml file
+    #[arg(long, value_name = "PYPROJECT_TOML")]
+    pyproject: Option<PathBuf>,
+    /// Enables verbose diagnostics.
+    #[arg(short, long)]
+    verbose: bool,
+    /// Turns off all output.
+    #[arg(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+}
+
+pub fn execute(cmd: Args) -> Result<(), Error> {
+    let output = CommandOutput::from_quiet_and_verbose(cmd.quiet, cmd.verbose);
+    let project = PyProject::load_or_discover(cmd.pyproject.as_deref())?;
+    let projects = match project.workspace().cloned() {
+        Some(workspace) if cmd.all => workspace.iter_projects().collect::<Result<Vec<_>, _>>()?,
+        _ => vec![project],
+    };
+
+    let mut unnormalized = 0;
+    for mut project in projects {
+        let path = project.toml_path().into_owned();
+        if !project.normalize_all_dependencies() {
+            echo!(if verbose output, "{} is already normalized", path.display());
+        } else if cmd.check {
+            echo!(if output, "{} {}", style("would normalize").yellow(), path.display());
+            unnormalized += 1;
+        } else {
+            project.save()?;
+            echo!(if output, "Normalized {}", path.display());
+        }
+    }
+
+    if unnormalized > 0 {
+        Err(QuietExit(1).into())
+    } else {
+        Ok(())
+    }
+}
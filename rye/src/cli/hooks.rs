@@ -0,0 +1,249 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{bail, Context, Error};
+use clap::{Parser, ValueEnum};
+use console::style;
+
+use crate::config::Config;
+use crate::lock::KeyringProvider;
+use crate::pyproject::PyProject;
+use crate::sync::{autosync, get_venv_drift};
+use crate::utils::{CommandOutput, IoPathContext};
+
+/// Marks hooks that were written by Rye.
+const HOOK_MARKER: &str = "# installed by rye (rye hooks install)";
+
+/// Manage the git hooks of the project.
+#[derive(Parser, Debug)]
+pub struct Args {
+    #[command(subcommand)]
+    command: SubCommand,
+}
+
+/// Installs git hooks that check if the virtualenv is out of sync.
+///
+/// After a checkout or merge changed the lockfile the hooks print a hint to
+/// run `rye sync` (or run it if `behavior.sync-on-checkout` is enabled).
+/// Without flags all hooks are installed.
+#[derive(Parser, Debug)]
+pub struct InstallCommand {
+    /// Install the `post-checkout` hook.
+    #[arg(long)]
+    post_checkout: bool,
+    /// Install the `post-merge` hook (runs after `git pull`).
+    #[arg(long)]
+    post_merge: bool,
+    /// Replace existing hooks not installed by Rye.
+    #[arg(short, long)]
+    force: bool,
+    /// Use this pyproject.toml file
+    #[arg(long, value_name = "PYPROJECT_TOML")]
+    pyproject: Option<PathBuf>,
+    /// Enables verbose diagnostics.
+    #[arg(short, long)]
+    verbose: bool,
+    /// Turns off all output.
+    #[arg(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+}
+
+/// Removes the git hooks installed by `rye hooks install`.
+#[derive(Parser, Debug)]
+pub struct RemoveCommand {
+    /// Use this pyproject.toml file
+    #[arg(long, value_name = "PYPROJECT_TOML")]
+    pyproject: Option<PathBuf>,
+    /// Enables verbose diagnostics.
+    #[arg(short, long)]
+    verbose: bool,
+    /// Turns off all output.
+    #[arg(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+}
+
+/// Runs a hook (invoked by git).
+#[derive(Parser, Debug)]
+pub struct RunCommand {
+    /// Use this pyproject.toml file
+    #[arg(long, value_name = "PYPROJECT_TOML")]
+    pyproject: Option<PathBuf>,
+    /// The hook to run.
+    hook: Hook,
+    /// The arguments git passes to the hook.
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+    args: Vec<String>,
+}
+
+#[derive(Parser, Debug)]
+enum SubCommand {
+    Install(InstallCommand),
+    Remove(RemoveCommand),
+    #[command(hide = true)]
+    Run(RunCommand),
+}
+
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
+#[value(rename_all = "kebab-case")]
+enum Hook {
+    PostCheckout,
+    PostMerge,
+}
+
+impl Hook {
+    fn all() -> [Hook; 2] {
+        [Hook::PostCheckout, Hook::PostMerge]
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Hook::PostCheckout => "post-checkout",
+            Hook::PostMerge => "post-merge",
+        }
+    }
+
+    /// Returns the hook script for the project at the given pyproject.toml.
+    ///
+    /// Git runs hooks from the root of the work tree, which is not where the
+    /// project is if it lives in a subfolder of the repository.
+    fn script(self, pyproject: &Path) -> String {
+        format!(
+            "#!/bin/sh\n\
+             {}\n\
+             command -v rye >/dev/null 2>&1 || exit 0\n\
+             exec rye hooks run --pyproject '{}' {} \"$@\"\n",
+            HOOK_MARKER,
+            pyproject.display().to_string().replace('\'', "'\\''"),
+            self.name()
+        )
+    }
+}
+
+pub fn execute(cmd: Args) -> Result<(), Error> {
+    match cmd.command {
+        SubCommand::Install(cmd) => install(cmd),
+        SubCommand::Remove(cmd) => remove(cmd),
+        SubCommand::Run(cmd) => run(cmd),
+    }
+}
+
+fn install(cmd: InstallCommand) -> Result<(), Error> {
+    let output = CommandOutput::from_quiet_and_verbose(cmd.quiet, cmd.verbose);
+    let project = PyProject::load_or_discover(cmd.pyproject.as_deref())?;
+    let hooks_dir = get_hooks_dir(&project.workspace_path())?;
+    let hooks: Vec<_> = Hook::all()
+        .into_iter()
+        .filter(|hook| match hook {
+            Hook::PostCheckout => cmd.post_checkout || !cmd.post_merge,
+            Hook::PostMerge => cmd.post_merge || !cmd.post_checkout,
+        })
+        .collect();
+
+    for hook in &hooks {
+        let path = hooks_dir.join(hook.name());
+        if !cmd.force && path.is_file() && !is_rye_hook(&path) {
+            bail!(
+                "{} hook already exists at {} (use --force to replace it)",
+                hook.name(),
+                path.display()
+            );
+        }
+    }
+
+    fs::create_dir_all(&hooks_dir).path_context(&hooks_dir, "failed to create hooks folder")?;
+    for hook in hooks {
+        let path = hooks_dir.join(hook.name());
+        fs::write(&path, hook.script(&project.toml_path()))
+            .path_context(&path, "failed to write hook")?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o755))
+                .path_context(&path, "failed to make hook executable")?;
+        }
+        echo!(
+            if output,
+            "Installed {} hook into {}",
+            style(hook.name()).cyan(),
+            style(path.display()).cyan()
+        );
+    }
+    Ok(())
+}
+
+fn remove(cmd: RemoveCommand) -> Result<(), Error> {
+    let output = CommandOutput::from_quiet_and_verbose(cmd.quiet, cmd.verbose);
+    let project = PyProject::load_or_discover(cmd.pyproject.as_deref())?;
+    let hooks_dir = get_hooks_dir(&project.workspace_path())?;
+    let mut removed = false;
+    for hook in Hook::all() {
+        let path = hooks_dir.join(hook.name());
+        if path.is_file() && is_rye_hook(&path) {
+            fs::remove_file(&path).path_context(&path, "failed to remove hook")?;
+            echo!(if output, "Removed {} hook", style(hook.name()).cyan());
+            removed = true;
+        }
+    }
+    if !removed {
+        echo!(if output, "No hooks installed by Rye");
+    }
+    Ok(())
+}
+
+fn run(cmd: RunCommand) -> Result<(), Error> {
+    // git passes 0 as the last argument for checkouts of individual files.
+    if cmd.hook == Hook::PostCheckout && cmd.args.get(2).map(|x| x.as_str()) == Some("0") {
+        return Ok(());
+    }
+
+    // hooks run in all repositories the hook was installed into, including
+    // checkouts of revisions that do not have a project yet.
+    let project = match PyProject::load_or_discover(cmd.pyproject.as_deref()) {
+        Ok(project) => project,
+        Err(_) => return Ok(()),
+    };
+    let lockfile = match get_venv_drift(&project.venv_path()) {
+        Some(lockfile) => lockfile,
+        None => return Ok(()),
+    };
+
+    if Config::current().sync_on_checkout() {
+        echo!(
+            "{} changed, syncing virtualenv",
+            style(lockfile.display()).cyan()
+        );
+        // a failing hook does not undo the checkout, so only warn.
+        if let Err(err) = autosync(&project, CommandOutput::NORMAL, KeyringProvider::default()) {
+            warn!("failed to sync virtualenv: {}", err);
+        }
+    } else {
+        warn!(
+            "{} changed, run `rye sync` to update the virtualenv.",
+            lockfile.display()
+        );
+    }
+    Ok(())
+}
+
+/// Returns the hooks folder of the git repository the project is in.
+///
+/// This honors `core.hooksPath` and linked worktrees.
+fn get_hooks_dir(root: &Path) -> Result<PathBuf, Error> {
+    let rv = Command::new("git")
+        .args(["rev-parse", "--git-path", "hooks"])
+        .current_dir(root)
+        .output()
+        .context("failed to run git")?;
+    if !rv.status.success() {
+        bail!("{} is not in a git repository", root.display());
+    }
+    let hooks_dir = String::from_utf8_lossy(&rv.stdout).trim().to_string();
+    Ok(root.join(hooks_dir))
+}
+
+fn is_rye_hook(path: &Path) -> bool {
+    fs::read_to_string(path)
+        .map(|contents| contents.contains(HOOK_MARKER))
+        .unwrap_or(false)
+}
@@ -0,0 +1,46 @@
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Error};
+use clap::Parser;
+
+use crate::bundle::{import_bundle, ImportOptions};
+use crate::utils::CommandOutput;
+
+/// Restores a project from a bundle written by `rye export`.
+///
+/// If the bundle includes the toolchain and the wheels of all dependencies
+/// no network access is needed.
+#[derive(Parser, Debug)]
+pub struct Args {
+    /// The bundle to restore.
+    #[arg(long, value_name = "PATH")]
+    archive: PathBuf,
+    /// Where to restore the project to (defaults to the bundle name).
+    path: Option<PathBuf>,
+    /// Enables verbose diagnostics.
+    #[arg(short, long)]
+    verbose: bool,
+    /// Turns off all output.
+    #[arg(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+}
+
+pub fn execute(cmd: Args) -> Result<(), Error> {
+    let output = CommandOutput::from_quiet_and_verbose(cmd.quiet, cmd.verbose);
+    let target = match cmd.path {
+        Some(path) => path,
+        None => {
+            let name = cmd
+                .archive
+                .file_name()
+                .and_then(|x| x.to_str())
+                .map(|x| x.split('.').next().unwrap_or(x))
+                .filter(|x| !x.is_empty())
+                .ok_or_else(|| anyhow!("unable to derive a folder name, pass a path"))?;
+            PathBuf::from(name)
+        }
+    };
+    import_bundle(&cmd.archive, &target, ImportOptions { output })?;
+    echo!(if output, "Done!");
+    Ok(())
+}
@@ -0,0 +1,305 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::Error;
+use clap::Parser;
+use console::style;
+use serde::Serialize;
+
+use crate::config::Config;
+use crate::lock::LockMode;
+use crate::prefetch::schedule_pinned_toolchain;
+use crate::pyproject::{read_venv_marker, PyProject};
+use crate::sources::py::PythonVersion;
+use crate::sync::{get_lockfile_path, get_sync_variant, hash_lockfile, read_sync_state};
+
+/// Environment variables that change how rye behaves.
+const ENV_OVERRIDES: &[&str] = &[
+    "RYE_HOME",
+    "RYE_PYTHON",
+    "RYE_MACHINE_CONFIG",
+    "RYE_NETWORK_TIMEOUT",
+    "RYE_CONNECT_TIMEOUT",
+];
+
+/// Prints a summary of the project.
+#[derive(Parser, Debug)]
+pub struct Args {
+    /// Print the summary as JSON.
+    #[arg(long)]
+    json: bool,
+    /// Use this pyproject.toml file
+    #[arg(long, value_name = "PYPROJECT_TOML")]
+    pyproject: Option<PathBuf>,
+}
+
+/// Output structure for `rye info --json`
+// Reserves the right to expand with new fields.
+#[derive(Serialize, Debug)]
+struct Info {
+    name: Option<String>,
+    version: Option<String>,
+    path: PathBuf,
+    python: Option<PythonInfo>,
+    venv: VenvInfo,
+    locked_dependencies: LockedDependencies,
+    workspace: Option<WorkspaceInfo>,
+    sources: Vec<SourceInfo>,
+    config_overrides: Vec<ConfigOverride>,
+}
+
+#[derive(Serialize, Debug)]
+struct PythonInfo {
+    version: PythonVersion,
+    source: String,
+}
+
+#[derive(Serialize, Debug)]
+struct VenvInfo {
+    path: PathBuf,
+    status: VenvStatus,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+enum VenvStatus {
+    Missing,
+    Unmanaged,
+    PythonMismatch,
+    NotSynced,
+    OutOfSync,
+    UpToDate,
+}
+
+impl VenvStatus {
+    fn description(self) -> &'static str {
+        match self {
+            VenvStatus::Missing => "missing",
+            VenvStatus::Unmanaged => "not managed by rye",
+            VenvStatus::PythonMismatch => "python version changed",
+            VenvStatus::NotSynced => "not synced",
+            VenvStatus::OutOfSync => "out of sync with lockfile",
+            VenvStatus::UpToDate => "up to date",
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+struct LockedDependencies {
+    production: Option<usize>,
+    dev: Option<usize>,
+}
+
+#[derive(Serialize, Debug)]
+struct WorkspaceInfo {
+    path: PathBuf,
+    is_root: bool,
+    members: usize,
+}
+
+#[derive(Serialize, Debug)]
+struct SourceInfo {
+    name: String,
+    url: String,
+}
+
+#[derive(Serialize, Debug)]
+struct ConfigOverride {
+    key: String,
+    value: String,
+    origin: &'static str,
+}
+
+pub fn execute(cmd: Args) -> Result<(), Error> {
+    let project = PyProject::load_or_discover(cmd.pyproject.as_deref())?;
+    let info = collect_info(&project)?;
+    schedule_pinned_toolchain(&project);
+
+    if cmd.json {
+        serde_json::to_writer_pretty(std::io::stdout().lock(), &info)?;
+        echo!();
+        return Ok(());
+    }
+
+    echo!(
+        "{} {}",
+        style(info.name.as_deref().unwrap_or("<unnamed>")).yellow(),
+        info.version.as_deref().unwrap_or("(dynamic version)")
+    );
+    echo!("  {} {}", style("path:").dim(), info.path.display());
+    match info.python {
+        Some(ref python) => echo!(
+            "  {} {} ({})",
+            style("python:").dim(),
+            style(&python.version).cyan(),
+            python.source
+        ),
+        None => echo!("  {} {}", style("python:").dim(), style("unknown").red()),
+    }
+    let status = info.venv.status.description();
+    echo!(
+        "  {} {} ({})",
+        style("venv:").dim(),
+        info.venv.path.display(),
+        if info.venv.status == VenvStatus::UpToDate {
+            style(status).green()
+        } else {
+            style(status).red()
+        }
+    );
+    echo!(
+        "  {} {} ({} with dev)",
+        style("locked dependencies:").dim(),
+        format_count(info.locked_dependencies.production),
+        format_count(info.locked_dependencies.dev)
+    );
+    if let Some(ref workspace) = info.workspace {
+        echo!(
+            "  {} {} ({}, {} members)",
+            style("workspace:").dim(),
+            workspace.path.display(),
+            if workspace.is_root { "root" } else { "member" },
+            workspace.members
+        );
+    }
+    echo!("  {}", style("sources:").dim());
+    for source in &info.sources {
+        echo!("    {} ({})", style(&source.name).cyan(), source.url);
+    }
+    if !info.config_overrides.is_empty() {
+        echo!("  {}", style("config overrides:").dim());
+        for item in &info.config_overrides {
+            echo!(
+                "    {} = {} ({})",
+                style(&item.key).cyan(),
+                item.value,
+                style(item.origin).dim()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+fn collect_info(project: &PyProject) -> Result<Info, Error> {
+    let resolution = project.venv_python_version_resolution().ok();
+    let venv = project.venv_path().into_owned();
+    let status = get_venv_status(&venv, resolution.as_ref().map(|x| &x.version));
+    let variant = get_sync_variant(project, None)?;
+    let dynamic_version = project
+        .dynamic()
+        .map_or(false, |x| x.iter().any(|x| x == "version"));
+
+    let mut sources = project.sources()?;
+    sources.sort_by_cached_key(|x| (x.name != "default", x.name.to_string()));
+
+    let mut config_overrides = Config::current()
+        .explicit_settings()
+        .into_iter()
+        .map(|(key, value, is_default)| ConfigOverride {
+            key,
+            value,
+            origin: if is_default {
+                "machine config"
+            } else {
+                "config.toml"
+            },
+        })
+        .collect::<Vec<_>>();
+    for key in ENV_OVERRIDES {
+        if let Some(value) = env::var_os(key).filter(|x| !x.is_empty()) {
+            config_overrides.push(ConfigOverride {
+                key: key.to_string(),
+                value: value.to_string_lossy().into_owned(),
+                origin: "environment",
+            });
+        }
+    }
+
+    Ok(Info {
+        name: project.name().map(|x| x.to_string()),
+        version: if dynamic_version {
+            None
+        } else {
+            project.declared_version().map(|x| x.to_string())
+        },
+        path: project.root_path().into_owned(),
+        python: resolution.map(|x| PythonInfo {
+            version: x.version,
+            source: x.source.to_string(),
+        }),
+        venv: VenvInfo { path: venv, status },
+        locked_dependencies: LockedDependencies {
+            production: count_locked(&get_lockfile_path(
+                project,
+                &LockMode::Production,
+                variant.as_deref(),
+            )),
+            dev: count_locked(&get_lockfile_path(
+                project,
+                &LockMode::Dev,
+                variant.as_deref(),
+            )),
+        },
+        workspace: project.workspace().map(|workspace| WorkspaceInfo {
+            path: workspace.path().into_owned(),
+            is_root: project.is_workspace_root(),
+            members: workspace.iter_projects().filter_map(Result::ok).count(),
+        }),
+        sources: sources
+            .into_iter()
+            .map(|x| SourceInfo {
+                name: x.name,
+                url: x.url,
+            })
+            .collect(),
+        config_overrides,
+    })
+}
+
+fn get_venv_status(venv: &Path, py_ver: Option<&PythonVersion>) -> VenvStatus {
+    if !venv.is_dir() {
+        return VenvStatus::Missing;
+    }
+    let Some(marker) = read_venv_marker(venv) else {
+        return VenvStatus::Unmanaged;
+    };
+    if py_ver.map_or(false, |x| *x != marker.python) {
+        return VenvStatus::PythonMismatch;
+    }
+    match read_sync_state(venv) {
+        None => VenvStatus::NotSynced,
+        Some(state) => {
+            if hash_lockfile(&state.lockfile).ok().as_deref() == Some(state.lock_hash.as_str()) {
+                VenvStatus::UpToDate
+            } else {
+                VenvStatus::OutOfSync
+            }
+        }
+    }
+}
+
+/// Counts the pinned packages in a lockfile.
+///
+/// Comments, options and the editable installs of the projects themselves
+/// are skipped.
+fn count_locked(lockfile: &Path) -> Option<usize> {
+    let contents = fs::read_to_string(lockfile).ok()?;
+    Some(
+        contents
+            .lines()
+            .filter(|line| {
+                !line.is_empty()
+                    && !line.starts_with(|c: char| c == '#' || c == '-' || c.is_whitespace())
+            })
+            .count(),
+    )
+}
+
+fn format_count(count: Option<usize>) -> String {
+    match count {
+        Some(count) => count.to_string(),
+        None => "not locked".into(),
+    }
+}
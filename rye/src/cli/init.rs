@@ -5,13 +5,13 @@ use std::str::FromStr;
 use std::{env, fs};
 
 use anyhow::{anyhow, bail, Context, Error};
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use configparser::ini::Ini;
 use console::style;
 use license::License;
 use minijinja::{context, Environment};
 use monotrail_utils::RequirementsTxt;
-use pep440_rs::VersionSpecifier;
+use pep440_rs::{Version, VersionSpecifier, VersionSpecifiers};
 use pep508_rs::Requirement;
 use serde_json::Value;
 use tempfile::tempdir;
@@ -20,13 +20,14 @@ use crate::bootstrap::ensure_self_venv;
 use crate::config::Config;
 use crate::platform::{
     get_default_author_with_fallback, get_latest_cpython_version, get_pinnable_version,
-    get_python_version_request_from_pyenv_pin,
 };
+use crate::project_template::Template;
 use crate::pyproject::BuildSystem;
+use crate::pyversion::get_python_version_request_from_pyenv_pin;
 use crate::sources::py::PythonVersionRequest;
 use crate::utils::{
-    copy_dir, escape_string, format_requirement, get_venv_python_bin, is_inside_git_work_tree,
-    CommandOutput, CopyDirOptions, IoPathContext,
+    copy_dir, escape_string, format_requirement, get_venv_python_bin, internal_command,
+    is_inside_git_work_tree, CommandOutput, CopyDirOptions, IoPathContext,
 };
 
 /// Initialize a new or existing Python project with Rye.
@@ -65,6 +66,13 @@ pub struct Args {
     /// Don't import from setup.cfg, setup.py, or requirements files.
     #[arg(long)]
     no_import: bool,
+    /// Create the package in a PEP 420 namespace (eg: `company.tools.myproj`).
+    ///
+    /// The package is placed in `src/company/tools/myproj` without an
+    /// `__init__.py` in the namespace folders.  The name of the project
+    /// defaults to the dotted path with dashes.
+    #[arg(long, value_name = "PACKAGE", conflicts_with = "is_virtual")]
+    namespace: Option<String>,
     /// Initialize this as a virtual package.
     ///
     /// A virtual package can have dependencies but is itself not installed as a
@@ -77,6 +85,18 @@ pub struct Args {
     /// Development requirements files to initialize pyproject.toml with.
     #[arg(long, name = "DEV_REQUIREMENTS_FILE", conflicts_with = "no_import")]
     dev_requirements: Option<Vec<PathBuf>>,
+    /// Generate a CI workflow for the given provider.
+    #[arg(long, value_enum)]
+    ci: Option<CiProvider>,
+    /// Generate the project from a template (a path, git URL or `gh:org/repo`).
+    #[arg(long, conflicts_with_all = ["lib", "script", "build_system", "ci", "namespace"])]
+    template: Option<String>,
+    /// Set a variable of the template.
+    #[arg(long = "template-var", value_name = "KEY=VALUE", requires = "template")]
+    template_vars: Vec<String>,
+    /// Do not run the post-generation hook of the template.
+    #[arg(long, requires = "template")]
+    no_template_hooks: bool,
     /// Enables verbose diagnostics.
     #[arg(short, long)]
     verbose: bool,
@@ -97,6 +117,13 @@ struct ArgTemplateChoice {
     script: bool,
 }
 
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq)]
+#[value(rename_all = "lowercase")]
+pub enum CiProvider {
+    /// GitHub Actions
+    Github,
+}
+
 enum TemplateChoice {
     Lib,
     Script,
@@ -131,6 +158,12 @@ const CARGO_TOML_TEMPLATE: &str = include_str!("../templates/lib/maturin/Cargo.t
 /// Template for fresh gitignore files.
 const GITIGNORE_TEMPLATE: &str = include_str!("../templates/gitignore.j2");
 
+/// Template for the GitHub Actions workflow.
+const GITHUB_WORKFLOW_TEMPLATE: &str = include_str!("../templates/ci/github.yml.j2");
+
+/// Python versions considered for the CI test matrix.
+const CI_PYTHON_VERSIONS: &[&str] = &["3.8", "3.9", "3.10", "3.11", "3.12"];
+
 /// Script used for setup.py setup proxy.
 const SETUP_PY_PROXY_SCRIPT: &str = r#"
 import json, sys
@@ -192,11 +225,17 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
         requires_python = format!(">= {}.{}", py.major, py.minor.unwrap_or_default());
     }
 
+    let namespace = cmd.namespace.as_deref().map(parse_namespace).transpose()?;
+
     // In some cases there might not be a file name (eg: docker root)
     let name = slug::slugify(cmd.name.unwrap_or_else(|| {
-        dir.file_name()
-            .map(|x| x.to_string_lossy().into_owned())
-            .unwrap_or_else(|| "unknown".into())
+        match namespace {
+            Some(ref parts) => parts.join("-"),
+            None => dir
+                .file_name()
+                .map(|x| x.to_string_lossy().into_owned())
+                .unwrap_or_else(|| "unknown".into()),
+        }
     }));
 
     let version = "0.1.0";
@@ -205,21 +244,27 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
         Some(license) => Some(license),
         None => cfg.default_license(),
     };
-    if license.is_some() && !license_file.is_file() {
+    let build_system = match cmd.build_system {
+        Some(build_system) => build_system,
+        None => cfg
+            .default_build_system()?
+            .unwrap_or(BuildSystem::Hatchling),
+    };
+    if let Some(ref license) = license {
         let license_obj: &dyn License = license
-            .clone()
-            .unwrap()
             .parse()
-            .expect("current license not an valid license id");
-        let license_text = license_obj.text();
-        let rv = env.render_named_str(
-            "LICENSE.txt",
-            LICENSE_TEMPLATE,
-            context! {
-                license_text,
-            },
-        )?;
-        fs::write(&license_file, rv).path_context(&license_file, "create license file")?;
+            .map_err(|_| anyhow!("'{}' is not a valid SPDX license identifier", license))?;
+        if !license_file.is_file() && cmd.template.is_none() {
+            let license_text = license_obj.text();
+            let rv = env.render_named_str(
+                "LICENSE.txt",
+                LICENSE_TEMPLATE,
+                context! {
+                    license_text,
+                },
+            )?;
+            fs::write(&license_file, rv).path_context(&license_file, "create license file")?;
+        }
     }
 
     let output = CommandOutput::from_quiet_and_verbose(cmd.quiet, cmd.verbose);
@@ -265,6 +310,27 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
         metadata.dependencies = Some(Vec::new())
     }
 
+    // crate a python module safe name.  This is the name on the metadata with
+    // underscores instead of dashes to form a valid python package name and in
+    // case it starts with a digit, an underscore is prepended.  In a namespace
+    // this is the dotted path of the package instead.
+    let name_safe = match namespace {
+        Some(ref parts) => parts.join("."),
+        None => {
+            let mut name_safe = metadata.name.as_ref().unwrap().replace('-', "_");
+            if name_safe
+                .chars()
+                .next()
+                .map_or(true, |c| c.is_ascii_digit())
+            {
+                name_safe.insert(0, '_');
+            }
+            name_safe
+        }
+    };
+    // the folder of the package relative to the source folder.
+    let package_dir = name_safe.replace('.', "/");
+
     // write .python-version
     if !cmd.no_pin && !python_version_file.is_file() {
         // get_pinnable_version ideally doesn't fail, but if it does we fall back to
@@ -275,6 +341,57 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
             .path_context(&python_version_file, "could not write .python-version file")?;
     }
 
+    if let Some(ref source) = cmd.template {
+        let template = Template::load(source, output)?;
+        let (author_name, author_email) = metadata.author.clone().unwrap_or_default();
+        let defaults = [
+            ("name", metadata.name.clone().unwrap_or_default()),
+            ("name_safe", name_safe),
+            ("version", metadata.version.clone().unwrap_or_default()),
+            (
+                "description",
+                metadata.description.clone().unwrap_or_default(),
+            ),
+            ("author_name", author_name),
+            ("author_email", author_email),
+            (
+                "requires_python",
+                metadata.requires_python.clone().unwrap_or_default(),
+            ),
+            ("license", metadata.license.clone().unwrap_or_default()),
+        ]
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value))
+        .collect();
+        let explicit = cmd
+            .template_vars
+            .iter()
+            .map(|x| {
+                x.split_once('=')
+                    .map(|(key, value)| (key.trim().to_string(), value.to_string()))
+                    .ok_or_else(|| anyhow!("invalid template variable '{}', expected KEY=VALUE", x))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let variables = template.resolve_variables(defaults, &explicit)?;
+
+        template.generate(&dir, &variables, output)?;
+        init_git_repository(&dir);
+        if !cmd.no_template_hooks {
+            template.run_post_generate(&dir, &variables, output)?;
+        }
+        template.record(&toml)?;
+
+        echo!(
+            if output,
+            "{} Initialized project in {} from template {}",
+            style("success:").green(),
+            dir.display(),
+            source
+        );
+        echo!(if output, "  Run `rye sync` to get started");
+        return Ok(());
+    }
+
     // create a readme if one is missing
     let with_readme = if readme.is_file() {
         true
@@ -293,11 +410,6 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
         false
     };
 
-    let build_system = match cmd.build_system {
-        Some(build_system) => build_system,
-        None => cfg.default_build_system().unwrap_or(BuildSystem::Hatchling),
-    };
-
     let private = cmd.private;
 
     // What template are we using?
@@ -314,30 +426,8 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
         bail!("--script is not supported when the build-system is maturin");
     }
 
-    // crate a python module safe name.  This is the name on the metadata with
-    // underscores instead of dashes to form a valid python package name and in
-    // case it starts with a digit, an underscore is prepended.
-    let mut name_safe = metadata.name.as_ref().unwrap().replace('-', "_");
-    if name_safe
-        .chars()
-        .next()
-        .map_or(true, |c| c.is_ascii_digit())
-    {
-        name_safe.insert(0, '_');
-    }
-
     // if git init is successful prepare the local git repository
-    if !is_inside_git_work_tree(&dir)
-        && Command::new("git")
-            .arg("init")
-            .current_dir(&dir)
-            .stdout(Stdio::null())
-            .stderr(Stdio::null())
-            .status()
-            .map(|status| status.success())
-            .unwrap_or(false)
-        && is_metadata_author_none
-    {
+    if init_git_repository(&dir) && is_metadata_author_none {
         let new_author = get_default_author_with_fallback(&dir);
         if author != new_author {
             metadata.author = new_author;
@@ -364,6 +454,8 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
         context! {
             name => metadata.name,
             name_safe => name_safe,
+            package_dir => package_dir,
+            is_namespace => namespace.is_some(),
             description => metadata.description,
             version => metadata.version,
             author => metadata.author,
@@ -380,6 +472,26 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
     )?;
     fs::write(&toml, rv).context("failed to write pyproject.toml")?;
 
+    if let Some(CiProvider::Github) = cmd.ci {
+        let workflow = dir.join(".github").join("workflows").join("ci.yml");
+        if !workflow.is_file() {
+            let python_versions =
+                get_ci_python_versions(metadata.requires_python.as_deref().unwrap_or(""));
+            let rv = env.render_named_str(
+                "ci.yml",
+                GITHUB_WORKFLOW_TEMPLATE,
+                context! {
+                    python_versions,
+                },
+            )?;
+            if let Some(parent) = workflow.parent() {
+                fs::create_dir_all(parent)
+                    .path_context(parent, "failed to create workflows folder")?;
+            }
+            fs::write(&workflow, rv).path_context(&workflow, "failed to write CI workflow")?;
+        }
+    }
+
     if !is_virtual {
         let src_dir = dir.join("src");
         if !imported_something && !src_dir.is_dir() {
@@ -387,7 +499,7 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
             match (template, build_system) {
                 (TemplateChoice::Lib, BuildSystem::Maturin) => {
                     fs::create_dir_all(&src_dir).ok();
-                    let project_dir = dir.join("python").join(&name_safe);
+                    let project_dir = dir.join("python").join(&package_dir);
                     fs::create_dir_all(&project_dir).ok();
                     let rv = env.render_named_str("lib.rs", LIB_RS_TEMPLATE, context! { name })?;
                     fs::write(src_dir.join("lib.rs"), rv).context("failed to write lib.rs")?;
@@ -396,7 +508,7 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
                         CARGO_TOML_TEMPLATE,
                         context! {
                             name,
-                            name_safe,
+                            name_safe => name_safe.replace('.', "_"),
                         },
                     )?;
                     fs::write(dir.join("Cargo.toml"), rv).context("failed to write Cargo.toml")?;
@@ -411,7 +523,7 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
                         .context("failed to write __init__.py")?;
                 }
                 (TemplateChoice::Lib, _) => {
-                    let project_dir = src_dir.join(&name_safe);
+                    let project_dir = src_dir.join(&package_dir);
                     fs::create_dir_all(&project_dir).ok();
                     let rv = env.render_named_str(
                         "__init__.py",
@@ -422,7 +534,7 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
                         .context("failed to write __init__.py")?;
                 }
                 (TemplateChoice::Script, _) => {
-                    let project_dir = src_dir.join(&name_safe);
+                    let project_dir = src_dir.join(&package_dir);
                     fs::create_dir_all(&project_dir).ok();
 
                     let rv1 = env.render_named_str(
@@ -457,6 +569,63 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
     Ok(())
 }
 
+/// Splits the dotted path of a package in a namespace into its parts.
+fn parse_namespace(namespace: &str) -> Result<Vec<String>, Error> {
+    let parts = namespace
+        .split('.')
+        .map(|x| x.to_string())
+        .collect::<Vec<_>>();
+    let is_identifier = |part: &str| {
+        part.chars()
+            .next()
+            .map_or(false, |c| c.is_ascii_alphabetic() || c == '_')
+            && part.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    };
+    if parts.len() < 2 || !parts.iter().all(|x| is_identifier(x)) {
+        bail!(
+            "invalid namespace '{}', expected a dotted path of the package (eg: company.tools.myproj)",
+            namespace
+        );
+    }
+    Ok(parts)
+}
+
+/// Runs `git init` unless the folder is already in a git repository.
+///
+/// Returns `true` if a new repository was created.
+fn init_git_repository(dir: &Path) -> bool {
+    !is_inside_git_work_tree(&dir.to_path_buf())
+        && Command::new("git")
+            .arg("init")
+            .current_dir(dir)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .map(|status| status.success())
+            .unwrap_or(false)
+}
+
+/// Returns the Python versions of the CI matrix that satisfy `requires-python`.
+///
+/// If nothing matches (or the specifiers cannot be parsed) the newest version
+/// is used so that the workflow is never empty.
+fn get_ci_python_versions(requires_python: &str) -> Vec<&'static str> {
+    let specifiers = VersionSpecifiers::from_str(requires_python).ok();
+    let rv: Vec<_> = CI_PYTHON_VERSIONS
+        .iter()
+        .copied()
+        .filter(|ver| match (&specifiers, Version::from_str(ver)) {
+            (Some(specifiers), Ok(ver)) => specifiers.contains(&ver),
+            _ => true,
+        })
+        .collect();
+    if rv.is_empty() {
+        CI_PYTHON_VERSIONS[CI_PYTHON_VERSIONS.len() - 1..].to_vec()
+    } else {
+        rv
+    }
+}
+
 #[derive(Default)]
 struct Metadata {
     name: Option<String>,
@@ -667,7 +836,7 @@ fn get_setup_py_json<T: AsRef<Path>>(path: T, python: T) -> Result<Value, Error>
     let setuptools_proxy = temp_dir.path().join("setuptools.py");
     fs::write(setuptools_proxy, SETUP_PY_PROXY_SCRIPT)?;
 
-    let cmd = Command::new(python)
+    let cmd = internal_command(python)
         .arg(setup_py)
         .env("PYTHONPATH", temp_dir.path())
         .stderr(Stdio::piped())
@@ -6,9 +6,9 @@ use pep508_rs::Requirement;
 
 use crate::cli::add::ReqExtras;
 use crate::config::Config;
-use crate::installer::{install, resolve_local_requirement};
+use crate::installer::{get_pinned_tool_python, install, resolve_local_requirement};
 use crate::sources::py::PythonVersionRequest;
-use crate::utils::CommandOutput;
+use crate::utils::{override_network_timeout, CommandOutput};
 
 /// Installs a package as global tool. This is an alias of `rye tools install`.
 #[derive(Parser, Debug)]
@@ -24,11 +24,16 @@ pub struct Args {
     #[arg(long)]
     extra_requirement: Vec<String>,
     /// Optionally the Python version to use.
+    ///
+    /// The version is remembered and used again when the tool is reinstalled.
     #[arg(short, long)]
     python: Option<String>,
     /// Force install the package even if it's already there.
     #[arg(short, long)]
     force: bool,
+    /// Overall network timeout in seconds.
+    #[arg(long, value_name = "SECONDS")]
+    timeout: Option<u64>,
     /// Enables verbose diagnostics.
     #[arg(short, long)]
     verbose: bool,
@@ -39,6 +44,7 @@ pub struct Args {
 
 pub fn execute(mut cmd: Args) -> Result<(), Error> {
     let output = CommandOutput::from_quiet_and_verbose(cmd.quiet, cmd.verbose);
+    override_network_timeout(cmd.timeout);
     let mut extra_requirements = Vec::new();
 
     // main requirement
@@ -52,24 +58,23 @@ pub fn execute(mut cmd: Args) -> Result<(), Error> {
         extra_requirements.push(handle_requirement(&req, output, false)?);
     }
 
-    let py_ver: PythonVersionRequest = match cmd.python {
-        Some(ref py) => py.parse()?,
-        None => Config::current()
-            .default_toolchain()
-            .unwrap_or(PythonVersionRequest {
-                name: None,
-                arch: None,
-                os: None,
-                major: 3,
-                minor: None,
-                patch: None,
-                suffix: None,
-            }),
+    // an explicitly requested python is remembered for reinstalls of the tool
+    let pinned = cmd
+        .python
+        .as_ref()
+        .map(|py| py.parse::<PythonVersionRequest>())
+        .transpose()?
+        .or_else(|| get_pinned_tool_python(&requirement.name));
+    let pin_python = pinned.is_some();
+    let py_ver = match pinned {
+        Some(py_ver) => py_ver,
+        None => Config::current().default_tool_python()?,
     };
 
     install(
         requirement,
         &py_ver,
+        pin_python,
         cmd.force,
         &cmd.include_dep,
         &extra_requirements,
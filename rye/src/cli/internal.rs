@@ -0,0 +1,255 @@
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Error};
+use clap::{Parser, ValueEnum};
+use serde::{Deserialize, Serialize};
+
+use crate::lock::{parse_locked_packages, LockMode, LockedPackage};
+use crate::prefetch::run_job;
+use crate::pyproject::{find_project_root_from, PyProject};
+use crate::sources::py::PythonVersion;
+use crate::sync::{get_lockfile_path, get_sync_variant};
+use crate::utils::{IoPathContext, QuietExit};
+
+/// The version of the API.  It is bumped for incompatible changes only.
+const API_VERSION: u32 = 1;
+
+/// Interfaces for plugins and editor tooling.
+#[derive(Parser, Debug)]
+pub struct Args {
+    #[command(subcommand)]
+    command: SubCommand,
+}
+
+/// Answers a JSON request read from stdin with a JSON response on stdout.
+///
+/// Requests carry the `api-version` they were written for.  Responses carry
+/// the same `api-version` and either a `result` or an `error`.
+#[derive(Parser, Debug)]
+pub struct ApiCommand {
+    /// The method to call.
+    method: ApiMethod,
+}
+
+/// Downloads a toolchain in the background (spawned by `behavior.prefetch-pinned`).
+#[derive(Parser, Debug)]
+pub struct PrefetchCommand {
+    /// The toolchain to download.
+    version: String,
+}
+
+#[derive(ValueEnum, Copy, Clone, Debug)]
+enum ApiMethod {
+    /// Returns rye's view of the project at a path.
+    ResolveProject,
+    /// Returns the packages pinned in a project's lockfile.
+    ReadLock,
+}
+
+#[derive(Parser, Debug)]
+enum SubCommand {
+    Api(ApiCommand),
+    #[command(hide = true)]
+    Prefetch(PrefetchCommand),
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct Request {
+    api_version: u32,
+    /// The folder or `pyproject.toml` to look at, defaults to the current folder.
+    #[serde(default)]
+    path: Option<PathBuf>,
+    /// Read the dev lockfile (`read-lock` only).
+    #[serde(default)]
+    dev: bool,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+struct Response<T> {
+    api_version: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<T>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+struct ResolvedProject {
+    name: Option<String>,
+    version: Option<String>,
+    root: PathBuf,
+    pyproject: PathBuf,
+    workspace_root: PathBuf,
+    is_workspace_root: bool,
+    venv: PathBuf,
+    python: Option<String>,
+    is_virtual: bool,
+    lockfile: PathBuf,
+    dev_lockfile: PathBuf,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+struct LockContents {
+    lockfile: PathBuf,
+    packages: Vec<LockedPackage>,
+}
+
+pub fn execute(cmd: Args) -> Result<(), Error> {
+    match cmd.command {
+        SubCommand::Api(args) => api(args),
+        SubCommand::Prefetch(args) => run_job(&args.version.parse::<PythonVersion>()?),
+    }
+}
+
+fn api(cmd: ApiCommand) -> Result<(), Error> {
+    let mut input = String::new();
+    std::io::stdin().read_to_string(&mut input)?;
+    let ok = match cmd.method {
+        ApiMethod::ResolveProject => respond(handle(&input, resolve_project)),
+        ApiMethod::ReadLock => respond(handle(&input, read_lock)),
+    }?;
+    if ok {
+        Ok(())
+    } else {
+        Err(QuietExit(1).into())
+    }
+}
+
+fn handle<T, F>(input: &str, f: F) -> Result<T, Error>
+where
+    F: FnOnce(&Request) -> Result<T, Error>,
+{
+    let request: Request =
+        serde_json::from_str(input).map_err(|err| anyhow!("invalid request: {}", err))?;
+    if request.api_version != API_VERSION {
+        bail!(
+            "unsupported api-version {} (supported: {})",
+            request.api_version,
+            API_VERSION
+        );
+    }
+    f(&request)
+}
+
+/// Prints the response and returns whether the call succeeded.
+fn respond<T: Serialize>(result: Result<T, Error>) -> Result<bool, Error> {
+    let ok = result.is_ok();
+    let response = match result {
+        Ok(result) => Response {
+            api_version: API_VERSION,
+            result: Some(result),
+            error: None,
+        },
+        Err(err) => Response {
+            api_version: API_VERSION,
+            result: None,
+            error: Some(format!("{:#}", err)),
+        },
+    };
+    serde_json::to_writer(std::io::stdout().lock(), &response)?;
+    echo!();
+    Ok(ok)
+}
+
+fn load_project(path: Option<&Path>) -> Result<PyProject, Error> {
+    let path = match path {
+        Some(path) => path.canonicalize().path_context(path, "invalid path")?,
+        None => std::env::current_dir()?,
+    };
+    if path.is_file() {
+        return PyProject::load(&path);
+    }
+    match find_project_root_from(&path) {
+        Some(root) => PyProject::load(&root.join("pyproject.toml")),
+        None => bail!("did not find pyproject.toml in {}", path.display()),
+    }
+}
+
+fn resolve_project(request: &Request) -> Result<ResolvedProject, Error> {
+    let project = load_project(request.path.as_deref())?;
+    let workspace_root = project.workspace_path().into_owned();
+    let variant = get_sync_variant(&project, None)?;
+    Ok(ResolvedProject {
+        name: project.name().map(|x| x.to_string()),
+        version: project.declared_version().map(|x| x.to_string()),
+        root: project.root_path().into_owned(),
+        pyproject: project.toml_path().into_owned(),
+        is_workspace_root: project.is_workspace_root(),
+        venv: project.venv_path().into_owned(),
+        python: project.venv_python_version().ok().map(|x| x.to_string()),
+        is_virtual: project.is_virtual(),
+        lockfile: get_lockfile_path(&project, &LockMode::Production, variant.as_deref()),
+        dev_lockfile: get_lockfile_path(&project, &LockMode::Dev, variant.as_deref()),
+        workspace_root,
+    })
+}
+
+fn read_lock(request: &Request) -> Result<LockContents, Error> {
+    let project = load_project(request.path.as_deref())?;
+    let workspace_root = project.workspace_path();
+    let mode = if request.dev {
+        LockMode::Dev
+    } else {
+        LockMode::Production
+    };
+    let lockfile = get_lockfile_path(
+        &project,
+        &mode,
+        get_sync_variant(&project, None)?.as_deref(),
+    );
+    let contents =
+        fs::read_to_string(&lockfile).path_context(&lockfile, "unable to read lockfile")?;
+    Ok(LockContents {
+        packages: parse_locked_packages(&contents, &workspace_root),
+        lockfile,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use super::{handle, read_lock, resolve_project, Request};
+
+    #[test]
+    fn test_api() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        fs::write(
+            root.join("pyproject.toml"),
+            "[project]\nname = \"My-Project\"\nversion = \"1.0\"\n",
+        )
+        .unwrap();
+        fs::write(root.join(".python-version"), "cpython@3.12.3\n").unwrap();
+        fs::write(
+            root.join("requirements.lock"),
+            "-e file:.\nidna==3.7\n    # via my-project\n",
+        )
+        .unwrap();
+        let request = format!(
+            r#"{{"api-version": 1, "path": {}}}"#,
+            serde_json::to_string(&root).unwrap()
+        );
+
+        let project = handle(&request, resolve_project).unwrap();
+        assert_eq!(project.name.as_deref(), Some("My-Project"));
+        assert_eq!(project.root, root);
+        assert_eq!(project.python.as_deref(), Some("cpython@3.12.3"));
+        assert_eq!(project.lockfile, root.join("requirements.lock"));
+
+        let lock = handle(&request, read_lock).unwrap();
+        assert_eq!(lock.packages.len(), 2);
+        assert_eq!(lock.packages[0].name, "my-project");
+        assert!(lock.packages[0].dependencies.contains("idna"));
+
+        let err = handle(r#"{"api-version": 2}"#, |_: &Request| Ok(())).unwrap_err();
+        assert_eq!(err.to_string(), "unsupported api-version 2 (supported: 1)");
+        assert!(handle(r#"{"api-version": 1, "nope": 1}"#, |_: &Request| Ok(())).is_err());
+    }
+}
@@ -0,0 +1,225 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, bail, Error};
+use clap::Parser;
+use console::style;
+use serde::Serialize;
+
+use crate::bootstrap::get_site_packages_dir;
+use crate::pyproject::{normalize_package_name, read_venv_marker, PyProject};
+use crate::sync::find_installed_dists;
+use crate::utils::{get_venv_python_bin, CommandOutput, IoPathContext};
+
+/// Manage the Jupyter kernel of the project.
+#[derive(Parser, Debug)]
+pub struct Args {
+    #[command(subcommand)]
+    command: SubCommand,
+}
+
+/// Registers the virtualenv of the project as a Jupyter kernel.
+///
+/// The kernel is registered for the current user and launches the Python
+/// interpreter of the virtualenv, which needs `ipykernel` installed.
+#[derive(Parser, Debug)]
+pub struct InstallCommand {
+    /// The name of the kernel (defaults to the name of the project).
+    #[arg(long)]
+    name: Option<String>,
+    /// The name of the kernel shown in Jupyter.
+    #[arg(long)]
+    display_name: Option<String>,
+    /// Overwrite an existing kernel of the same name.
+    #[arg(short, long)]
+    force: bool,
+    /// Use this pyproject.toml file
+    #[arg(long, value_name = "PYPROJECT_TOML")]
+    pyproject: Option<PathBuf>,
+    /// Enables verbose diagnostics.
+    #[arg(short, long)]
+    verbose: bool,
+    /// Turns off all output.
+    #[arg(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+}
+
+/// Removes a Jupyter kernel registered with `rye kernel install`.
+#[derive(Parser, Debug)]
+pub struct RemoveCommand {
+    /// The name of the kernel (defaults to the name of the project).
+    #[arg(long)]
+    name: Option<String>,
+    /// Use this pyproject.toml file
+    #[arg(long, value_name = "PYPROJECT_TOML")]
+    pyproject: Option<PathBuf>,
+    /// Enables verbose diagnostics.
+    #[arg(short, long)]
+    verbose: bool,
+    /// Turns off all output.
+    #[arg(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+}
+
+#[derive(Parser, Debug)]
+enum SubCommand {
+    Install(InstallCommand),
+    Remove(RemoveCommand),
+}
+
+/// The `kernel.json` of a kernelspec.
+#[derive(Serialize, Debug)]
+struct KernelSpec {
+    argv: Vec<String>,
+    display_name: String,
+    language: &'static str,
+    env: KernelEnv,
+    metadata: KernelMetadata,
+}
+
+#[derive(Serialize, Debug)]
+struct KernelEnv {
+    #[serde(rename = "VIRTUAL_ENV")]
+    virtual_env: String,
+}
+
+#[derive(Serialize, Debug)]
+struct KernelMetadata {
+    debugger: bool,
+    rye: RyeMetadata,
+}
+
+#[derive(Serialize, Debug)]
+struct RyeMetadata {
+    project: String,
+}
+
+pub fn execute(cmd: Args) -> Result<(), Error> {
+    match cmd.command {
+        SubCommand::Install(cmd) => install(cmd),
+        SubCommand::Remove(cmd) => remove(cmd),
+    }
+}
+
+fn install(cmd: InstallCommand) -> Result<(), Error> {
+    let output = CommandOutput::from_quiet_and_verbose(cmd.quiet, cmd.verbose);
+    let project = PyProject::load_or_discover(cmd.pyproject.as_deref())?;
+    let venv = project.venv_path();
+    if read_venv_marker(&venv).is_none() {
+        bail!(
+            "virtualenv {} does not exist, run `rye sync` first",
+            venv.display()
+        );
+    }
+    if !find_installed_dists(&get_site_packages_dir(&venv)?)?
+        .iter()
+        .any(|x| x.name == "ipykernel")
+    {
+        warn!("ipykernel is not installed, add it with `rye add --dev ipykernel`");
+    }
+
+    let name = get_kernel_name(&project, cmd.name.as_deref())?;
+    let kernel_dir = get_kernels_dir()?.join(&name);
+    let kernel_json = kernel_dir.join("kernel.json");
+    if kernel_json.is_file() && !cmd.force {
+        bail!(
+            "kernel '{}' already exists (use --force to overwrite)",
+            name
+        );
+    }
+
+    let spec = KernelSpec {
+        argv: vec![
+            get_venv_python_bin(&venv).to_string_lossy().into_owned(),
+            "-m".into(),
+            "ipykernel_launcher".into(),
+            "-f".into(),
+            "{connection_file}".into(),
+        ],
+        display_name: cmd
+            .display_name
+            .unwrap_or_else(|| format!("Python ({})", project.name().unwrap_or(&name))),
+        language: "python",
+        env: KernelEnv {
+            virtual_env: venv.to_string_lossy().into_owned(),
+        },
+        metadata: KernelMetadata {
+            debugger: true,
+            rye: RyeMetadata {
+                project: project.root_path().to_string_lossy().into_owned(),
+            },
+        },
+    };
+    fs::create_dir_all(&kernel_dir).path_context(&kernel_dir, "failed to create kernel folder")?;
+    fs::write(&kernel_json, serde_json::to_string_pretty(&spec)?)
+        .path_context(&kernel_json, "failed to write kernel.json")?;
+    echo!(
+        if output,
+        "Installed kernel {} into {}",
+        style(&name).cyan(),
+        style(kernel_dir.display()).cyan()
+    );
+    Ok(())
+}
+
+fn remove(cmd: RemoveCommand) -> Result<(), Error> {
+    let output = CommandOutput::from_quiet_and_verbose(cmd.quiet, cmd.verbose);
+    let project = PyProject::load_or_discover(cmd.pyproject.as_deref())?;
+    let name = get_kernel_name(&project, cmd.name.as_deref())?;
+    let kernel_dir = get_kernels_dir()?.join(&name);
+    if !kernel_dir.join("kernel.json").is_file() {
+        bail!("kernel '{}' is not installed", name);
+    }
+    fs::remove_dir_all(&kernel_dir).path_context(&kernel_dir, "failed to remove kernel")?;
+    echo!(if output, "Removed kernel {}", style(&name).cyan());
+    Ok(())
+}
+
+/// Returns the name of the kernel, which is the project name by default.
+///
+/// Jupyter only accepts ASCII letters, digits, `.`, `_` and `-` in kernel names.
+fn get_kernel_name(project: &PyProject, name: Option<&str>) -> Result<String, Error> {
+    let name = match name {
+        Some(name) => name.to_string(),
+        None => normalize_package_name(
+            project
+                .name()
+                .ok_or_else(|| anyhow!("project has no name, pass --name"))?,
+        ),
+    };
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+    {
+        bail!(
+            "invalid kernel name '{}', only letters, digits, '.', '_' and '-' are allowed",
+            name
+        );
+    }
+    Ok(name.to_ascii_lowercase())
+}
+
+/// Returns the folder Jupyter looks up the kernels of the current user in.
+fn get_kernels_dir() -> Result<PathBuf, Error> {
+    if let Some(dir) = env::var_os("JUPYTER_DATA_DIR") {
+        return Ok(PathBuf::from(dir).join("kernels"));
+    }
+    let data_dir = if cfg!(windows) {
+        env::var_os("APPDATA")
+            .map(|x| PathBuf::from(x).join("jupyter"))
+            .ok_or_else(|| anyhow!("could not find APPDATA folder"))?
+    } else {
+        let home = home::home_dir().ok_or_else(|| anyhow!("could not find home dir"))?;
+        if cfg!(target_os = "macos") {
+            home.join("Library").join("Jupyter")
+        } else {
+            env::var_os("XDG_DATA_HOME")
+                .map(PathBuf::from)
+                .unwrap_or_else(|| home.join(".local").join("share"))
+                .join("jupyter")
+        }
+    };
+    Ok(data_dir.join("kernels"))
+}
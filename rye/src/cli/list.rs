@@ -1,15 +1,11 @@
 use std::path::PathBuf;
-use std::process::Command;
 
-use anyhow::{bail, Error};
+use anyhow::Error;
 use clap::Parser;
 
-use crate::bootstrap::ensure_self_venv;
-use crate::config::Config;
-use crate::consts::VENV_BIN;
+use crate::backend::Backend;
 use crate::pyproject::PyProject;
 use crate::utils::{get_venv_python_bin, CommandOutput};
-use crate::uv::{UvBuilder, UvWithVenv};
 
 /// Prints the currently installed packages.
 #[derive(Parser, Debug)]
@@ -25,36 +21,9 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
     if !python.is_file() {
         return Ok(());
     }
-    let self_venv = ensure_self_venv(CommandOutput::Normal)?;
-
-    if Config::current().use_uv() {
-        let uv = UvBuilder::new()
-            .with_output(CommandOutput::Normal)
-            .ensure_exists()?;
-        if !project.rye_managed() {
-            UvWithVenv::new(uv, &project.venv_path(), &project.venv_python_version()?).freeze()?;
-        } else {
-            uv.venv(
-                &project.venv_path(),
-                &python,
-                &project.venv_python_version()?,
-                None,
-            )?
-            .freeze()?;
-        }
-    } else {
-        let status = Command::new(self_venv.join(VENV_BIN).join("pip"))
-            .arg("--python")
-            .arg(&python)
-            .arg("freeze")
-            .env("PYTHONWARNINGS", "ignore")
-            .env("PIP_DISABLE_PIP_VERSION_CHECK", "1")
-            .status()?;
-
-        if !status.success() {
-            bail!("failed to print dependencies via pip");
-        }
-    };
-
-    Ok(())
+    Backend::current().freeze(
+        &project.venv_path(),
+        &project.venv_python_version()?,
+        CommandOutput::NORMAL,
+    )
 }
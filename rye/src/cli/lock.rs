@@ -1,15 +1,27 @@
-use std::path::PathBuf;
+use std::fs;
+use std::path::{Path, PathBuf};
 
-use anyhow::Error;
+use anyhow::{bail, Context, Error};
 use clap::Parser;
+use console::style;
 
-use crate::lock::{KeyringProvider, LockOptions};
+use crate::bootstrap::{fetch, FetchOptions};
+use crate::lock::{
+    merge_lockfiles, update_single_project_lockfile, update_workspace_lockfile, KeyringProvider,
+    LockMode, LockOptions,
+};
+use crate::pyproject::{ExpandedSources, PyProject};
+use crate::sources::py::PythonVersionRequest;
 use crate::sync::{sync, SyncMode, SyncOptions};
-use crate::utils::CommandOutput;
+use crate::utils::atomic::write_atomic_with_backup;
+use crate::utils::{override_network_timeout, CommandOutput, IoPathContext};
 
 /// Updates the lockfiles without installing dependencies.
 #[derive(Parser, Debug)]
 pub struct Args {
+    /// Overall network timeout in seconds.
+    #[arg(long, value_name = "SECONDS")]
+    timeout: Option<u64>,
     /// Enables verbose diagnostics.
     #[arg(short, long)]
     verbose: bool,
@@ -46,26 +58,147 @@ pub struct Args {
     /// Set to true to lock with hashes in the lockfile.
     #[arg(long)]
     generate_hashes: bool,
+    /// Allow this private package to be resolved from the public index.
+    #[arg(long, value_name = "NAME")]
+    allow_shadowing: Vec<String>,
+    /// Merge two lockfiles with a common base into OURS (usable as git merge driver).
+    #[arg(long, num_args = 3, value_names = ["BASE", "OURS", "THEIRS"], conflicts_with = "update_all")]
+    merge: Option<Vec<PathBuf>>,
+    /// The path of the lockfile that is being merged (`%P` for git).
+    #[arg(long, requires = "merge")]
+    merge_path: Option<PathBuf>,
+    /// Lock as if running on this platform (eg: `linux`, `macos`, `windows`, `emscripten`, `wasi`).
+    #[arg(long, value_name = "PLATFORM")]
+    platform: Option<String>,
+    /// Lock as if running on this Python version.
+    #[arg(long, value_name = "VERSION")]
+    python_version: Option<PythonVersionRequest>,
+    /// Lock the dependency variant with this name.
+    #[arg(long, value_name = "VARIANT")]
+    variant: Option<String>,
+    /// Print which requirements constrain each pinned version.
+    #[arg(long)]
+    explain: bool,
 }
 
 pub fn execute(cmd: Args) -> Result<(), Error> {
     let output = CommandOutput::from_quiet_and_verbose(cmd.quiet, cmd.verbose);
+    override_network_timeout(cmd.timeout);
+    let lock_options = LockOptions {
+        update: cmd.update,
+        update_all: cmd.update_all,
+        pre: cmd.pre,
+        features: cmd.features,
+        all_features: cmd.all_features,
+        with_sources: cmd.with_sources,
+        reset: cmd.reset,
+        generate_hashes: cmd.generate_hashes,
+        allow_shadowing: cmd.allow_shadowing,
+        python_version: cmd.python_version.map(|x| x.format_simple()),
+        python_platform: cmd.platform,
+        variant: cmd.variant,
+        explain: cmd.explain,
+        report_from_index: true,
+        ..LockOptions::default()
+    };
+
+    if let Some(paths) = cmd.merge {
+        return merge(
+            &paths,
+            cmd.merge_path.as_deref(),
+            cmd.pyproject.as_deref(),
+            lock_options,
+            cmd.keyring_provider,
+            output,
+        );
+    }
+
     sync(SyncOptions {
         output,
         mode: SyncMode::LockOnly,
-        lock_options: LockOptions {
-            update: cmd.update,
-            update_all: cmd.update_all,
-            pre: cmd.pre,
-            features: cmd.features,
-            all_features: cmd.all_features,
-            with_sources: cmd.with_sources,
-            reset: cmd.reset,
-            generate_hashes: cmd.generate_hashes,
-        },
+        lock_options,
         pyproject: cmd.pyproject,
         keyring_provider: cmd.keyring_provider,
         ..SyncOptions::default()
     })?;
     Ok(())
 }
+
+/// Merges lockfiles and re-resolves the conflicting entries.
+///
+/// The result is written to the `OURS` lockfile which is what git expects
+/// from a merge driver.
+fn merge(
+    paths: &[PathBuf],
+    merge_path: Option<&Path>,
+    pyproject: Option<&Path>,
+    mut lock_options: LockOptions,
+    keyring_provider: KeyringProvider,
+    output: CommandOutput,
+) -> Result<(), Error> {
+    let [base, ours, theirs] = paths else {
+        bail!("--merge requires exactly three lockfiles");
+    };
+    let read = |path: &Path| fs::read_to_string(path).path_context(path, "unable to read lockfile");
+    let merged = merge_lockfiles(&read(base)?, &read(ours)?, &read(theirs)?);
+    write_atomic_with_backup(ours, &merged.contents)
+        .path_context(ours, "unable to write merged lockfile")?;
+
+    for name in &merged.conflicts {
+        echo!(if output, "Conflicting pins for {}, re-resolving", style(name).cyan());
+    }
+
+    let pyproject = PyProject::load_or_discover(pyproject)?;
+    if pyproject.lock_with_sources() {
+        lock_options.with_sources = true;
+    }
+    if pyproject.structured_lock() {
+        lock_options.structured = true;
+    }
+    if pyproject.resolution_report() {
+        lock_options.report = true;
+    }
+    lock_options.update.extend(merged.conflicts);
+
+    let (lock_mode, variant) = LockMode::from_lockfile_name(
+        merge_path
+            .unwrap_or(ours)
+            .file_name()
+            .and_then(|x| x.to_str())
+            .unwrap_or_default(),
+    );
+    lock_options.variant = variant;
+    let py_ver = fetch(
+        &pyproject.venv_python_version()?.into(),
+        FetchOptions::with_output(output),
+    )
+    .context("failed fetching toolchain ahead of merge")?;
+    let sources = ExpandedSources::from_sources(&pyproject.sources()?)?;
+
+    if let Some(workspace) = pyproject.workspace() {
+        update_workspace_lockfile(
+            &py_ver,
+            workspace,
+            lock_mode,
+            ours,
+            output,
+            &sources,
+            &lock_options,
+            keyring_provider,
+        )
+    } else {
+        update_single_project_lockfile(
+            &py_ver,
+            &pyproject,
+            lock_mode,
+            ours,
+            output,
+            &sources,
+            &lock_options,
+            keyring_provider,
+        )
+    }
+    .context("could not re-resolve merged lockfile")?;
+
+    Ok(())
+}
@@ -1,4 +1,5 @@
 use std::env;
+use std::ffi::OsString;
 use std::path::PathBuf;
 
 use anyhow::{bail, Error};
@@ -6,16 +7,27 @@ use clap::Parser;
 
 mod add;
 mod build;
+mod cache;
+mod check_wheels;
 mod config;
+mod devcontainer;
+mod export;
 mod fetch;
 mod fmt;
+mod fmt_pyproject;
+mod hooks;
+mod import;
+mod info;
 mod init;
 mod install;
+mod internal;
+mod kernel;
 mod lint;
 mod list;
 mod lock;
 mod make_req;
 mod pin;
+mod plugin;
 mod publish;
 mod remove;
 mod run;
@@ -27,15 +39,17 @@ mod test;
 mod toolchain;
 mod tools;
 mod uninstall;
+mod vendor;
+mod venv;
+mod verify_env;
 mod version;
 
 use git_testament::git_testament;
 
-use crate::bootstrap::{get_self_venv_status, SELF_PYTHON_TARGET_VERSION};
-use crate::config::Config;
+use crate::backend::Backend;
+use crate::bootstrap::{get_self_venv_python, SELF_PYTHON_TARGET_VERSION};
 use crate::platform::symlinks_supported;
-use crate::pyproject::read_venv_marker;
-use crate::utils::IoPathContext;
+use crate::utils::{record_command, IoPathContext, QuietExit, Verbosity};
 
 git_testament!(TESTAMENT);
 
@@ -48,21 +62,59 @@ struct Args {
     /// Load one or more .env files.
     #[arg(long)]
     env_file: Vec<PathBuf>,
+    /// Use this folder as the rye home (same as `RYE_HOME`).
+    #[arg(long, value_name = "PATH", global = true)]
+    app_dir: Option<PathBuf>,
     /// Print the version
     #[arg(long)]
     version: bool,
+    /// Use output suited for CI logs (enabled if the CI env var is set).
+    ///
+    /// This disables colors and progress bars and instead prints periodic
+    /// progress lines with timestamps.
+    #[arg(long)]
+    ci: bool,
+    /// Ignore the `tool.rye.policy` of the project (also `RYE_OVERRIDE_POLICY=1`).
+    #[arg(long, global = true)]
+    override_policy: bool,
+    /// Fail instead of downloading toolchains or bootstrapping rye internals
+    /// (also `RYE_OFFLINE=1`).
+    ///
+    /// Toolchains are still installed from the download cache or a local
+    /// toolchain mirror.
+    #[arg(long, global = true)]
+    offline: bool,
+    /// Hide the output of pip and other tools rye runs.
+    ///
+    /// The errors of these tools are still shown.  Rye's own messages are not affected.
+    #[arg(long, global = true, conflicts_with = "show_pip")]
+    quiet_pip: bool,
+    /// Show the full output of pip and other tools rye runs.
+    ///
+    /// This also applies if rye's own messages are hidden with --quiet.
+    #[arg(long, global = true)]
+    show_pip: bool,
 }
 
 #[derive(Parser, Debug)]
 enum Command {
     Add(add::Args),
     Build(build::Args),
+    Cache(cache::Args),
+    CheckWheels(check_wheels::Args),
     Config(config::Args),
+    Devcontainer(devcontainer::Args),
+    Export(export::Args),
     Fetch(fetch::Args),
     #[command(alias = "format")]
     Fmt(fmt::Args),
+    FmtPyproject(fmt_pyproject::Args),
+    Hooks(hooks::Args),
+    Import(import::Args),
+    Info(info::Args),
     Init(init::Args),
     Install(install::Args),
+    Kernel(kernel::Args),
     Lock(lock::Args),
     #[command(alias = "check")]
     Lint(lint::Args),
@@ -79,10 +131,17 @@ enum Command {
     #[command(name = "self")]
     Rye(rye::Args),
     Uninstall(uninstall::Args),
+    Vendor(vendor::Args),
+    Venv(venv::Args),
+    VerifyEnv(verify_env::Args),
     Version(version::Args),
     List(list::Args),
     #[command(hide = true)]
     Shell(shell::Args),
+    #[command(hide = true)]
+    Internal(internal::Args),
+    #[command(external_subcommand)]
+    Plugin(Vec<OsString>),
 }
 
 pub mod shell {
@@ -95,6 +154,7 @@ pub fn execute() -> Result<(), Error> {
     // common initialization
     crate::platform::init()?;
     crate::config::load()?;
+    crate::tui::init_output();
 
     let args = env::args_os().collect::<Vec<_>>();
 
@@ -108,12 +168,36 @@ pub fn execute() -> Result<(), Error> {
 
     let args = Args::try_parse()?;
 
+    // --app-dir is exported as `RYE_HOME` so that the processes rye spawns use
+    // the same folder.
+    if let Some(ref app_dir) = args.app_dir {
+        env::set_var("RYE_HOME", env::current_dir()?.join(app_dir));
+        crate::platform::init()?;
+        crate::config::load()?;
+    }
+    crate::platform::check_app_dir_owner()?;
+
     // handle --env-file.  As this happens here this cannot influence `RYE_HOME` or
     // the behavior of the shims.
     for env_file in &args.env_file {
         dotenvy::from_path(env_file).path_context(env_file, "unable to load env file")?;
     }
 
+    if args.ci || crate::tui::is_ci_env() {
+        crate::tui::enable_ci_mode();
+    }
+    if args.override_policy {
+        crate::policy::enable_override();
+    }
+    if args.offline {
+        crate::bootstrap::enable_offline();
+    }
+    if args.quiet_pip {
+        crate::utils::set_child_verbosity(Verbosity::Quiet);
+    } else if args.show_pip {
+        crate::utils::set_child_verbosity(Verbosity::Verbose);
+    }
+
     let cmd = if args.version {
         return print_version();
     } else if let Some(cmd) = args.command {
@@ -122,14 +206,37 @@ pub fn execute() -> Result<(), Error> {
         unreachable!()
     };
 
-    match cmd {
+    rye::check_shims_on_path();
+    if let Err(err) = crate::shims::refresh_packaged_core_shims() {
+        warn!("could not refresh python shims: {}", err);
+    }
+    crate::utils::cleanup::collect_orphaned_temp_files();
+    crate::deprecations::check_config();
+    // `rye self` and `rye config` must keep working to fix the mismatch.
+    if !matches!(
+        cmd,
+        Command::Rye(_) | Command::Config(_) | Command::Internal(_)
+    ) {
+        rye::check_required_version()?;
+    }
+
+    let result = match cmd {
         Command::Add(cmd) => add::execute(cmd),
         Command::Build(cmd) => build::execute(cmd),
+        Command::Cache(cmd) => cache::execute(cmd),
+        Command::CheckWheels(cmd) => check_wheels::execute(cmd),
         Command::Config(cmd) => config::execute(cmd),
+        Command::Devcontainer(cmd) => devcontainer::execute(cmd),
+        Command::Export(cmd) => export::execute(cmd),
         Command::Fetch(cmd) => fetch::execute(cmd),
         Command::Fmt(cmd) => fmt::execute(cmd),
+        Command::FmtPyproject(cmd) => fmt_pyproject::execute(cmd),
+        Command::Hooks(cmd) => hooks::execute(cmd),
+        Command::Import(cmd) => import::execute(cmd),
+        Command::Info(cmd) => info::execute(cmd),
         Command::Init(cmd) => init::execute(cmd),
         Command::Install(cmd) => install::execute(cmd),
+        Command::Kernel(cmd) => kernel::execute(cmd),
         Command::Lock(cmd) => lock::execute(cmd),
         Command::Lint(cmd) => lint::execute(cmd),
         Command::MakeReq(cmd) => make_req::execute(cmd),
@@ -144,8 +251,13 @@ pub fn execute() -> Result<(), Error> {
         Command::Tools(cmd) => tools::execute(cmd),
         Command::Rye(cmd) => rye::execute(cmd),
         Command::Uninstall(cmd) => uninstall::execute(cmd),
+        Command::Vendor(cmd) => vendor::execute(cmd),
+        Command::Venv(cmd) => venv::execute(cmd),
+        Command::VerifyEnv(cmd) => verify_env::execute(cmd),
         Command::Version(cmd) => version::execute(cmd),
         Command::List(cmd) => list::execute(cmd),
+        Command::Internal(cmd) => internal::execute(cmd),
+        Command::Plugin(args) => plugin::execute(args),
         Command::Shell(..) => {
             bail!(
                 "unknown command. The shell command was removed. Activate the virtualenv with '{}' instead.",
@@ -156,7 +268,14 @@ pub fn execute() -> Result<(), Error> {
                 }
             );
         }
-    }
+    };
+
+    let status = match result {
+        Ok(()) => 0,
+        Err(ref err) => err.downcast_ref::<QuietExit>().map_or(1, |x| x.0),
+    };
+    record_command(&env::args_os().collect::<Vec<_>>(), status);
+    result
 }
 
 fn print_version() -> Result<(), Error> {
@@ -168,11 +287,7 @@ fn print_version() -> Result<(), Error> {
         std::env::consts::ARCH
     );
 
-    let self_venv_python = match get_self_venv_status() {
-        Ok(venv_dir) | Err((venv_dir, _)) => read_venv_marker(&venv_dir).map(|mark| mark.python),
-    };
-
-    if let Some(python) = self_venv_python {
+    if let Some(python) = get_self_venv_python() {
         echo!("self-python: {}", python);
     } else {
         echo!(
@@ -181,6 +296,6 @@ fn print_version() -> Result<(), Error> {
         );
     }
     echo!("symlink support: {}", symlinks_supported());
-    echo!("uv enabled: {}", Config::current().use_uv());
+    echo!("uv enabled: {}", Backend::current().is_uv());
     Ok(())
 }
@@ -1,15 +1,18 @@
 use std::env;
-use std::fs;
+use std::env::consts::{ARCH, OS};
 use std::path::PathBuf;
+use std::str::FromStr;
 
 use anyhow::Context;
-use anyhow::{anyhow, Error};
+use anyhow::{anyhow, bail, Error};
 use clap::Parser;
+use pep440_rs::{Version, VersionSpecifiers};
 
-use crate::platform::get_pinnable_version;
+use crate::platform::{get_pinnable_version, list_known_toolchains};
 use crate::pyproject::DiscoveryUnsuccessful;
 use crate::pyproject::PyProject;
-use crate::sources::py::PythonVersionRequest;
+use crate::sources::py::{iter_downloadable, PythonVersion, PythonVersionRequest};
+use crate::utils::atomic::write_atomic;
 use crate::utils::IoPathContext;
 
 /// Pins a Python version to this project.
@@ -21,7 +24,13 @@ use crate::utils::IoPathContext;
 #[derive(Parser, Debug)]
 pub struct Args {
     /// The version of Python to pin.
-    version: String,
+    #[arg(required_unless_present = "auto")]
+    version: Option<String>,
+    /// Pin the newest version matching `requires-python` of the project.
+    ///
+    /// Installed toolchains are preferred over ones that need to be downloaded.
+    #[arg(long, conflicts_with = "version")]
+    auto: bool,
     /// Issue a relaxed pin
     #[arg(long)]
     relaxed: bool,
@@ -34,13 +43,6 @@ pub struct Args {
 }
 
 pub fn execute(cmd: Args) -> Result<(), Error> {
-    let req: PythonVersionRequest = cmd
-        .version
-        .parse()
-        .with_context(|| format!("'{}' is not a valid version", cmd.version))?;
-    let to_write = get_pinnable_version(&req, cmd.relaxed)
-        .ok_or_else(|| anyhow!("unsupported/unknown version for this platform"))?;
-
     let pyproject = match PyProject::load_or_discover(cmd.pyproject.as_deref()) {
         Ok(proj) => Some(proj),
         Err(err) => {
@@ -53,11 +55,31 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
         }
     };
 
+    let to_write = if cmd.auto {
+        let requires_python = pyproject
+            .as_ref()
+            .and_then(|x| x.requires_python())
+            .ok_or_else(|| anyhow!("--auto requires a project with requires-python"))?;
+        let version = find_auto_pin_version(requires_python)?;
+        if cmd.relaxed {
+            format!("{}.{}", version.major, version.minor)
+        } else {
+            format!("{}.{}.{}", version.major, version.minor, version.patch)
+        }
+    } else {
+        let version = cmd.version.as_deref().unwrap_or_default();
+        let req: PythonVersionRequest = version
+            .parse()
+            .with_context(|| format!("'{}' is not a valid version", version))?;
+        get_pinnable_version(&req, cmd.relaxed)
+            .ok_or_else(|| anyhow!("unsupported/unknown version for this platform"))?
+    };
+
     let version_file = match pyproject {
         Some(ref proj) => proj.root_path().join(".python-version"),
         None => env::current_dir()?.join(".python-version"),
     };
-    fs::write(&version_file, format!("{}\n", to_write))
+    write_atomic(&version_file, format!("{}\n", to_write))
         .path_context(&version_file, "failed to write .python-version file")?;
 
     if !cmd.no_update_requires_python {
@@ -76,3 +98,34 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
 
     Ok(())
 }
+
+/// Finds the newest cpython version that satisfies `requires-python`.
+///
+/// Installed toolchains win over newer ones that would need to be downloaded.
+fn find_auto_pin_version(requires_python: &str) -> Result<PythonVersion, Error> {
+    let specifiers = VersionSpecifiers::from_str(requires_python)
+        .map_err(|msg| anyhow!("invalid requires-python '{}': {}", requires_python, msg))?;
+    let matches = |version: &PythonVersion| {
+        version.name == "cpython"
+            && version.os == OS
+            && version.arch == ARCH
+            && Version::from_str(&format!(
+                "{}.{}.{}",
+                version.major, version.minor, version.patch
+            ))
+            .map_or(false, |x| specifiers.contains(&x))
+    };
+
+    let installed = list_known_toolchains()?
+        .into_iter()
+        .map(|x| x.0)
+        .filter(matches)
+        .max();
+    match installed.or_else(|| iter_downloadable(OS, ARCH).filter(matches).max()) {
+        Some(version) => Ok(version),
+        None => bail!(
+            "no toolchain is available that satisfies requires-python '{}'",
+            requires_python
+        ),
+    }
+}
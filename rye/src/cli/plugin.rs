@@ -0,0 +1,64 @@
+use std::env;
+use std::ffi::OsString;
+use std::process::Command;
+
+use anyhow::{bail, Error};
+
+use crate::config::Config;
+use crate::platform::get_app_dir;
+use crate::pyproject::PyProject;
+use crate::utils::exec_spawn;
+
+/// Runs `rye-<name>` from `PATH` for a subcommand rye does not know.
+///
+/// Like cargo, this lets external tools extend rye.  The plugin receives
+/// the remaining arguments and learns about rye and the current project
+/// through environment variables.
+pub fn execute(args: Vec<OsString>) -> Result<(), Error> {
+    let Some((name, args)) = args.split_first() else {
+        bail!("missing subcommand");
+    };
+    let name = name.to_string_lossy();
+    let bin = format!("rye-{}", name);
+    let Ok(path) = which::which(&bin) else {
+        bail!(
+            "unknown command '{}'. No rye built-in or plugin ({} on PATH) has this name.",
+            name,
+            bin
+        );
+    };
+
+    let mut cmd = Command::new(path);
+    cmd.args(args);
+    for (key, value) in plugin_env()? {
+        cmd.env(key, value);
+    }
+    match exec_spawn(&mut cmd)? {}
+}
+
+/// Returns the environment variables that describe rye and the project.
+fn plugin_env() -> Result<Vec<(&'static str, OsString)>, Error> {
+    let mut rv = vec![
+        ("RYE_VERSION", OsString::from(env!("CARGO_PKG_VERSION"))),
+        ("RYE_HOME", get_app_dir().as_os_str().to_owned()),
+        (
+            "RYE_CONFIG",
+            Config::current().path().as_os_str().to_owned(),
+        ),
+    ];
+    if let Ok(exe) = env::current_exe() {
+        rv.push(("RYE_EXE", exe.into_os_string()));
+    }
+    if let Ok(project) = PyProject::discover() {
+        rv.push((
+            "RYE_PROJECT_ROOT",
+            project.root_path().as_os_str().to_owned(),
+        ));
+        rv.push((
+            "RYE_WORKSPACE_ROOT",
+            project.workspace_path().as_os_str().to_owned(),
+        ));
+        rv.push(("RYE_VENV", project.venv_path().as_os_str().to_owned()));
+    }
+    Ok(rv)
+}
@@ -6,6 +6,7 @@ use pep508_rs::Requirement;
 
 use crate::config::Config;
 use crate::lock::KeyringProvider;
+use crate::policy::Operation;
 use crate::pyproject::{DependencyKind, PyProject};
 use crate::sync::autosync;
 use crate::utils::{format_requirement, CommandOutput};
@@ -44,25 +45,29 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
     let mut removed_packages = Vec::new();
 
     let mut pyproject_toml = PyProject::discover()?;
+    pyproject_toml
+        .policy()
+        .check(Operation::Remove, &pyproject_toml.policy_path())?;
+    let dep_kind = if cmd.dev {
+        DependencyKind::Dev
+    } else if let Some(ref section) = cmd.optional {
+        DependencyKind::Optional(section.into())
+    } else {
+        DependencyKind::Normal
+    };
     for str_requirement in cmd.requirements {
         let requirement = Requirement::from_str(&str_requirement)?;
-        if let Some(removed) = pyproject_toml.remove_dependency(
-            &requirement,
-            if cmd.dev {
-                DependencyKind::Dev
-            } else if let Some(ref section) = cmd.optional {
-                DependencyKind::Optional(section.into())
-            } else {
-                DependencyKind::Normal
-            },
-        )? {
+        if let Some(removed) = pyproject_toml.remove_dependency(&requirement, dep_kind.clone())? {
             removed_packages.push(removed);
         }
     }
+    if pyproject_toml.sort_dependencies() {
+        pyproject_toml.normalize_dependencies(&dep_kind);
+    }
 
     pyproject_toml.save()?;
 
-    if output != CommandOutput::Quiet {
+    if !output.is_quiet() {
         for requirement in removed_packages {
             echo!("Removed {}", format_requirement(&requirement));
         }
@@ -8,10 +8,17 @@ use anyhow::{bail, Context, Error};
 use clap::Parser;
 use console::style;
 
-use crate::pyproject::{PyProject, Script};
-use crate::sync::{sync, SyncOptions};
+use crate::bootstrap::{fetch, FetchOptions};
+use crate::native::{get_native_env, get_native_prefixes};
+use crate::platform::get_toolchain_python_bin;
+use crate::pyproject::{latest_available_python_version, PyProject, Script};
+use crate::sources::py::PythonVersionRequest;
+use crate::sync::{check_venv_drift, sync, SyncOptions};
 use crate::tui::redirect_to_stderr;
-use crate::utils::{exec_spawn, get_venv_python_bin, success_status, IoPathContext};
+use crate::utils::{
+    append_python_path, exec_spawn, get_venv_python_bin, success_status, CommandOutput,
+    IoPathContext,
+};
 
 /// Runs a command installed into this package.
 #[derive(Parser, Debug)]
@@ -26,6 +33,11 @@ pub struct Args {
     /// Use this pyproject.toml file
     #[arg(long, value_name = "PYPROJECT_TOML")]
     pyproject: Option<PathBuf>,
+    /// Run the command with this Python toolchain instead of a project.
+    ///
+    /// The toolchain is fetched if it's not installed yet.
+    #[arg(long, value_name = "VERSION", conflicts_with_all = ["pyproject", "list"])]
+    python: Option<String>,
 }
 
 #[derive(Parser, Debug)]
@@ -36,11 +48,20 @@ enum Cmd {
 
 pub fn execute(cmd: Args) -> Result<(), Error> {
     let _guard = redirect_to_stderr(true);
+    if let Some(ref python) = cmd.python {
+        let args = match cmd.cmd {
+            Some(Cmd::External(args)) => args,
+            None => bail!("no command given to run with Python {}", python),
+        };
+        invoke_with_toolchain(&python.parse()?, args)?;
+        unreachable!();
+    }
     let pyproject = PyProject::load_or_discover(cmd.pyproject.as_deref())?;
 
     // make sure we have the minimal virtualenv.
     sync(SyncOptions::python_only().pyproject(cmd.pyproject))
         .context("failed to sync ahead of run")?;
+    check_venv_drift(&pyproject)?;
 
     if cmd.list || cmd.cmd.is_none() {
         return list_scripts(&pyproject);
@@ -61,6 +82,7 @@ fn invoke_script(
 ) -> Result<ExitStatus, Error> {
     let venv_bin = pyproject.venv_bin_path();
     let mut env_overrides = None;
+    let mut shell = None;
 
     match pyproject.get_script_cmd(&args[0].to_string_lossy()) {
         Some(Script::Call(entry, env_vars, env_file)) => {
@@ -107,6 +129,11 @@ fn invoke_script(
                     .collect();
             }
         }
+        Some(Script::Shell(shell_cmd, env_vars, env_file)) => {
+            env_overrides = Some(load_env_vars(pyproject, env_file, env_vars)?);
+            args[0] = OsString::from(&shell_cmd.shell);
+            shell = Some(shell_cmd);
+        }
         Some(Script::External(_)) => {
             args[0] = venv_bin.join(&args[0]).into();
         }
@@ -136,9 +163,20 @@ fn invoke_script(
     }
 
     let mut cmd = Command::new(&args[0]);
-    cmd.args(&args[1..]);
+    match shell {
+        Some(shell) => shell.add_args(&mut cmd, &args[1..]),
+        None => {
+            cmd.args(&args[1..]);
+        }
+    }
+    let native_env = get_native_env(&get_native_prefixes(&pyproject.venv_path()))?;
     cmd.env("VIRTUAL_ENV", &*pyproject.venv_path());
-    if let Some(path) = env::var_os("PATH") {
+    let path = native_env
+        .iter()
+        .find(|(key, _)| *key == "PATH")
+        .map(|(_, value)| value.clone())
+        .or_else(|| env::var_os("PATH"));
+    if let Some(path) = path {
         let mut paths = split_paths(&path).collect::<Vec<_>>();
         paths.insert(0, venv_bin.into());
         let new_path = join_paths(paths)?;
@@ -146,6 +184,8 @@ fn invoke_script(
     } else {
         cmd.env("PATH", &*venv_bin);
     }
+    append_python_path(&mut cmd, &pyproject.extra_python_paths())?;
+    cmd.envs(native_env.into_iter().filter(|(key, _)| *key != "PATH"));
     if let Some(env_overrides) = env_overrides {
         cmd.envs(env_overrides.iter());
     }
@@ -158,6 +198,47 @@ fn invoke_script(
     }
 }
 
+/// Runs a command directly on a toolchain without a project.
+fn invoke_with_toolchain(
+    version: &PythonVersionRequest,
+    args: Vec<OsString>,
+) -> Result<ExitStatus, Error> {
+    let version = match latest_available_python_version(version) {
+        Some(version) => version,
+        None => bail!("unknown version {}", version),
+    };
+    let version = fetch(
+        &version.into(),
+        FetchOptions::with_output(CommandOutput::NORMAL),
+    )
+    .context("failed to fetch toolchain")?;
+    let py_bin = get_toolchain_python_bin(&version)?;
+    let bin_dir = py_bin
+        .parent()
+        .context("toolchain python has no parent directory")?;
+
+    // the toolchain might only come with python3, so make `python` resolve
+    // to the toolchain as well.
+    let mut cmd = if args[0] == "python" {
+        Command::new(&py_bin)
+    } else {
+        Command::new(&args[0])
+    };
+    cmd.args(&args[1..]);
+    if let Some(path) = env::var_os("PATH") {
+        let mut paths = split_paths(&path).collect::<Vec<_>>();
+        paths.insert(0, bin_dir.into());
+        let new_path = join_paths(paths)?;
+        cmd.env("PATH", new_path);
+    } else {
+        cmd.env("PATH", bin_dir);
+    }
+    cmd.env_remove("VIRTUAL_ENV");
+    cmd.env_remove("PYTHONHOME");
+
+    match exec_spawn(&mut cmd)? {}
+}
+
 fn load_env_vars(
     pyproject: &PyProject,
     env_file: Option<PathBuf>,
@@ -1,22 +1,62 @@
 use std::borrow::Cow;
 use std::convert::Infallible;
-use std::env;
 use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::{env, fs};
 
 use anyhow::{anyhow, bail, Context, Error};
 use same_file::is_same_file;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::process::Command;
 
 use crate::bootstrap::{ensure_self_venv, get_pip_runner};
-use crate::config::Config;
+use crate::config::{get_machine_config_path, Config};
 use crate::consts::VENV_BIN;
-use crate::platform::{get_python_version_request_from_pyenv_pin, get_toolchain_python_bin};
+use crate::platform::{get_app_dir, get_toolchain_python_bin, is_app_dir_owned};
 use crate::pyproject::{latest_available_python_version, PyProject};
+use crate::pyversion::{resolve_python_version_request, PythonVersionSource};
+use crate::shims::{mark_running_as_shim, parse_versioned_shim_name, ShimConflict};
 use crate::sources::py::PythonVersionRequest;
 use crate::sync::{sync, SyncOptions};
 use crate::tui::redirect_to_stderr;
-use crate::utils::{exec_spawn, get_venv_python_bin, CommandOutput};
+use crate::utils::{
+    append_log_line, exec_spawn, get_venv_python_bin, CommandOutput, IoPathContext,
+};
+
+/// A cached shim resolution.
+///
+/// The entry is valid as long as none of the files it depends on changed.
+/// Missing files are recorded too, so that creating one (for instance a new
+/// `.python-version`) invalidates the entry.
+#[derive(Serialize, Deserialize, Debug)]
+struct ShimCacheEntry {
+    program: PathBuf,
+    deps: Vec<(PathBuf, Option<u64>)>,
+}
+
+impl ShimCacheEntry {
+    fn new(program: PathBuf, deps: Vec<PathBuf>) -> ShimCacheEntry {
+        ShimCacheEntry {
+            program,
+            deps: deps
+                .into_iter()
+                .map(|path| {
+                    let mtime = get_mtime(&path);
+                    (path, mtime)
+                })
+                .collect(),
+        }
+    }
+
+    fn is_fresh(&self) -> bool {
+        self.deps
+            .iter()
+            .all(|(path, mtime)| get_mtime(path) == *mtime)
+    }
+}
 
 fn detect_shim(args: &[OsString]) -> Option<String> {
     // Shims are detected if the executable is linked into
@@ -89,6 +129,21 @@ fn find_shadowed_target(target: &str, args: &[OsString]) -> Result<Option<Vec<Os
             }
         }
 
+        // pyenv and conda pick an interpreter by themselves, which is easily
+        // not the one that was expected.
+        if let Some(conflict) = bin
+            .parent()
+            .and_then(ShimConflict::detect)
+            .filter(|_| Config::current().check_path())
+        {
+            warn!(
+                "{} is not provided by rye here, falling back to {} from {}",
+                target,
+                conflict.tool(),
+                bin.display()
+            );
+        }
+
         let mut args = args.to_vec();
         args[0] = bin.into();
         return Ok(Some(args));
@@ -179,6 +234,40 @@ fn is_python_shim(target: &str) -> bool {
     matches_shim(target, "python") || matches_shim(target, "python3")
 }
 
+/// Resolves a versioned python shim (`python3.12`) to the latest installed toolchain
+/// of that version.  Unlike the `python` shim this ignores the project.
+fn get_versioned_python_shim_target(
+    major: u8,
+    minor: u8,
+    args: &[OsString],
+) -> Result<Vec<OsString>, Error> {
+    let request = PythonVersionRequest {
+        name: None,
+        arch: None,
+        os: None,
+        major,
+        minor: Some(minor),
+        patch: None,
+        suffix: None,
+    };
+    let py = latest_available_python_version(&request)
+        .map(|py_ver| get_toolchain_python_bin(&py_ver))
+        .transpose()?
+        .filter(|py| py.is_file());
+    let Some(py) = py else {
+        bail!(
+            "Python {}.{} is not installed. Install with `rye fetch {}.{}`",
+            major,
+            minor,
+            major,
+            minor
+        );
+    };
+    let mut args = args.to_vec();
+    args[0] = py.into();
+    Ok(args)
+}
+
 /// Figures out where a shim should point to.
 fn get_shim_target(
     target: &str,
@@ -224,7 +313,7 @@ fn get_shim_target(
 
         // secret pip shims
         if matches_shim(target, "pip") || matches_shim(target, "pip3") {
-            return Ok(Some(get_pip_shim(pyproject, args, CommandOutput::Normal)?));
+            return Ok(Some(get_pip_shim(pyproject, args, CommandOutput::NORMAL)?));
         }
 
     // Global shims (either implicit or requested)
@@ -232,31 +321,30 @@ fn get_shim_target(
         let config = Config::current();
         let mut remove1 = false;
 
-        let (version_request, implicit_request) = if let Some(rest) = args
+        let explicit = match args
             .get(1)
             .and_then(|x| x.as_os_str().to_str())
             .and_then(|x| x.strip_prefix('+'))
         {
-            remove1 = true;
-            (
-                PythonVersionRequest::from_str(rest)
-                    .context("invalid Python version requested from command line")?,
-                false,
-            )
-        } else if config.global_python() {
-            (
-                match get_python_version_request_from_pyenv_pin(&std::env::current_dir()?) {
-                    Some(version_request) => version_request,
-                    None => config.default_toolchain()?,
-                },
-                true,
-            )
-        } else {
-            // if neither requested explicitly, nor global-python is enabled, we fall
-            // back to the next shadowed target
-            return find_shadowed_target(target, args);
+            Some(rest) => {
+                remove1 = true;
+                Some(
+                    PythonVersionRequest::from_str(rest)
+                        .context("invalid Python version requested from command line")?,
+                )
+            }
+            None => None,
         };
 
+        // if neither requested explicitly, nor global-python is enabled, we fall
+        // back to the next shadowed target
+        if explicit.is_none() && !config.global_python() && env::var_os("RYE_PYTHON").is_none() {
+            return find_shadowed_target(target, args);
+        }
+        let (version_request, source) =
+            resolve_python_version_request(explicit, &env::current_dir()?, None)?;
+        let implicit_request = source != PythonVersionSource::CommandLine;
+
         let py_ver = latest_available_python_version(&version_request)
             .ok_or_else(|| anyhow!("Unable to determine target Python version"))?;
         let py = get_toolchain_python_bin(&py_ver)?;
@@ -286,6 +374,131 @@ fn get_shim_target(
     find_shadowed_target(target, args)
 }
 
+fn get_mtime(path: &Path) -> Option<u64> {
+    let mtime = fs::metadata(path).and_then(|x| x.modified()).ok()?;
+    mtime
+        .duration_since(UNIX_EPOCH)
+        .ok()
+        .map(|x| x.as_nanos() as u64)
+}
+
+fn get_shim_cache_path(shim_name: &str) -> Result<PathBuf, Error> {
+    let mut hasher = Sha256::new();
+    hasher.update(env::current_dir()?.to_string_lossy().as_bytes());
+    hasher.update(b"\0");
+    hasher.update(shim_name.as_bytes());
+    hasher.update(b"\0");
+    hasher.update(env::var("RYE_PYTHON").unwrap_or_default().as_bytes());
+    let key = hex::encode(hasher.finalize());
+    Ok(get_app_dir()
+        .join("cache")
+        .join("shims")
+        .join(format!("{}.json", &key[..16])))
+}
+
+/// Returns the files that influence how a shim resolves from a folder.
+///
+/// These are the project and version pins of all parent folders, the
+/// config files and the installed toolchains.
+fn get_shim_cache_deps(cwd: &Path) -> Vec<PathBuf> {
+    let mut rv = Vec::new();
+    for dir in cwd.ancestors() {
+        rv.push(dir.join("pyproject.toml"));
+        rv.push(dir.join(".python-version"));
+    }
+    let app_dir = get_app_dir();
+    rv.push(app_dir.join("config.toml"));
+    rv.extend(get_machine_config_path());
+    rv.push(app_dir.join("py"));
+    rv
+}
+
+/// Looks up a previous resolution of the shim for the current folder.
+fn get_cached_shim_target(shim_name: &str, args: &[OsString]) -> Option<Vec<OsString>> {
+    // explicit version selections are never cached
+    if args
+        .get(1)
+        .and_then(|x| x.to_str())
+        .map_or(false, |x| x.starts_with('+'))
+    {
+        return None;
+    }
+    let contents = fs::read(get_shim_cache_path(shim_name).ok()?).ok()?;
+    let entry: ShimCacheEntry = serde_json::from_slice(&contents).ok()?;
+    if !entry.is_fresh() {
+        return None;
+    }
+    let mut args = args.to_vec();
+    args[0] = entry.program.into();
+    Some(args)
+}
+
+/// Remembers the resolution of a shim for the current folder.
+///
+/// Only targets that point into the project's virtualenv or to a rye
+/// managed toolchain are cached as others depend on `PATH`.
+fn cache_shim_target(
+    shim_name: &str,
+    args: &[OsString],
+    target: &[OsString],
+    pyproject: Option<&PyProject>,
+) -> Result<(), Error> {
+    if target.get(1..) != args.get(1..) {
+        return Ok(());
+    }
+    let program = PathBuf::from(&target[0]);
+    let mut deps = get_shim_cache_deps(&env::current_dir()?);
+    match pyproject {
+        Some(pyproject) if program.starts_with(pyproject.venv_path()) => {
+            deps.push(pyproject.venv_path().join("rye-venv.json"));
+        }
+        None if program.starts_with(get_app_dir()) => {}
+        _ => return Ok(()),
+    }
+    deps.push(program.clone());
+
+    let path = get_shim_cache_path(shim_name)?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).path_context(parent, "unable to create cache folder")?;
+    }
+    fs::write(
+        &path,
+        serde_json::to_vec(&ShimCacheEntry::new(program, deps))?,
+    )
+    .path_context(&path, "unable to write shim cache")
+}
+
+/// The shim trace is rotated once it grows beyond this size.
+const SHIM_TRACE_MAX_SIZE: u64 = 1024 * 1024;
+
+/// Returns the path of the log of shim resolutions.
+fn get_shim_trace_path() -> PathBuf {
+    get_app_dir().join("logs").join("shims.log")
+}
+
+/// Records how a shim resolved if tracing is enabled.
+///
+/// `source` tells if the target came from the cache or was resolved.  This
+/// is best effort and never fails the shim.
+fn trace_shim(shim_name: &str, target: &[OsString], pyproject: Option<&PyProject>, source: &str) {
+    if !is_app_dir_owned() {
+        return;
+    }
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |x| x.as_secs());
+    let line = format!(
+        "{} shim={} source={} cwd={} project={} target={}",
+        timestamp,
+        shim_name,
+        source,
+        env::current_dir().map_or_else(|_| "-".into(), |x| x.display().to_string()),
+        pyproject.map_or_else(|| "-".into(), |x| x.root_path().display().to_string()),
+        Path::new(&target[0]).display()
+    );
+    append_log_line(&get_shim_trace_path(), &line, SHIM_TRACE_MAX_SIZE).ok();
+}
+
 fn spawn_shim(args: Vec<OsString>) -> Result<Infallible, Error> {
     let mut cmd = Command::new(&args[0]);
     cmd.args(&args[1..]);
@@ -316,9 +529,33 @@ fn matches_shim(s: &str, reference: &str) -> bool {
 /// executable is invoked as a shim executable.
 pub fn execute_shim(args: &[OsString]) -> Result<(), Error> {
     if let Some(shim_name) = detect_shim(args) {
-        let pyproject = PyProject::discover().ok();
-        if let Some(args) = get_shim_target(&shim_name, args, pyproject.as_ref())? {
+        mark_running_as_shim();
+        let trace = Config::current().trace_shims();
+        if let Some((major, minor)) = parse_versioned_shim_name(&shim_name) {
+            let target = get_versioned_python_shim_target(major, minor, args)?;
+            if trace {
+                trace_shim(&shim_name, &target, None, "versioned");
+            }
+            match spawn_shim(target)? {}
+        }
+        if let Some(args) = get_cached_shim_target(&shim_name, args) {
+            if trace {
+                let pyproject = PyProject::discover().ok();
+                trace_shim(&shim_name, &args, pyproject.as_ref(), "cache");
+            }
             match spawn_shim(args)? {}
+        }
+        let pyproject = PyProject::discover().ok();
+        if let Some(target) = get_shim_target(&shim_name, args, pyproject.as_ref())? {
+            if trace {
+                trace_shim(&shim_name, &target, pyproject.as_ref(), "resolved");
+            }
+            // the cache is an optimization, failing to write it is not an error.
+            // It is not written into the folder of another user (`sudo python`).
+            if is_app_dir_owned() {
+                cache_shim_target(&shim_name, args, &target, pyproject.as_ref()).ok();
+            }
+            match spawn_shim(target)? {}
         } else if is_python_shim(&shim_name) {
             if pyproject.is_some() {
                 bail!("Target Python binary '{}' not found in project. Most likely running 'rye sync' will resolve this.", shim_name);
@@ -336,3 +573,30 @@ pub fn execute_shim(args: &[OsString]) -> Result<(), Error> {
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use super::ShimCacheEntry;
+
+    #[test]
+    fn test_shim_cache_entry_freshness() {
+        let dir = tempfile::tempdir().unwrap();
+        let pin = dir.path().join(".python-version");
+        let program = dir.path().join("python");
+        fs::write(&program, "").unwrap();
+
+        let entry = ShimCacheEntry::new(program.clone(), vec![pin.clone(), program.clone()]);
+        assert!(entry.is_fresh());
+
+        // creating a file that was missing invalidates the entry
+        fs::write(&pin, "3.12\n").unwrap();
+        assert!(!entry.is_fresh());
+
+        // so does removing the program
+        let entry = ShimCacheEntry::new(program.clone(), vec![pin, program.clone()]);
+        fs::remove_file(&program).unwrap();
+        assert!(!entry.is_fresh());
+    }
+}
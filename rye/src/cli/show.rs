@@ -5,6 +5,7 @@ use anyhow::Error;
 use clap::Parser;
 use console::style;
 
+use crate::deprecations::{warn_deprecated, Deprecation};
 use crate::pyproject::{get_current_venv_python_version, PyProject};
 
 /// Prints the current state of the project.
@@ -20,7 +21,7 @@ pub struct Args {
 
 pub fn execute(cmd: Args) -> Result<(), Error> {
     if cmd.installed_deps {
-        warn!("--installed-deps is deprecated, use `rye list`");
+        warn_deprecated(Deprecation::ShowInstalledDeps);
         return crate::cli::list::execute(crate::cli::list::Args {
             pyproject: cmd.pyproject,
         });
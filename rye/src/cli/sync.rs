@@ -4,8 +4,8 @@ use anyhow::Error;
 use clap::Parser;
 
 use crate::lock::{KeyringProvider, LockOptions};
-use crate::sync::{sync, SyncMode, SyncOptions};
-use crate::utils::CommandOutput;
+use crate::sync::{check_sync, sync, SyncMode, SyncOptions};
+use crate::utils::{override_network_timeout, CommandOutput, QuietExit};
 
 /// Updates the virtualenv based on the pyproject.toml
 #[derive(Parser, Debug)]
@@ -13,12 +13,37 @@ pub struct Args {
     /// Force the environment to be re-created
     #[arg(short, long)]
     force: bool,
+    /// Replace a virtualenv that was not created by rye without asking.
+    #[arg(long)]
+    force_replace: bool,
     /// Do not include dev dependencies.
     #[arg(long)]
     no_dev: bool,
+    /// Install this dependency group instead of the dev dependencies.
+    #[arg(long, conflicts_with = "no_dev")]
+    group: Option<String>,
+    /// Install this dependency variant instead of the detected one.
+    #[arg(long, value_name = "VARIANT")]
+    variant: Option<String>,
     /// Do not update the lockfile.
     #[arg(long)]
     no_lock: bool,
+    /// Only install the dependencies, not the project itself.
+    #[arg(long)]
+    no_install_project: bool,
+    /// Do not write a `.python-version` derived from `requires-python` if the
+    /// project has none.
+    #[arg(long)]
+    no_write_pin: bool,
+    /// Byte-compile all installed packages after installation.
+    #[arg(long, overrides_with = "no_compile_bytecode")]
+    compile_bytecode: bool,
+    /// Do not write any bytecode during installation.
+    #[arg(long, overrides_with = "compile_bytecode")]
+    no_compile_bytecode: bool,
+    /// Overall network timeout in seconds.
+    #[arg(long, value_name = "SECONDS")]
+    timeout: Option<u64>,
     /// Enables verbose diagnostics.
     #[arg(short, long)]
     verbose: bool,
@@ -55,11 +80,23 @@ pub struct Args {
     /// Set to true to lock with hashes in the lockfile.
     #[arg(long)]
     generate_hashes: bool,
+    /// Allow this private package to be resolved from the public index.
+    #[arg(long, value_name = "NAME")]
+    allow_shadowing: Vec<String>,
+    /// Write a JSON report of what was installed, upgraded and removed.
+    #[arg(long, value_name = "PATH")]
+    report: Option<PathBuf>,
+    /// Only check that the virtualenv matches the lockfile, without changing anything.
+    ///
+    /// Exits with status 1 and prints the differences if it does not.
+    #[arg(long, conflicts_with_all = ["force", "force_replace", "update", "update_all", "report"])]
+    check: bool,
 }
 
 pub fn execute(cmd: Args) -> Result<(), Error> {
     let output = CommandOutput::from_quiet_and_verbose(cmd.quiet, cmd.verbose);
-    sync(SyncOptions {
+    override_network_timeout(cmd.timeout);
+    let options = SyncOptions {
         output,
         dev: !cmd.no_dev,
         mode: if cmd.force {
@@ -68,6 +105,7 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
             SyncMode::Regular
         },
         force: cmd.force,
+        force_replace: cmd.force_replace,
         no_lock: cmd.no_lock,
         lock_options: LockOptions {
             update: cmd.update,
@@ -78,9 +116,31 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
             with_sources: cmd.with_sources,
             reset: cmd.reset,
             generate_hashes: cmd.generate_hashes,
+            allow_shadowing: cmd.allow_shadowing,
+            variant: cmd.variant,
+            ..LockOptions::default()
         },
         keyring_provider: cmd.keyring_provider,
         pyproject: cmd.pyproject,
-    })?;
+        group: cmd.group,
+        compile_bytecode: if cmd.compile_bytecode {
+            Some(true)
+        } else if cmd.no_compile_bytecode {
+            Some(false)
+        } else {
+            None
+        },
+        report: cmd.report,
+        offline_wheels: None,
+        no_install_project: cmd.no_install_project,
+        write_pin: !cmd.no_write_pin,
+    };
+    if cmd.check {
+        if !check_sync(&options)? {
+            return Err(QuietExit(1).into());
+        }
+        return Ok(());
+    }
+    sync(options)?;
     Ok(())
 }
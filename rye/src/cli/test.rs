@@ -1,19 +1,24 @@
+use std::collections::{BTreeMap, BTreeSet};
 use std::env::consts::EXE_EXTENSION;
 use std::ffi::OsString;
-use std::path::PathBuf;
-use std::process::Command;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
 
-use anyhow::{bail, Error};
+use anyhow::{bail, Context, Error};
 use clap::Parser;
 use console::style;
 use same_file::is_same_file;
+use tempfile::tempdir;
 
+use crate::bootstrap::{ensure_self_venv_with_coverage, get_site_packages_dir};
 use crate::config::Config;
 use crate::consts::VENV_BIN;
 use crate::lock::KeyringProvider;
 use crate::pyproject::{locate_projects, normalize_package_name, DependencyKind, PyProject};
-use crate::sync::autosync;
-use crate::utils::{CommandOutput, QuietExit};
+use crate::sync::{autosync, check_venv_drift, find_installed_dists, sync_test_env};
+use crate::utils::{
+    append_python_path, get_venv_python_bin, internal_command, CommandOutput, QuietExit, Verbosity,
+};
 
 /// Run the tests on the project.
 ///
@@ -26,6 +31,9 @@ pub struct Args {
     /// Perform the operation on a specific package
     #[arg(short, long)]
     package: Vec<String>,
+    /// Only test the members affected by the changes in a git range.
+    #[arg(long, value_name = "GIT_RANGE", conflicts_with_all = ["all", "package"])]
+    changed: Option<String>,
     /// Use this pyproject.toml file
     #[arg(long, value_name = "PYPROJECT_TOML")]
     pyproject: Option<PathBuf>,
@@ -35,6 +43,12 @@ pub struct Args {
     // Disable test output capture to stdout
     #[arg(long = "no-capture", short = 's')]
     no_capture: bool,
+    /// Also run the tests in all environments of `tool.rye.test.envs`.
+    #[arg(long)]
+    all_envs: bool,
+    /// Collect coverage data and print a combined report.
+    #[arg(long)]
+    coverage: bool,
     /// Enables verbose diagnostics.
     #[arg(short, long)]
     verbose: bool,
@@ -71,7 +85,22 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
         .join("pytest")
         .with_extension(EXE_EXTENSION);
 
-    let projects = locate_projects(project, cmd.all, &cmd.package[..])?;
+    let test_envs = project.test_envs();
+    if cmd.all_envs && test_envs.is_empty() {
+        bail!("no test environments configured, add them to tool.rye.test.envs");
+    }
+    let workspace_root = project.workspace_path().to_path_buf();
+    let projects = match cmd.changed {
+        Some(ref range) => {
+            let projects = find_changed_projects(project, range, output)?;
+            if projects.is_empty() {
+                echo!(if output, "No projects are affected by the changes in {}", range);
+                return Ok(());
+            }
+            projects
+        }
+        None => locate_projects(project, cmd.all, &cmd.package[..])?,
+    };
 
     if !pytest.is_file() {
         let has_pytest = has_pytest_dependency(&projects)?;
@@ -84,52 +113,114 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
         } else {
             bail!("pytest not installed. Run `rye add --dev pytest`");
         }
+    } else {
+        check_venv_drift(&projects[0])?;
     }
 
-    for (idx, project) in projects.iter().enumerate() {
-        if output != CommandOutput::Quiet {
-            if idx > 0 {
-                echo!();
-            }
-            echo!(
-                "Running tests for {} ({})",
-                style(project.name().unwrap_or("<unknown>")).cyan(),
-                style(project.root_path().display()).dim()
-            );
+    // the virtualenv of the project always comes first
+    let mut envs = vec![(None, projects[0].venv_path().to_path_buf())];
+    if cmd.all_envs {
+        for env in &test_envs {
+            let venv = sync_test_env(&projects[0], env, output)?;
+            envs.push((Some(env.as_str()), venv));
         }
+    }
 
-        let mut pytest_cmd = Command::new(&pytest);
-        if cmd.no_capture {
-            pytest_cmd.arg("--capture=no");
+    // coverage data of every run is collected separately and combined at the end
+    let coverage_dir = if cmd.coverage { Some(tempdir()?) } else { None };
+    let mut coverage_files = Vec::new();
+
+    let mut idx = 0;
+    for (env, venv) in &envs {
+        let python = get_venv_python_bin(venv);
+        let pytest = venv
+            .join(VENV_BIN)
+            .join("pytest")
+            .with_extension(EXE_EXTENSION);
+        if !pytest.is_file() {
+            bail!("pytest not installed in {}", venv.display());
+        }
+        if cmd.coverage && !has_coverage(venv)? {
+            bail!(
+                "coverage not installed in {}. Run `rye add --dev coverage`",
+                venv.display()
+            );
         }
-        match output {
-            CommandOutput::Normal => {}
-            CommandOutput::Verbose => {
-                pytest_cmd.arg("-v");
+
+        for project in &projects {
+            if !output.is_quiet() {
+                if idx > 0 {
+                    echo!();
+                }
+                match env {
+                    Some(env) => echo!(
+                        "Running tests for {} in {} ({})",
+                        style(project.name().unwrap_or("<unknown>")).cyan(),
+                        style(env).cyan(),
+                        style(project.root_path().display()).dim()
+                    ),
+                    None => echo!(
+                        "Running tests for {} ({})",
+                        style(project.name().unwrap_or("<unknown>")).cyan(),
+                        style(project.root_path().display()).dim()
+                    ),
+                }
             }
-            CommandOutput::Quiet => {
-                pytest_cmd.arg("-q");
+
+            let mut pytest_cmd = match coverage_dir {
+                Some(ref coverage_dir) => {
+                    let coverage_file = coverage_dir.path().join(format!(".coverage.{}", idx));
+                    let mut pytest_cmd = Command::new(&python);
+                    pytest_cmd
+                        .env("COVERAGE_FILE", &coverage_file)
+                        .arg("-m")
+                        .arg("coverage")
+                        .arg("run")
+                        .arg("-m")
+                        .arg("pytest");
+                    coverage_files.push(coverage_file);
+                    pytest_cmd
+                }
+                None => Command::new(&pytest),
+            };
+            idx += 1;
+            if cmd.no_capture {
+                pytest_cmd.arg("--capture=no");
             }
-        }
-        pytest_cmd.args(&cmd.extra_args);
-        pytest_cmd
-            .arg("--rootdir")
-            .arg(project.root_path().as_os_str())
-            .current_dir(project.root_path());
-
-        // always ignore projects that are nested but not selected.
-        for path in &project_roots {
-            if !is_same_file(path, project.root_path()).unwrap_or(false) {
-                pytest_cmd.arg("--ignore").arg(path.as_os_str());
+            match output.child() {
+                Verbosity::Normal => {}
+                Verbosity::Verbose => {
+                    pytest_cmd.arg("-v");
+                }
+                Verbosity::Quiet => {
+                    pytest_cmd.arg("-q");
+                }
             }
-        }
+            pytest_cmd.args(&cmd.extra_args);
+            pytest_cmd
+                .arg("--rootdir")
+                .arg(project.root_path().as_os_str())
+                .current_dir(project.root_path());
+            append_python_path(&mut pytest_cmd, &project.extra_python_paths())?;
 
-        let status = pytest_cmd.status()?;
-        if !status.success() {
-            failed_with = Some(status.code().unwrap_or(1));
+            // always ignore projects that are nested but not selected.
+            for path in &project_roots {
+                if !is_same_file(path, project.root_path()).unwrap_or(false) {
+                    pytest_cmd.arg("--ignore").arg(path.as_os_str());
+                }
+            }
+
+            let status = pytest_cmd.status()?;
+            if !status.success() {
+                failed_with = Some(status.code().unwrap_or(1));
+            }
         }
     }
 
+    if cmd.coverage {
+        report_coverage(&workspace_root, &coverage_files, output)?;
+    }
+
     if let Some(code) = failed_with {
         Err(Error::new(QuietExit(code)))
     } else {
@@ -137,6 +228,188 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
     }
 }
 
+/// Returns the projects affected by the changes in a git range.
+///
+/// Changed files belong to the innermost project that contains them.  The
+/// projects depending on an affected project, directly or through other
+/// members, are affected too.  Changes to workspace wide files such as the
+/// lockfiles affect all projects.
+fn find_changed_projects(
+    project: PyProject,
+    range: &str,
+    output: CommandOutput,
+) -> Result<Vec<PyProject>, Error> {
+    let root = project.workspace_path().to_path_buf();
+    let members = match project.workspace() {
+        Some(workspace) => workspace.iter_projects().collect::<Result<Vec<_>, _>>()?,
+        None => vec![project],
+    };
+
+    let rv = Command::new("git")
+        .args(["diff", "--name-only", "--relative"])
+        .arg(range)
+        .arg("--")
+        .current_dir(&root)
+        .output()
+        .context("failed to run git")?;
+    if !rv.status.success() {
+        bail!(
+            "failed to find the files changed in {}: {}",
+            range,
+            String::from_utf8_lossy(&rv.stderr).trim()
+        );
+    }
+    let stdout = String::from_utf8_lossy(&rv.stdout);
+    let files = stdout.lines().filter(|x| !x.is_empty()).collect::<Vec<_>>();
+    for file in &files {
+        echo!(if verbose output, "changed: {}", file);
+    }
+
+    let mut roots = Vec::new();
+    let mut deps = BTreeMap::new();
+    for member in &members {
+        let name = member.normalized_name()?;
+        let member_root = member.root_path();
+        roots.push((
+            name.clone(),
+            member_root
+                .strip_prefix(&root)
+                .unwrap_or(&member_root)
+                .to_path_buf(),
+        ));
+        deps.insert(
+            name,
+            member
+                .iter_dependencies(DependencyKind::Normal)
+                .chain(member.iter_dependencies(DependencyKind::Dev))
+                .filter_map(|dep| dep.expand(|name| std::env::var(name).ok()).ok())
+                .map(|req| normalize_package_name(&req.name))
+                .collect::<BTreeSet<_>>(),
+        );
+    }
+
+    let affected = match map_changed_files(&roots, &files) {
+        Some(changed) => find_affected(&deps, changed),
+        None => {
+            echo!(if verbose output, "workspace wide files changed, testing all projects");
+            deps.keys().cloned().collect()
+        }
+    };
+    let mut projects = Vec::new();
+    for member in members {
+        if affected.contains(&member.normalized_name()?) {
+            projects.push(member);
+        }
+    }
+    projects.sort_by(|a, b| a.name().cmp(&b.name()));
+    Ok(projects)
+}
+
+/// Is the file at the workspace root an input of all projects?
+fn is_workspace_file(path: &Path) -> bool {
+    let Some(name) = path.to_str() else {
+        return false;
+    };
+    name == "pyproject.toml"
+        || name == ".python-version"
+        || (name.starts_with("requirements") && name.ends_with(".lock"))
+}
+
+/// Maps the changed files to the projects they belong to.
+///
+/// `roots` are the names of the projects with their folders relative to the
+/// workspace root.  Returns `None` if a workspace wide file changed.
+fn map_changed_files(roots: &[(String, PathBuf)], files: &[&str]) -> Option<BTreeSet<String>> {
+    let mut rv = BTreeSet::new();
+    for file in files {
+        let path = Path::new(file);
+        if is_workspace_file(path) {
+            return None;
+        }
+        if let Some((name, _)) = roots
+            .iter()
+            .filter(|(_, root)| path.starts_with(root))
+            .max_by_key(|(_, root)| root.components().count())
+        {
+            rv.insert(name.clone());
+        }
+    }
+    Some(rv)
+}
+
+/// Adds the projects that depend on the changed ones, also transitively.
+fn find_affected(
+    deps: &BTreeMap<String, BTreeSet<String>>,
+    changed: BTreeSet<String>,
+) -> BTreeSet<String> {
+    let mut pending = changed.iter().cloned().collect::<Vec<_>>();
+    let mut rv = changed;
+    while let Some(name) = pending.pop() {
+        for (project, project_deps) in deps {
+            if project_deps.contains(&name) && rv.insert(project.clone()) {
+                pending.push(project.clone());
+            }
+        }
+    }
+    rv
+}
+
+/// Is coverage installed into the virtualenv?
+fn has_coverage(venv: &Path) -> Result<bool, Error> {
+    Ok(find_installed_dists(&get_site_packages_dir(venv)?)?
+        .iter()
+        .any(|x| x.name == "coverage"))
+}
+
+/// Combines the coverage data of all runs into `.coverage` in the workspace
+/// root and prints a report.
+///
+/// This uses the coverage installed into rye internals, so that the report
+/// does not depend on any of the environments.
+fn report_coverage(root: &Path, files: &[PathBuf], output: CommandOutput) -> Result<(), Error> {
+    let files = files.iter().filter(|x| x.is_file()).collect::<Vec<_>>();
+    if files.is_empty() {
+        warn!("no coverage data was collected");
+        return Ok(());
+    }
+    let self_venv = ensure_self_venv_with_coverage(output)?;
+    let coverage = self_venv
+        .join(VENV_BIN)
+        .join("coverage")
+        .with_extension(EXE_EXTENSION);
+    let data_file = root.join(".coverage");
+
+    let mut combine = internal_command(&coverage);
+    combine
+        .arg("combine")
+        .arg("--quiet")
+        .arg("--data-file")
+        .arg(&data_file)
+        .args(&files)
+        .current_dir(root);
+    if !combine.status()?.success() {
+        bail!("failed to combine coverage data");
+    }
+
+    if !output.is_quiet() {
+        echo!();
+        echo!("Combined coverage ({})", style(data_file.display()).dim());
+    }
+    let mut report = internal_command(&coverage);
+    report
+        .arg("report")
+        .arg("--data-file")
+        .arg(&data_file)
+        .current_dir(root);
+    if output.child() == Verbosity::Quiet {
+        report.stdout(Stdio::null());
+    }
+    if !report.status()?.success() {
+        bail!("failed to report coverage");
+    }
+    Ok(())
+}
+
 /// Does any of those projects have a pytest dependency?
 fn has_pytest_dependency(projects: &[PyProject]) -> Result<bool, Error> {
     for project in projects {
@@ -153,3 +426,59 @@ fn has_pytest_dependency(projects: &[PyProject]) -> Result<bool, Error> {
     }
     Ok(false)
 }
+
+#[cfg(test)]
+mod test {
+    use std::collections::{BTreeMap, BTreeSet};
+    use std::path::PathBuf;
+
+    use super::{find_affected, map_changed_files};
+
+    fn set(names: &[&str]) -> BTreeSet<String> {
+        names.iter().map(|x| x.to_string()).collect()
+    }
+
+    #[test]
+    fn test_map_changed_files() {
+        let roots = vec![
+            ("root".to_string(), PathBuf::new()),
+            ("core".to_string(), PathBuf::from("packages/core")),
+            ("core-ext".to_string(), PathBuf::from("packages/core-ext")),
+        ];
+        assert_eq!(
+            map_changed_files(
+                &roots,
+                &["packages/core-ext/src/ext.py", "packages/core/README.md"]
+            ),
+            Some(set(&["core", "core-ext"]))
+        );
+        assert_eq!(
+            map_changed_files(&roots, &["src/root/__init__.py"]),
+            Some(set(&["root"]))
+        );
+        assert_eq!(
+            map_changed_files(&roots, &["requirements-dev.lock", "packages/core/x.py"]),
+            None
+        );
+        assert_eq!(
+            map_changed_files(&roots, &["packages/core/pyproject.toml"]),
+            Some(set(&["core"]))
+        );
+    }
+
+    #[test]
+    fn test_find_affected() {
+        let deps = BTreeMap::from([
+            ("api".to_string(), set(&["core", "flask"])),
+            ("cli".to_string(), set(&["api"])),
+            ("core".to_string(), set(&[])),
+            ("docs".to_string(), set(&["sphinx"])),
+        ]);
+        assert_eq!(
+            find_affected(&deps, set(&["core"])),
+            set(&["api", "cli", "core"])
+        );
+        assert_eq!(find_affected(&deps, set(&["cli"])), set(&["cli"]));
+        assert_eq!(find_affected(&deps, set(&[])), set(&[]));
+    }
+}
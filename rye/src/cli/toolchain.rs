@@ -3,21 +3,37 @@ use std::collections::HashMap;
 use std::env::consts::{ARCH, OS};
 use std::fs;
 use std::path::{Path, PathBuf};
-use std::process::Command;
 
 use anyhow::{anyhow, bail, Context, Error};
 use clap::Parser;
 use clap::ValueEnum;
 use console::style;
+use indicatif::HumanBytes;
 use serde::Deserialize;
 use serde::Serialize;
+use toml_edit::DocumentMut;
+use walkdir::WalkDir;
 
+use crate::bootstrap::{download_toolchain, fetch, install_from_archive, FetchOptions};
+use crate::config::Config;
+use crate::envs::list_envs;
 use crate::installer::list_installed_tools;
 use crate::piptools::get_pip_tools_venv_path;
-use crate::platform::{get_app_dir, get_canonical_py_path, list_known_toolchains};
-use crate::pyproject::read_venv_marker;
-use crate::sources::py::{iter_downloadable, PythonVersion};
-use crate::utils::{symlink_file, IoPathContext};
+use crate::platform::{
+    get_app_dir, get_canonical_py_path, get_toolchain_python_bin, list_known_toolchains,
+    list_toolchain_users,
+};
+use crate::pyproject::{get_current_venv_python_version, read_venv_marker, PyProject};
+use crate::pyversion::get_python_version_request_from_pyenv_pin;
+use crate::shims::refresh_versioned_shims;
+use crate::sources::py::{
+    get_download_url, iter_downloadable, matches_version, PythonVersion, PythonVersionRequest,
+};
+use crate::utils::atomic::write_atomic;
+use crate::utils::{
+    check_checksum, internal_command, symlink_file, tui_theme, CommandOutput, IoPathContext,
+    QuietExit,
+};
 
 const INSPECT_SCRIPT: &str = r#"
 import json
@@ -58,6 +74,82 @@ pub struct RegisterCommand {
     name: Option<String>,
 }
 
+/// Installs a toolchain from a local archive.
+///
+/// The archive is checked and unpacked like a download, which makes this
+/// useful for machines without network access.
+#[derive(Parser, Debug)]
+pub struct InstallCommand {
+    /// Name and version of the toolchain (eg: `cpython@3.11.8`).
+    version: String,
+    /// The archive to install from (.tar.zst, .tar.gz, .tar.bz2 or .zip).
+    #[arg(long)]
+    archive: PathBuf,
+    /// The expected sha256 checksum of the archive.
+    ///
+    /// Defaults to the builtin checksum if the archive is an official download.
+    #[arg(long)]
+    sha256: Option<String>,
+    /// Replace the toolchain if it is already installed.
+    #[arg(short, long)]
+    force: bool,
+    /// Enables verbose diagnostics.
+    #[arg(short, long)]
+    verbose: bool,
+    /// Turns off all output.
+    #[arg(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+}
+
+/// Downloads toolchain archives into a folder for use as a toolchain mirror.
+///
+/// The folder can be configured as `python-downloads.toolchain-mirror` on
+/// machines without network access or be served over HTTPS.  Archives that
+/// are already in the folder with a matching checksum are skipped.
+#[derive(Parser, Debug)]
+pub struct DownloadAllCommand {
+    /// Only download the toolchains matching these versions (eg: `cpython@3.12`).
+    #[arg(value_name = "VERSION")]
+    versions: Vec<String>,
+    /// The folder to download the archives into.
+    #[arg(long)]
+    target: PathBuf,
+    /// The operating system to download for (defaults to this machine).
+    #[arg(long)]
+    os: Option<String>,
+    /// The architecture to download for (defaults to this machine).
+    #[arg(long)]
+    arch: Option<String>,
+    /// Enables verbose diagnostics.
+    #[arg(short, long)]
+    verbose: bool,
+    /// Turns off all output.
+    #[arg(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+}
+
+/// Removes downloaded toolchains that are no longer used.
+///
+/// A toolchain is kept if rye itself, a tool or a named virtualenv uses it,
+/// or if a project that was synced with rye uses it through its virtualenv or
+/// its `.python-version` pin.  Registered toolchains are never removed.  The
+/// disk usage of every toolchain is reported.
+#[derive(Parser, Debug)]
+pub struct GcCommand {
+    /// Only report what would be removed.
+    #[arg(long)]
+    dry_run: bool,
+    /// Always keep the N newest toolchains.
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    keep_latest: usize,
+    /// Enables verbose diagnostics.
+    #[arg(short, long)]
+    verbose: bool,
+    /// Turns off all output.
+    #[arg(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+}
+
 /// Removes a toolchain.
 #[derive(Parser, Debug)]
 pub struct RemoveCommand {
@@ -79,6 +171,41 @@ pub struct ListCommand {
     format: Option<Format>,
 }
 
+/// Installs the toolchains listed in a manifest file.
+///
+/// The manifest is a TOML file with a `toolchains` list of toolchain versions
+/// (eg: `cpython@3.12`).  Missing toolchains are fetched and installed ones
+/// that are not listed are reported or, with `--remove-extra`, removed.
+#[derive(Parser, Debug)]
+pub struct SyncCommand {
+    /// The manifest file.
+    #[arg(default_value = "toolchains.toml")]
+    manifest: PathBuf,
+    /// Remove installed toolchains that are not in the manifest.
+    #[arg(long)]
+    remove_extra: bool,
+    /// Only report the drift and exit with 1 if there is any.
+    #[arg(long)]
+    check: bool,
+    /// Enables verbose diagnostics.
+    #[arg(short, long)]
+    verbose: bool,
+    /// Turns off all output.
+    #[arg(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+}
+
+/// Prints the Python interpreter a project would use.
+///
+/// This also explains how the version was picked from `.python-version`,
+/// `requires-python` or the `default.toolchain` config.
+#[derive(Parser, Debug)]
+pub struct WhichCommand {
+    /// The project to report on (defaults to the current project).
+    #[arg(long, value_name = "PATH")]
+    project: Option<PathBuf>,
+}
+
 #[derive(ValueEnum, Copy, Clone, Serialize, Debug, PartialEq)]
 #[value(rename_all = "snake_case")]
 #[serde(rename_all = "snake_case")]
@@ -88,24 +215,56 @@ enum Format {
 
 #[derive(Parser, Debug)]
 enum SubCommand {
+    DownloadAll(DownloadAllCommand),
     Fetch(crate::cli::fetch::Args),
+    Gc(GcCommand),
+    Install(InstallCommand),
     List(ListCommand),
     Register(RegisterCommand),
     Remove(RemoveCommand),
+    Sync(SyncCommand),
+    Which(WhichCommand),
 }
 
 pub fn execute(cmd: Args) -> Result<(), Error> {
     match cmd.command {
         SubCommand::Register(args) => register(args),
+        SubCommand::DownloadAll(args) => download_all(args),
         SubCommand::Fetch(args) => crate::cli::fetch::execute(args),
+        SubCommand::Gc(args) => gc(args),
+        SubCommand::Install(args) => install(args),
         SubCommand::List(args) => list(args),
         SubCommand::Remove(args) => remove(args),
+        SubCommand::Sync(args) => sync(args),
+        SubCommand::Which(args) => which(args),
     }
 }
 
 fn register(cmd: RegisterCommand) -> Result<(), Error> {
     let target_version = register_toolchain(&cmd.path, cmd.name.as_deref(), |_| Ok(()))?;
     echo!("Registered {} as {}", cmd.path.display(), target_version);
+    refresh_versioned_shims()?;
+    Ok(())
+}
+
+fn install(cmd: InstallCommand) -> Result<(), Error> {
+    let output = CommandOutput::from_quiet_and_verbose(cmd.quiet, cmd.verbose);
+    let version: PythonVersion = cmd.version.parse().with_context(|| {
+        format!(
+            "'{}' is not a complete toolchain version (eg: cpython@3.11.8)",
+            cmd.version
+        )
+    })?;
+    install_from_archive(
+        &version,
+        &cmd.archive,
+        cmd.sha256.as_deref(),
+        FetchOptions {
+            output,
+            force: cmd.force,
+            ..FetchOptions::default()
+        },
+    )?;
     Ok(())
 }
 
@@ -127,7 +286,13 @@ fn check_in_use(ver: &PythonVersion) -> Result<(), Error> {
     for (tool, info) in &installed_tools {
         if let Some(ref venv_marker) = info.venv_marker {
             if &venv_marker.python == ver {
-                bail!("toolchain {} is still in use by tool {}", ver, tool);
+                bail!(
+                    "toolchain {} is still in use by tool {}. Move it to another toolchain \
+                     with `rye tools reinstall --python <VERSION> {}`",
+                    ver,
+                    tool,
+                    tool
+                );
             }
         }
     }
@@ -152,6 +317,335 @@ pub fn remove(cmd: RemoveCommand) -> Result<(), Error> {
     } else {
         echo!("Toolchain is not installed");
     }
+    refresh_versioned_shims()?;
+    Ok(())
+}
+
+/// Reads the toolchain versions from a manifest file.
+fn read_manifest(path: &Path) -> Result<Vec<PythonVersionRequest>, Error> {
+    let contents =
+        fs::read_to_string(path).path_context(path, "unable to read toolchain manifest")?;
+    let doc = contents
+        .parse::<DocumentMut>()
+        .with_context(|| format!("invalid toolchain manifest {}", path.display()))?;
+    let Some(toolchains) = doc.get("toolchains").and_then(|x| x.as_array()) else {
+        bail!(
+            "toolchain manifest {} has no `toolchains` list",
+            path.display()
+        );
+    };
+    toolchains
+        .iter()
+        .map(|item| {
+            let version = item
+                .as_str()
+                .ok_or_else(|| anyhow!("invalid toolchain {} in {}", item, path.display()))?;
+            version
+                .parse::<PythonVersionRequest>()
+                .with_context(|| format!("invalid toolchain '{}' in {}", version, path.display()))
+        })
+        .collect()
+}
+
+fn sync(cmd: SyncCommand) -> Result<(), Error> {
+    let output = CommandOutput::from_quiet_and_verbose(cmd.quiet, cmd.verbose);
+    let requests = read_manifest(&cmd.manifest)?;
+    let installed = list_known_toolchains()?
+        .into_iter()
+        .map(|(version, _)| version)
+        .collect::<Vec<_>>();
+    let missing = requests
+        .iter()
+        .filter(|req| !installed.iter().any(|ver| matches_version(req, ver)))
+        .collect::<Vec<_>>();
+    let extra = installed
+        .iter()
+        .filter(|ver| !requests.iter().any(|req| matches_version(req, ver)))
+        .collect::<Vec<_>>();
+
+    if cmd.check {
+        for req in &missing {
+            echo!(if output, "{} {}", style("missing:").red(), req);
+        }
+        for ver in &extra {
+            echo!(if output, "{} {}", style("extra:").yellow(), ver);
+        }
+        if !missing.is_empty() || (cmd.remove_extra && !extra.is_empty()) {
+            return Err(QuietExit(1).into());
+        }
+        echo!(if output, "Toolchains are in sync with {}", cmd.manifest.display());
+        return Ok(());
+    }
+
+    for req in &missing {
+        fetch(
+            req,
+            FetchOptions {
+                output,
+                ..FetchOptions::default()
+            },
+        )?;
+    }
+    let mut removed = 0;
+    for ver in &extra {
+        if !cmd.remove_extra {
+            echo!(if output, "{} {} is not in the manifest", style("extra:").yellow(), ver);
+        } else if let Err(err) = check_in_use(ver) {
+            warn!("not removing {}: {}", ver, err);
+        } else {
+            remove(RemoveCommand {
+                version: ver.to_string(),
+                force: true,
+            })?;
+            removed += 1;
+        }
+    }
+    echo!(
+        if output,
+        "Toolchains in sync with {} ({} installed, {} removed)",
+        cmd.manifest.display(),
+        missing.len(),
+        removed
+    );
+    Ok(())
+}
+
+fn download_all(cmd: DownloadAllCommand) -> Result<(), Error> {
+    let output = CommandOutput::from_quiet_and_verbose(cmd.quiet, cmd.verbose);
+    let os = cmd.os.as_deref().unwrap_or(OS);
+    let arch = cmd.arch.as_deref().unwrap_or(ARCH);
+    let mut requests = Vec::new();
+    for version in &cmd.versions {
+        let mut req: PythonVersionRequest = version.parse()?;
+        req.os.get_or_insert_with(|| os.to_string().into());
+        req.arch.get_or_insert_with(|| arch.to_string().into());
+        requests.push(req);
+    }
+    let versions = iter_downloadable(os, arch)
+        .filter(|ver| requests.is_empty() || requests.iter().any(|req| matches_version(req, ver)))
+        .collect::<Vec<_>>();
+    if versions.is_empty() {
+        bail!("no downloadable toolchains found for {}-{}", os, arch);
+    }
+
+    fs::create_dir_all(&cmd.target).path_context(&cmd.target, "unable to create target folder")?;
+    let mut downloaded = 0;
+    for version in &versions {
+        let Some((_, url, sha256)) = get_download_url(&version.clone().into()) else {
+            continue;
+        };
+        let filename = url.rsplit('/').next().unwrap_or(url).replace("%2B", "+");
+        let path = cmd.target.join(&filename);
+        if let (Ok(contents), Some(sha256)) = (fs::read(&path), sha256) {
+            if check_checksum(&contents, sha256).is_ok() {
+                echo!(if verbose output, "{} is up to date", filename);
+                continue;
+            }
+        }
+
+        echo!(if output, "{} {}", style("Downloading").cyan(), version);
+        let contents = download_toolchain(url, output)
+            .with_context(|| format!("failed to download {}", version))?;
+        match sha256 {
+            Some(sha256) => check_checksum(&contents, sha256)
+                .with_context(|| format!("checksum of {} failed", version))?,
+            None => warn!(
+                "no checksum known for {}, its contents are not verified",
+                version
+            ),
+        }
+        write_atomic(&path, &contents).path_context(&path, "unable to write archive")?;
+        downloaded += 1;
+    }
+    echo!(
+        if output,
+        "Toolchain mirror {} is up to date ({} of {} archives downloaded)",
+        cmd.target.display(),
+        downloaded,
+        versions.len()
+    );
+    Ok(())
+}
+
+/// Returns what uses each toolchain.
+fn find_toolchain_users(
+    installed: &[PythonVersion],
+) -> Result<HashMap<PythonVersion, Vec<String>>, Error> {
+    let mut users = HashMap::<PythonVersion, Vec<String>>::new();
+    let mut add = |ver: &PythonVersion, user: String| {
+        users.entry(ver.clone()).or_default().push(user);
+    };
+
+    if let Some(marker) = read_venv_marker(&get_app_dir().join("self")) {
+        add(&marker.python, "rye itself".into());
+    }
+    for (tool, info) in list_installed_tools()? {
+        if let Some(marker) = info.venv_marker {
+            add(&marker.python, format!("tool {}", tool));
+        }
+    }
+    for env in list_envs()? {
+        if let Some(marker) = env.venv_marker {
+            add(&marker.python, format!("virtualenv {}", env.name));
+        }
+    }
+
+    // the default toolchain is what the global python shim and new projects use
+    if let Ok(req) = Config::current().default_toolchain() {
+        if let Some(ver) = installed.iter().filter(|x| matches_version(&req, x)).max() {
+            add(ver, "the default toolchain".into());
+        }
+    }
+
+    for (root, py_path) in list_toolchain_users() {
+        let user = format!("project {}", root.display());
+        let mut used = Vec::new();
+        // the toolchain of the last sync, the virtualenv, and the newest
+        // installed toolchain matching the pin are all in use.
+        for ver in installed {
+            if get_canonical_py_path(ver)? == py_path {
+                used.push(ver.clone());
+            }
+        }
+        for venv in find_project_venvs(&root) {
+            if let Some(marker) = read_venv_marker(&venv) {
+                used.push(marker.python);
+            }
+        }
+        if let Some(req) = get_python_version_request_from_pyenv_pin(&root) {
+            if let Some(ver) = installed.iter().filter(|x| matches_version(&req, x)).max() {
+                used.push(ver.clone());
+            }
+        }
+        used.sort();
+        used.dedup();
+        for ver in &used {
+            add(ver, user.clone());
+        }
+    }
+
+    Ok(users)
+}
+
+/// Returns the virtualenvs of a project.
+///
+/// This is the virtualenv of the project and everything next to it that
+/// looks like one, such as the `.venv-<env>` test environments.
+fn find_project_venvs(root: &Path) -> Vec<PathBuf> {
+    let mut rv = Vec::new();
+    if let Ok(pyproject) = PyProject::load(&root.join("pyproject.toml")) {
+        rv.push(pyproject.venv_path().into_owned());
+    }
+    if let Ok(entries) = fs::read_dir(root) {
+        for entry in entries.filter_map(|x| x.ok()) {
+            if entry.file_name().to_string_lossy().starts_with(".venv") {
+                rv.push(entry.path());
+            }
+        }
+    }
+    rv.sort();
+    rv.dedup();
+    rv
+}
+
+fn gc(cmd: GcCommand) -> Result<(), Error> {
+    let output = CommandOutput::from_quiet_and_verbose(cmd.quiet, cmd.verbose);
+    let mut installed = Vec::new();
+    for (ver, _) in list_known_toolchains()? {
+        // registered toolchains are links to interpreters elsewhere
+        let path = get_canonical_py_path(&ver)?;
+        if path.is_dir() {
+            installed.push((ver, path));
+        }
+    }
+    installed.sort_by(|a, b| b.0.cmp(&a.0));
+
+    // projects only record their toolchain when they are synced, so before
+    // the first sync after an upgrade every project looks unused.
+    if !cmd.dry_run && list_toolchain_users().is_empty() {
+        if !console::user_attended_stderr() {
+            bail!(
+                "no project recorded its toolchain yet; run `rye sync` in your projects \
+                 first or check with --dry-run what would be removed"
+            );
+        }
+        if !dialoguer::Confirm::with_theme(tui_theme())
+            .with_prompt("No project recorded its toolchain yet, remove all unused toolchains?")
+            .interact()?
+        {
+            return Ok(());
+        }
+    }
+    let users = find_toolchain_users(
+        &installed
+            .iter()
+            .map(|(ver, _)| ver.clone())
+            .collect::<Vec<_>>(),
+    )?;
+
+    let mut removed = 0;
+    let mut freed = 0;
+    for (idx, (ver, path)) in installed.iter().enumerate() {
+        let size = WalkDir::new(path)
+            .into_iter()
+            .filter_map(|x| x.ok())
+            .filter_map(|x| x.metadata().ok())
+            .filter(|x| x.is_file())
+            .map(|x| x.len())
+            .sum::<u64>();
+        if let Some(users) = users.get(ver) {
+            echo!(
+                if output,
+                "{} ({}): used by {}",
+                style(ver).green(),
+                HumanBytes(size),
+                users.join(", ")
+            );
+            continue;
+        }
+        if idx < cmd.keep_latest {
+            echo!(
+                if output,
+                "{} ({}): kept as one of the newest",
+                style(ver).green(),
+                HumanBytes(size)
+            );
+            continue;
+        }
+        if cmd.dry_run {
+            echo!(
+                if output,
+                "{} ({}): unused, would be removed",
+                style(ver).red(),
+                HumanBytes(size)
+            );
+        } else {
+            fs::remove_dir_all(path).path_context(path, "failed to remove toolchain")?;
+            // the pip-tools of the toolchain cannot be used without it
+            let pip_tools = get_pip_tools_venv_path(ver);
+            if pip_tools.is_dir() {
+                fs::remove_dir_all(&pip_tools)
+                    .path_context(&pip_tools, "failed to remove pip-tools")?;
+            }
+            echo!(
+                if output,
+                "{} ({}): unused, removed",
+                style(ver).red(),
+                HumanBytes(size)
+            );
+        }
+        removed += 1;
+        freed += size;
+    }
+
+    if cmd.dry_run {
+        echo!(if output, "Would remove {} toolchains ({})", removed, HumanBytes(freed));
+    } else {
+        if removed > 0 {
+            refresh_versioned_shims()?;
+        }
+        echo!(if output, "Removed {} toolchains ({})", removed, HumanBytes(freed));
+    }
     Ok(())
 }
 
@@ -222,6 +716,54 @@ fn list(cmd: ListCommand) -> Result<(), Error> {
     Ok(())
 }
 
+fn which(cmd: WhichCommand) -> Result<(), Error> {
+    let pyproject_toml = cmd.project.map(|path| {
+        if path.is_dir() {
+            path.join("pyproject.toml")
+        } else {
+            path
+        }
+    });
+    let project = PyProject::load_or_discover(pyproject_toml.as_deref())?;
+    let resolution = project.venv_python_version_resolution()?;
+    let py_bin = get_toolchain_python_bin(&resolution.version)?;
+    let installed = py_bin.is_file();
+
+    echo!("{}", py_bin.display());
+    echo!(
+        "  {} {}",
+        style("project:").dim(),
+        project.toml_path().display()
+    );
+    echo!(
+        "  {} {} ({})",
+        style("requested:").dim(),
+        resolution.request,
+        resolution.source
+    );
+    echo!(
+        "  {} {}{}",
+        style("resolved:").dim(),
+        style(&resolution.version).green(),
+        if installed {
+            ""
+        } else {
+            " (not installed, fetched on next sync)"
+        }
+    );
+    match get_current_venv_python_version(&project.venv_path()) {
+        Some(ver) if ver != resolution.version => echo!(
+            "  {} {} (out of date, run `rye sync` to update)",
+            style("virtualenv:").dim(),
+            style(ver).yellow()
+        ),
+        Some(ver) => echo!("  {} {}", style("virtualenv:").dim(), ver),
+        None => echo!("  {} not created yet", style("virtualenv:").dim()),
+    }
+
+    Ok(())
+}
+
 pub fn register_toolchain<F>(
     path: &Path,
     name: Option<&str>,
@@ -230,7 +772,7 @@ pub fn register_toolchain<F>(
 where
     F: FnOnce(&PythonVersion) -> Result<(), Error>,
 {
-    let output = Command::new(path)
+    let output = internal_command(path)
         .arg("-c")
         .arg(INSPECT_SCRIPT)
         .output()
@@ -1,8 +1,12 @@
-use anyhow::Error;
+use anyhow::{Context, Error};
 use clap::Parser;
 use console::style;
 
-use crate::installer::list_installed_tools;
+use crate::installer::{
+    install_from_lock, is_tool_in_sync, list_installed_tools, list_locked_tools, reinstall,
+};
+use crate::sources::py::PythonVersionRequest;
+use crate::utils::CommandOutput;
 
 /// Helper utility to manage global tools.
 #[derive(Parser, Debug)]
@@ -20,6 +24,49 @@ pub struct ListCommand {
     /// Show the version of tools.
     #[arg(short = 'v', long)]
     include_version: bool,
+    /// Show the Python version used by the tools.
+    #[arg(short = 'p', long)]
+    include_python: bool,
+}
+
+/// Reinstall all tools from their lockfiles.
+///
+/// Tools that already match their lockfile are left alone.
+#[derive(Parser, Debug)]
+pub struct SyncCommand {
+    /// Reinstall tools even if they are up to date.
+    #[arg(short, long)]
+    force: bool,
+    /// Enables verbose diagnostics.
+    #[arg(short, long)]
+    verbose: bool,
+    /// Turns off all output.
+    #[arg(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+}
+
+/// Reinstall tools, optionally with another Python version.
+///
+/// The tools are installed again from the requirement they were installed
+/// with and their dependencies are resolved again.  This is used to move
+/// tools to a new toolchain.
+#[derive(Parser, Debug)]
+pub struct ReinstallCommand {
+    /// The tools to reinstall.
+    #[arg(required_unless_present = "all", conflicts_with = "all")]
+    tools: Vec<String>,
+    /// Reinstall all tools.
+    #[arg(short, long)]
+    all: bool,
+    /// The Python version to use.  The tools are pinned to it.
+    #[arg(short, long)]
+    python: Option<String>,
+    /// Enables verbose diagnostics.
+    #[arg(short, long)]
+    verbose: bool,
+    /// Turns off all output.
+    #[arg(short, long, conflicts_with = "verbose")]
+    quiet: bool,
 }
 
 #[derive(Parser, Debug)]
@@ -28,6 +75,8 @@ enum SubCommand {
     Install(crate::cli::install::Args),
     Uninstall(crate::cli::uninstall::Args),
     List(ListCommand),
+    Sync(SyncCommand),
+    Reinstall(ReinstallCommand),
 }
 
 pub fn execute(cmd: Args) -> Result<(), Error> {
@@ -35,9 +84,65 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
         SubCommand::Install(args) => crate::cli::install::execute(args),
         SubCommand::Uninstall(args) => crate::cli::uninstall::execute(args),
         SubCommand::List(args) => list_tools(args),
+        SubCommand::Sync(args) => sync_tools(args),
+        SubCommand::Reinstall(args) => reinstall_tools(args),
     }
 }
 
+fn reinstall_tools(cmd: ReinstallCommand) -> Result<(), Error> {
+    let output = CommandOutput::from_quiet_and_verbose(cmd.quiet, cmd.verbose);
+    let py_ver = cmd
+        .python
+        .as_deref()
+        .map(|x| x.parse::<PythonVersionRequest>())
+        .transpose()?;
+    let tools = if cmd.all {
+        list_locked_tools()?
+    } else {
+        cmd.tools
+    };
+
+    for tool in &tools {
+        echo!(if output, "Reinstalling {}", style(tool).cyan());
+        reinstall(tool, py_ver.as_ref(), output.quieter())
+            .with_context(|| format!("failed to reinstall tool {}", tool))?;
+    }
+
+    echo!(if output, "Done!");
+    Ok(())
+}
+
+fn sync_tools(cmd: SyncCommand) -> Result<(), Error> {
+    let output = CommandOutput::from_quiet_and_verbose(cmd.quiet, cmd.verbose);
+    let locked = list_locked_tools()?;
+
+    for tool in &locked {
+        if !cmd.force && is_tool_in_sync(tool, output)? {
+            echo!(if verbose output, "{} is up to date", style(tool).cyan());
+            continue;
+        }
+        echo!(if output, "Syncing {}", style(tool).cyan());
+        install_from_lock(tool, output.quieter())
+            .with_context(|| format!("failed to sync tool {}", tool))?;
+    }
+
+    // tools installed before lockfiles existed cannot be synced
+    let mut unlocked = list_installed_tools()?
+        .into_keys()
+        .filter(|tool| !locked.contains(tool))
+        .collect::<Vec<_>>();
+    unlocked.sort();
+    for tool in unlocked {
+        warn!(
+            "{} has no lockfile, reinstall it with `rye install --force {}`",
+            tool, tool
+        );
+    }
+
+    echo!(if output, "Done!");
+    Ok(())
+}
+
 fn list_tools(cmd: ListCommand) -> Result<(), Error> {
     let mut tools = list_installed_tools()?.into_iter().collect::<Vec<_>>();
     tools.sort_by_key(|(tool, _)| tool.clone());
@@ -53,6 +158,12 @@ fn list_tools(cmd: ListCommand) -> Result<(), Error> {
             } else {
                 echo!("{} {}", style(tool).cyan(), info.version);
             }
+        } else if cmd.include_python {
+            if let Some(ref venv) = info.venv_marker {
+                echo!("{} ({})", style(tool).cyan(), venv.python);
+            } else {
+                echo!("{}", style(tool).cyan());
+            }
         } else {
             echo!("{}", style(tool).cyan());
         }
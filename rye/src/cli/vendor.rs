@@ -0,0 +1,114 @@
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{bail, Error};
+use clap::Parser;
+use console::style;
+use tempfile::tempdir_in;
+
+use crate::bundle::{download_wheels, find_lockfiles};
+use crate::pyproject::PyProject;
+use crate::utils::cleanup::RemoveOnInterrupt;
+use crate::utils::{CommandOutput, IoPathContext};
+
+/// Downloads the distributions of all locked dependencies into a vendor folder.
+///
+/// Once dependencies are vendored, `rye sync` installs exclusively from that
+/// folder.
+#[derive(Parser, Debug)]
+pub struct Args {
+    /// The folder to vendor into, relative to the workspace root.
+    ///
+    /// Defaults to the configured `vendor-dir` or `vendor`.
+    #[arg(long, value_name = "PATH")]
+    dir: Option<PathBuf>,
+    /// Use this pyproject.toml file
+    #[arg(long, value_name = "PYPROJECT_TOML")]
+    pyproject: Option<PathBuf>,
+    /// Enables verbose diagnostics.
+    #[arg(short, long)]
+    verbose: bool,
+    /// Turns off all output.
+    #[arg(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+}
+
+pub fn execute(cmd: Args) -> Result<(), Error> {
+    let output = CommandOutput::from_quiet_and_verbose(cmd.quiet, cmd.verbose);
+    let project = PyProject::load_or_discover(cmd.pyproject.as_deref())?;
+    let root = project.workspace_path();
+    let py_ver = project.venv_python_version()?;
+    let lockfiles = find_lockfiles(&root)?;
+    if lockfiles.is_empty() {
+        bail!(
+            "no lockfiles found in {}, run `rye lock` first",
+            root.display()
+        );
+    }
+
+    let configured = project.vendor_dir();
+    let dir = match cmd.dir {
+        Some(ref dir) => root.join(dir),
+        None => configured.clone().unwrap_or_else(|| root.join("vendor")),
+    };
+
+    // the vendor folder is replaced as a whole, so refuse to replace a
+    // folder that was not vendored into before.
+    if configured.as_ref() != Some(&dir)
+        && dir.is_dir()
+        && fs::read_dir(&dir)
+            .path_context(&dir, "unable to read vendor folder")?
+            .next()
+            .is_some()
+    {
+        bail!(
+            "folder '{}' is not empty and not the vendor folder of the project",
+            dir.display()
+        );
+    }
+
+    // download next to the vendor folder so that it can be swapped in
+    // without leaving a half populated folder behind.
+    echo!(if output, "Downloading dependencies");
+    let scratch = tempdir_in(&*root)?;
+    let _scratch_guard = RemoveOnInterrupt::new(scratch.path());
+    let download_dir = scratch.path().join("vendor");
+    fs::create_dir_all(&download_dir).path_context(&download_dir, "unable to create folder")?;
+    download_wheels(&project, &py_ver, &lockfiles, &download_dir, output)?;
+
+    if dir.is_dir() {
+        fs::remove_dir_all(&dir).path_context(&dir, "unable to remove old vendor folder")?;
+    } else if let Some(parent) = dir.parent() {
+        fs::create_dir_all(parent).path_context(parent, "unable to create folder")?;
+    }
+    fs::rename(&download_dir, &dir)
+        .path_context(&dir, "unable to move vendor folder into place")?;
+
+    let count = fs::read_dir(&dir)
+        .path_context(&dir, "unable to read vendor folder")?
+        .count();
+    echo!(
+        if output,
+        "Vendored {} distributions into {}",
+        count,
+        style(dir.display()).cyan()
+    );
+
+    if configured.as_ref() != Some(&dir) {
+        let rel = match dir.strip_prefix(&*root) {
+            Ok(rel) => rel.to_string_lossy().replace('\\', "/"),
+            Err(_) => dir.display().to_string(),
+        };
+        let mut workspace_root = PyProject::load(&root.join("pyproject.toml"))?;
+        workspace_root.set_vendor_dir(&rel);
+        workspace_root.save()?;
+        echo!(
+            if output,
+            "Set {} in {}, `rye sync` now installs from the vendor folder",
+            style(format!("vendor-dir = \"{}\"", rel)).cyan(),
+            style(workspace_root.toml_path().display()).cyan()
+        );
+    }
+
+    Ok(())
+}
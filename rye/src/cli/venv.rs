@@ -0,0 +1,211 @@
+use std::convert::Infallible;
+use std::env::{self, join_paths, split_paths};
+use std::ffi::OsString;
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::{Context, Error};
+use clap::Parser;
+use console::style;
+use pep508_rs::Requirement;
+
+use crate::config::Config;
+use crate::consts::VENV_BIN;
+use crate::envs::{add_packages, create_env, get_existing_env_path, list_envs, remove_env};
+use crate::sources::py::PythonVersionRequest;
+use crate::utils::{exec_spawn, get_venv_python_bin, CommandOutput};
+
+/// Manage named virtualenvs that are not tied to a project.
+#[derive(Parser, Debug)]
+pub struct Args {
+    #[command(subcommand)]
+    command: SubCommand,
+}
+
+/// Creates a named virtualenv.
+#[derive(Parser, Debug)]
+pub struct CreateCommand {
+    /// The name of the virtualenv.
+    name: String,
+    /// The Python version to use (defaults to the default toolchain).
+    #[arg(short, long)]
+    python: Option<String>,
+    /// Packages to install into the virtualenv.
+    #[arg(long, num_args = 1..)]
+    add: Vec<String>,
+    /// Link the scripts of the installed packages into the shims folder.
+    #[arg(long)]
+    shims: bool,
+    /// Recreate the virtualenv if it exists already.
+    #[arg(short, long)]
+    force: bool,
+    /// Enables verbose diagnostics.
+    #[arg(short, long)]
+    verbose: bool,
+    /// Turns off all output.
+    #[arg(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+}
+
+/// Installs packages into a named virtualenv.
+#[derive(Parser, Debug)]
+pub struct AddCommand {
+    /// The name of the virtualenv.
+    name: String,
+    /// The packages to install.
+    #[arg(required = true)]
+    packages: Vec<String>,
+    /// Enables verbose diagnostics.
+    #[arg(short, long)]
+    verbose: bool,
+    /// Turns off all output.
+    #[arg(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+}
+
+/// Runs a command within a named virtualenv.
+#[derive(Parser, Debug)]
+pub struct RunCommand {
+    /// The name of the virtualenv.
+    name: String,
+    /// The command to run.
+    #[command(subcommand)]
+    cmd: Cmd,
+}
+
+#[derive(Parser, Debug)]
+enum Cmd {
+    #[command(external_subcommand)]
+    External(Vec<OsString>),
+}
+
+/// Lists the named virtualenvs.
+#[derive(Parser, Debug)]
+pub struct ListCommand {
+    /// Show the packages that were added to the virtualenvs.
+    #[arg(long)]
+    include_packages: bool,
+}
+
+/// Removes a named virtualenv.
+#[derive(Parser, Debug)]
+pub struct RemoveCommand {
+    /// The name of the virtualenv.
+    name: String,
+    /// Enables verbose diagnostics.
+    #[arg(short, long)]
+    verbose: bool,
+    /// Turns off all output.
+    #[arg(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+}
+
+#[derive(Parser, Debug)]
+enum SubCommand {
+    Create(CreateCommand),
+    Add(AddCommand),
+    Run(RunCommand),
+    List(ListCommand),
+    Remove(RemoveCommand),
+}
+
+pub fn execute(cmd: Args) -> Result<(), Error> {
+    match cmd.command {
+        SubCommand::Create(args) => create(args),
+        SubCommand::Add(args) => add(args),
+        SubCommand::Run(args) => run(args),
+        SubCommand::List(args) => list(args),
+        SubCommand::Remove(args) => remove(args),
+    }
+}
+
+fn parse_requirements(packages: &[String]) -> Result<Vec<Requirement>, Error> {
+    packages
+        .iter()
+        .map(|x| {
+            x.parse::<Requirement>()
+                .with_context(|| format!("failed to parse requirement '{}'", x))
+        })
+        .collect()
+}
+
+fn create(cmd: CreateCommand) -> Result<(), Error> {
+    let output = CommandOutput::from_quiet_and_verbose(cmd.quiet, cmd.verbose);
+    let packages = parse_requirements(&cmd.add)?;
+    let py_ver = match cmd.python {
+        Some(ref python) => python.parse::<PythonVersionRequest>()?,
+        None => Config::current().default_toolchain()?,
+    };
+    let path = create_env(&cmd.name, &py_ver, &packages, cmd.shims, cmd.force, output)?;
+    echo!(
+        if output,
+        "Created virtualenv {} in {}",
+        style(&cmd.name).cyan(),
+        style(path.display()).cyan()
+    );
+    Ok(())
+}
+
+fn add(cmd: AddCommand) -> Result<(), Error> {
+    let output = CommandOutput::from_quiet_and_verbose(cmd.quiet, cmd.verbose);
+    let packages = parse_requirements(&cmd.packages)?;
+    add_packages(&cmd.name, &packages, output)?;
+    echo!(if output, "Added packages to {}", style(&cmd.name).cyan());
+    Ok(())
+}
+
+fn run(cmd: RunCommand) -> Result<(), Error> {
+    let Cmd::External(args) = cmd.cmd;
+    let venv = get_existing_env_path(&cmd.name)?;
+    match invoke_in_env(&venv, args)? {}
+}
+
+/// Runs a command with the virtualenv activated.
+fn invoke_in_env(venv: &Path, args: Vec<OsString>) -> Result<Infallible, Error> {
+    let bin_dir = venv.join(VENV_BIN);
+    let mut cmd = if args[0] == "python" {
+        Command::new(get_venv_python_bin(venv))
+    } else {
+        Command::new(&args[0])
+    };
+    cmd.args(&args[1..]);
+    if let Some(path) = env::var_os("PATH") {
+        let mut paths = split_paths(&path).collect::<Vec<_>>();
+        paths.insert(0, bin_dir);
+        cmd.env("PATH", join_paths(paths)?);
+    } else {
+        cmd.env("PATH", bin_dir);
+    }
+    cmd.env("VIRTUAL_ENV", venv);
+    cmd.env_remove("PYTHONHOME");
+
+    exec_spawn(&mut cmd)
+}
+
+fn list(cmd: ListCommand) -> Result<(), Error> {
+    for env in list_envs()? {
+        match env.venv_marker {
+            Some(ref venv) => echo!("{} ({})", style(&env.name).cyan(), venv.python),
+            None => echo!(
+                "{} ({})",
+                style(&env.name).red(),
+                style("seems broken").red()
+            ),
+        }
+        if cmd.include_packages {
+            for package in &env.env_marker.packages {
+                echo!("  {}", package);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn remove(cmd: RemoveCommand) -> Result<(), Error> {
+    let output = CommandOutput::from_quiet_and_verbose(cmd.quiet, cmd.verbose);
+    let venv = get_existing_env_path(&cmd.name)?;
+    remove_env(&cmd.name)?;
+    echo!(if verbose output, "removed {}", venv.display());
+    echo!(if output, "Removed virtualenv {}", style(&cmd.name).cyan());
+    Ok(())
+}
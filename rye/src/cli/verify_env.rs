@@ -0,0 +1,262 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Error};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use clap::Parser;
+use console::style;
+use sha2::{Digest, Sha256};
+
+use crate::bootstrap::get_site_packages_dir;
+use crate::config::Config;
+use crate::lock::{parse_locked_packages, strip_project_installs};
+use crate::pyproject::PyProject;
+use crate::sync::{
+    compare_with_lock, find_installed_dists, get_project_names, hash_lockfile, read_sync_state,
+};
+use crate::utils::{CommandOutput, IoPathContext, QuietExit};
+
+/// Verifies the installed files of the virtualenv against the lockfile.
+///
+/// Every file listed in the `RECORD` of an installed distribution is hashed
+/// again to detect modifications after the installation, and the installed
+/// distributions are compared with the lockfile the virtualenv was synced from.
+#[derive(Parser, Debug)]
+pub struct Args {
+    /// Use this pyproject.toml file
+    #[arg(long, value_name = "PYPROJECT_TOML")]
+    pyproject: Option<PathBuf>,
+    /// Enables verbose diagnostics.
+    #[arg(short, long)]
+    verbose: bool,
+    /// Turns off all output.
+    #[arg(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+}
+
+/// A file that does not match the `RECORD` of its distribution.
+#[derive(Debug, PartialEq, Eq)]
+enum FileProblem {
+    Modified(String),
+    Missing(String),
+}
+
+pub fn execute(cmd: Args) -> Result<(), Error> {
+    let output = CommandOutput::from_quiet_and_verbose(cmd.quiet, cmd.verbose);
+    let project = PyProject::load_or_discover(cmd.pyproject.as_deref())?;
+    let venv = project.venv_path();
+    if !venv.is_dir() {
+        bail!("virtualenv {} does not exist", venv.display());
+    }
+    let site_packages = get_site_packages_dir(&venv)?;
+    let mut problems = 0;
+
+    let mut dist_infos = fs::read_dir(&site_packages)
+        .path_context(&site_packages, "unable to read site-packages")?
+        .filter_map(|x| x.ok().map(|x| x.path()))
+        .filter(|x| x.extension().map_or(false, |x| x == "dist-info"))
+        .collect::<Vec<_>>();
+    dist_infos.sort();
+    for dist_info in &dist_infos {
+        let record_path = dist_info.join("RECORD");
+        let record = match fs::read_to_string(&record_path) {
+            Ok(record) => record,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                echo!(
+                    if output,
+                    "{}: {}",
+                    style(dist_info_name(dist_info)).cyan(),
+                    style("no RECORD file").yellow()
+                );
+                problems += 1;
+                continue;
+            }
+            Err(err) => return Err(err).path_context(&record_path, "unable to read RECORD"),
+        };
+        let findings = verify_record(&site_packages, &record)?;
+        for finding in &findings {
+            let (path, what) = match finding {
+                FileProblem::Modified(path) => (path, "modified"),
+                FileProblem::Missing(path) => (path, "missing"),
+            };
+            echo!(
+                if output,
+                "{}: {} {}",
+                style(dist_info_name(dist_info)).cyan(),
+                path,
+                style(what).yellow()
+            );
+        }
+        problems += findings.len();
+    }
+    echo!(
+        if verbose output,
+        "verified the files of {} distributions",
+        dist_infos.len()
+    );
+
+    match read_sync_state(&venv) {
+        None => {
+            echo!(
+                if output,
+                "{}",
+                style("virtualenv was never synced, cannot compare with a lockfile").yellow()
+            );
+            problems += 1;
+        }
+        Some(state) => {
+            if hash_lockfile(&state.lockfile).ok().as_deref() != Some(state.lock_hash.as_str()) {
+                echo!(
+                    if output,
+                    "{}",
+                    style(format!(
+                        "{} changed since the last sync",
+                        state.lockfile.display()
+                    ))
+                    .yellow()
+                );
+                problems += 1;
+            }
+            let mut contents = fs::read_to_string(&state.lockfile)
+                .path_context(&state.lockfile, "unable to read lockfile")?;
+            if state.no_install_project {
+                contents = strip_project_installs(
+                    &contents,
+                    &project.workspace_path(),
+                    &get_project_names(&project)?,
+                );
+            }
+            let locked = parse_locked_packages(&contents, &project.workspace_path());
+            let installed = find_installed_dists(&site_packages)?;
+            let unlocked: &[&str] = if Config::current().venv_pip() {
+                &["pip"]
+            } else {
+                &[]
+            };
+            let mismatches = compare_with_lock(&locked, &installed, unlocked);
+            for mismatch in &mismatches {
+                echo!(if output, "{}", mismatch);
+            }
+            problems += mismatches.len();
+        }
+    }
+
+    if problems == 0 {
+        echo!(if output, "All installed files match");
+        Ok(())
+    } else {
+        Err(QuietExit(1).into())
+    }
+}
+
+/// Returns `name version` for a `.dist-info` folder.
+fn dist_info_name(dist_info: &Path) -> String {
+    let stem = dist_info
+        .file_stem()
+        .map(|x| x.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    match stem.split_once('-') {
+        Some((name, version)) => format!("{} {}", name, version),
+        None => stem,
+    }
+}
+
+/// Hashes every file of a `RECORD` again.
+///
+/// Entries without a hash (the `RECORD` itself, bytecode) and hashes other
+/// than sha256 are skipped.
+fn verify_record(site_packages: &Path, record: &str) -> Result<Vec<FileProblem>, Error> {
+    let mut rv = Vec::new();
+    for line in record.lines() {
+        let Some((path, hash)) = parse_record_line(line) else {
+            continue;
+        };
+        let Some(expected) = hash.strip_prefix("sha256=") else {
+            continue;
+        };
+        let full_path = site_packages.join(&path);
+        match fs::read(&full_path) {
+            Ok(contents) => {
+                if encode_record_hash(&Sha256::digest(contents)) != expected {
+                    rv.push(FileProblem::Modified(path));
+                }
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                rv.push(FileProblem::Missing(path));
+            }
+            Err(err) => return Err(err).path_context(&full_path, "unable to hash file"),
+        }
+    }
+    Ok(rv)
+}
+
+/// Splits a `RECORD` line into the path and the hash.
+///
+/// `RECORD` is a CSV file, paths containing commas are quoted.
+fn parse_record_line(line: &str) -> Option<(String, &str)> {
+    let mut parts = line.rsplitn(3, ',');
+    let _size = parts.next()?;
+    let hash = parts.next()?;
+    let path = parts.next()?;
+    let path = match path.strip_prefix('"').and_then(|x| x.strip_suffix('"')) {
+        Some(quoted) => quoted.replace("\"\"", "\""),
+        None => path.to_string(),
+    };
+    if hash.is_empty() {
+        None
+    } else {
+        Some((path, hash))
+    }
+}
+
+/// Encodes a digest like `RECORD` files do (urlsafe base64 without padding).
+fn encode_record_hash(digest: &[u8]) -> String {
+    URL_SAFE_NO_PAD.encode(digest)
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use sha2::{Digest, Sha256};
+
+    use super::{encode_record_hash, parse_record_line, verify_record, FileProblem};
+
+    #[test]
+    fn test_encode_record_hash() {
+        assert_eq!(
+            encode_record_hash(&Sha256::digest(b"")),
+            "47DEQpj8HBSa-_TImW-5JCeuQeRkm5NMpJWZG3hSuFU"
+        );
+        assert_eq!(encode_record_hash(b"ab"), "YWI");
+        assert_eq!(encode_record_hash(b"a"), "YQ");
+    }
+
+    #[test]
+    fn test_verify_record() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("pkg")).unwrap();
+        fs::write(dir.path().join("pkg/__init__.py"), "").unwrap();
+        fs::write(dir.path().join("pkg/util.py"), "changed = True\n").unwrap();
+        let record = "\
+pkg/__init__.py,sha256=47DEQpj8HBSa-_TImW-5JCeuQeRkm5NMpJWZG3hSuFU,0
+pkg/util.py,sha256=47DEQpj8HBSa-_TImW-5JCeuQeRkm5NMpJWZG3hSuFU,0
+\"pkg/a,b.py\",sha256=47DEQpj8HBSa-_TImW-5JCeuQeRkm5NMpJWZG3hSuFU,0
+pkg-1.0.dist-info/RECORD,,
+pkg/__pycache__/__init__.cpython-312.pyc,,
+";
+        assert_eq!(
+            parse_record_line("\"pkg/a,b.py\",sha256=x,0"),
+            Some(("pkg/a,b.py".to_string(), "sha256=x"))
+        );
+        assert_eq!(
+            verify_record(dir.path(), record).unwrap(),
+            vec![
+                FileProblem::Modified("pkg/util.py".into()),
+                FileProblem::Missing("pkg/a,b.py".into()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,178 @@
+//! One-time notices about deprecated config keys, lockfiles and commands.
+//!
+//! Every notice has a key made of its id and, for notices about a project,
+//! a path within the project.  The keys of notices that were shown are
+//! recorded in the rye home so that each notice appears once per user or
+//! project.  `behavior.deprecation-warnings` turns all notices off and
+//! `rye self migrate` applies the rewrites that can be done automatically.
+use std::collections::{BTreeSet, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use once_cell::sync::Lazy;
+use toml_edit::DocumentMut;
+
+use crate::config::Config;
+use crate::platform::get_app_dir;
+use crate::utils::atomic::write_atomic;
+
+/// Config keys that were renamed as `(table, old key, new key)`.
+const RENAMED_CONFIG_KEYS: &[(&str, &str, &str)] = &[
+    ("default", "dependency_operator", "dependency-operator"),
+    ("behavior", "force_rye_managed", "force-rye-managed"),
+];
+
+/// Notices shown by this process, also if they could not be recorded.
+static SHOWN: Lazy<Mutex<HashSet<String>>> = Lazy::new(Default::default);
+
+/// A deprecated feature that is still in use.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Deprecation {
+    /// A config key that was renamed.
+    ConfigKey {
+        table: &'static str,
+        old: &'static str,
+        new: &'static str,
+    },
+    /// `rye show --installed-deps`, replaced by `rye list`.
+    ShowInstalledDeps,
+    /// A lockfile that does not record the flags it was locked with.
+    UnflaggedLockfile(PathBuf),
+}
+
+impl Deprecation {
+    /// The key the notice is remembered by.
+    pub fn key(&self) -> String {
+        match self {
+            Deprecation::ConfigKey { table, old, .. } => format!("config-key:{}.{}", table, old),
+            Deprecation::ShowInstalledDeps => "show-installed-deps".into(),
+            Deprecation::UnflaggedLockfile(path) => {
+                format!("unflagged-lockfile:{}", path.display())
+            }
+        }
+    }
+
+    /// The notice shown to the user.
+    pub fn message(&self) -> String {
+        match self {
+            Deprecation::ConfigKey { table, old, new } => format!(
+                "config key {}.{} is deprecated, it was renamed to {}.{}.  \
+                 Run `rye self migrate` to update the config.",
+                table, old, table, new
+            ),
+            Deprecation::ShowInstalledDeps => {
+                "`rye show --installed-deps` is deprecated, use `rye list`".into()
+            }
+            Deprecation::UnflaggedLockfile(path) => format!(
+                "{} was written by an older rye and does not record the flags it was \
+                 locked with.  Pass flags such as --all-features again when locking.",
+                path.display()
+            ),
+        }
+    }
+}
+
+fn get_notices_path() -> PathBuf {
+    get_app_dir().join("deprecations.json")
+}
+
+/// Records a notice in the file of shown notices.
+///
+/// Returns `false` if it was recorded before.
+fn record_notice(path: &Path, key: &str) -> bool {
+    let mut shown = fs::read(path)
+        .ok()
+        .and_then(|x| serde_json::from_slice::<BTreeSet<String>>(&x).ok())
+        .unwrap_or_default();
+    if !shown.insert(key.to_string()) {
+        return false;
+    }
+    if let Ok(contents) = serde_json::to_vec_pretty(&shown) {
+        write_atomic(path, contents).ok();
+    }
+    true
+}
+
+/// Warns about a deprecation unless the notice was shown before.
+pub fn warn_deprecated(deprecation: Deprecation) {
+    if !Config::current().deprecation_warnings() {
+        return;
+    }
+    let key = deprecation.key();
+    if !SHOWN.lock().unwrap().insert(key.clone()) {
+        return;
+    }
+    if record_notice(&get_notices_path(), &key) {
+        warn!("{}", deprecation.message());
+    }
+}
+
+/// Returns the renamed keys that are set in a config.
+pub fn find_renamed_config_keys(doc: &DocumentMut) -> Vec<Deprecation> {
+    RENAMED_CONFIG_KEYS
+        .iter()
+        .filter(|(table, old, _)| doc.get(table).and_then(|x| x.get(old)).is_some())
+        .map(|&(table, old, new)| Deprecation::ConfigKey { table, old, new })
+        .collect()
+}
+
+/// Warns about the deprecated keys in the config.
+pub fn check_config() {
+    for deprecation in find_renamed_config_keys(Config::current().doc()) {
+        warn_deprecated(deprecation);
+    }
+}
+
+/// Renames the deprecated keys of a config.
+///
+/// If both keys are set the new one wins.  Returns the applied rewrites.
+pub fn migrate_config(doc: &mut DocumentMut) -> Vec<Deprecation> {
+    let rv = find_renamed_config_keys(doc);
+    for deprecation in &rv {
+        let Deprecation::ConfigKey { table, old, new } = *deprecation else {
+            continue;
+        };
+        if let Some(table) = doc.get_mut(table).and_then(|x| x.as_table_like_mut()) {
+            if let Some(item) = table.remove(old) {
+                if table.get(new).is_none() {
+                    table.insert(new, item);
+                }
+            }
+        }
+    }
+    rv
+}
+
+#[cfg(test)]
+mod test {
+    use toml_edit::DocumentMut;
+
+    use super::{migrate_config, record_notice};
+
+    #[test]
+    fn test_record_notice() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("deprecations.json");
+        assert!(record_notice(&path, "show-installed-deps"));
+        assert!(record_notice(
+            &path,
+            "unflagged-lockfile:/a/requirements.lock"
+        ));
+        assert!(!record_notice(&path, "show-installed-deps"));
+    }
+
+    #[test]
+    fn test_migrate_config() {
+        let mut doc = "[default]\ndependency_operator = \"~=\"\n\n\
+                       [behavior]\nforce_rye_managed = false\nforce-rye-managed = true\n"
+            .parse::<DocumentMut>()
+            .unwrap();
+        assert_eq!(migrate_config(&mut doc).len(), 2);
+        assert_eq!(
+            doc.to_string(),
+            "[default]\ndependency-operator = \"~=\"\n\n[behavior]\nforce-rye-managed = true\n"
+        );
+        assert!(migrate_config(&mut doc).is_empty());
+    }
+}
@@ -0,0 +1,302 @@
+//! Named virtualenvs that are not tied to a project.
+//!
+//! They live in `venvs/<name>` within the rye home and are managed with
+//! `rye venv`.  Next to the regular `rye-venv.json` marker every environment
+//! records the packages that were added to it and whether the scripts of
+//! these packages are linked into the shims folder.
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Error};
+use pep508_rs::Requirement;
+use serde::{Deserialize, Serialize};
+
+use crate::backend::{Backend, InstallOptions};
+use crate::bootstrap::{ensure_self_venv, fetch, FetchOptions};
+use crate::config::Config;
+use crate::platform::get_app_dir;
+use crate::pyproject::{normalize_package_name, read_venv_marker, ExpandedSources};
+use crate::shims::{link_tool_scripts, remove_tool_shims};
+use crate::sources::py::{PythonVersion, PythonVersionRequest};
+use crate::sync::{create_virtualenv, VenvMarker};
+use crate::utils::atomic::write_atomic;
+use crate::utils::{CommandOutput, IoPathContext};
+
+/// The marker with the rye specific information about a named environment.
+const ENV_MARKER: &str = "rye-env.json";
+
+/// What is recorded about a named environment.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct EnvMarker {
+    /// The requirements that were added to the environment.
+    pub packages: Vec<String>,
+    /// Are the scripts of the packages linked into the shims folder?
+    pub shims: bool,
+}
+
+/// A named environment.
+#[derive(Debug)]
+pub struct EnvInfo {
+    pub name: String,
+    pub venv_marker: Option<VenvMarker>,
+    pub env_marker: EnvMarker,
+}
+
+/// Returns the folder that holds the named environments.
+pub fn get_envs_dir() -> PathBuf {
+    get_app_dir().join("venvs")
+}
+
+/// Environment names are folder names and show up in prompts.
+fn is_valid_env_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+/// Returns the path of a named environment.
+pub fn get_env_path(name: &str) -> Result<PathBuf, Error> {
+    if !is_valid_env_name(name) {
+        bail!("'{}' is not a valid virtualenv name", name);
+    }
+    Ok(get_envs_dir().join(name))
+}
+
+/// Returns the path of an existing named environment.
+pub fn get_existing_env_path(name: &str) -> Result<PathBuf, Error> {
+    let path = get_env_path(name)?;
+    if !path.join(ENV_MARKER).is_file() {
+        bail!(
+            "virtualenv '{}' does not exist, create it with `rye venv create {}`",
+            name,
+            name
+        );
+    }
+    Ok(path)
+}
+
+fn read_env_marker(path: &Path) -> EnvMarker {
+    fs::read(path.join(ENV_MARKER))
+        .ok()
+        .and_then(|x| serde_json::from_slice(&x).ok())
+        .unwrap_or_default()
+}
+
+fn write_env_marker(path: &Path, marker: &EnvMarker) -> Result<(), Error> {
+    let path = path.join(ENV_MARKER);
+    write_atomic(&path, serde_json::to_string_pretty(marker)?)
+        .path_context(&path, "failed writing virtualenv marker file")
+}
+
+/// Lists the named environments sorted by name.
+pub fn list_envs() -> Result<Vec<EnvInfo>, Error> {
+    let envs_dir = get_envs_dir();
+    if !envs_dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut rv = Vec::new();
+    for entry in
+        fs::read_dir(&envs_dir).path_context(&envs_dir, "unable to enumerate virtualenvs")?
+    {
+        let path = entry?.path();
+        if !path.join(ENV_MARKER).is_file() {
+            continue;
+        }
+        // environments moved aside while being recreated are hidden
+        let Some(name) = path
+            .file_name()
+            .map(|x| x.to_string_lossy().to_string())
+            .filter(|x| is_valid_env_name(x))
+        else {
+            continue;
+        };
+        rv.push(EnvInfo {
+            name,
+            venv_marker: read_venv_marker(&path),
+            env_marker: read_env_marker(&path),
+        });
+    }
+    rv.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(rv)
+}
+
+/// Creates a named environment and installs the given packages into it.
+///
+/// If `shims` is set the scripts of the packages are linked into the shims
+/// folder, also for packages added later.
+pub fn create_env(
+    name: &str,
+    py_ver: &PythonVersionRequest,
+    packages: &[Requirement],
+    shims: bool,
+    force: bool,
+    output: CommandOutput,
+) -> Result<PathBuf, Error> {
+    let path = get_env_path(name)?;
+    let shim_dir = get_app_dir().join("shims");
+
+    // the previous environment is moved aside and only removed once the new
+    // one was created successfully.
+    let backup = if path.exists() {
+        if !force {
+            bail!(
+                "virtualenv '{}' already exists, pass --force to recreate it",
+                name
+            );
+        }
+        let backup = get_envs_dir().join(format!(".{}.old", name));
+        if backup.exists() {
+            fs::remove_dir_all(&backup).path_context(&backup, "unable to remove virtualenv")?;
+        }
+        remove_tool_shims(&path, &shim_dir)?;
+        fs::rename(&path, &backup).path_context(&path, "unable to move virtualenv aside")?;
+        Some(backup)
+    } else {
+        None
+    };
+
+    match create_env_at(&path, name, py_ver, packages, shims, output) {
+        Ok(()) => {
+            if let Some(backup) = backup {
+                fs::remove_dir_all(&backup).ok();
+            }
+            Ok(path)
+        }
+        Err(err) => {
+            remove_env(name).ok();
+            if let Some(backup) = backup {
+                let marker = read_env_marker(&backup);
+                if fs::rename(&backup, &path).is_ok() && marker.shims {
+                    link_env_shims(&path, &marker, &[], output.quieter()).ok();
+                }
+            }
+            Err(err)
+        }
+    }
+}
+
+fn create_env_at(
+    path: &Path,
+    name: &str,
+    py_ver: &PythonVersionRequest,
+    packages: &[Requirement],
+    shims: bool,
+    output: CommandOutput,
+) -> Result<(), Error> {
+    let self_venv = ensure_self_venv(output)?;
+    let py_ver = fetch(py_ver, FetchOptions::with_output(output))?;
+    create_virtualenv(output, &self_venv, &py_ver, path, name)?;
+    let marker = EnvMarker {
+        packages: Vec::new(),
+        shims,
+    };
+    write_env_marker(path, &marker)?;
+    add_to_env(path, &py_ver, marker, packages, output)
+}
+
+/// Installs packages into an existing named environment.
+pub fn add_packages(
+    name: &str,
+    packages: &[Requirement],
+    output: CommandOutput,
+) -> Result<(), Error> {
+    let path = get_existing_env_path(name)?;
+    let Some(venv_marker) = read_venv_marker(&path) else {
+        bail!(
+            "virtualenv '{}' is broken, recreate it with `rye venv create --force {}`",
+            name,
+            name
+        );
+    };
+    add_to_env(
+        &path,
+        &venv_marker.python,
+        read_env_marker(&path),
+        packages,
+        output,
+    )
+}
+
+fn add_to_env(
+    path: &Path,
+    py_ver: &PythonVersion,
+    mut marker: EnvMarker,
+    packages: &[Requirement],
+    output: CommandOutput,
+) -> Result<(), Error> {
+    if packages.is_empty() {
+        return Ok(());
+    }
+    let sources = ExpandedSources::from_sources(&Config::current().sources()?)?;
+    Backend::current().install(path, py_ver, packages, InstallOptions::new(sources), output)?;
+
+    for package in packages {
+        let name = normalize_package_name(&package.name);
+        // a package added again replaces its previous requirement
+        marker.packages.retain(|x| {
+            x.parse::<Requirement>()
+                .map_or(true, |x| normalize_package_name(&x.name) != name)
+        });
+        marker.packages.push(package.to_string());
+    }
+    write_env_marker(path, &marker)?;
+
+    if marker.shims {
+        link_env_shims(path, &marker, packages, output)?;
+    }
+    Ok(())
+}
+
+/// Links the scripts of all packages of an environment into the shims folder.
+///
+/// The shims that point into the environment are removed first, so packages
+/// can be added again.  Only the scripts of `added` packages are reported.
+fn link_env_shims(
+    path: &Path,
+    marker: &EnvMarker,
+    added: &[Requirement],
+    output: CommandOutput,
+) -> Result<(), Error> {
+    let shim_dir = get_app_dir().join("shims");
+    remove_tool_shims(path, &shim_dir)?;
+    for package in &marker.packages {
+        let Ok(package) = package.parse::<Requirement>() else {
+            continue;
+        };
+        let name = normalize_package_name(&package.name);
+        let is_added = added
+            .iter()
+            .any(|x| normalize_package_name(&x.name) == name);
+        let output = if is_added { output } else { output.quieter() };
+        link_tool_scripts(&package.name, path, &[], &shim_dir, output)?;
+    }
+    Ok(())
+}
+
+/// Removes a named environment and the shims that point into it.
+pub fn remove_env(name: &str) -> Result<(), Error> {
+    let path = get_env_path(name)?;
+    if !path.exists() {
+        return Ok(());
+    }
+    remove_tool_shims(&path, &get_app_dir().join("shims"))?;
+    fs::remove_dir_all(&path).path_context(&path, "unable to remove virtualenv")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::is_valid_env_name;
+
+    #[test]
+    fn test_is_valid_env_name() {
+        assert!(is_valid_env_name("data-science"));
+        assert!(is_valid_env_name("py3.12_tools"));
+        assert!(!is_valid_env_name(""));
+        assert!(!is_valid_env_name(".hidden"));
+        assert!(!is_valid_env_name("../escape"));
+        assert!(!is_valid_env_name("a b"));
+    }
+}
@@ -0,0 +1,513 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Error};
+use once_cell::sync::Lazy;
+use pep440_rs::Version;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use url::Url;
+
+use crate::config::Config;
+use crate::platform::get_app_dir;
+use crate::pyproject::{normalize_package_name, ExpandedSources};
+use crate::sources::py::PythonVersion;
+use crate::utils::{CommandOutput, IoPathContext};
+
+/// The content type of the JSON simple API (PEP 691).
+const SIMPLE_JSON_CONTENT_TYPE: &str = "application/vnd.pypi.simple.v1+json";
+
+/// The content type of the HTML simple API (PEP 691 name for PEP 503).
+const SIMPLE_HTML_CONTENT_TYPE: &str = "application/vnd.pypi.simple.v1+html";
+
+/// Prefer JSON, but accept HTML from indexes that only implement PEP 503.
+const SIMPLE_ACCEPT: &str = "application/vnd.pypi.simple.v1+json, \
+    application/vnd.pypi.simple.v1+html;q=0.2, text/html;q=0.01";
+
+static ANCHOR_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?is)<a\s([^>]*)>(.*?)</a\s*>").unwrap());
+static ATTR_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"([a-zA-Z_:-]+)(?:\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s"'>]+)))?"#).unwrap()
+});
+
+/// How long a response stays fresh if the index does not say.
+const DEFAULT_MAX_AGE: u64 = 600;
+
+/// A cached response of the simple API for a single project.
+///
+/// HTML responses are converted so that the body is always in the format
+/// of the JSON simple API.
+#[derive(Serialize, Deserialize, Debug)]
+struct CacheEntry {
+    etag: Option<String>,
+    last_modified: Option<String>,
+    /// Unix timestamp until which the entry can be used without revalidation.
+    expires: u64,
+    body: String,
+}
+
+/// The simple API response for a project.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ProjectMetadata {
+    pub name: String,
+    #[serde(default)]
+    pub files: Vec<ProjectFile>,
+}
+
+/// A single distribution file of a project.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ProjectFile {
+    pub filename: String,
+    #[serde(default)]
+    pub url: String,
+    #[serde(default)]
+    pub hashes: BTreeMap<String, String>,
+    #[serde(rename = "requires-python")]
+    pub requires_python: Option<String>,
+    #[serde(default)]
+    pub yanked: serde_json::Value,
+}
+
+impl ProjectFile {
+    /// Yanked is either a boolean or the reason for the yank.
+    pub fn is_yanked(&self) -> bool {
+        !matches!(
+            self.yanked,
+            serde_json::Value::Null | serde_json::Value::Bool(false)
+        )
+    }
+
+    /// Returns the version this file was built for.
+    pub fn version(&self) -> Option<Version> {
+        let version = if let Some(stem) = self.filename.strip_suffix(".whl") {
+            stem.split('-').nth(1)?
+        } else {
+            let stem = self
+                .filename
+                .strip_suffix(".tar.gz")
+                .or_else(|| self.filename.strip_suffix(".zip"))?;
+            stem.rsplit_once('-')?.1
+        };
+        Version::from_str(version).ok()
+    }
+
+    /// Checks if this file is installable on this machine with the given python.
+    ///
+    /// Source distributions always are.  For wheels this only looks at the
+    /// python and platform tags.
+    pub fn is_compatible(&self, py_ver: &PythonVersion) -> bool {
+        let Some(stem) = self.filename.strip_suffix(".whl") else {
+            return true;
+        };
+        let mut tags = stem.rsplit('-');
+        let (Some(platform), Some(_abi), Some(python)) = (tags.next(), tags.next(), tags.next())
+        else {
+            return false;
+        };
+        let python_ok = python.split('.').any(|tag| {
+            tag == format!("py{}", py_ver.major)
+                || tag == format!("py{}{}", py_ver.major, py_ver.minor)
+                || tag == format!("cp{}{}", py_ver.major, py_ver.minor)
+        });
+        python_ok && platform.split('.').any(is_platform_tag_compatible)
+    }
+}
+
+/// Checks if a wheel platform tag can be installed on this operating system.
+///
+/// Architectures are not compared.
+pub fn is_platform_tag_compatible(tag: &str) -> bool {
+    let platform_prefixes: &[&str] = match std::env::consts::OS {
+        "linux" => &["manylinux", "musllinux", "linux"],
+        "macos" => &["macosx"],
+        "windows" => &["win"],
+        _ => &[],
+    };
+    tag == "any"
+        || platform_prefixes
+            .iter()
+            .any(|prefix| tag.starts_with(prefix))
+}
+
+/// Looks up project metadata on the indexes of a project.
+///
+/// Indexes are consulted in order and the first one that knows a project
+/// wins, which is how the installers treat extra indexes as well.
+#[derive(Debug)]
+pub struct IndexClient {
+    index_urls: Vec<Url>,
+    output: CommandOutput,
+}
+
+impl IndexClient {
+    /// Creates a client for the indexes of the given sources.
+    ///
+    /// Find links sources are not indexes and are ignored.
+    pub fn from_sources(sources: &ExpandedSources, output: CommandOutput) -> IndexClient {
+        IndexClient {
+            index_urls: sources
+                .index_urls
+                .iter()
+                .map(|(url, _)| url.clone())
+                .collect(),
+            output,
+        }
+    }
+
+    /// Returns the metadata of a project from the first index that has it.
+    pub fn get_project(&self, name: &str) -> Result<Option<ProjectMetadata>, Error> {
+        for index_url in &self.index_urls {
+            if let Some(metadata) = get_project_metadata(index_url, name, self.output)? {
+                return Ok(Some(metadata));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Returns the folder where index metadata is cached.
+pub fn get_metadata_cache_dir() -> PathBuf {
+    get_app_dir().join("cache").join("metadata")
+}
+
+fn get_cache_path(index_url: &Url, name: &str) -> PathBuf {
+    let index_key = hex::encode(Sha256::digest(index_url.as_str().as_bytes()));
+    get_metadata_cache_dir()
+        .join(&index_key[..16])
+        .join(format!("{}.json", name))
+}
+
+fn read_cache_entry(index_url: &Url, name: &str) -> Option<CacheEntry> {
+    let contents = fs::read(get_cache_path(index_url, name)).ok()?;
+    serde_json::from_slice(&contents).ok()
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |x| x.as_secs())
+}
+
+/// Parses a `Cache-Control` header into the number of seconds a response
+/// is fresh.  `None` means the response must not be stored.
+fn parse_cache_control(value: Option<&str>) -> Option<u64> {
+    let Some(value) = value else {
+        return Some(DEFAULT_MAX_AGE);
+    };
+    let mut max_age = DEFAULT_MAX_AGE;
+    for directive in value.split(',').map(|x| x.trim().to_ascii_lowercase()) {
+        if directive == "no-store" {
+            return None;
+        } else if directive == "no-cache" {
+            max_age = 0;
+        } else if let Some(seconds) = directive.strip_prefix("max-age=") {
+            max_age = seconds.trim_matches('"').parse().unwrap_or(0);
+        }
+    }
+    Some(max_age)
+}
+
+/// Looks up the metadata of a project on a simple index.
+///
+/// Responses are cached under the app dir and honor `Cache-Control`, `ETag`
+/// and `Last-Modified` so that repeated lookups of the same package do not
+/// go to the index again.  Returns `None` if the index does not know the
+/// project.
+pub fn get_project_metadata(
+    index_url: &Url,
+    name: &str,
+    output: CommandOutput,
+) -> Result<Option<ProjectMetadata>, Error> {
+    let name = normalize_package_name(name);
+    let mut url = index_url.to_string();
+    if !url.ends_with('/') {
+        url.push('/');
+    }
+    url.push_str(&name);
+    url.push('/');
+    if !url.starts_with("https://") {
+        bail!("Refusing insecure index request");
+    }
+
+    let cache_path = get_cache_path(index_url, &name);
+    let cached = read_cache_entry(index_url, &name);
+    if let Some(ref entry) = cached {
+        if entry.expires > now() {
+            echo!(if verbose output, "using cached metadata for {}", name);
+            return Ok(Some(serde_json::from_str(&entry.body)?));
+        }
+    }
+
+    echo!(if verbose output, "fetching metadata for {} from {}", name, url);
+    let response = fetch(&url, cached.as_ref())?;
+    let content_type = response.content_type.as_deref().unwrap_or("");
+    let (body, etag, last_modified) = match response.code {
+        404 => return Ok(None),
+        304 => match cached {
+            Some(entry) => (
+                entry.body,
+                response.etag.or(entry.etag),
+                response.last_modified.or(entry.last_modified),
+            ),
+            None => bail!("index answered {} with 304 for an uncached request", url),
+        },
+        200 if content_type.starts_with(SIMPLE_JSON_CONTENT_TYPE) => (
+            String::from_utf8(response.body)?,
+            response.etag,
+            response.last_modified,
+        ),
+        200 if content_type.starts_with(SIMPLE_HTML_CONTENT_TYPE)
+            || content_type.starts_with("text/html") =>
+        {
+            let html = String::from_utf8_lossy(&response.body);
+            let base_url = Url::parse(response.url.as_deref().unwrap_or(&url))?;
+            (
+                serde_json::to_string(&parse_simple_html(&name, &html, &base_url))?,
+                response.etag,
+                response.last_modified,
+            )
+        }
+        200 => bail!(
+            "index {} answered with unsupported content type '{}'",
+            index_url,
+            content_type
+        ),
+        code => bail!("index request to {} failed with status {}", url, code),
+    };
+    let metadata = serde_json::from_str(&body)?;
+
+    if let Some(max_age) = parse_cache_control(response.cache_control.as_deref()) {
+        let entry = CacheEntry {
+            etag,
+            last_modified,
+            expires: now() + max_age,
+            body,
+        };
+        if let Some(parent) = cache_path.parent() {
+            fs::create_dir_all(parent).path_context(parent, "unable to create cache folder")?;
+        }
+        fs::write(&cache_path, serde_json::to_vec(&entry)?)
+            .path_context(&cache_path, "unable to write metadata cache")?;
+    }
+
+    Ok(Some(metadata))
+}
+
+/// Parses the HTML simple API page (PEP 503) of a project.
+///
+/// Links are resolved against the URL of the page and the hash in the
+/// fragment is extracted.  The anchor text is the filename.
+pub fn parse_simple_html(name: &str, html: &str, base_url: &Url) -> ProjectMetadata {
+    let mut files = Vec::new();
+    for anchor in ANCHOR_RE.captures_iter(html) {
+        let mut href = None;
+        let mut requires_python = None;
+        let mut yanked = serde_json::Value::Null;
+        for attr in ATTR_RE.captures_iter(&anchor[1]) {
+            let value = attr
+                .get(2)
+                .or_else(|| attr.get(3))
+                .or_else(|| attr.get(4))
+                .map(|x| unescape_html(x.as_str()));
+            match attr[1].to_ascii_lowercase().as_str() {
+                "href" => href = value,
+                "data-requires-python" => requires_python = value.filter(|x| !x.is_empty()),
+                "data-yanked" => {
+                    yanked = match value {
+                        Some(reason) if !reason.is_empty() => serde_json::Value::String(reason),
+                        _ => serde_json::Value::Bool(true),
+                    }
+                }
+                _ => {}
+            }
+        }
+        let Some(mut url) = href.and_then(|x| base_url.join(&x).ok()) else {
+            continue;
+        };
+        let mut hashes = BTreeMap::new();
+        if let Some((algorithm, digest)) = url.fragment().and_then(|x| x.split_once('=')) {
+            hashes.insert(algorithm.to_string(), digest.to_string());
+        }
+        url.set_fragment(None);
+        let filename = unescape_html(anchor[2].trim());
+        let filename = if filename.is_empty() {
+            match url.path_segments().and_then(|x| x.last()) {
+                Some(segment) => segment.to_string(),
+                None => continue,
+            }
+        } else {
+            filename
+        };
+        files.push(ProjectFile {
+            filename,
+            url: url.to_string(),
+            hashes,
+            requires_python,
+            yanked,
+        });
+    }
+    ProjectMetadata {
+        name: normalize_package_name(name),
+        files,
+    }
+}
+
+fn unescape_html(value: &str) -> String {
+    value
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&#x27;", "'")
+        .replace("&amp;", "&")
+}
+
+struct Response {
+    code: u32,
+    /// The URL of the response after redirects.
+    url: Option<String>,
+    content_type: Option<String>,
+    cache_control: Option<String>,
+    etag: Option<String>,
+    last_modified: Option<String>,
+    body: Vec<u8>,
+}
+
+fn fetch(url: &str, cached: Option<&CacheEntry>) -> Result<Response, Error> {
+    let config = Config::current();
+    let mut handle = curl::easy::Easy::new();
+    handle.url(url)?;
+    handle.follow_location(true)?;
+    if let Some(proxy) = config.https_proxy_url() {
+        handle.proxy(&proxy)?;
+    }
+    if let Some(timeout) = config.network_connect_timeout() {
+        handle.connect_timeout(timeout)?;
+    }
+    if let Some(timeout) = config.network_timeout() {
+        handle.timeout(timeout)?;
+    }
+    #[cfg(windows)]
+    {
+        handle.ssl_options(curl::easy::SslOpt::new().no_revoke(true))?;
+    }
+
+    let mut headers = curl::easy::List::new();
+    headers.append(&format!("Accept: {}", SIMPLE_ACCEPT))?;
+    if let Some(entry) = cached {
+        if let Some(ref etag) = entry.etag {
+            headers.append(&format!("If-None-Match: {}", etag))?;
+        }
+        if let Some(ref last_modified) = entry.last_modified {
+            headers.append(&format!("If-Modified-Since: {}", last_modified))?;
+        }
+    }
+    handle.http_headers(headers)?;
+
+    let mut response_headers = Vec::new();
+    let mut body = Vec::new();
+    {
+        let mut transfer = handle.transfer();
+        transfer.header_function(|line| {
+            if let Ok(line) = std::str::from_utf8(line) {
+                if let Some((key, value)) = line.split_once(':') {
+                    response_headers
+                        .push((key.trim().to_ascii_lowercase(), value.trim().to_string()));
+                }
+            }
+            true
+        })?;
+        transfer.write_function(|data| {
+            body.extend_from_slice(data);
+            Ok(data.len())
+        })?;
+        transfer
+            .perform()
+            .map_err(|err| Error::new(err).context(format!("request to {} failed", url)))?;
+    }
+
+    // with redirects the headers of all responses are collected, the last one wins
+    let header = |name: &str| {
+        response_headers
+            .iter()
+            .rev()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.clone())
+    };
+    Ok(Response {
+        code: handle.response_code()?,
+        url: handle.effective_url()?.map(|x| x.to_string()),
+        content_type: header("content-type"),
+        cache_control: header("cache-control"),
+        etag: header("etag"),
+        last_modified: header("last-modified"),
+        body,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use url::Url;
+
+    use super::{parse_cache_control, parse_simple_html, ProjectFile, DEFAULT_MAX_AGE};
+    use crate::sources::py::PythonVersion;
+
+    fn file(filename: &str) -> ProjectFile {
+        ProjectFile {
+            filename: filename.into(),
+            url: String::new(),
+            hashes: Default::default(),
+            requires_python: None,
+            yanked: serde_json::Value::Null,
+        }
+    }
+
+    #[test]
+    fn test_parse_cache_control() {
+        assert_eq!(parse_cache_control(None), Some(DEFAULT_MAX_AGE));
+        assert_eq!(parse_cache_control(Some("max-age=600, public")), Some(600));
+        assert_eq!(parse_cache_control(Some("no-cache")), Some(0));
+        assert_eq!(parse_cache_control(Some("private, no-store")), None);
+    }
+
+    #[test]
+    fn test_project_file() {
+        let py_ver: PythonVersion = "cpython@3.12.3".parse().unwrap();
+        let sdist = file("Flask-3.0.3.tar.gz");
+        assert_eq!(sdist.version(), Some("3.0.3".parse().unwrap()));
+        assert!(sdist.is_compatible(&py_ver));
+        let wheel = file("flask-3.0.3-py3-none-any.whl");
+        assert_eq!(wheel.version(), Some("3.0.3".parse().unwrap()));
+        assert!(wheel.is_compatible(&py_ver));
+        assert!(!file("numpy-1.26.4-cp311-cp311-win_amd64.whl").is_compatible(&py_ver));
+    }
+
+    #[test]
+    fn test_parse_simple_html() {
+        let html = r##"<!DOCTYPE html>
+<html>
+  <head><title>Links for flask</title></head>
+  <body>
+    <h1>Links for flask</h1>
+    <a href="../../packages/flask-3.0.2.tar.gz#sha256=abc">Flask-3.0.2.tar.gz</a><br/>
+    <a href="https://files.example.com/flask-3.0.3-py3-none-any.whl#sha256=def" data-requires-python="&gt;=3.8">flask-3.0.3-py3-none-any.whl</a><br/>
+    <a href="/packages/flask-3.0.4-py3-none-any.whl" data-yanked="broken">flask-3.0.4-py3-none-any.whl</a>
+  </body>
+</html>"##;
+        let base_url = Url::parse("https://index.example.com/simple/flask/").unwrap();
+        let metadata = parse_simple_html("Flask", html, &base_url);
+        assert_eq!(metadata.name, "flask");
+        assert_eq!(metadata.files.len(), 3);
+        assert_eq!(
+            metadata.files[0].url,
+            "https://index.example.com/packages/flask-3.0.2.tar.gz"
+        );
+        assert_eq!(metadata.files[0].hashes["sha256"], "abc");
+        assert_eq!(metadata.files[1].requires_python.as_deref(), Some(">=3.8"));
+        assert!(!metadata.files[1].is_yanked());
+        assert!(metadata.files[2].is_yanked());
+    }
+}
@@ -1,6 +1,6 @@
-use std::collections::{BTreeMap, HashMap};
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
-use std::process::{Command, Stdio};
+use std::process::Stdio;
 use std::{env, fs};
 
 use anyhow::{bail, Context, Error};
@@ -9,64 +9,23 @@ use once_cell::sync::Lazy;
 use pep508_rs::{Requirement, VersionOrUrl};
 use regex::Regex;
 use same_file::is_same_file;
+use serde::{Deserialize, Serialize};
 use url::Url;
 
+use crate::backend::{Backend, InstallOptions};
 use crate::bootstrap::{ensure_self_venv, fetch, FetchOptions};
 use crate::config::Config;
 use crate::consts::VENV_BIN;
 use crate::platform::get_app_dir;
 use crate::pyproject::{normalize_package_name, read_venv_marker, ExpandedSources};
+use crate::shims::{link_tool_scripts, remove_tool_shims};
 use crate::sources::py::PythonVersionRequest;
 use crate::sync::{create_virtualenv, VenvMarker};
+use crate::utils::atomic::write_atomic;
 use crate::utils::{
-    get_short_executable_name, get_venv_python_bin, is_executable, symlink_file, CommandOutput,
-    IoPathContext,
+    get_short_executable_name, get_venv_python_bin, internal_command, CommandOutput, IoPathContext,
 };
-use crate::uv::{UvBuilder, UvInstallOptions};
 
-const FIND_SCRIPT_SCRIPT: &str = r#"
-import os
-import re
-import sys
-import json
-
-if sys.version_info >= (3, 8):
-    from importlib.metadata import distribution, PackageNotFoundError
-else:
-    from importlib_metadata import distribution, PackageNotFoundError
-
-_package_re = re.compile('(?i)^([a-z0-9._-]+)')
-
-result = {}
-
-def dump_all(dist, root=False):
-    rv = []
-    for file in dist.files or ():
-        rv.append(os.path.normpath(dist.locate_file(file)))
-    result["" if root else dist.name] = rv
-    req = []
-    for r in dist.requires or ():
-        name = _package_re.match(r)
-        if name is not None:
-            req.append(name.group())
-    return req
-
-root = sys.argv[1]
-to_resolve = [root]
-seen = set()
-while to_resolve:
-    try:
-        d = to_resolve.pop()
-        dist = distribution(d)
-    except Exception:
-        continue
-    if dist.name in seen:
-        continue
-    seen.add(dist.name)
-    to_resolve.extend(dump_all(dist, root=d==root))
-
-print(json.dumps(result))
-"#;
 static SUCCESSFULLY_DOWNLOADED_RE: Lazy<Regex> =
     Lazy::new(|| Regex::new("(?m)^Successfully downloaded (.*?)$").unwrap());
 
@@ -94,6 +53,27 @@ impl ToolInfo {
     }
 }
 
+/// Additional information that is recorded for an installed tool.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct ToolMarker {
+    /// The python version the tool was explicitly installed with.
+    pub python: Option<String>,
+}
+
+/// The metadata recorded in the header of a tool lockfile.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub struct ToolLock {
+    /// The requirement the tool was installed from.
+    pub requirement: String,
+    /// The exact python version the tool was installed with.
+    pub python: String,
+    /// The python version the tool was explicitly pinned to.
+    pub pinned_python: Option<String>,
+    pub include_deps: Vec<String>,
+    pub extra_requirements: Vec<String>,
+}
+
 const TOOL_VERSION_SCRIPT: &str = r#"
 import sys
 from importlib.metadata import version
@@ -102,9 +82,14 @@ tool_name = sys.argv[1]
 print(version(tool_name))
 "#;
 
+/// Installs a tool.
+///
+/// If `pin_python` is set, the requested python version is recorded so that
+/// reinstalls of the tool keep using it.
 pub fn install(
     requirement: Requirement,
     py_ver: &PythonVersionRequest,
+    pin_python: bool,
     force: bool,
     include_deps: &[String],
     extra_requirements: &[Requirement],
@@ -125,11 +110,13 @@ pub fn install(
     if target_venv_path.is_dir() && !force {
         bail!("package already installed");
     }
-    let py = get_venv_python_bin(&target_venv_path);
-    let target_venv_bin_path = target_venv_path.join(VENV_BIN);
 
     uninstall_helper(&target_venv_path, &shim_dir)?;
 
+    let tool_marker = ToolMarker {
+        python: pin_python.then(|| py_ver.to_string()),
+    };
+
     // make sure we have a compatible python version
     let py_ver = fetch(py_ver, FetchOptions::with_output(output))?;
 
@@ -141,194 +128,291 @@ pub fn install(
         requirement.name.as_str(),
     )?;
 
-    if Config::current().use_uv() {
-        let result = UvBuilder::new()
-            .with_output(output.quieter())
-            .with_sources(sources)
-            .ensure_exists()?
-            .venv(&target_venv_path, &py, &py_ver, None)?
-            .with_output(output)
-            .install(
-                &requirement,
-                UvInstallOptions {
-                    importlib_workaround: py_ver.major == 3 && py_ver.minor == 7,
-                    extras: extra_requirements.to_vec(),
-                    refresh: force,
-                },
-            );
-        if result.is_err() {
-            uninstall_helper(&target_venv_path, &shim_dir)?;
-            return result;
-        }
-    } else {
-        let mut cmd = Command::new(self_venv.join(VENV_BIN).join("pip"));
-        cmd.arg("--python")
-            .arg(&py)
-            .arg("install")
-            .env("PYTHONWARNINGS", "ignore")
-            .env("PIP_DISABLE_PIP_VERSION_CHECK", "1");
-
-        sources.add_as_pip_args(&mut cmd);
-        if output == CommandOutput::Verbose {
-            cmd.arg("--verbose");
-        } else {
-            if output == CommandOutput::Quiet {
-                cmd.arg("-q");
-            }
-            cmd.env("PYTHONWARNINGS", "ignore");
-        }
-        cmd.arg("--").arg(&requirement.to_string());
+    let mut requirements = vec![requirement.clone()];
+    requirements.extend(extra_requirements.iter().cloned());
+    let result = Backend::current().install(
+        &target_venv_path,
+        &py_ver,
+        &requirements,
+        InstallOptions {
+            importlib_workaround: py_ver.major == 3 && py_ver.minor == 7,
+            refresh: force,
+            ..InstallOptions::new(sources)
+        },
+        output,
+    );
+    if result.is_err() {
+        uninstall_helper(&target_venv_path, &shim_dir)?;
+        return result;
+    }
+    write_tool_marker(&target_venv_path, &tool_marker)?;
+    write_tool_lock(
+        &requirement.name,
+        &self_venv,
+        &target_venv_path,
+        &ToolLock {
+            requirement: requirement.to_string(),
+            python: py_ver.to_string(),
+            pinned_python: tool_marker.python,
+            include_deps: include_deps.clone(),
+            extra_requirements: extra_requirements.iter().map(|x| x.to_string()).collect(),
+        },
+    )?;
 
-        // we don't support versions below 3.7, but for 3.7 we need importlib-metadata
-        // to be installed
-        if py_ver.major == 3 && py_ver.minor == 7 {
-            cmd.arg("importlib-metadata==6.6.0");
-        }
+    link_tool_scripts(
+        &requirement.name,
+        &target_venv_path,
+        &include_deps,
+        &shim_dir,
+        output,
+    )?;
 
-        for extra in extra_requirements {
-            cmd.arg(extra.to_string());
-        }
+    Ok(())
+}
 
-        let status = cmd.status()?;
-        if !status.success() {
-            uninstall_helper(&target_venv_path, &shim_dir)?;
-            bail!("tool installation failed");
-        }
-    };
-    let out = Command::new(py)
-        .arg("-c")
-        .arg(FIND_SCRIPT_SCRIPT)
-        .arg(&requirement.name)
-        .stdout(Stdio::piped())
-        .output()
-        .context("unable to dump package manifest from installed package")?;
-    let all_files: BTreeMap<String, Vec<PathBuf>> = serde_json::from_slice(&out.stdout)
-        .with_context(|| {
-            format!(
-                "failed to resolve manifest\n{}",
-                String::from_utf8_lossy(&out.stderr)
-            )
-        })?;
-
-    let mut installed = Vec::new();
-    let mut scripts_found = Vec::new();
-    if let Some(files) = all_files.get("") {
-        installed.extend(install_scripts(files, &target_venv_bin_path, &shim_dir)?);
-    }
+/// Installs a tool strictly from its lockfile.
+///
+/// The tool is reinstalled with the exact Python version and packages
+/// recorded when it was last installed with `rye install`.
+pub fn install_from_lock(package: &str, output: CommandOutput) -> Result<(), Error> {
+    let lockfile = get_tool_lockfile_path(package);
+    let (lock, _) = read_tool_lock(&lockfile)?;
+    let requirement: Requirement = lock
+        .requirement
+        .parse()
+        .with_context(|| format!("invalid requirement in {}", lockfile.display()))?;
+    let config = Config::current();
+    let sources = ExpandedSources::from_sources(&config.sources()?)?;
+    let app_dir = get_app_dir();
+    let shim_dir = app_dir.join("shims");
+    let self_venv = ensure_self_venv(output)?;
+    let target_venv_path = app_dir.join("tools").join(normalize_package_name(package));
 
-    for (package, files) in all_files.iter() {
-        if package.is_empty() {
-            continue;
-        }
-        if include_deps.contains(&normalize_package_name(package)) {
-            installed.extend(install_scripts(files, &target_venv_bin_path, &shim_dir)?);
-        } else {
-            let scripts = find_scripts(files, &target_venv_bin_path);
-            if !scripts.is_empty() {
-                scripts_found.push((package, scripts));
-            }
-        }
+    uninstall_helper(&target_venv_path, &shim_dir)?;
+
+    let py_ver = fetch(
+        &lock.python.parse::<PythonVersionRequest>()?,
+        FetchOptions::with_output(output),
+    )?;
+
+    create_virtualenv(
+        output,
+        &self_venv,
+        &py_ver,
+        &target_venv_path,
+        requirement.name.as_str(),
+    )?;
+
+    let result = Backend::current().install_lockfile(
+        &target_venv_path,
+        &py_ver,
+        &lockfile,
+        InstallOptions::new(sources),
+        output,
+    );
+    if result.is_err() {
+        uninstall_helper(&target_venv_path, &shim_dir)?;
+        return result;
     }
+    write_tool_marker(
+        &target_venv_path,
+        &ToolMarker {
+            python: lock.pinned_python,
+        },
+    )?;
+
+    link_tool_scripts(
+        &requirement.name,
+        &target_venv_path,
+        &lock.include_deps,
+        &shim_dir,
+        output,
+    )?;
 
-    if !scripts_found.is_empty()
-        && output != CommandOutput::Quiet
-        && (installed.is_empty() || output == CommandOutput::Verbose)
-    {
-        echo!(
-            "{}",
-            style("Found additional non installed scripts in dependencies:").yellow()
+    Ok(())
+}
+
+/// Reinstalls a tool from the requirement recorded in its lockfile.
+///
+/// Unlike [`install_from_lock`] the dependencies are resolved again, which
+/// allows moving a tool to another python version.  If `py_ver` is given
+/// the tool is pinned to it, otherwise the previous pin or the default is
+/// used.
+pub fn reinstall(
+    package: &str,
+    py_ver: Option<&PythonVersionRequest>,
+    output: CommandOutput,
+) -> Result<(), Error> {
+    let lockfile = get_tool_lockfile_path(package);
+    if !lockfile.is_file() {
+        bail!(
+            "{} has no lockfile, reinstall it with `rye install --force {}`",
+            package,
+            package
         );
-        scripts_found.sort();
-        for (package, scripts) in scripts_found.iter() {
-            echo!("{}:", style(package).green());
-            for script in scripts {
-                echo!("  - {}", style(script).cyan());
-            }
+    }
+    let (lock, _) = read_tool_lock(&lockfile)?;
+    let requirement: Requirement = lock
+        .requirement
+        .parse()
+        .with_context(|| format!("invalid requirement in {}", lockfile.display()))?;
+    let extra_requirements = lock
+        .extra_requirements
+        .iter()
+        .map(|x| x.parse::<Requirement>())
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("invalid extra requirement in {}", lockfile.display()))?;
+    let pinned = match py_ver {
+        Some(py_ver) => Some(py_ver.clone()),
+        None => lock
+            .pinned_python
+            .as_deref()
+            .map(|x| x.parse::<PythonVersionRequest>())
+            .transpose()?,
+    };
+    let pin_python = pinned.is_some();
+    let py_ver = match pinned {
+        Some(py_ver) => py_ver,
+        None => Config::current().default_tool_python()?,
+    };
+
+    install(
+        requirement,
+        &py_ver,
+        pin_python,
+        true,
+        &lock.include_deps,
+        &extra_requirements,
+        output,
+    )
+}
+
+/// Checks if an installed tool still matches its lockfile.
+pub fn is_tool_in_sync(package: &str, output: CommandOutput) -> Result<bool, Error> {
+    let (lock, pins) = read_tool_lock(&get_tool_lockfile_path(package))?;
+    let target_venv_path = get_app_dir()
+        .join("tools")
+        .join(normalize_package_name(package));
+    match read_venv_marker(&target_venv_path) {
+        Some(marker) if marker.python.to_string() == lock.python => {}
+        _ => return Ok(false),
+    }
+    let self_venv = ensure_self_venv(output)?;
+    Ok(freeze_tool(&self_venv, &target_venv_path)?.trim() == pins.trim())
+}
+
+/// Reads the marker of an installed tool.
+pub fn read_tool_marker(target_venv_path: &Path) -> Option<ToolMarker> {
+    let contents = fs::read(target_venv_path.join("rye-tool.json")).ok()?;
+    serde_json::from_slice(&contents).ok()
+}
+
+fn write_tool_marker(target_venv_path: &Path, marker: &ToolMarker) -> Result<(), Error> {
+    let path = target_venv_path.join("rye-tool.json");
+    write_atomic(&path, serde_json::to_string_pretty(marker)?)
+        .path_context(&path, "failed writing tool marker file")
+}
+
+/// Returns the path to the lockfile of a tool.
+pub fn get_tool_lockfile_path(package: &str) -> PathBuf {
+    get_app_dir()
+        .join("tools")
+        .join(format!("{}.lock", normalize_package_name(package)))
+}
+
+/// Reads a tool lockfile and returns the metadata and the pinned requirements.
+pub fn read_tool_lock(path: &Path) -> Result<(ToolLock, String), Error> {
+    let contents = fs::read_to_string(path).path_context(path, "unable to read tool lockfile")?;
+    // the header is separated from the pinned requirements by an empty line
+    let (head, pins) = contents.split_once("\n\n").unwrap_or((&contents, ""));
+    let mut header = serde_json::Map::new();
+    for line in head.lines() {
+        if let Some((key, value)) = line.strip_prefix("# ").and_then(|x| x.split_once(": ")) {
+            header.insert(key.to_string(), serde_json::from_str(value)?);
         }
-        echo!("To install scripts from these packages pass the appropriate --include-dep");
     }
+    let lock = serde_json::from_value(serde_json::Value::Object(header))
+        .path_context(path, "invalid tool lockfile header")?;
+    Ok((lock, pins.to_string()))
+}
 
-    if output != CommandOutput::Quiet {
-        echo!();
-        if installed.is_empty() {
-            warn!("installed package did not expose any scripts")
-        } else {
-            echo!("Installed scripts:");
-            for script in installed {
-                echo!("  - {}", style(script).cyan());
-            }
-            if output != CommandOutput::Verbose && !scripts_found.is_empty() {
-                echo!();
-                echo!(
-                    "note: {}",
-                    style("additional scripts were encountered in non-installed dependencies.")
-                        .dim()
-                );
-            }
+/// Records the packages installed into a tool's virtualenv in its lockfile.
+fn write_tool_lock(
+    package: &str,
+    self_venv: &Path,
+    target_venv_path: &Path,
+    lock: &ToolLock,
+) -> Result<(), Error> {
+    let mut rv = String::from("# generated by rye\n");
+    if let serde_json::Value::Object(header) = serde_json::to_value(lock)? {
+        for (key, value) in header {
+            rv.push_str(&format!("# {}: {}\n", key, value));
         }
     }
+    rv.push('\n');
+    rv.push_str(&freeze_tool(self_venv, target_venv_path)?);
 
-    Ok(())
+    let path = get_tool_lockfile_path(package);
+    write_atomic(&path, rv).path_context(&path, "failed writing tool lockfile")
 }
 
-fn find_scripts(files: &[PathBuf], target_venv_bin_path: &Path) -> Vec<String> {
-    let mut rv = Vec::new();
-    for file in files {
-        if let Ok(rest) = file.strip_prefix(target_venv_bin_path) {
-            rv.push(rest.to_string_lossy().to_string());
-        }
+/// Returns the `pip freeze` output of a tool's virtualenv.
+fn freeze_tool(self_venv: &Path, target_venv_path: &Path) -> Result<String, Error> {
+    let out = internal_command(self_venv.join(VENV_BIN).join("pip"))
+        .arg("--python")
+        .arg(get_venv_python_bin(target_venv_path))
+        .arg("freeze")
+        .env("PYTHONWARNINGS", "ignore")
+        .env("PIP_DISABLE_PIP_VERSION_CHECK", "1")
+        .stdout(Stdio::piped())
+        .output()
+        .context("unable to freeze tool virtualenv")?;
+    if !out.status.success() {
+        bail!(
+            "failed to freeze tool virtualenv\n{}",
+            String::from_utf8_lossy(&out.stderr)
+        );
     }
-    rv
+    Ok(String::from_utf8_lossy(&out.stdout).into_owned())
 }
 
-fn install_scripts(
-    files: &[PathBuf],
-    target_venv_bin_path: &Path,
-    shim_dir: &Path,
-) -> Result<Vec<String>, Error> {
-    let mut rv = Vec::new();
-    for file in files {
-        if let Ok(rest) = file.strip_prefix(target_venv_bin_path) {
-            // In some cases we are given paths here which point to sub-folders of the
-            // script/bin folder.  For instance in some cases it has been shown that
-            // __pycache__/something.pyc shows up there.  These are obviously not good
-            // targets to link as they would never show up via PATH discovery.  Skip
-            // over these.
-            //
-            // Also do not try to link things which are not considered executables on
-            // this operating system.
-            if !rest.parent().map_or(true, |x| x == Path::new("")) || !is_executable(file) {
-                continue;
-            }
+/// Returns the names of all tools that have a lockfile.
+pub fn list_locked_tools() -> Result<Vec<String>, Error> {
+    let tool_dir = get_app_dir().join("tools");
+    if !tool_dir.is_dir() {
+        return Ok(Vec::new());
+    }
 
-            let shim_target = shim_dir.join(rest);
-
-            // on windows we want to fall back to hardlinks.  That might be problematic in
-            // some cases, but it should work for most cases where setuptools or other
-            // systems created exe files.  Caveat: uninstallation currently does not work
-            // when hardlinks are used.
-            #[cfg(windows)]
-            {
-                if symlink_file(file, &shim_target).is_err() {
-                    fs::hard_link(file, &shim_target)
-                        .path_context(file, "unable to symlink tool")?;
-                }
-            }
-            #[cfg(unix)]
-            {
-                symlink_file(file, shim_target).path_context(file, "unable to symlink tool")?;
+    let mut rv = Vec::new();
+    for entry in fs::read_dir(&tool_dir).path_context(&tool_dir, "unable to enumerate tools")? {
+        let path = entry?.path();
+        if path.extension().map_or(false, |x| x == "lock") && path.is_file() {
+            if let Some(name) = path.file_stem().and_then(|x| x.to_str()) {
+                rv.push(name.to_string());
             }
-            rv.push(get_short_executable_name(file));
         }
     }
+    rv.sort();
     Ok(rv)
 }
 
+/// Returns the python version a tool was pinned to when it was installed.
+pub fn get_pinned_tool_python(package: &str) -> Option<PythonVersionRequest> {
+    let target_venv_path = get_app_dir()
+        .join("tools")
+        .join(normalize_package_name(package));
+    read_tool_marker(&target_venv_path)?.python?.parse().ok()
+}
+
 pub fn uninstall(package: &str, output: CommandOutput) -> Result<(), Error> {
     let app_dir = get_app_dir();
     let shim_dir = app_dir.join("shims");
     let tool_dir = app_dir.join("tools");
     let target_venv_path = tool_dir.join(normalize_package_name(package));
+    let lockfile = get_tool_lockfile_path(package);
+    if lockfile.is_file() {
+        fs::remove_file(&lockfile).path_context(&lockfile, "unable to remove tool lockfile")?;
+    }
     if !target_venv_path.is_dir() {
         echo!("{} is not installed", style(package).cyan());
         return Ok(());
@@ -336,7 +420,7 @@ pub fn uninstall(package: &str, output: CommandOutput) -> Result<(), Error> {
 
     uninstall_helper(&target_venv_path, &shim_dir)
         .with_context(|| format!("unable to uninstall {}", target_venv_path.display()))?;
-    if output != CommandOutput::Quiet {
+    if !output.is_quiet() {
         echo!("Uninstalled {}", style(package).cyan());
     }
     Ok(())
@@ -374,7 +458,7 @@ pub fn list_installed_tools() -> Result<HashMap<String, ToolInfo>, Error> {
             }
         }
 
-        let output = Command::new(target_venv_bin_path.join("python"))
+        let output = internal_command(target_venv_bin_path.join("python"))
             .arg("-c")
             .arg(TOOL_VERSION_SCRIPT)
             .arg(tool_name.clone())
@@ -401,17 +485,7 @@ fn uninstall_helper(target_venv_path: &Path, shim_dir: &Path) -> Result<(), Erro
         return Ok(());
     }
 
-    for script in fs::read_dir(&target_venv_bin_path)
-        .path_context(&target_venv_bin_path, "unable to enumerate scripts")?
-    {
-        let script = script?;
-        if let Some(base_name) = script.path().file_name() {
-            let shim_path = shim_dir.join(base_name);
-            if let Ok(true) = is_same_file(&shim_path, &script.path()) {
-                fs::remove_file(&shim_path).ok();
-            }
-        }
-    }
+    remove_tool_shims(target_venv_path, shim_dir)?;
 
     fs::remove_dir_all(target_venv_path).ok();
 
@@ -429,7 +503,7 @@ pub fn resolve_local_requirement(
         return Ok(None);
     }
 
-    let output = Command::new(self_venv.join("bin/pip"))
+    let output = internal_command(self_venv.join(VENV_BIN).join("pip"))
         .arg("download")
         .arg("--no-deps")
         .arg("--")
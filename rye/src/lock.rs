@@ -1,29 +1,47 @@
 use std::borrow::Cow;
-use std::collections::{HashMap, HashSet};
-use std::io::{BufWriter, Write};
-use std::path::Path;
-use std::process::Command;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use std::{env, fmt, fs};
 
-use anyhow::{anyhow, bail, Context, Error};
+use anyhow::{anyhow, Context, Error};
 use clap::ValueEnum;
 use minijinja::render;
 use once_cell::sync::Lazy;
-use pep508_rs::Requirement;
+use pep440_rs::Operator;
+use pep508_rs::{Requirement, VersionOrUrl};
 use regex::Regex;
 use serde::Serialize;
+use sha2::{Digest, Sha256};
 use tempfile::NamedTempFile;
 use url::Url;
+use walkdir::WalkDir;
 
+use crate::backend::{Backend, ResolveRequest};
 use crate::config::Config;
-use crate::piptools::{get_pip_compile, get_pip_tools_version, PipToolsVersion};
+use crate::deprecations::{warn_deprecated, Deprecation};
+use crate::platform::get_app_dir;
+use crate::provenance::{
+    collect_direct_requirements, get_report_path, get_report_resolution_key,
+    render_resolution_report,
+};
 use crate::pyproject::{
-    normalize_package_name, DependencyKind, ExpandedSources, PyProject, Workspace,
+    normalize_package_name, strip_credentials, DependencyKind, DependencyRef, ExpandedSources,
+    PyProject, Workspace,
 };
+use crate::shadowing::check_shadowing;
 use crate::sources::py::PythonVersion;
-use crate::utils::{set_proxy_variables, CommandOutput, IoPathContext};
-use crate::uv::{UvBuilder, UvPackageUpgrade};
+use crate::tui::Heartbeat;
+use crate::utils::atomic::write_atomic_with_backup;
+use crate::utils::cleanup::RemoveOnInterrupt;
+use crate::utils::{CommandOutput, IoPathContext};
+use crate::variants::Variant;
+use crate::wasm::{
+    check_resolution, fetch_pyodide_lock, write_pyodide_constraints, WasmTarget,
+    DEFAULT_PYODIDE_VERSION, RESOLVE_PLATFORM,
+};
 
 static FILE_EDITABLE_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^-e (file://.*?)\s*$").unwrap());
 static DEP_COMMENT_RE: Lazy<Regex> =
@@ -37,28 +55,100 @@ static REQUIREMENTS_HEADER: &str = r#"# generated by rye
 #   all-features: {{ lock_options.all_features|tojson }}
 #   with-sources: {{ lock_options.with_sources|tojson }}
 #   generate-hashes: {{ lock_options.generate_hashes|tojson }}
+{%- if lock_options.python_version %}
+#   python-version: {{ lock_options.python_version|tojson }}
+{%- endif %}
+{%- if lock_options.python_platform %}
+#   platform: {{ lock_options.python_platform|tojson }}
+{%- endif %}
+{%- if lock_options.variant %}
+#   variant: {{ lock_options.variant|tojson }}
+{%- endif %}
+{%- if lock_options.allow_shadowing %}
+#   allowed-shadowing: {{ lock_options.allow_shadowing|tojson }}
+{%- endif %}
 
 "#;
-static PARAM_RE: Lazy<Regex> =
-    Lazy::new(|| Regex::new(r"^#   (pre|features|all-features|with-sources):\s*(.*?)$").unwrap());
+static LOCK_FLAGS_HEADER: &str = "# last locked with the following flags:";
+/// The version of the structured lockfile format.
+///
+/// The requirements style lockfiles count as the first version of the
+/// lockfile format, so the structured format starts out as the second.
+const STRUCTURED_LOCK_VERSION: i64 = 2;
+const LOCK_CACHE_MAX_ENTRIES: usize = 256;
+const LOCK_CACHE_MAX_AGE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+static PARAM_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^#   (pre|features|all-features|with-sources|allowed-shadowing):\s*(.*?)$")
+        .unwrap()
+});
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum LockMode {
     Production,
     Dev,
+    /// Production dependencies and a dependency group.
+    Group(String),
+}
+
+impl LockMode {
+    /// Returns the name of the lockfile for this mode.
+    pub fn lockfile_name(&self) -> Cow<'static, str> {
+        match self {
+            LockMode::Production => "requirements.lock".into(),
+            LockMode::Dev => "requirements-dev.lock".into(),
+            LockMode::Group(name) => format!("requirements-{}.lock", name).into(),
+        }
+    }
+
+    /// Returns the name of the lockfile for this mode and a dependency variant.
+    pub fn variant_lockfile_name(&self, variant: Option<&str>) -> Cow<'static, str> {
+        match variant {
+            Some(variant) => {
+                let name = self.lockfile_name();
+                format!("{}.{}.lock", name.trim_end_matches(".lock"), variant).into()
+            }
+            None => self.lockfile_name(),
+        }
+    }
+
+    /// Recovers the mode and the variant from the name of a lockfile.
+    pub fn from_lockfile_name(name: &str) -> (LockMode, Option<String>) {
+        let stem = name.strip_suffix(".lock").unwrap_or(name);
+        let (stem, variant) = match stem.split_once('.') {
+            Some((stem, variant)) => (stem, Some(variant.to_string())),
+            None => (stem, None),
+        };
+        let mode = match stem.strip_prefix("requirements-") {
+            Some("dev") => LockMode::Dev,
+            Some(group) => LockMode::Group(group.to_string()),
+            None => LockMode::Production,
+        };
+        (mode, variant)
+    }
 }
 
 impl fmt::Display for LockMode {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "{}",
-            match self {
-                LockMode::Production => "production",
-                LockMode::Dev => "dev",
+        match self {
+            LockMode::Production => write!(f, "production"),
+            LockMode::Dev => write!(f, "dev"),
+            LockMode::Group(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+/// Returns the names of the dependency groups of a project or its workspace.
+pub fn get_dependency_group_names(pyproject: &PyProject) -> Result<BTreeSet<String>, Error> {
+    let mut rv = BTreeSet::new();
+    match pyproject.workspace() {
+        Some(workspace) => {
+            for project in workspace.iter_projects() {
+                rv.extend(project?.dependency_groups()?.into_keys());
             }
-        )
+        }
+        None => rv.extend(pyproject.dependency_groups()?.into_keys()),
     }
+    Ok(rv)
 }
 
 /// Keyring provider type to use for credential lookup.
@@ -92,6 +182,31 @@ pub struct LockOptions {
     pub reset: bool,
     /// Generate hashes in the lock file.
     pub generate_hashes: bool,
+    /// Lock for this Python version instead of the one of the project.
+    pub python_version: Option<String>,
+    /// Lock for this platform instead of the current one.
+    pub python_platform: Option<String>,
+    /// Also lock the dependencies of this variant (`tool.rye.variants`).
+    pub variant: Option<String>,
+    /// Private packages that may be resolved from the public index.
+    pub allow_shadowing: Vec<String>,
+    /// Names and patterns of the packages that are private to the workspace.
+    #[serde(skip)]
+    pub private_packages: Vec<String>,
+    /// Also write a structured TOML lockfile next to the lockfile.
+    pub structured: bool,
+    /// Also write a resolution report next to the lockfile.
+    pub report: bool,
+    /// Print the resolution report.
+    #[serde(skip)]
+    pub explain: bool,
+    /// Fetch the metadata for the resolution report from the index if the
+    /// locked version is not installed.
+    #[serde(skip)]
+    pub report_from_index: bool,
+    /// The Pyodide release to lock for with `--platform emscripten`.
+    #[serde(skip)]
+    pub pyodide_version: Option<String>,
 }
 
 impl LockOptions {
@@ -111,10 +226,7 @@ impl LockOptions {
         }
 
         let mut rv = opts.clone();
-        for line in s
-            .lines()
-            .skip_while(|x| *x != "# last locked with the following flags:")
-        {
+        for line in s.lines().skip_while(|x| *x != LOCK_FLAGS_HEADER) {
             if let Some(m) = PARAM_RE.captures(line) {
                 let value = &m[2];
                 match &m[1] {
@@ -130,6 +242,13 @@ impl LockOptions {
                     "with-sources" => {
                         rv.with_sources = rv.with_sources || serde_json::from_str(value)?
                     }
+                    "allowed-shadowing" => {
+                        for name in serde_json::from_str::<Vec<String>>(value)? {
+                            if !rv.allow_shadowing.contains(&name) {
+                                rv.allow_shadowing.push(name);
+                            }
+                        }
+                    }
                     _ => unreachable!(),
                 }
             }
@@ -143,6 +262,14 @@ impl LockOptions {
     }
 }
 
+/// Returns the platform a lockfile was generated for with `--platform`.
+pub fn get_locked_platform(s: &str) -> Option<String> {
+    s.lines()
+        .take_while(|x| x.starts_with('#'))
+        .find_map(|x| x.strip_prefix("#   platform: "))
+        .and_then(|x| serde_json::from_str(x).ok())
+}
+
 /// Creates lockfiles for all projects in the workspace.
 #[allow(clippy::too_many_arguments)]
 pub fn update_workspace_lockfile(
@@ -157,7 +284,7 @@ pub fn update_workspace_lockfile(
 ) -> Result<(), Error> {
     echo!(if output, "Generating {} lockfile: {}", lock_mode, lockfile.display());
 
-    let lock_options = restore_lock_options(lockfile, lock_options)?;
+    let mut lock_options = restore_lock_options(lockfile, lock_options)?;
     let features_by_project = collect_workspace_features(&lock_options);
     let mut req_file = NamedTempFile::new()?;
 
@@ -176,28 +303,75 @@ pub fn update_workspace_lockfile(
         local_projects.insert(pyproject.normalized_name()?, rel_url);
         projects.push(pyproject);
     }
+    lock_options.to_mut().private_packages = local_projects
+        .keys()
+        .cloned()
+        .chain(workspace.private_packages())
+        .collect();
 
+    let mut direct = Vec::new();
     for pyproject in &projects {
+        let name = pyproject.normalized_name()?;
         dump_dependencies(
-            pyproject,
             &local_projects,
             req_file.as_file_mut(),
-            DependencyKind::Normal,
+            pyproject.iter_dependencies(DependencyKind::Normal),
         )?;
-        if lock_mode == LockMode::Dev {
-            dump_dependencies(
-                pyproject,
-                &local_projects,
-                req_file.as_file_mut(),
-                DependencyKind::Dev,
-            )?;
+        collect_direct_requirements(
+            &name,
+            pyproject.iter_dependencies(DependencyKind::Normal),
+            &mut direct,
+        );
+        match lock_mode {
+            LockMode::Production => {}
+            LockMode::Dev => {
+                dump_dependencies(
+                    &local_projects,
+                    req_file.as_file_mut(),
+                    pyproject.iter_dependencies(DependencyKind::Dev),
+                )?;
+                collect_direct_requirements(
+                    &format!("{} (dev)", name),
+                    pyproject.iter_dependencies(DependencyKind::Dev),
+                    &mut direct,
+                );
+            }
+            LockMode::Group(ref group) => {
+                let deps = pyproject
+                    .dependency_groups()?
+                    .remove(group)
+                    .unwrap_or_default();
+                dump_dependencies(
+                    &local_projects,
+                    req_file.as_file_mut(),
+                    deps.iter().cloned(),
+                )?;
+                collect_direct_requirements(
+                    &format!("{} (group {})", name, group),
+                    deps.into_iter(),
+                    &mut direct,
+                );
+            }
         }
     }
+    if let Some(ref variant) = lock_options.variant {
+        let variant = get_variant(&workspace.variants()?, variant)?;
+        dump_dependencies(
+            &local_projects,
+            req_file.as_file_mut(),
+            variant.iter_dependencies(),
+        )?;
+        collect_direct_requirements(
+            &format!("variant {}", variant.name),
+            variant.iter_dependencies(),
+            &mut direct,
+        );
+    }
 
     req_file.flush()?;
 
     let exclusions = find_exclusions(&projects)?;
-    generate_lockfile(
+    let resolution_key = generate_lockfile(
         output,
         py_ver,
         &workspace.path(),
@@ -209,10 +383,34 @@ pub fn update_workspace_lockfile(
         true,
         keyring_provider,
     )?;
+    report_resolution(
+        lockfile,
+        &workspace.path(),
+        &workspace.venv_path(),
+        &direct,
+        sources,
+        &lock_options,
+        resolution_key.as_deref(),
+        output,
+    )?;
 
     Ok(())
 }
 
+fn get_variant<'a>(variants: &'a [Variant], name: &str) -> Result<&'a Variant, Error> {
+    variants
+        .iter()
+        .find(|x| x.name == name)
+        .ok_or_else(|| anyhow!("unknown variant '{}'", name))
+}
+
+/// Does the lockfile record the flags it was locked with?
+///
+/// Lockfiles written by old versions of rye do not.
+pub fn records_lock_flags(contents: &str) -> bool {
+    contents.lines().any(|x| x == LOCK_FLAGS_HEADER)
+}
+
 /// Tries to restore the lock options from the given lockfile.
 fn restore_lock_options<'o>(
     lockfile: &Path,
@@ -220,6 +418,9 @@ fn restore_lock_options<'o>(
 ) -> Result<Cow<'o, LockOptions>, Error> {
     if lockfile.is_file() {
         let requirements = fs::read_to_string(lockfile)?;
+        if !records_lock_flags(&requirements) {
+            warn_deprecated(Deprecation::UnflaggedLockfile(lockfile.to_path_buf()));
+        }
         Ok(LockOptions::restore(&requirements, lock_options)?)
     } else {
         Ok(Cow::Borrowed(lock_options))
@@ -299,12 +500,11 @@ fn find_exclusions(projects: &[PyProject]) -> Result<HashSet<Requirement>, Error
 }
 
 fn dump_dependencies(
-    pyproject: &PyProject,
     local_projects: &HashMap<String, String>,
     out: &mut fs::File,
-    dep_kind: DependencyKind,
+    deps: impl Iterator<Item = DependencyRef>,
 ) -> Result<(), Error> {
-    for dep in pyproject.iter_dependencies(dep_kind) {
+    for dep in deps {
         if let Ok(expanded_dep) = dep.expand(|_| {
             // we actually do not care what it expands to much, for as long
             // as the end result parses
@@ -340,7 +540,10 @@ pub fn update_single_project_lockfile(
 ) -> Result<(), Error> {
     echo!(if output, "Generating {} lockfile: {}", lock_mode, lockfile.display());
 
-    let lock_options = restore_lock_options(lockfile, lock_options)?;
+    let mut lock_options = restore_lock_options(lockfile, lock_options)?;
+    lock_options.to_mut().private_packages = std::iter::once(pyproject.normalized_name()?)
+        .chain(pyproject.private_packages())
+        .collect();
     let mut req_file = NamedTempFile::new()?;
 
     // virtual packages are themselves not installed
@@ -355,19 +558,59 @@ pub fn update_single_project_lockfile(
         )?;
     }
 
+    let name = pyproject.normalized_name()?;
+    let mut direct = Vec::new();
     for dep in pyproject.iter_dependencies(DependencyKind::Normal) {
         writeln!(req_file, "{}", dep)?;
     }
-    if lock_mode == LockMode::Dev {
-        for dep in pyproject.iter_dependencies(DependencyKind::Dev) {
+    collect_direct_requirements(
+        &name,
+        pyproject.iter_dependencies(DependencyKind::Normal),
+        &mut direct,
+    );
+    match lock_mode {
+        LockMode::Production => {}
+        LockMode::Dev => {
+            for dep in pyproject.iter_dependencies(DependencyKind::Dev) {
+                writeln!(req_file, "{}", dep)?;
+            }
+            collect_direct_requirements(
+                &format!("{} (dev)", name),
+                pyproject.iter_dependencies(DependencyKind::Dev),
+                &mut direct,
+            );
+        }
+        LockMode::Group(ref group) => {
+            let deps = pyproject
+                .dependency_groups()?
+                .remove(group)
+                .unwrap_or_default();
+            for dep in &deps {
+                writeln!(req_file, "{}", dep)?;
+            }
+            collect_direct_requirements(
+                &format!("{} (group {})", name, group),
+                deps.into_iter(),
+                &mut direct,
+            );
+        }
+    }
+    if let Some(ref variant) = lock_options.variant {
+        let variant = get_variant(&pyproject.variants()?, variant)?;
+        for dep in variant.iter_dependencies() {
             writeln!(req_file, "{}", dep)?;
         }
+        collect_direct_requirements(
+            &format!("variant {}", variant.name),
+            variant.iter_dependencies(),
+            &mut direct,
+        );
     }
 
     req_file.flush()?;
 
     let exclusions = find_exclusions(std::slice::from_ref(pyproject))?;
-    generate_lockfile(
+    let resolution_key = generate_lockfile(
         output,
         py_ver,
         &pyproject.workspace_path(),
@@ -379,10 +622,73 @@ pub fn update_single_project_lockfile(
         false,
         keyring_provider,
     )?;
+    report_resolution(
+        lockfile,
+        &pyproject.workspace_path(),
+        &pyproject.venv_path(),
+        &direct,
+        sources,
+        &lock_options,
+        resolution_key.as_deref(),
+        output,
+    )?;
 
     Ok(())
 }
 
+/// Writes the resolution report of a lockfile and prints it for `--explain`.
+///
+/// A report that was rendered for the same resolution is reused.  Only
+/// reports with metadata from the index record the resolution key, so that
+/// `rye lock` completes the report of a `rye sync`.
+#[allow(clippy::too_many_arguments)]
+fn report_resolution(
+    lockfile: &Path,
+    workspace_root: &Path,
+    venv: &Path,
+    direct: &[(String, Requirement)],
+    sources: &ExpandedSources,
+    lock_options: &LockOptions,
+    resolution_key: Option<&str>,
+    output: CommandOutput,
+) -> Result<(), Error> {
+    if !lock_options.report && !lock_options.explain {
+        return Ok(());
+    }
+    let path = get_report_path(lockfile);
+    let existing = resolution_key
+        .and_then(|_| fs::read_to_string(&path).ok())
+        .filter(|x| get_report_resolution_key(x) == resolution_key);
+    let report = match existing {
+        Some(report) => {
+            echo!(if verbose output, "reusing resolution report {}", path.display());
+            report
+        }
+        None => {
+            let sources = Some(sources).filter(|_| lock_options.report_from_index);
+            let resolution_key = resolution_key.filter(|_| sources.is_some());
+            let report = render_resolution_report(
+                lockfile,
+                workspace_root,
+                venv,
+                direct,
+                sources,
+                resolution_key,
+                output,
+            )?;
+            if lock_options.report {
+                write_atomic_with_backup(&path, &report)
+                    .path_context(&path, "unable to write resolution report")?;
+            }
+            report
+        }
+    };
+    if lock_options.explain {
+        echo!(if output, "{}", report.trim_end());
+    }
+    Ok(())
+}
+
 #[allow(clippy::too_many_arguments)]
 fn generate_lockfile(
     output: CommandOutput,
@@ -395,101 +701,116 @@ fn generate_lockfile(
     exclusions: &HashSet<Requirement>,
     no_deps: bool,
     keyring_provider: KeyringProvider,
-) -> Result<(), Error> {
-    let use_uv = Config::current().use_uv();
+) -> Result<Option<String>, Error> {
+    let backend = Backend::current();
+    let _heartbeat = Heartbeat::start("resolving dependencies");
     let scratch = tempfile::tempdir()?;
+    let _scratch_guard = RemoveOnInterrupt::new(scratch.path());
     let requirements_file = scratch.path().join("requirements.txt");
-    if lockfile.is_file() {
-        fs::copy(lockfile, &requirements_file)
-            .path_context(&requirements_file, "unable to restore requirements file")?;
-    } else if !use_uv {
-        fs::write(&requirements_file, b"").path_context(
-            &requirements_file,
-            "unable to write empty requirements file",
-        )?;
+
+    // explicit updates always have to go to the resolver
+    let cache_inputs = if Config::current().lock_cache()
+        && !lock_options.update_all
+        && lock_options.update.is_empty()
+    {
+        Some(hash_lock_inputs(
+            py_ver,
+            workspace_path,
+            requirements_file_in,
+            sources,
+            lock_options,
+            no_deps,
+            backend,
+        )?)
+    } else {
+        None
     };
+    let prior_lockfile = fs::read_to_string(lockfile).unwrap_or_default();
+    // without a prior lockfile the resolver picks the latest versions, which
+    // a cached resolution would no longer reflect.
+    let cached = cache_inputs
+        .as_ref()
+        .filter(|_| !prior_lockfile.trim().is_empty())
+        .and_then(|inputs| fs::read(get_lock_cache_path(inputs, &prior_lockfile)).ok());
 
-    if use_uv {
-        let upgrade = {
-            if lock_options.update_all {
-                UvPackageUpgrade::All
-            } else if !lock_options.update.is_empty() {
-                UvPackageUpgrade::Packages(lock_options.update.clone())
-            } else {
-                UvPackageUpgrade::Nothing
+    if let Some(ref cached) = cached {
+        echo!(if verbose output, "using cached resolution for {}", lockfile.display());
+        fs::write(&requirements_file, cached)
+            .path_context(&requirements_file, "unable to restore cached resolution")?;
+    } else {
+        backend.check_lock_options(lock_options, keyring_provider)?;
+        if lockfile.is_file() {
+            fs::copy(lockfile, &requirements_file)
+                .path_context(&requirements_file, "unable to restore requirements file")?;
+        }
+
+        // WebAssembly targets are resolved for linux, pinned to the packages of
+        // the Pyodide distribution, and checked afterwards.
+        let wasm_target = lock_options
+            .python_platform
+            .as_deref()
+            .and_then(WasmTarget::from_platform);
+        let pyodide = match wasm_target {
+            Some(WasmTarget::Emscripten) => Some(fetch_pyodide_lock(
+                lock_options
+                    .pyodide_version
+                    .as_deref()
+                    .unwrap_or(DEFAULT_PYODIDE_VERSION),
+                output,
+            )?),
+            _ => None,
+        };
+        let constraints = match pyodide {
+            Some(ref pyodide) => {
+                let path = scratch.path().join("pyodide-constraints.txt");
+                write_pyodide_constraints(pyodide, &path)?;
+                Some(path)
             }
+            None => None,
         };
 
-        UvBuilder::new()
-            .with_output(output.quieter())
-            .with_sources(sources.clone())
-            .with_workdir(workspace_path)
-            .ensure_exists()?
-            .lockfile(
-                py_ver,
+        // markers and wheel tags are evaluated for the simulated environment
+        let py_ver = match (&lock_options.python_version, &pyodide) {
+            (Some(version), _) => version.parse::<PythonVersion>()?,
+            (None, Some(pyodide)) => pyodide.python_version()?,
+            (None, None) => py_ver.clone(),
+        };
+
+        backend.resolve(
+            &ResolveRequest {
+                py_ver: &py_ver,
+                workspace_path,
                 requirements_file_in,
-                &requirements_file,
-                lock_options.pre,
-                env::var("__RYE_UV_EXCLUDE_NEWER").ok(),
-                upgrade,
+                requirements_file: &requirements_file,
+                sources,
+                lock_options,
+                python_platform: match wasm_target {
+                    Some(_) => Some(RESOLVE_PLATFORM.to_string()),
+                    None => lock_options.python_platform.clone(),
+                },
+                constraints: constraints.as_deref(),
+                no_deps,
                 keyring_provider,
-                lock_options.generate_hashes,
-            )?;
-    } else {
-        if keyring_provider != KeyringProvider::Disabled {
-            bail!("`--keyring-provider` option requires the uv backend");
-        }
-        let mut cmd = Command::new(get_pip_compile(py_ver, output)?);
-        // legacy pip tools requires some extra parameters
-        if get_pip_tools_version(py_ver) == PipToolsVersion::Legacy {
-            cmd.arg("--resolver=backtracking");
-        }
-        cmd.arg("--strip-extras")
-            .arg("--allow-unsafe")
-            .arg("--no-header")
-            .arg("--annotate")
-            .arg("--pip-args")
-            .arg(format!(
-                "--python-version=\"{}.{}.{}\"{}",
-                py_ver.major,
-                py_ver.minor,
-                py_ver.patch,
-                if no_deps { " --no-deps" } else { "" }
-            ));
-        if lock_options.pre {
-            cmd.arg("--pre");
-        }
-        if lock_options.generate_hashes {
-            cmd.arg("--generate-hashes");
-            cmd.arg("--reuse-hashes");
-        }
-
-        cmd.arg(if output == CommandOutput::Verbose {
-            "--verbose"
-        } else {
-            "-q"
-        })
-        .arg("-o")
-        .arg(&requirements_file)
-        .arg(requirements_file_in)
-        .current_dir(workspace_path)
-        .env("PYTHONWARNINGS", "ignore")
-        .env("PROJECT_ROOT", make_project_root_fragment(workspace_path));
-
-        for pkg in &lock_options.update {
-            cmd.arg("--upgrade-package");
-            cmd.arg(pkg);
-        }
-        if lock_options.update_all {
-            cmd.arg("--upgrade");
-        }
-        sources.add_as_pip_args(&mut cmd);
-        set_proxy_variables(&mut cmd);
-        let status = cmd.status().context("unable to run pip-compile")?;
-        if !status.success() {
-            bail!("failed to generate lockfile");
-        };
-    };
+            },
+            output,
+        )?;
+
+        if let Some(target) = wasm_target {
+            let resolved = fs::read_to_string(&requirements_file)
+                .path_context(&requirements_file, "unable to read resolver output")?;
+            check_resolution(target, &resolved, pyodide.as_ref(), sources, output)?;
+        }
+    }
+
+    let resolved = fs::read_to_string(&requirements_file)
+        .path_context(&requirements_file, "unable to read resolver output")?;
+    check_shadowing(
+        &resolved,
+        &lock_options.private_packages,
+        &lock_options.allow_shadowing,
+        sources,
+        output,
+    )?;
 
     finalize_lockfile(
         &requirements_file,
@@ -500,7 +821,38 @@ fn generate_lockfile(
         lock_options,
     )?;
 
-    Ok(())
+    // identifies the resolution so that its report is only rendered once
+    let resolution_key = cache_inputs
+        .as_ref()
+        .map(|inputs| lock_cache_key(inputs, &fs::read_to_string(lockfile).unwrap_or_default()));
+
+    if let (Some(inputs), None) = (cache_inputs, cached) {
+        // the result is stored for the prior lockfile and for the new one as
+        // locking again without changes uses the new lockfile as input.
+        let resolved = fs::read(&requirements_file)
+            .path_context(&requirements_file, "unable to read resolver output")?;
+        let cache_dir = get_lock_cache_dir();
+        fs::create_dir_all(&cache_dir).path_context(&cache_dir, "unable to create lock cache")?;
+        for prior in [
+            fs::read_to_string(lockfile).unwrap_or_default(),
+            prior_lockfile,
+        ] {
+            if prior.trim().is_empty() {
+                continue;
+            }
+            let path = get_lock_cache_path(&inputs, &prior);
+            fs::write(&path, &resolved).path_context(&path, "unable to write lock cache")?;
+        }
+        prune_lock_cache(&cache_dir);
+    }
+
+    if lock_options.structured {
+        write_structured_lockfile(lockfile, workspace_path, py_ver, sources, lock_options)?;
+    } else {
+        remove_structured_lockfile(lockfile)?;
+    }
+
+    Ok(resolution_key)
 }
 
 fn finalize_lockfile(
@@ -511,8 +863,7 @@ fn finalize_lockfile(
     sources: &ExpandedSources,
     lock_options: &LockOptions,
 ) -> Result<(), Error> {
-    let mut rv =
-        BufWriter::new(fs::File::create(out).path_context(out, "unable to finalize lockfile")?);
+    let mut rv = Vec::new();
     lock_options.write_header(&mut rv)?;
 
     // only if we are asked to include sources we do that.
@@ -525,6 +876,9 @@ fn finalize_lockfile(
         .path_context(generated, "unable to parse resolver output")?
         .lines()
     {
+        let line = make_portable_direct_reference(line, workspace_root);
+        let line = line.as_ref();
+
         // we deal with this explicitly.
         if line.trim().is_empty()
             || line.starts_with("--index-url ")
@@ -564,9 +918,602 @@ fn finalize_lockfile(
         } else if line.starts_with('#') {
             continue;
         }
+
+        // resolvers do not hash local files, a trailing backslash means
+        // that hashes follow already.
+        if lock_options.generate_hashes && !line.ends_with('\\') {
+            if let Some(hash) = hash_direct_reference(line, workspace_root)? {
+                writeln!(rv, "{} \\\n    --hash={}", line, hash)?;
+                continue;
+            }
+        }
         writeln!(rv, "{}", line)?;
     }
-    Ok(())
+    write_atomic_with_backup(out, rv).path_context(out, "unable to finalize lockfile")
+}
+
+/// Splits a requirement line with a direct reference into the part before
+/// the URL, the URL and the rest of the line (marker, hashes).
+fn split_direct_reference(line: &str) -> Option<(&str, &str, &str)> {
+    if line.starts_with(|c: char| c.is_whitespace() || c == '#' || c == '-') {
+        return None;
+    }
+    let (name, rest) = line.split_once(" @ ")?;
+    let end = rest
+        .find(|c: char| c.is_whitespace() || c == ';')
+        .unwrap_or(rest.len());
+    Some((name, &rest[..end], &rest[end..]))
+}
+
+/// Returns the local path a `file:` URL points to.
+///
+/// `${PROJECT_ROOT}` is expanded the same way as for the installers.
+fn get_direct_reference_path(url: &str, workspace_root: &Path) -> Option<PathBuf> {
+    let url = url.replace(
+        "${PROJECT_ROOT}",
+        &make_project_root_fragment(workspace_root),
+    );
+    let mut url = Url::parse(&url).ok()?;
+    if url.scheme() != "file" {
+        return None;
+    }
+    url.set_fragment(None);
+    url.to_file_path().ok()
+}
+
+/// Rewrites direct references to files within the workspace to be relative
+/// to `${PROJECT_ROOT}` so that the lockfile works in every checkout.
+fn make_portable_direct_reference<'a>(line: &'a str, workspace_root: &Path) -> Cow<'a, str> {
+    let Some((name, url, rest)) = split_direct_reference(line) else {
+        return Cow::Borrowed(line);
+    };
+    if url.contains("${PROJECT_ROOT}") {
+        return Cow::Borrowed(line);
+    }
+    let Some(path) = get_direct_reference_path(url, workspace_root) else {
+        return Cow::Borrowed(line);
+    };
+    if !path.starts_with(workspace_root) || path == workspace_root {
+        return Cow::Borrowed(line);
+    }
+    match make_relative_url(&path, workspace_root) {
+        Ok(rel_url) => Cow::Owned(format!(
+            "{} @ file:///${{PROJECT_ROOT}}/{}{}",
+            name,
+            rel_url.trim_start_matches("file:"),
+            rest
+        )),
+        Err(_) => Cow::Borrowed(line),
+    }
+}
+
+/// Returns the hash of the archive a direct reference points to.
+///
+/// Remote URLs are only hashed if they carry a `sha256` fragment, local
+/// files are hashed from disk.  Local directories are built for their
+/// metadata, so they are identified by the newest modification time of the
+/// files within them.
+fn hash_direct_reference(line: &str, workspace_root: &Path) -> Result<Option<String>, Error> {
+    let Some((_, url, _)) = split_direct_reference(line) else {
+        return Ok(None);
+    };
+    if let Some(hash) = url
+        .split_once('#')
+        .and_then(|x| x.1.split('&').find_map(|x| x.strip_prefix("sha256=")))
+    {
+        return Ok(Some(format!("sha256:{}", hash)));
+    }
+    match get_direct_reference_path(url, workspace_root) {
+        Some(path) if path.is_file() => {
+            let contents = fs::read(&path).path_context(&path, "unable to hash dependency")?;
+            Ok(Some(format!(
+                "sha256:{}",
+                hex::encode(Sha256::digest(contents))
+            )))
+        }
+        Some(path) if path.is_dir() => {
+            let newest = WalkDir::new(&path)
+                .into_iter()
+                .filter_entry(|x| {
+                    x.depth() == 0 || !x.file_name().to_string_lossy().starts_with('.')
+                })
+                .filter_map(|x| x.ok())
+                .filter_map(|x| x.metadata().ok()?.modified().ok())
+                .max()
+                .and_then(|x| x.duration_since(UNIX_EPOCH).ok())
+                .unwrap_or_default();
+            Ok(Some(format!("mtime:{}", newest.as_nanos())))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Returns the folder where resolver results are cached.
+pub fn get_lock_cache_dir() -> PathBuf {
+    get_app_dir().join("cache").join("lock")
+}
+
+/// Removes cached resolutions that were not written for a while.
+///
+/// Only the newest [`LOCK_CACHE_MAX_ENTRIES`] resolutions are kept and none
+/// older than [`LOCK_CACHE_MAX_AGE`].  Failures are ignored.
+fn prune_lock_cache(cache_dir: &Path) {
+    let Ok(entries) = fs::read_dir(cache_dir) else {
+        return;
+    };
+    let mut entries = entries
+        .filter_map(|x| x.ok())
+        .filter_map(|x| Some((x.metadata().ok()?.modified().ok()?, x.path())))
+        .collect::<Vec<_>>();
+    entries.sort_by(|a, b| b.0.cmp(&a.0));
+    let now = SystemTime::now();
+    for (idx, (modified, path)) in entries.into_iter().enumerate() {
+        let age = now.duration_since(modified).unwrap_or_default();
+        if idx >= LOCK_CACHE_MAX_ENTRIES || age > LOCK_CACHE_MAX_AGE {
+            fs::remove_file(path).ok();
+        }
+    }
+}
+
+/// Hashes everything that goes into a resolution except the prior lockfile.
+fn hash_lock_inputs(
+    py_ver: &PythonVersion,
+    workspace_path: &Path,
+    requirements_file_in: &Path,
+    sources: &ExpandedSources,
+    lock_options: &LockOptions,
+    no_deps: bool,
+    backend: Backend,
+) -> Result<Sha256, Error> {
+    let mut hasher = Sha256::new();
+    let mut add = |key: &str, value: &str| {
+        hasher.update(key.as_bytes());
+        hasher.update(b"\0");
+        hasher.update(value.as_bytes());
+        hasher.update(b"\0");
+    };
+    add("rye", env!("CARGO_PKG_VERSION"));
+    add("backend", &backend.to_string());
+    add("python", &py_ver.to_string());
+    add(
+        "python-version",
+        lock_options.python_version.as_deref().unwrap_or(""),
+    );
+    add(
+        "platform",
+        lock_options.python_platform.as_deref().unwrap_or(""),
+    );
+    add(
+        "pyodide-version",
+        lock_options.pyodide_version.as_deref().unwrap_or(""),
+    );
+    add("pre", &lock_options.pre.to_string());
+    add("generate-hashes", &lock_options.generate_hashes.to_string());
+    add("no-deps", &no_deps.to_string());
+    add(
+        "exclude-newer",
+        &env::var("__RYE_UV_EXCLUDE_NEWER").unwrap_or_default(),
+    );
+    add("workspace", &workspace_path.to_string_lossy());
+    for (url, default) in &sources.index_urls {
+        add(if *default { "index" } else { "extra-index" }, url.as_str());
+    }
+    for url in &sources.find_links {
+        add("find-links", url.as_str());
+    }
+    for host in sources.trusted_hosts.iter().collect::<BTreeSet<_>>() {
+        add("trusted-host", host);
+    }
+
+    // local projects contribute their metadata (eg: extras) to the resolution
+    let requirements = fs::read_to_string(requirements_file_in)
+        .path_context(requirements_file_in, "unable to read requirements")?;
+    add("requirements", &requirements);
+    for line in requirements.lines() {
+        if let Some(url) = line.strip_prefix("-e file:") {
+            let path = workspace_path
+                .join(url.split_once('[').map_or(url, |x| x.0).replace("%20", " "))
+                .join("pyproject.toml");
+            add(
+                "pyproject",
+                &strip_dependency_groups(&fs::read_to_string(&path).unwrap_or_default()),
+            );
+        } else if let Some(hash) = hash_direct_reference(line, workspace_path)? {
+            // replacing a local wheel with a different build has to re-lock
+            add("direct-reference", &hash);
+        }
+    }
+    Ok(hasher)
+}
+
+/// Removes the dependency groups from the contents of a `pyproject.toml`.
+///
+/// The dependencies of the locked group are part of the requirements, so
+/// changing one group does not invalidate the resolutions of the others.
+fn strip_dependency_groups(pyproject: &str) -> Cow<'_, str> {
+    let Ok(mut doc) = pyproject.parse::<toml_edit::DocumentMut>() else {
+        return Cow::Borrowed(pyproject);
+    };
+    match doc
+        .get_mut("tool")
+        .and_then(|x| x.get_mut("rye"))
+        .and_then(|x| x.as_table_like_mut())
+    {
+        Some(rye) if rye.remove("dependency-groups").is_some() => Cow::Owned(doc.to_string()),
+        _ => Cow::Borrowed(pyproject),
+    }
+}
+
+/// Returns the cache path of a resolution for the given prior lockfile.
+///
+/// Only the pins of the prior lockfile matter, the header and annotations
+/// are ignored.
+fn get_lock_cache_path(inputs: &Sha256, prior_lockfile: &str) -> PathBuf {
+    get_lock_cache_dir().join(format!("{}.txt", lock_cache_key(inputs, prior_lockfile)))
+}
+
+fn lock_cache_key(inputs: &Sha256, prior_lockfile: &str) -> String {
+    let mut hasher = inputs.clone();
+    for (key, entry) in parse_lockfile_entries(prior_lockfile) {
+        if !key.starts_with('-') {
+            hasher.update(entry.as_bytes());
+        }
+    }
+    hex::encode(hasher.finalize())[..32].to_string()
+}
+
+/// A package pinned in a lockfile.
+#[derive(Serialize, Debug, Default, PartialEq, Eq)]
+pub struct LockedPackage {
+    pub name: String,
+    pub version: Option<String>,
+    pub url: Option<String>,
+    pub editable: bool,
+    pub marker: Option<String>,
+    pub hashes: Vec<String>,
+    pub dependencies: BTreeSet<String>,
+}
+
+/// Returns the path of the structured lockfile that accompanies a lockfile.
+fn get_structured_lockfile_path(lockfile: &Path) -> PathBuf {
+    let mut rv = lockfile.as_os_str().to_owned();
+    rv.push(".toml");
+    PathBuf::from(rv)
+}
+
+/// Removes a structured lockfile left behind from when it was enabled.
+///
+/// Files that were not generated by rye are left alone.
+fn remove_structured_lockfile(lockfile: &Path) -> Result<(), Error> {
+    let path = get_structured_lockfile_path(lockfile);
+    match fs::read_to_string(&path) {
+        Ok(contents) if contents.starts_with("# generated by rye") => {
+            fs::remove_file(&path).path_context(&path, "unable to remove structured lockfile")
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Parses a finalized lockfile into its packages.
+///
+/// The `via` annotations are turned around into the dependencies of each
+/// package.  They come as `# via parent` for a single parent, or as `# via`
+/// followed by one `#   parent` line per parent.  Editable installs are named
+/// after the project they point to.
+pub fn parse_locked_packages(s: &str, workspace_root: &Path) -> Vec<LockedPackage> {
+    let mut rv: Vec<LockedPackage> = Vec::new();
+    let mut required_by = Vec::new();
+    let mut in_via = false;
+
+    for line in s.lines() {
+        if line.trim().is_empty() || line.starts_with('#') || line.starts_with("--") {
+            in_via = false;
+            continue;
+        }
+        if line.starts_with(char::is_whitespace) {
+            let Some(package) = rv.last_mut() else {
+                continue;
+            };
+            let line = line.trim().trim_end_matches('\\').trim();
+            let parent = if line == "# via" {
+                in_via = true;
+                None
+            } else if let Some(parent) = line.strip_prefix("# via ") {
+                in_via = false;
+                Some(parent)
+            } else if let Some(parent) = line.strip_prefix('#').filter(|_| in_via) {
+                Some(parent)
+            } else {
+                in_via = false;
+                if let Some(hash) = line.strip_prefix("--hash=") {
+                    package.hashes.push(hash.to_string());
+                }
+                None
+            };
+            // parents can carry a note (`my-project (pyproject.toml)`) and
+            // requirement files show up as `-r requirements.in`.
+            if let Some(parent) = parent
+                .and_then(|x| x.split_whitespace().next())
+                .filter(|x| !x.starts_with('-'))
+            {
+                required_by.push((normalize_package_name(parent), package.name.clone()));
+            }
+            continue;
+        }
+        in_via = false;
+
+        let line = line.trim_end_matches('\\').trim();
+        let package = if let Some(url) = line.strip_prefix("-e ") {
+            let url = url.trim();
+            let url = url.split_once('[').map_or(url, |x| x.0);
+            LockedPackage {
+                name: get_editable_project_name(url, workspace_root)
+                    .unwrap_or_else(|| url.to_string()),
+                url: Some(url.to_string()),
+                editable: true,
+                ..Default::default()
+            }
+        } else if let Ok(req) = line.parse::<Requirement>() {
+            let (version, url) = match req.version_or_url {
+                Some(VersionOrUrl::VersionSpecifier(ref specs)) => (
+                    specs
+                        .iter()
+                        .find(|x| *x.operator() == Operator::Equal)
+                        .map(|x| x.version().to_string()),
+                    None,
+                ),
+                Some(VersionOrUrl::Url(ref url)) => (None, Some(url.to_string())),
+                None => (None, None),
+            };
+            LockedPackage {
+                name: normalize_package_name(&req.name),
+                version,
+                url,
+                marker: req.marker.as_ref().map(|x| x.to_string()),
+                ..Default::default()
+            }
+        } else {
+            continue;
+        };
+        rv.push(package);
+    }
+
+    for (parent, child) in required_by {
+        if let Some(package) = rv.iter_mut().find(|x| x.name == parent) {
+            package.dependencies.insert(child);
+        }
+    }
+    rv
+}
+
+/// Removes the editable installs of the given projects from a lockfile.
+///
+/// Everything else, including editable installs of other local packages,
+/// is kept as is.
+pub fn strip_project_installs(s: &str, workspace_root: &Path, projects: &[String]) -> String {
+    let mut rv = String::new();
+    let mut skip_continuation = false;
+    for line in s.lines() {
+        let continued = line.trim_end().ends_with('\\');
+        if skip_continuation {
+            skip_continuation = continued;
+            continue;
+        }
+        if let Some(url) = line.strip_prefix("-e ") {
+            let url = url.trim_end_matches('\\').trim();
+            let url = url.split_once('[').map_or(url, |x| x.0);
+            if get_editable_project_name(url, workspace_root)
+                .map_or(false, |name| projects.contains(&name))
+            {
+                skip_continuation = continued;
+                continue;
+            }
+        }
+        rv.push_str(line);
+        rv.push('\n');
+    }
+    rv
+}
+
+/// Looks up the project name of an editable `file:` URL in the lockfile.
+fn get_editable_project_name(url: &str, workspace_root: &Path) -> Option<String> {
+    let path = Url::from_directory_path(workspace_root)
+        .ok()?
+        .join(url.strip_prefix("file:")?)
+        .ok()?
+        .to_file_path()
+        .ok()?;
+    let doc = fs::read_to_string(path.join("pyproject.toml"))
+        .ok()?
+        .parse::<toml_edit::DocumentMut>()
+        .ok()?;
+    doc.get("project")
+        .and_then(|x| x.get("name"))
+        .and_then(|x| x.as_str())
+        .map(normalize_package_name)
+}
+
+/// Writes the structured (version 2) lockfile next to a finalized lockfile.
+///
+/// The requirements style lockfile stays the source of truth for installing,
+/// the structured one carries the same pins with their dependency edges so
+/// that tools do not have to resolve again to inspect the dependency graph.
+fn write_structured_lockfile(
+    lockfile: &Path,
+    workspace_root: &Path,
+    py_ver: &PythonVersion,
+    sources: &ExpandedSources,
+    lock_options: &LockOptions,
+) -> Result<(), Error> {
+    let contents =
+        fs::read_to_string(lockfile).path_context(lockfile, "unable to read lockfile")?;
+    let mut doc = toml_edit::DocumentMut::new();
+    doc.decor_mut().set_prefix(
+        "# generated by rye\n# use `rye lock` or `rye sync` to update this lockfile\n\n",
+    );
+    doc.insert("version", toml_edit::value(STRUCTURED_LOCK_VERSION));
+
+    let mut options = toml_edit::Table::new();
+    options.insert(
+        "python",
+        toml_edit::value(match lock_options.python_version {
+            Some(ref version) => version.clone(),
+            None => py_ver.to_string(),
+        }),
+    );
+    options.insert("pre", toml_edit::value(lock_options.pre));
+    options.insert(
+        "features",
+        toml_edit::value(lock_options.features.iter().collect::<toml_edit::Array>()),
+    );
+    options.insert("all-features", toml_edit::value(lock_options.all_features));
+    options.insert(
+        "generate-hashes",
+        toml_edit::value(lock_options.generate_hashes),
+    );
+    if let Some(ref platform) = lock_options.python_platform {
+        options.insert("platform", toml_edit::value(platform));
+    }
+    options.insert(
+        "indexes",
+        toml_edit::value(
+            sources
+                .index_urls
+                .iter()
+                .map(|(url, _)| strip_credentials(url).to_string())
+                .collect::<toml_edit::Array>(),
+        ),
+    );
+    doc.insert("options", toml_edit::Item::Table(options));
+
+    let mut packages = toml_edit::ArrayOfTables::new();
+    for package in parse_locked_packages(&contents, workspace_root) {
+        let mut table = toml_edit::Table::new();
+        table.insert("name", toml_edit::value(&package.name));
+        if let Some(ref version) = package.version {
+            table.insert("version", toml_edit::value(version));
+        }
+        let source = if package.editable {
+            "editable"
+        } else if package.url.is_some() {
+            "url"
+        } else {
+            "registry"
+        };
+        table.insert("source", toml_edit::value(source));
+        if let Some(ref url) = package.url {
+            table.insert("url", toml_edit::value(url));
+        }
+        if let Some(ref marker) = package.marker {
+            table.insert("marker", toml_edit::value(marker));
+        }
+        if !package.hashes.is_empty() {
+            table.insert(
+                "hashes",
+                toml_edit::value(package.hashes.iter().collect::<toml_edit::Array>()),
+            );
+        }
+        table.insert(
+            "dependencies",
+            toml_edit::value(package.dependencies.iter().collect::<toml_edit::Array>()),
+        );
+        packages.push(table);
+    }
+    doc.insert("package", toml_edit::Item::ArrayOfTables(packages));
+
+    let path = get_structured_lockfile_path(lockfile);
+    write_atomic_with_backup(&path, doc.to_string())
+        .path_context(&path, "unable to write structured lockfile")
+}
+
+/// The result of a three-way merge of lockfiles.
+#[derive(Debug, Default)]
+pub struct MergedLockfile {
+    /// The merged lockfile with conflicting pins removed.
+    pub contents: String,
+    /// Normalized names of the packages that changed on both sides.
+    pub conflicts: Vec<String>,
+}
+
+/// Performs a three-way merge of lockfiles.
+///
+/// Pins that only changed on one side are taken from that side.  Pins that
+/// changed differently on both sides are dropped from the result and reported
+/// as conflicts so that the resolver can pick a consistent version for them.
+pub fn merge_lockfiles(base: &str, ours: &str, theirs: &str) -> MergedLockfile {
+    let base_entries = parse_lockfile_entries(base);
+    let our_entries = parse_lockfile_entries(ours);
+    let their_entries = parse_lockfile_entries(theirs);
+    let mut rv = MergedLockfile::default();
+
+    // retain our header so that the prior lock options can be restored
+    for line in ours
+        .lines()
+        .take_while(|x| x.is_empty() || x.starts_with('#'))
+    {
+        rv.contents.push_str(line);
+        rv.contents.push('\n');
+    }
+
+    let keys: BTreeSet<_> = base_entries
+        .keys()
+        .chain(our_entries.keys())
+        .chain(their_entries.keys())
+        .collect();
+    for key in keys {
+        let b = base_entries.get(key);
+        let o = our_entries.get(key);
+        let t = their_entries.get(key);
+        let merged = if o == t || t == b {
+            o
+        } else if o == b {
+            t
+        } else {
+            rv.conflicts.push(key.clone());
+            None
+        };
+        if let Some(entry) = merged {
+            rv.contents.push_str(entry);
+        }
+    }
+
+    rv
+}
+
+/// Splits a lockfile into its entries keyed by normalized package name.
+///
+/// Comments (including the `via` annotations) are not retained as they are
+/// regenerated when the lockfile is written out again.
+fn parse_lockfile_entries(s: &str) -> BTreeMap<String, String> {
+    let mut rv = BTreeMap::new();
+    let mut current: Option<(String, String)> = None;
+    for line in s.lines() {
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line.starts_with(char::is_whitespace) {
+            if let Some((_, ref mut entry)) = current {
+                if !line.trim_start().starts_with('#') {
+                    entry.push_str(line);
+                    entry.push('\n');
+                }
+            }
+            continue;
+        }
+        if let Some((key, entry)) = current.take() {
+            rv.insert(key, entry);
+        }
+        let spec = line.trim_end_matches('\\').trim();
+        let key = match spec.parse::<Requirement>() {
+            Ok(req) => normalize_package_name(&req.name),
+            Err(_) => spec.to_string(),
+        };
+        current = Some((key, format!("{}\n", line)));
+    }
+    if let Some((key, entry)) = current {
+        rv.insert(key, entry);
+    }
+    rv
 }
 
 pub fn make_project_root_fragment(root: &Path) -> String {
@@ -617,3 +1564,190 @@ fn test_make_relativec_url() {
         "file:."
     );
 }
+
+#[test]
+fn test_direct_references() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    fs::create_dir(root.join("wheels")).unwrap();
+    fs::write(root.join("wheels/foo-1.0-py3-none-any.whl"), b"wheel").unwrap();
+    let wheel_url = Url::from_file_path(root.join("wheels/foo-1.0-py3-none-any.whl")).unwrap();
+
+    let line = format!("foo @ {} ; sys_platform == 'linux'", wheel_url);
+    assert_eq!(
+        make_portable_direct_reference(&line, root),
+        "foo @ file:///${PROJECT_ROOT}/wheels/foo-1.0-py3-none-any.whl ; sys_platform == 'linux'"
+    );
+    assert_eq!(
+        make_portable_direct_reference("foo @ https://example.com/foo.whl", root),
+        "foo @ https://example.com/foo.whl"
+    );
+    assert_eq!(make_portable_direct_reference("foo==1.0", root), "foo==1.0");
+
+    let portable = "foo @ file:///${PROJECT_ROOT}/wheels/foo-1.0-py3-none-any.whl";
+    let expected = format!("sha256:{}", hex::encode(Sha256::digest(b"wheel")));
+    assert_eq!(
+        hash_direct_reference(portable, root).unwrap(),
+        Some(expected.clone())
+    );
+    assert_eq!(
+        hash_direct_reference(&format!("foo @ {}", wheel_url), root).unwrap(),
+        Some(expected)
+    );
+    assert_eq!(
+        hash_direct_reference("foo @ https://example.com/foo.whl#sha256=abc", root).unwrap(),
+        Some("sha256:abc".into())
+    );
+    assert_eq!(
+        hash_direct_reference("foo @ https://example.com/foo.whl", root).unwrap(),
+        None
+    );
+
+    let dir_ref = "foo @ file:///${PROJECT_ROOT}/wheels";
+    let before = hash_direct_reference(dir_ref, root).unwrap().unwrap();
+    assert!(before.starts_with("mtime:"));
+    fs::File::options()
+        .write(true)
+        .open(root.join("wheels/foo-1.0-py3-none-any.whl"))
+        .unwrap()
+        .set_modified(SystemTime::now() + Duration::from_secs(60))
+        .unwrap();
+    assert_ne!(
+        hash_direct_reference(dir_ref, root).unwrap().unwrap(),
+        before
+    );
+}
+
+#[test]
+fn test_prune_lock_cache() {
+    let dir = tempfile::tempdir().unwrap();
+    let old = dir.path().join("old.txt");
+    let new = dir.path().join("new.txt");
+    fs::write(&old, b"").unwrap();
+    fs::write(&new, b"").unwrap();
+    fs::File::options()
+        .write(true)
+        .open(&old)
+        .unwrap()
+        .set_modified(SystemTime::now() - LOCK_CACHE_MAX_AGE - Duration::from_secs(60))
+        .unwrap();
+    prune_lock_cache(dir.path());
+    assert!(!old.exists());
+    assert!(new.exists());
+}
+
+#[test]
+fn test_merge_lockfiles() {
+    let base = "# generated by rye\n\n-e file:.\nanyio==4.0.0\n    # via httpx\nidna==3.4\nsniffio==1.3.0\n";
+    let ours = "# generated by rye\n\n-e file:.\nanyio==4.1.0\n    # via httpx\nidna==3.6\nsniffio==1.3.0\n";
+    let theirs =
+        "# generated by rye\n\n-e file:.\nanyio==4.0.0\nidna==3.5\nsniffio==1.3.1\nzipp==3.17.0\n";
+    let merged = merge_lockfiles(base, ours, theirs);
+    assert_eq!(merged.conflicts, vec!["idna".to_string()]);
+    assert_eq!(
+        merged.contents,
+        "# generated by rye\n\n-e file:.\nanyio==4.1.0\nsniffio==1.3.1\nzipp==3.17.0\n"
+    );
+}
+
+#[test]
+fn test_parse_locked_packages() {
+    let lockfile = "# generated by rye\n\n-e file:.\nanyio==4.3.0 \\\n    --hash=sha256:abc\n    # via httpx\nhttpx==0.27.0\n    # via my-project\ncolorama==0.4.6 ; sys_platform == 'win32'\n    # via httpx\n# idna==3.6 (excluded)\n";
+    let packages = parse_locked_packages(lockfile, Path::new("/nonexistent"));
+    assert_eq!(packages.len(), 4);
+    assert_eq!(packages[0].name, "file:.");
+    assert!(packages[0].editable);
+    assert_eq!(packages[1].name, "anyio");
+    assert_eq!(packages[1].version.as_deref(), Some("4.3.0"));
+    assert_eq!(packages[1].hashes, vec!["sha256:abc".to_string()]);
+    assert_eq!(
+        packages[2].dependencies.iter().collect::<Vec<_>>(),
+        ["anyio", "colorama"]
+    );
+    assert!(packages[3]
+        .marker
+        .as_deref()
+        .map_or(false, |x| x.contains("sys_platform")));
+}
+
+#[test]
+fn test_parse_locked_packages_multiple_parents() {
+    let lockfile = "\
+-e file:.
+anyio==4.3.0
+    # via
+    #   httpx
+    #   starlette
+certifi==2024.2.2 \\
+    --hash=sha256:abc
+    # via
+    #   -r requirements.in
+    #   httpx
+httpx==0.27.0
+    # via my-project (pyproject.toml)
+starlette==0.37.2
+    # via my-project
+";
+    let packages = parse_locked_packages(lockfile, Path::new("/nonexistent"));
+    let get_deps = |name: &str| {
+        packages
+            .iter()
+            .find(|x| x.name == name)
+            .unwrap()
+            .dependencies
+            .iter()
+            .cloned()
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(get_deps("httpx"), ["anyio", "certifi"]);
+    assert_eq!(get_deps("starlette"), ["anyio"]);
+    assert_eq!(get_deps("anyio"), Vec::<String>::new());
+    assert_eq!(packages[2].hashes, vec!["sha256:abc".to_string()]);
+}
+
+#[test]
+fn test_lock_cache_key() {
+    let inputs = Sha256::new();
+    let key = lock_cache_key(&inputs, "# generated by rye\n-e file:.\nanyio==4.0.0\n");
+    assert_eq!(
+        key,
+        lock_cache_key(
+            &inputs,
+            "# other header\n-e file:.\nanyio==4.0.0\n    # via httpx\n"
+        )
+    );
+    assert_ne!(key, lock_cache_key(&inputs, "anyio==4.1.0\n"));
+    assert_ne!(
+        key,
+        lock_cache_key(&Sha256::new_with_prefix("x"), "anyio==4.0.0\n")
+    );
+}
+
+#[test]
+fn test_strip_dependency_groups() {
+    let a = "[project]\nname = \"foo\"\n\n[tool.rye.dependency-groups]\ndocs = [\"mkdocs\"]\n";
+    let b = "[project]\nname = \"foo\"\n\n[tool.rye.dependency-groups]\ndocs = [\"sphinx\"]\n";
+    assert_eq!(strip_dependency_groups(a), strip_dependency_groups(b));
+    assert!(!strip_dependency_groups(a).contains("mkdocs"));
+
+    let plain = "[project]\nname = \"foo\"\n";
+    assert!(matches!(strip_dependency_groups(plain), Cow::Borrowed(_)));
+}
+
+#[test]
+fn test_variant_lockfile_names() {
+    let name = LockMode::Dev.variant_lockfile_name(Some("gpu"));
+    assert_eq!(name, "requirements-dev.gpu.lock");
+    assert_eq!(
+        LockMode::from_lockfile_name(&name),
+        (LockMode::Dev, Some("gpu".to_string()))
+    );
+    assert_eq!(
+        LockMode::from_lockfile_name("requirements-docs.lock"),
+        (LockMode::Group("docs".into()), None)
+    );
+    assert_eq!(
+        LockMode::from_lockfile_name("requirements.lock"),
+        (LockMode::Production, None)
+    );
+}
@@ -1,24 +1,44 @@
 use std::sync::atomic::{AtomicBool, Ordering};
 
+use crate::utils::cleanup::{handle_interrupt, is_interrupted_error};
 use crate::utils::panic::trap_bad_pipe;
 use crate::utils::QuietExit;
 
 #[macro_use]
 mod tui;
 
+mod backend;
 mod bootstrap;
+mod bundle;
 mod cli;
 mod config;
 mod consts;
+mod deprecations;
+mod envs;
+mod index;
 mod installer;
 mod lock;
+mod native;
+mod notices;
 mod piptools;
 mod platform;
+mod policy;
+mod prefetch;
+mod project_template;
+mod provenance;
 mod pyproject;
+mod pyversion;
+mod schema;
+mod shadowing;
+mod shims;
 mod sources;
 mod sync;
+mod sysdeps;
 mod utils;
 mod uv;
+mod variants;
+mod venv;
+mod wasm;
 
 static SHOW_CONTINUE_PROMPT: AtomicBool = AtomicBool::new(false);
 static DISABLE_CTRLC_HANDLER: AtomicBool = AtomicBool::new(false);
@@ -33,19 +53,24 @@ pub fn disable_ctrlc_handler() {
     DISABLE_CTRLC_HANDLER.store(true, Ordering::Relaxed);
 }
 
+/// The exit code of a process that was stopped with ctrl-c.
+fn interrupted_exit_code() -> i32 {
+    if cfg!(windows) {
+        0xC000013Au32 as i32
+    } else {
+        130
+    }
+}
+
 pub fn main() {
     crate::utils::panic::set_panic_hook();
 
     ctrlc::set_handler(move || {
-        if !DISABLE_CTRLC_HANDLER.load(Ordering::Relaxed) {
+        if !DISABLE_CTRLC_HANDLER.load(Ordering::Relaxed) && handle_interrupt() {
             let term = console::Term::stderr();
             term.show_cursor().ok();
             term.flush().ok();
-            std::process::exit(if cfg!(windows) {
-                0xC000013Au32 as i32
-            } else {
-                130
-            });
+            std::process::exit(interrupted_exit_code());
         }
     })
     .unwrap();
@@ -60,6 +85,9 @@ pub fn main() {
                     err.exit_code()
                 } else if let Some(QuietExit(code)) = err.downcast_ref() {
                     *code
+                } else if is_interrupted_error(&err) {
+                    console::Term::stderr().show_cursor().ok();
+                    interrupted_exit_code()
                 } else {
                     error!("{:?}", err);
                     1
@@ -0,0 +1,304 @@
+//! Prebuilt native libraries of a project (`[tool.rye.native-deps]`).
+//!
+//! Building source distributions that link against libraries such as GDAL
+//! or OpenSSL fails on machines where the system packages cannot be
+//! installed.  A project can declare prebuilt bundles of these libraries
+//! which are unpacked into prefixes within the virtualenv.  The prefixes are
+//! exported through `PATH`, the library and include search paths and
+//! `PKG_CONFIG_PATH` while syncing and for `rye run`.
+use std::env::consts::{ARCH, OS};
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context, Error};
+use console::style;
+use serde::{Deserialize, Serialize};
+use toml_edit::DocumentMut;
+
+use crate::bootstrap::download_url;
+use crate::utils::{check_checksum, unpack_archive, CommandOutput, IoPathContext};
+
+/// The folder within the virtualenv that holds the prefixes.
+const NATIVE_DIR: &str = "rye-native";
+
+/// The file in a prefix that records what was unpacked.
+const MARKER: &str = "rye-native.json";
+
+/// A native library bundle declared by a project.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct NativeDep {
+    pub name: String,
+    /// The URL of the archive for this platform.
+    pub url: String,
+    /// The expected sha256 checksum of the archive.
+    pub sha256: Option<String>,
+    /// How many leading path components are removed when unpacking.
+    pub strip: usize,
+}
+
+/// Returns the platform key used in URLs and checksum tables (`linux-x86_64`).
+fn get_platform_key() -> String {
+    format!("{}-{}", OS, ARCH)
+}
+
+/// Reads the native dependencies for this platform from `[tool.rye.native-deps]`.
+///
+/// The `url` can contain `{os}` and `{arch}` placeholders.  The `sha256` is
+/// either a string or a table keyed by `<os>-<arch>`.
+pub fn get_native_deps(doc: &DocumentMut) -> Result<Vec<NativeDep>, Error> {
+    get_native_deps_for(doc, OS, ARCH)
+}
+
+fn get_native_deps_for(doc: &DocumentMut, os: &str, arch: &str) -> Result<Vec<NativeDep>, Error> {
+    let Some(deps) = doc
+        .get("tool")
+        .and_then(|x| x.get("rye"))
+        .and_then(|x| x.get("native-deps"))
+    else {
+        return Ok(Vec::new());
+    };
+    let deps = deps
+        .as_table_like()
+        .ok_or_else(|| anyhow!("tool.rye.native-deps must be a table"))?;
+
+    let platform = format!("{}-{}", os, arch);
+    let mut rv = Vec::new();
+    for (name, dep) in deps.iter() {
+        let path = format!("tool.rye.native-deps.{}", name);
+        if name.is_empty() || name.contains(|c| matches!(c, '/' | '\\' | '.')) {
+            bail!("{} is not a valid native dependency name", path);
+        }
+        let dep = dep
+            .as_table_like()
+            .ok_or_else(|| anyhow!("{} must be a table", path))?;
+        let url = dep
+            .get("url")
+            .and_then(|x| x.as_str())
+            .ok_or_else(|| anyhow!("{}.url must be a string", path))?
+            .replace("{os}", os)
+            .replace("{arch}", arch);
+        let sha256 = match dep.get("sha256") {
+            None => None,
+            Some(item) if item.is_str() => item.as_str().map(|x| x.to_string()),
+            Some(item) => {
+                let table = item
+                    .as_table_like()
+                    .ok_or_else(|| anyhow!("{}.sha256 must be a string or a table", path))?;
+                let sha256 = table
+                    .get(&platform)
+                    .ok_or_else(|| anyhow!("{}.sha256 has no checksum for {}", path, platform))?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("{}.sha256.{} must be a string", path, platform))?;
+                Some(sha256.to_string())
+            }
+        };
+        let strip = match dep.get("strip") {
+            Some(strip) => strip
+                .as_integer()
+                .and_then(|x| usize::try_from(x).ok())
+                .ok_or_else(|| anyhow!("{}.strip must be a positive integer", path))?,
+            None => 0,
+        };
+        rv.push(NativeDep {
+            name: name.to_string(),
+            url,
+            sha256,
+            strip,
+        });
+    }
+    Ok(rv)
+}
+
+/// Returns the prefixes of the native dependencies installed into a virtualenv.
+pub fn get_native_prefixes(venv: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(venv.join(NATIVE_DIR)) else {
+        return Vec::new();
+    };
+    let mut rv = entries
+        .filter_map(|x| x.ok())
+        .map(|x| x.path())
+        .filter(|x| x.join(MARKER).is_file())
+        .collect::<Vec<_>>();
+    rv.sort();
+    rv
+}
+
+/// Installs the native dependencies into the virtualenv.
+///
+/// Prefixes that are up to date are kept, the ones of dependencies that are
+/// no longer declared are removed.
+pub fn sync_native_deps(
+    deps: &[NativeDep],
+    venv: &Path,
+    output: CommandOutput,
+) -> Result<(), Error> {
+    let native_dir = venv.join(NATIVE_DIR);
+    for prefix in get_native_prefixes(venv) {
+        let name = prefix.file_name().map(|x| x.to_string_lossy().to_string());
+        if !deps.iter().any(|x| Some(&x.name) == name.as_ref()) {
+            echo!(if verbose output, "removing native dependency {}", prefix.display());
+            fs::remove_dir_all(&prefix)
+                .path_context(&prefix, "unable to remove native dependency")?;
+        }
+    }
+
+    for dep in deps {
+        let prefix = native_dir.join(&dep.name);
+        let marker = prefix.join(MARKER);
+        let installed = fs::read(&marker)
+            .ok()
+            .and_then(|x| serde_json::from_slice::<NativeDep>(&x).ok());
+        if installed.as_ref() == Some(dep) {
+            continue;
+        }
+
+        echo!(if output, "{} native dependency {}", style("Downloading").cyan(), dep.name);
+        let contents = download_url(&dep.url, output)
+            .with_context(|| format!("failed to download native dependency {}", dep.name))?;
+        match dep.sha256 {
+            Some(ref sha256) => check_checksum(&contents, sha256)
+                .with_context(|| format!("checksum of native dependency {} failed", dep.name))?,
+            None => warn!(
+                "native dependency {} has no sha256 for {}, its contents are not verified",
+                dep.name,
+                get_platform_key()
+            ),
+        }
+
+        if prefix.is_dir() {
+            fs::remove_dir_all(&prefix)
+                .path_context(&prefix, "unable to remove native dependency")?;
+        }
+        fs::create_dir_all(&prefix).path_context(&prefix, "unable to create native prefix")?;
+        unpack_archive(&contents, &prefix, dep.strip)
+            .with_context(|| format!("unable to unpack native dependency {}", dep.name))?;
+        fs::write(&marker, serde_json::to_vec(dep)?)
+            .path_context(&marker, "unable to write native dependency marker")?;
+    }
+    Ok(())
+}
+
+/// Returns the environment variables that expose the prefixes.
+///
+/// The prefixes are put in front of the current values.
+pub fn get_native_env(prefixes: &[PathBuf]) -> Result<Vec<(&'static str, OsString)>, Error> {
+    if prefixes.is_empty() {
+        return Ok(Vec::new());
+    }
+    let library_path = if cfg!(windows) {
+        "PATH"
+    } else if cfg!(target_os = "macos") {
+        "DYLD_LIBRARY_PATH"
+    } else {
+        "LD_LIBRARY_PATH"
+    };
+    let mut vars: Vec<(&'static str, Vec<PathBuf>)> = vec![
+        ("PATH", prefixes.iter().map(|x| x.join("bin")).collect()),
+        (
+            "PKG_CONFIG_PATH",
+            prefixes
+                .iter()
+                .flat_map(|x| {
+                    [
+                        x.join("lib").join("pkgconfig"),
+                        x.join("share").join("pkgconfig"),
+                    ]
+                })
+                .collect(),
+        ),
+        (
+            "CPATH",
+            prefixes.iter().map(|x| x.join("include")).collect(),
+        ),
+        (
+            "LIBRARY_PATH",
+            prefixes.iter().map(|x| x.join("lib")).collect(),
+        ),
+    ];
+    match vars.iter_mut().find(|(key, _)| *key == library_path) {
+        Some((_, paths)) => paths.extend(prefixes.iter().map(|x| x.join("lib"))),
+        None => vars.push((
+            library_path,
+            prefixes.iter().map(|x| x.join("lib")).collect(),
+        )),
+    }
+
+    let mut rv = Vec::new();
+    for (key, mut paths) in vars {
+        paths.retain(|x| x.is_dir());
+        if paths.is_empty() {
+            continue;
+        }
+        if let Some(current) = std::env::var_os(key) {
+            paths.extend(std::env::split_paths(&current));
+        }
+        let value = std::env::join_paths(paths)
+            .with_context(|| format!("unable to extend {} with native dependencies", key))?;
+        rv.push((key, value));
+    }
+    Ok(rv)
+}
+
+#[cfg(test)]
+mod test {
+    use toml_edit::DocumentMut;
+
+    use super::{get_native_deps_for, get_native_env, NativeDep};
+
+    #[test]
+    fn test_get_native_deps() {
+        let doc = r#"
+            [tool.rye.native-deps.gdal]
+            url = "https://example.com/gdal-3.8.5-{os}-{arch}.tar.gz"
+            sha256 = { linux-x86_64 = "abc", macos-aarch64 = "def" }
+            strip = 1
+
+            [tool.rye.native-deps.openssl]
+            url = "https://example.com/openssl-3.3.1.tar.gz"
+        "#
+        .parse::<DocumentMut>()
+        .unwrap();
+        assert_eq!(
+            get_native_deps_for(&doc, "macos", "aarch64").unwrap(),
+            vec![
+                NativeDep {
+                    name: "gdal".into(),
+                    url: "https://example.com/gdal-3.8.5-macos-aarch64.tar.gz".into(),
+                    sha256: Some("def".into()),
+                    strip: 1,
+                },
+                NativeDep {
+                    name: "openssl".into(),
+                    url: "https://example.com/openssl-3.3.1.tar.gz".into(),
+                    sha256: None,
+                    strip: 0,
+                },
+            ]
+        );
+        // a checksum table without this platform is an error
+        assert!(get_native_deps_for(&doc, "windows", "x86_64").is_err());
+    }
+
+    #[test]
+    fn test_get_native_env() {
+        let dir = tempfile::tempdir().unwrap();
+        let prefix = dir.path().join("gdal");
+        std::fs::create_dir_all(prefix.join("lib").join("pkgconfig")).unwrap();
+        std::fs::create_dir_all(prefix.join("include")).unwrap();
+
+        let env = get_native_env(&[prefix.clone()]).unwrap();
+        let get = |key: &str| {
+            env.iter()
+                .find(|x| x.0 == key)
+                .map(|x| std::env::split_paths(&x.1).next().unwrap())
+        };
+        assert_eq!(
+            get("PKG_CONFIG_PATH"),
+            Some(prefix.join("lib").join("pkgconfig"))
+        );
+        assert_eq!(get("CPATH"), Some(prefix.join("include")));
+        // there is no bin folder
+        assert!(env.iter().all(|x| x.0 != "PATH" || cfg!(windows)));
+    }
+}
@@ -0,0 +1,358 @@
+//! Third party notices for the locked dependencies of a project.
+//!
+//! Products that ship Python dependencies have to ship their license texts
+//! alongside.  The texts are taken from the `.dist-info` folders of the
+//! synced virtualenv: wheels carry them in `licenses/` (PEP 639) or, with
+//! older build backends, next to the `METADATA`.
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Context, Error};
+use pep508_rs::{MarkerEnvironment, MarkerTree, StringVersion};
+
+use crate::lock::LockedPackage;
+use crate::pyproject::normalize_package_name;
+use crate::sync::find_installed_dists;
+use crate::utils::{get_venv_python_bin, internal_command};
+
+/// Dumps the values of the PEP 508 environment markers of an interpreter.
+const MARKER_ENV_SCRIPT: &str = r#"
+import json, os, platform, sys
+def full_version(info):
+    rv = "%d.%d.%d" % info[:3]
+    if info.releaselevel != "final":
+        rv += info.releaselevel[0] + str(info.serial)
+    return rv
+print(json.dumps({
+    "implementation_name": sys.implementation.name,
+    "implementation_version": full_version(sys.implementation.version),
+    "os_name": os.name,
+    "platform_machine": platform.machine(),
+    "platform_python_implementation": platform.python_implementation(),
+    "platform_release": platform.release(),
+    "platform_system": platform.system(),
+    "platform_version": platform.version(),
+    "python_full_version": platform.python_version(),
+    "python_version": ".".join(platform.python_version_tuple()[:2]),
+    "sys_platform": sys.platform,
+}))
+"#;
+
+/// File names (without extension) that hold license texts.
+const LICENSE_FILE_STEMS: &[&str] = &["license", "licence", "copying", "notice", "copyright"];
+
+/// The license information of a locked package.
+#[derive(Debug)]
+pub struct PackageNotice {
+    pub name: String,
+    pub version: String,
+    /// The license declared in the metadata.
+    pub license: Option<String>,
+    /// The license files of the distribution with their contents.
+    pub texts: Vec<(String, String)>,
+}
+
+/// Returns the marker environment of the interpreter of a virtualenv.
+pub fn get_marker_environment(venv: &Path) -> Result<MarkerEnvironment, Error> {
+    let out = internal_command(get_venv_python_bin(venv))
+        .arg("-c")
+        .arg(MARKER_ENV_SCRIPT)
+        .output()
+        .context("unable to run the python of the virtualenv")?;
+    if !out.status.success() {
+        bail!(
+            "unable to determine the marker environment of the virtualenv:\n{}",
+            String::from_utf8_lossy(&out.stderr).trim_end()
+        );
+    }
+    parse_marker_environment(&out.stdout)
+}
+
+fn parse_marker_environment(json: &[u8]) -> Result<MarkerEnvironment, Error> {
+    let values: HashMap<String, String> =
+        serde_json::from_slice(json).context("invalid marker environment")?;
+    let get = |key: &str| {
+        values
+            .get(key)
+            .cloned()
+            .ok_or_else(|| anyhow!("marker environment lacks {}", key))
+    };
+    let get_version = |key: &str| {
+        let value = get(key)?;
+        StringVersion::from_str(&value)
+            .map_err(|_| anyhow!("invalid version '{}' for {}", value, key))
+    };
+    Ok(MarkerEnvironment {
+        implementation_name: get("implementation_name")?,
+        implementation_version: get_version("implementation_version")?,
+        os_name: get("os_name")?,
+        platform_machine: get("platform_machine")?,
+        platform_python_implementation: get("platform_python_implementation")?,
+        platform_release: get("platform_release")?,
+        platform_system: get("platform_system")?,
+        platform_version: get("platform_version")?,
+        python_full_version: get_version("python_full_version")?,
+        python_version: get_version("python_version")?,
+        sys_platform: get("sys_platform")?,
+    })
+}
+
+/// Checks if the marker of a locked package applies to an environment.
+///
+/// Markers that cannot be parsed are assumed to apply.
+fn marker_applies(pkg: &LockedPackage, env: &MarkerEnvironment) -> bool {
+    match pkg.marker.as_deref().map(MarkerTree::from_str) {
+        Some(Ok(marker)) => marker.evaluate(env, &[]),
+        Some(Err(_)) | None => true,
+    }
+}
+
+/// Collects the license information of the locked packages.
+///
+/// Editable packages are the project itself and skipped, as are packages
+/// whose marker excludes `env`.  Packages that are not installed in
+/// `site_packages` are returned in the second list.
+pub fn collect_notices(
+    site_packages: &Path,
+    locked: &[LockedPackage],
+    env: &MarkerEnvironment,
+) -> Result<(Vec<PackageNotice>, Vec<String>), Error> {
+    let installed = find_installed_dists(site_packages)?
+        .into_iter()
+        .map(|x| (x.name.clone(), x))
+        .collect::<HashMap<_, _>>();
+    let mut notices = Vec::new();
+    let mut missing = Vec::new();
+    for pkg in locked
+        .iter()
+        .filter(|x| !x.editable && marker_applies(x, env))
+    {
+        let Some(dist) = installed.get(&normalize_package_name(&pkg.name)) else {
+            missing.push(pkg.name.clone());
+            continue;
+        };
+        let metadata = fs::read_to_string(dist.dist_info.join("METADATA")).unwrap_or_default();
+        let headers = parse_headers(&metadata);
+        notices.push(PackageNotice {
+            name: pkg.name.clone(),
+            version: dist.version.clone(),
+            license: get_declared_license(&headers),
+            texts: find_license_files(&dist.dist_info, &headers)
+                .into_iter()
+                .filter_map(|(name, path)| Some((name, fs::read_to_string(path).ok()?)))
+                .collect(),
+        });
+    }
+    notices.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok((notices, missing))
+}
+
+/// Renders the notices into a `THIRD_PARTY_NOTICES` file.
+pub fn render_notices(project: &str, notices: &[PackageNotice]) -> String {
+    let rule = "=".repeat(80);
+    let mut rv = String::new();
+    writeln!(rv, "Third party notices for {}", project).unwrap();
+    writeln!(rv).unwrap();
+    writeln!(
+        rv,
+        "This software includes the following third party packages."
+    )
+    .unwrap();
+    for notice in notices {
+        writeln!(rv).unwrap();
+        writeln!(rv, "{}", rule).unwrap();
+        writeln!(rv, "{} {}", notice.name, notice.version).unwrap();
+        if let Some(ref license) = notice.license {
+            writeln!(rv, "License: {}", license).unwrap();
+        }
+        writeln!(rv, "{}", rule).unwrap();
+        if notice.texts.is_empty() {
+            writeln!(rv).unwrap();
+            writeln!(rv, "No license text was found in the distribution.").unwrap();
+        }
+        for (name, text) in &notice.texts {
+            writeln!(rv).unwrap();
+            if notice.texts.len() > 1 {
+                writeln!(rv, "--- {} ---", name).unwrap();
+                writeln!(rv).unwrap();
+            }
+            writeln!(rv, "{}", text.trim_end()).unwrap();
+        }
+    }
+    rv
+}
+
+/// Parses the headers of a `METADATA` file, joining continuation lines.
+fn parse_headers(metadata: &str) -> Vec<(String, String)> {
+    let mut rv: Vec<(String, String)> = Vec::new();
+    for line in metadata.lines().take_while(|x| !x.is_empty()) {
+        if line.starts_with(char::is_whitespace) {
+            if let Some((_, value)) = rv.last_mut() {
+                value.push('\n');
+                value.push_str(line.trim());
+            }
+        } else if let Some((key, value)) = line.split_once(':') {
+            rv.push((key.trim().to_string(), value.trim().to_string()));
+        }
+    }
+    rv
+}
+
+fn get_headers<'a>(headers: &'a [(String, String)], key: &'a str) -> impl Iterator<Item = &'a str> {
+    headers
+        .iter()
+        .filter(move |(k, _)| k.eq_ignore_ascii_case(key))
+        .map(|(_, v)| v.as_str())
+}
+
+/// Returns the declared license of a distribution.
+///
+/// This is the SPDX expression if there is one.  Old style `License` fields
+/// often contain the full text, these are only used if short.  Otherwise the
+/// trove classifiers are used.
+fn get_declared_license(headers: &[(String, String)]) -> Option<String> {
+    if let Some(expr) = get_headers(headers, "License-Expression").next() {
+        return Some(expr.to_string());
+    }
+    if let Some(license) = get_headers(headers, "License")
+        .find(|x| !x.is_empty() && !x.contains('\n') && x.len() <= 80 && *x != "UNKNOWN")
+    {
+        return Some(license.to_string());
+    }
+    let classifiers = get_headers(headers, "Classifier")
+        .filter_map(|x| x.strip_prefix("License :: "))
+        .filter_map(|x| x.rsplit(" :: ").next())
+        .collect::<Vec<_>>();
+    if classifiers.is_empty() {
+        None
+    } else {
+        Some(classifiers.join(", "))
+    }
+}
+
+/// Finds the license files of a distribution.
+///
+/// `License-File` headers are authoritative.  Without them the `licenses`
+/// folder and the `.dist-info` itself are searched for the usual names.
+fn find_license_files(dist_info: &Path, headers: &[(String, String)]) -> Vec<(String, PathBuf)> {
+    let mut rv = Vec::new();
+    for name in get_headers(headers, "License-File") {
+        if let Some(path) = [dist_info.join("licenses").join(name), dist_info.join(name)]
+            .into_iter()
+            .find(|x| x.is_file())
+        {
+            rv.push((name.to_string(), path));
+        }
+    }
+    if !rv.is_empty() {
+        return rv;
+    }
+    for dir in [dist_info.join("licenses"), dist_info.to_path_buf()] {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        let mut found = entries
+            .filter_map(|x| x.ok())
+            .map(|x| x.path())
+            .filter(|x| x.is_file() && is_license_file(x))
+            .map(|x| {
+                let name = x.strip_prefix(&dir).unwrap_or(&x).display().to_string();
+                (name, x)
+            })
+            .collect::<Vec<_>>();
+        found.sort();
+        rv.extend(found);
+    }
+    rv
+}
+
+fn is_license_file(path: &Path) -> bool {
+    path.file_stem()
+        .map(|x| x.to_string_lossy().to_ascii_lowercase())
+        .map_or(false, |stem| {
+            LICENSE_FILE_STEMS
+                .iter()
+                .any(|x| stem == *x || stem.starts_with(&format!("{}-", x)))
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use super::{
+        collect_notices, get_declared_license, parse_headers, parse_marker_environment,
+        render_notices,
+    };
+    use crate::lock::LockedPackage;
+
+    #[test]
+    fn test_get_declared_license() {
+        let headers = parse_headers(
+            "Metadata-Version: 2.1\nName: foo\nLicense: Copyright (c) Someone\n        \
+             All rights reserved.\nClassifier: License :: OSI Approved :: BSD License\n\nbody",
+        );
+        assert_eq!(
+            get_declared_license(&headers).as_deref(),
+            Some("BSD License")
+        );
+        let headers = parse_headers("Name: foo\nLicense-Expression: MIT OR Apache-2.0\n");
+        assert_eq!(
+            get_declared_license(&headers).as_deref(),
+            Some("MIT OR Apache-2.0")
+        );
+    }
+
+    #[test]
+    fn test_collect_notices() {
+        let dir = tempfile::tempdir().unwrap();
+        let dist_info = dir.path().join("Foo_Bar-1.0.dist-info");
+        fs::create_dir_all(dist_info.join("licenses")).unwrap();
+        fs::write(
+            dist_info.join("METADATA"),
+            "Metadata-Version: 2.4\nName: Foo_Bar\nVersion: 1.0\n\
+             License-Expression: MIT\nLicense-File: LICENSE\n",
+        )
+        .unwrap();
+        fs::write(dist_info.join("licenses").join("LICENSE"), "MIT License\n").unwrap();
+        let locked = [
+            ("foo-bar", None),
+            ("missing", None),
+            ("pywin32", Some("sys_platform == 'win32'")),
+        ]
+        .iter()
+        .map(|(name, marker)| LockedPackage {
+            name: name.to_string(),
+            version: Some("1.0".into()),
+            url: None,
+            editable: false,
+            marker: marker.map(|x| x.to_string()),
+            hashes: Vec::new(),
+            dependencies: Default::default(),
+        })
+        .collect::<Vec<_>>();
+        let env = parse_marker_environment(
+            br#"{"implementation_name": "cpython", "implementation_version": "3.12.1",
+                "os_name": "posix", "platform_machine": "x86_64",
+                "platform_python_implementation": "CPython", "platform_release": "6.1.0",
+                "platform_system": "Linux", "platform_version": "1 SMP",
+                "python_full_version": "3.12.1", "python_version": "3.12",
+                "sys_platform": "linux"}"#,
+        )
+        .unwrap();
+
+        let (notices, missing) = collect_notices(dir.path(), &locked, &env).unwrap();
+        assert_eq!(missing, vec!["missing".to_string()]);
+        assert_eq!(notices.len(), 1);
+        assert_eq!(
+            notices[0].texts,
+            vec![("LICENSE".into(), "MIT License\n".into())]
+        );
+        let rendered = render_notices("my-project", &notices);
+        assert!(rendered.contains("foo-bar 1.0\nLicense: MIT\n"));
+        assert!(rendered.ends_with("\nMIT License\n"));
+    }
+}
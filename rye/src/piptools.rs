@@ -1,22 +1,80 @@
+//! The pip-tools backend.
+//!
+//! Lockfiles are resolved with `pip-compile` and synced with `pip-sync` from
+//! a virtualenv of their own.  Everything else is installed with the pip of
+//! the self venv, and new dependencies are looked up with unearth.
 use std::fs;
-use std::path::PathBuf;
-use std::process::Command;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::str::FromStr;
 
-use anyhow::{bail, Context, Error};
+use anyhow::{anyhow, bail, Context, Error};
+use pep440_rs::{Operator, Version, VersionSpecifier, VersionSpecifiers};
+use pep508_rs::{Requirement, VersionOrUrl};
+use serde::Deserialize;
+use tempfile::{tempdir, NamedTempFile};
 
-use crate::bootstrap::ensure_self_venv;
+use crate::backend::{InstallOptions, ResolveRequest};
+use crate::bootstrap::{ensure_self_venv, get_pip_module};
 use crate::consts::VENV_BIN;
+use crate::lock::make_project_root_fragment;
 use crate::platform::get_app_dir;
+use crate::pyproject::{ExpandedSources, PyProject};
 use crate::sources::py::PythonVersion;
 use crate::sync::create_virtualenv;
-use crate::utils::{get_venv_python_bin, CommandOutput, IoPathContext};
+use crate::sysdeps::print_build_hints;
+use crate::utils::cleanup::{run_interruptible, run_interruptible_capture_stderr};
+use crate::utils::{
+    format_requirement, get_venv_python_bin, internal_command, set_network_timeouts,
+    set_proxy_variables, symlink_dir, CommandOutput, IoPathContext, Verbosity,
+};
 
-// When changing these, also update `SELF_VERSION` in bootstrap.rs to ensure
-// that the internals are re-created.
+// When changing these, also bump `SELF_VERSION` in bootstrap.rs without adding
+// an entry to `SELF_MIGRATIONS`.  The self venv is then rebuilt from scratch,
+// which also removes the pip-tools virtualenvs so they get re-created.
 pub const LATEST_PIP: &str = "pip==23.3.2";
 const PIP_TOOLS_LATEST_REQ: &[&str] = &[LATEST_PIP, "pip-tools==7.3.0"];
 const PIP_TOOLS_LEGACY_REQ: &[&str] = &["pip==22.2.0", "pip-tools==6.14.0"];
 
+const PACKAGE_FINDER_SCRIPT: &str = r#"
+import sys
+import json
+from unearth.finder import PackageFinder
+from unearth.session import PyPISession
+from packaging.version import Version
+
+py_ver = sys.argv[1]
+package = sys.argv[2]
+sources = json.loads(sys.argv[3])
+pre = len(sys.argv) > 4 and sys.argv[4] == "--pre"
+
+finder = PackageFinder(
+    index_urls=[x[0] for x in sources["index_urls"]],
+    find_links=sources["find_links"],
+    trusted_hosts=sources["trusted_hosts"],
+)
+if py_ver:
+    finder.target_python.py_ver = tuple(map(int, py_ver.split('.')))
+choices = iter(finder.find_matches(package))
+if not pre:
+    choices = (m for m in choices if not(m.version and Version(m.version).is_prerelease))
+
+print(json.dumps([x.as_json() for x in choices]))
+"#;
+
+#[derive(Deserialize, Debug)]
+struct Match {
+    name: String,
+    version: Option<String>,
+    link: Option<Link>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Link {
+    requires_python: Option<String>,
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
 /// Which version of piptools are in use?
 pub enum PipToolsVersion {
@@ -57,7 +115,7 @@ fn get_pip_tools_bin(py_ver: &PythonVersion, output: CommandOutput) -> Result<Pa
     echo!(if output, "Creating virtualenv for pip-tools");
     create_virtualenv(output, &self_venv, py_ver, &venv, "pip-tools")?;
 
-    let mut cmd = Command::new(self_venv.join(VENV_BIN).join("pip"));
+    let mut cmd = internal_command(self_venv.join(VENV_BIN).join("pip"));
     cmd.arg("--python")
         .arg(&py)
         .arg("install")
@@ -65,7 +123,7 @@ fn get_pip_tools_bin(py_ver: &PythonVersion, output: CommandOutput) -> Result<Pa
         .args(version.requirements())
         .arg("-q")
         .env("PIP_DISABLE_PIP_VERSION_CHECK", "1");
-    if output == CommandOutput::Verbose {
+    if output.child() == Verbosity::Verbose {
         cmd.arg("--verbose");
     } else {
         cmd.arg("--quiet");
@@ -102,3 +160,373 @@ pub fn get_pip_compile(py_ver: &PythonVersion, output: CommandOutput) -> Result<
         .join(VENV_BIN)
         .join("pip-compile"))
 }
+
+/// Creates a virtualenv with the `venv` module, seeded with pip.
+///
+/// This is how internal virtualenvs are created without uv.  The marker is
+/// left to the caller.
+pub fn create_venv_with_pip(py_bin: &Path, venv_dir: &Path) -> Result<(), Error> {
+    let mut cmd = internal_command(py_bin);
+    cmd.arg("-m").arg("venv").arg(venv_dir);
+    let status = run_interruptible(&mut cmd)
+        .with_context(|| format!("unable to create venv using {}", py_bin.display()))?;
+    if !status.success() {
+        bail!("failed to create venv using {}", py_bin.display());
+    }
+    Ok(())
+}
+
+/// Installs or upgrades requirements with the pip of a virtualenv.
+pub fn pip_install_requirements(
+    venv_dir: &Path,
+    requirements: &str,
+    output: CommandOutput,
+) -> Result<(), Error> {
+    let mut req_file = NamedTempFile::new()?;
+    writeln!(req_file, "{}", requirements)?;
+    let mut cmd = internal_command(get_venv_python_bin(venv_dir));
+    cmd.arg("-m")
+        .arg("pip")
+        .arg("install")
+        .arg("--upgrade")
+        .arg("-r")
+        .arg(req_file.path())
+        .env("PIP_DISABLE_PIP_VERSION_CHECK", "1");
+    if output.child() == Verbosity::Verbose {
+        cmd.arg("--verbose");
+    } else {
+        cmd.arg("--quiet");
+        cmd.env("PYTHONWARNINGS", "ignore");
+    }
+    let status = run_interruptible(&mut cmd)
+        .with_context(|| format!("unable to install into venv at {}", venv_dir.display()))?;
+    if !status.success() {
+        bail!(
+            "failed to install requirements into venv at {}",
+            venv_dir.display()
+        );
+    }
+    Ok(())
+}
+
+/// Returns a command that runs the pip of the self venv against a virtualenv.
+fn self_pip_command(venv: &Path, output: CommandOutput) -> Result<Command, Error> {
+    let self_venv = ensure_self_venv(output)?;
+    let mut cmd = internal_command(self_venv.join(VENV_BIN).join("pip"));
+    cmd.arg("--python")
+        .arg(get_venv_python_bin(venv))
+        .env("PYTHONWARNINGS", "ignore")
+        .env("PIP_DISABLE_PIP_VERSION_CHECK", "1");
+    Ok(cmd)
+}
+
+/// Installs requirements into a virtualenv with the pip of the self venv.
+pub fn pip_install(
+    venv: &Path,
+    requirements: &[Requirement],
+    options: &InstallOptions,
+    output: CommandOutput,
+) -> Result<(), Error> {
+    let mut cmd = self_pip_command(venv, output)?;
+    cmd.arg("install");
+    options.sources.add_as_pip_args(&mut cmd);
+    if output.child() == Verbosity::Verbose {
+        cmd.arg("--verbose");
+    } else if output.child() == Verbosity::Quiet {
+        cmd.arg("-q");
+    }
+    cmd.arg("--");
+    for requirement in requirements {
+        cmd.arg(requirement.to_string());
+    }
+    // we don't support versions below 3.7, but for 3.7 we need importlib-metadata
+    // to be installed
+    if options.importlib_workaround {
+        cmd.arg("importlib-metadata==6.6.0");
+    }
+    if let Some(ref workdir) = options.workdir {
+        cmd.current_dir(workdir);
+    }
+    let status = run_interruptible(&mut cmd)?;
+    if !status.success() {
+        bail!("failed to install packages into {}", venv.display());
+    }
+    Ok(())
+}
+
+/// Installs the packages of a lockfile without their dependencies.
+pub fn pip_install_lockfile(
+    venv: &Path,
+    lockfile: &Path,
+    options: &InstallOptions,
+    output: CommandOutput,
+) -> Result<(), Error> {
+    let mut cmd = self_pip_command(venv, output)?;
+    cmd.arg("install").arg("--no-deps");
+    options.sources.add_as_pip_args(&mut cmd);
+    if output.child() == Verbosity::Verbose {
+        cmd.arg("--verbose");
+    } else if output.child() == Verbosity::Quiet {
+        cmd.arg("-q");
+    }
+    cmd.arg("-r").arg(lockfile);
+    if let Some(ref workdir) = options.workdir {
+        cmd.current_dir(workdir);
+    }
+    let status = run_interruptible(&mut cmd)?;
+    if !status.success() {
+        bail!("failed to install packages into {}", venv.display());
+    }
+    Ok(())
+}
+
+/// Syncs a virtualenv to a lockfile with `pip-sync`.
+pub fn pip_sync(
+    venv: &Path,
+    py_ver: &PythonVersion,
+    lockfile: &Path,
+    options: &InstallOptions,
+    output: CommandOutput,
+) -> Result<(), Error> {
+    let tempdir = tempdir()?;
+    let mut pip_sync_cmd = internal_command(get_pip_sync(py_ver, output)?);
+
+    // we need to run this after we have run the `get_pip_sync` command
+    // as this is what bootstraps or updates the pip tools installation.
+    // This is needed as on unix platforms we need to search the module path.
+    symlink_dir(
+        get_pip_module(&get_pip_tools_venv_path(py_ver)).context("could not locate pip")?,
+        tempdir.path().join("pip"),
+    )
+    .context("failed linking pip module into for pip-sync")?;
+
+    if let Some(ref workdir) = options.workdir {
+        pip_sync_cmd
+            .env("PROJECT_ROOT", make_project_root_fragment(workdir))
+            .current_dir(workdir);
+    }
+    pip_sync_cmd
+        .env("PYTHONPATH", tempdir.path())
+        .arg("--python-executable")
+        .arg(get_venv_python_bin(venv))
+        .arg("--pip-args")
+        .arg(if options.compile_bytecode == Some(false) {
+            "--no-deps --no-compile"
+        } else {
+            "--no-deps"
+        });
+
+    if output.child() != Verbosity::Quiet {
+        pip_sync_cmd.env("PYTHONWARNINGS", "ignore");
+    }
+
+    options.sources.add_as_pip_args(&mut pip_sync_cmd);
+
+    pip_sync_cmd.arg(lockfile);
+
+    if output.child() == Verbosity::Verbose {
+        pip_sync_cmd.arg("--verbose");
+    } else if output.child() == Verbosity::Quiet {
+        pip_sync_cmd.arg("-q");
+    }
+    set_proxy_variables(&mut pip_sync_cmd);
+    set_network_timeouts(&mut pip_sync_cmd);
+    let (status, log) =
+        run_interruptible_capture_stderr(&mut pip_sync_cmd).context("unable to run pip-sync")?;
+
+    if !status.success() {
+        print_build_hints(&log);
+        bail!("Installation of dependencies failed");
+    }
+    Ok(())
+}
+
+/// Resolves requirements into a lockfile with `pip-compile`.
+pub fn pip_compile(request: &ResolveRequest, output: CommandOutput) -> Result<(), Error> {
+    let py_ver = request.py_ver;
+    let lock_options = request.lock_options;
+    // pip-compile starts from the prior resolution in the output file
+    if !request.requirements_file.is_file() {
+        fs::write(request.requirements_file, b"").path_context(
+            request.requirements_file,
+            "unable to write empty requirements file",
+        )?;
+    }
+
+    let mut cmd = internal_command(get_pip_compile(py_ver, output)?);
+    // legacy pip tools requires some extra parameters
+    if get_pip_tools_version(py_ver) == PipToolsVersion::Legacy {
+        cmd.arg("--resolver=backtracking");
+    }
+    cmd.arg("--strip-extras")
+        .arg("--allow-unsafe")
+        .arg("--no-header")
+        .arg("--annotate")
+        .arg("--pip-args")
+        .arg(format!(
+            "--python-version=\"{}.{}.{}\"{}",
+            py_ver.major,
+            py_ver.minor,
+            py_ver.patch,
+            if request.no_deps { " --no-deps" } else { "" }
+        ));
+    if lock_options.pre {
+        cmd.arg("--pre");
+    }
+    if lock_options.generate_hashes {
+        cmd.arg("--generate-hashes");
+        cmd.arg("--reuse-hashes");
+    }
+
+    cmd.arg(if output.child() == Verbosity::Verbose {
+        "--verbose"
+    } else {
+        "-q"
+    })
+    .arg("-o")
+    .arg(request.requirements_file)
+    .arg(request.requirements_file_in)
+    .current_dir(request.workspace_path)
+    .env("PYTHONWARNINGS", "ignore")
+    .env(
+        "PROJECT_ROOT",
+        make_project_root_fragment(request.workspace_path),
+    );
+
+    for pkg in &lock_options.update {
+        cmd.arg("--upgrade-package");
+        cmd.arg(pkg);
+    }
+    if lock_options.update_all {
+        cmd.arg("--upgrade");
+    }
+    request.sources.add_as_pip_args(&mut cmd);
+    set_proxy_variables(&mut cmd);
+    set_network_timeouts(&mut cmd);
+    let status = run_interruptible(&mut cmd).context("unable to run pip-compile")?;
+    if !status.success() {
+        bail!("failed to generate lockfile");
+    }
+    Ok(())
+}
+
+/// Lists the packages of a virtualenv with the pip of the self venv.
+pub fn pip_freeze(venv: &Path, output: CommandOutput) -> Result<(), Error> {
+    let status = self_pip_command(venv, output)?.arg("freeze").status()?;
+    if !status.success() {
+        bail!("failed to print dependencies via pip");
+    }
+    Ok(())
+}
+
+/// Finds the latest version of a requirement with unearth.
+///
+/// The returned requirement has the name as it is on the index and pins
+/// the version that was found.
+pub fn find_latest_with_unearth(
+    pyproject: &PyProject,
+    py_ver: &PythonVersion,
+    requirement: &Requirement,
+    pre: bool,
+    output: CommandOutput,
+) -> Result<Requirement, Error> {
+    let python_path = ensure_self_venv(output)?.join(VENV_BIN).join("python");
+    let sources = ExpandedSources::from_sources(&pyproject.sources()?)?;
+    let find = |py_ver: Option<&PythonVersion>, pre: bool| {
+        find_best_matches_with_unearth(&python_path, &sources, py_ver, requirement, pre)
+    };
+    let matches = find(Some(py_ver), pre)?;
+    let Some(m) = matches.into_iter().next() else {
+        let all_matches = find(None, pre).unwrap_or_default();
+        if all_matches.is_empty() {
+            // if we did not consider pre-releases, maybe we could find it by doing so.  In
+            // that case give the user a helpful warning before erroring.
+            if !pre {
+                let all_pre_matches = find(None, true).unwrap_or_default();
+                if let Some(pre) = all_pre_matches.into_iter().next() {
+                    warn!(
+                        "{} ({}) was found considering pre-releases.  Pass --pre to allow use.",
+                        pre.name,
+                        pre.version.unwrap_or_default()
+                    );
+                }
+                bail!(
+                    "did not find package '{}' without using pre-releases.",
+                    format_requirement(requirement)
+                );
+            } else {
+                bail!("did not find package '{}'", format_requirement(requirement));
+            }
+        } else {
+            if !output.is_quiet() {
+                echo!("Available package versions:");
+                for pkg in all_matches {
+                    echo!(
+                        "  {} ({}) requires Python {}",
+                        pkg.name,
+                        pkg.version.unwrap_or_default(),
+                        pkg.link
+                            .as_ref()
+                            .and_then(|x| x.requires_python.as_ref())
+                            .map_or("unknown", |x| x as &str)
+                    );
+                }
+                echo!("A possible solution is to raise the version in `requires-python` in `pyproject.toml`.");
+            }
+            bail!(
+                "did not find a version of package '{}' compatible with this version of Python.",
+                format_requirement(requirement)
+            );
+        }
+    };
+
+    let mut rv = requirement.clone();
+    rv.name = m.name;
+    if let Some(version) = m.version {
+        let version =
+            Version::from_str(&version).map_err(|msg| anyhow!("invalid version: {}", msg))?;
+        rv.version_or_url = Some(VersionOrUrl::VersionSpecifier(
+            VersionSpecifiers::from_iter([VersionSpecifier::new(Operator::Equal, version, false)
+                .map_err(|msg| anyhow!("invalid version specifier: {}", msg))?]),
+        ));
+    }
+    Ok(rv)
+}
+
+fn find_best_matches_with_unearth(
+    python_path: &Path,
+    sources: &ExpandedSources,
+    py_ver: Option<&PythonVersion>,
+    requirement: &Requirement,
+    pre: bool,
+) -> Result<Vec<Match>, Error> {
+    let mut unearth = internal_command(python_path);
+    let mut requirement = requirement.clone();
+    requirement.marker = None;
+
+    unearth
+        .arg("-c")
+        .arg(PACKAGE_FINDER_SCRIPT)
+        .arg(match py_ver {
+            Some(ver) => ver.format_simple(),
+            None => "".into(),
+        })
+        .arg(&format_requirement(&requirement).to_string())
+        .arg(serde_json::to_string(sources)?);
+    if pre {
+        unearth.arg("--pre");
+    }
+    set_proxy_variables(&mut unearth);
+    set_network_timeouts(&mut unearth);
+    let unearth = unearth.stdout(Stdio::piped()).output()?;
+    if unearth.status.success() {
+        Ok(serde_json::from_slice(&unearth.stdout)?)
+    } else {
+        let log = String::from_utf8_lossy(&unearth.stderr);
+        bail!(
+            "failed to resolve package {}\n{}",
+            format_requirement(&requirement),
+            log
+        );
+    }
+}
@@ -1,17 +1,36 @@
+use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
 use std::sync::Mutex;
 use std::{env, fs};
 
 use anyhow::{anyhow, Context, Error};
+use fslock::LockFile;
+use once_cell::sync::Lazy;
 
 use crate::config::Config;
 use crate::pyproject::latest_available_python_version;
 use crate::sources::py::{PythonVersion, PythonVersionRequest};
+use crate::utils::atomic::write_atomic;
 use crate::utils::IoPathContext;
 
 static APP_DIR: Mutex<Option<&'static PathBuf>> = Mutex::new(None);
 
+/// Set by packagers at build time to the package manager that ships rye.
+const PACKAGED_BY: Option<&str> = option_env!("RYE_PACKAGED_BY");
+
+/// Install locations that belong entirely to a package manager.
+const PACKAGE_MANAGER_PREFIXES: &[(&str, &str)] = &[
+    ("/opt/homebrew/", "homebrew"),
+    ("/usr/local/Cellar/", "homebrew"),
+    ("/home/linuxbrew/.linuxbrew/", "homebrew"),
+    ("/nix/store/", "nix"),
+];
+
+/// Commands that tell if a file is owned by a distribution package.
+const PACKAGE_OWNER_QUERIES: &[(&str, &str, &str)] =
+    &[("dpkg-query", "-S", "apt"), ("rpm", "-qf", "rpm")];
+
 pub fn init() -> Result<(), Error> {
     let home = if let Some(rye_home) = env::var_os("RYE_HOME") {
         PathBuf::from(rye_home)
@@ -29,6 +48,77 @@ pub fn get_app_dir() -> &'static Path {
     APP_DIR.lock().unwrap().expect("platform not initialized")
 }
 
+/// Fails if the application directory belongs to another user.
+///
+/// Running rye as root against the folder of a regular user (`sudo rye ...`)
+/// would leave root owned files behind that break rye for that user.  The
+/// other way around (common in containers) rye fails halfway through with
+/// permission errors.
+pub fn check_app_dir_owner() -> Result<(), Error> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+
+        let app_dir = get_app_dir();
+        let Ok(metadata) = fs::metadata(app_dir) else {
+            return Ok(());
+        };
+        // SAFETY: geteuid cannot fail.
+        let uid = unsafe { libc::geteuid() };
+        if metadata.uid() == uid {
+            return Ok(());
+        }
+        if uid == 0 {
+            anyhow::bail!(
+                "rye is running as root but {} belongs to another user.\n\
+                 Files created now would be owned by root and break rye for that user.  Run \
+                 rye as that user, or pass --app-dir (or set RYE_HOME) to use a separate \
+                 folder for root.",
+                app_dir.display()
+            );
+        }
+        if !is_writable(app_dir) {
+            anyhow::bail!(
+                "{} belongs to another user and is not writable.\n\
+                 This happens if rye ran as root before (eg: with sudo or in a container).  \
+                 Fix the ownership with `sudo chown -R $(id -un) {}`, or pass --app-dir (or \
+                 set RYE_HOME) to use another folder.",
+                app_dir.display(),
+                app_dir.display()
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Does the application directory belong to the current user?
+pub fn is_app_dir_owned() -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+
+        // SAFETY: geteuid cannot fail.
+        let uid = unsafe { libc::geteuid() };
+        fs::metadata(get_app_dir()).map_or(true, |x| x.uid() == uid)
+    }
+    #[cfg(not(unix))]
+    {
+        true
+    }
+}
+
+#[cfg(unix)]
+fn is_writable(path: &Path) -> bool {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let Ok(path) = CString::new(path.as_os_str().as_bytes()) else {
+        return false;
+    };
+    // SAFETY: the path is a valid nul terminated string.
+    unsafe { libc::access(path.as_ptr(), libc::W_OK) == 0 }
+}
+
 /// Runs a check if symlinks are supported.
 pub fn symlinks_supported() -> bool {
     #[cfg(unix)]
@@ -37,8 +127,6 @@ pub fn symlinks_supported() -> bool {
     }
     #[cfg(windows)]
     {
-        use once_cell::sync::Lazy;
-
         fn probe() -> Result<(), std::io::Error> {
             let dir = tempfile::tempdir()?;
             let a_path = dir.path().join("a");
@@ -52,6 +140,56 @@ pub fn symlinks_supported() -> bool {
     }
 }
 
+/// Returns the package manager that installed rye, if any.
+///
+/// This is either configured at build time with `RYE_PACKAGED_BY` or detected
+/// from the location of the executable.
+pub fn get_system_package_manager() -> Option<&'static str> {
+    static PACKAGE_MANAGER: Lazy<Option<&'static str>> = Lazy::new(detect_package_manager);
+    *PACKAGE_MANAGER
+}
+
+fn detect_package_manager() -> Option<&'static str> {
+    if let Some(name) = PACKAGED_BY.filter(|x| !x.is_empty()) {
+        return Some(name);
+    }
+    let exe = env::current_exe().ok()?.canonicalize().ok()?;
+    if let Some((_, name)) = PACKAGE_MANAGER_PREFIXES
+        .iter()
+        .find(|(prefix, _)| exe.starts_with(prefix))
+    {
+        return Some(name);
+    }
+
+    // not everything in /usr comes from a package (eg: `make install` or a
+    // manual copy), so ask the distribution's package database.
+    if cfg!(target_os = "linux") && exe.starts_with("/usr/") {
+        return PACKAGE_OWNER_QUERIES
+            .iter()
+            .find(|(cmd, arg, _)| {
+                Command::new(cmd)
+                    .arg(arg)
+                    .arg(&exe)
+                    .stdout(Stdio::null())
+                    .stderr(Stdio::null())
+                    .status()
+                    .map_or(false, |x| x.success())
+            })
+            .map(|(_, _, name)| *name);
+    }
+    None
+}
+
+/// Returns the command that updates rye for a package manager if known.
+pub fn get_package_manager_update_command(package_manager: &str) -> Option<&'static str> {
+    match package_manager {
+        "homebrew" => Some("brew upgrade rye"),
+        "apt" => Some("sudo apt install --only-upgrade rye"),
+        "nix" => Some("nix profile upgrade rye"),
+        _ => None,
+    }
+}
+
 /// Returns the cache directory for a particular python version that can be downloaded.
 pub fn get_canonical_py_path(version: &PythonVersion) -> Result<PathBuf, Error> {
     let mut rv = get_app_dir().to_path_buf();
@@ -177,6 +315,66 @@ pub fn list_known_toolchains() -> Result<Vec<(PythonVersion, PathBuf)>, Error> {
     Ok(rv)
 }
 
+fn get_toolchain_users_path() -> PathBuf {
+    get_app_dir().join("toolchain-users.json")
+}
+
+/// Locks the toolchain users for a read-modify-write.
+///
+/// Concurrent syncs would otherwise drop each other's records.  The lock is
+/// released when the returned file is dropped.
+fn lock_toolchain_users() -> Option<LockFile> {
+    let mut lock_path = get_toolchain_users_path().into_os_string();
+    lock_path.push(".lock");
+    let mut lock = LockFile::open(&PathBuf::from(lock_path)).ok()?;
+    lock.lock().ok()?;
+    Some(lock)
+}
+
+fn read_toolchain_users() -> BTreeMap<PathBuf, PathBuf> {
+    fs::read(get_toolchain_users_path())
+        .ok()
+        .and_then(|x| serde_json::from_slice(&x).ok())
+        .unwrap_or_default()
+}
+
+/// Records that a project uses a toolchain.
+///
+/// The canonical path of the toolchain is stored by project root, which is
+/// what `rye toolchain gc` consults.  Failures are ignored.
+pub fn record_toolchain_user(project_root: &Path, version: &PythonVersion) {
+    let Ok(py_path) = get_canonical_py_path(version) else {
+        return;
+    };
+    let Some(_lock) = lock_toolchain_users() else {
+        return;
+    };
+    let mut users = read_toolchain_users();
+    if users.get(project_root) == Some(&py_path) {
+        return;
+    }
+    users.insert(project_root.to_path_buf(), py_path);
+    if let Ok(contents) = serde_json::to_vec_pretty(&users) {
+        write_atomic(get_toolchain_users_path(), contents).ok();
+    }
+}
+
+/// Returns the canonical toolchain paths used by projects, by project root.
+///
+/// Projects that no longer exist are dropped from the records.
+pub fn list_toolchain_users() -> BTreeMap<PathBuf, PathBuf> {
+    let _lock = lock_toolchain_users();
+    let mut users = read_toolchain_users();
+    let before = users.len();
+    users.retain(|root, _| root.join("pyproject.toml").is_file());
+    if users.len() != before {
+        if let Ok(contents) = serde_json::to_vec_pretty(&users) {
+            write_atomic(get_toolchain_users_path(), contents).ok();
+        }
+    }
+    users
+}
+
 /// Returns the default author from git or the config.
 pub fn get_default_author_with_fallback(dir: &PathBuf) -> Option<(String, String)> {
     let (mut name, mut email) = Config::current().default_author();
@@ -210,42 +408,6 @@ pub fn get_default_author_with_fallback(dir: &PathBuf) -> Option<(String, String
     ))
 }
 
-/// Reads the current `.python-version` file.
-pub fn get_python_version_request_from_pyenv_pin(root: &Path) -> Option<PythonVersionRequest> {
-    let mut here = root.to_owned();
-
-    loop {
-        here.push(".python-version");
-        if let Ok(contents) = fs::read_to_string(&here) {
-            return read_python_version(&contents);
-        }
-
-        // pop filename
-        here.pop();
-
-        // pop parent
-        if !here.pop() {
-            break;
-        }
-    }
-
-    None
-}
-
-/// Return the [`PythonVersionRequest`] from a `.python-version` file.
-fn read_python_version(contents: &str) -> Option<PythonVersionRequest> {
-    // Skip empty lines and comments.
-    let ver = contents.lines().find(|line| {
-        let trimmed = line.trim();
-        !(trimmed.is_empty() || trimmed.starts_with('#'))
-    })?;
-
-    // Parse the version.
-    let ver = ver.parse().ok()?;
-
-    Some(ver)
-}
-
 /// Returns the most recent cpython release.
 pub fn get_latest_cpython_version() -> Result<PythonVersion, Error> {
     latest_available_python_version(&PythonVersionRequest {
@@ -284,29 +446,10 @@ pub fn get_credentials() -> Result<toml_edit::DocumentMut, Error> {
 
 pub fn write_credentials(doc: &toml_edit::DocumentMut) -> Result<(), Error> {
     let path = get_credentials_filepath()?;
-    std::fs::write(&path, doc.to_string())
+    write_atomic(&path, doc.to_string())
         .path_context(&path, "unable to write to the credentials file")
 }
 
 pub fn get_credentials_filepath() -> Result<PathBuf, Error> {
     Ok(get_app_dir().join("credentials"))
 }
-
-#[cfg(test)]
-mod test {
-
-    #[test]
-    fn test_read_python_version() {
-        // Parse a simple version.
-        let ver = super::read_python_version("3.8.1\n");
-        assert_eq!(ver, Some("3.8.1".parse().unwrap()));
-
-        // Skip empty lines.
-        let ver = super::read_python_version("\n\n3.8.1\n");
-        assert_eq!(ver, Some("3.8.1".parse().unwrap()));
-
-        // Skip comments.
-        let ver = super::read_python_version("# comment\n3.8.1\n");
-        assert_eq!(ver, Some("3.8.1".parse().unwrap()));
-    }
-}
@@ -0,0 +1,175 @@
+//! Enforces the `[tool.rye.policy]` of a project.
+//!
+//! Shared repositories can forbid operations for contributors, for instance
+//! so that only CI regenerates lockfiles.  Maintainers can override the
+//! policy with `--override-policy` or `RYE_OVERRIDE_POLICY=1`.
+use std::env;
+use std::fmt;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use anyhow::{bail, Error};
+use toml_edit::DocumentMut;
+
+static OVERRIDE_POLICY: AtomicBool = AtomicBool::new(false);
+
+/// Ignores the policy of projects for the rest of the process.
+pub fn enable_override() {
+    OVERRIDE_POLICY.store(true, Ordering::Relaxed);
+}
+
+/// Is the policy overridden with the flag or the environment?
+pub fn is_overridden() -> bool {
+    OVERRIDE_POLICY.load(Ordering::Relaxed)
+        || env::var("RYE_OVERRIDE_POLICY").map_or(false, |x| {
+            !x.is_empty() && x != "0" && !x.eq_ignore_ascii_case("false")
+        })
+}
+
+/// An operation that can be forbidden by a policy.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Operation {
+    Lock,
+    Add,
+    Remove,
+}
+
+impl Operation {
+    fn key(self) -> &'static str {
+        match self {
+            Operation::Lock => "allow-lock",
+            Operation::Add => "allow-add",
+            Operation::Remove => "allow-remove",
+        }
+    }
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Operation::Lock => "updating lockfiles",
+            Operation::Add => "adding dependencies",
+            Operation::Remove => "removing dependencies",
+        })
+    }
+}
+
+/// The policy of a project.  Everything is allowed by default.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Policy {
+    pub allow_lock: bool,
+    pub allow_add: bool,
+    pub allow_remove: bool,
+    pub require_hashes: bool,
+}
+
+impl Default for Policy {
+    fn default() -> Policy {
+        Policy {
+            allow_lock: true,
+            allow_add: true,
+            allow_remove: true,
+            require_hashes: false,
+        }
+    }
+}
+
+impl Policy {
+    /// Reads the policy from `[tool.rye.policy]`.
+    pub fn from_doc(doc: &DocumentMut) -> Policy {
+        let policy = doc
+            .get("tool")
+            .and_then(|x| x.get("rye"))
+            .and_then(|x| x.get("policy"));
+        let flag = |key: &str, default: bool| {
+            policy
+                .and_then(|x| x.get(key))
+                .and_then(|x| x.as_bool())
+                .unwrap_or(default)
+        };
+        Policy {
+            allow_lock: flag("allow-lock", true),
+            allow_add: flag("allow-add", true),
+            allow_remove: flag("allow-remove", true),
+            require_hashes: flag("require-hashes", false),
+        }
+    }
+
+    /// Is the operation allowed for this invocation?
+    pub fn allows(&self, operation: Operation) -> bool {
+        is_overridden()
+            || match operation {
+                Operation::Lock => self.allow_lock,
+                Operation::Add => self.allow_add,
+                Operation::Remove => self.allow_remove,
+            }
+    }
+
+    /// Fails if the operation is forbidden.
+    pub fn check(&self, operation: Operation, pyproject: &Path) -> Result<(), Error> {
+        if !self.allows(operation) {
+            bail!(
+                "{} is not allowed by the project policy (`tool.rye.policy.{} = false` in {}).\n\
+                 Pass --override-policy to do it anyway.",
+                operation,
+                operation.key(),
+                pyproject.display()
+            );
+        }
+        Ok(())
+    }
+
+    /// Fails if hashes are required but the lockfile has requirements without.
+    pub fn check_lockfile_hashes(&self, lockfile: &Path, contents: &str) -> Result<(), Error> {
+        if self.require_hashes && !is_overridden() && !lockfile_has_hashes(contents) {
+            bail!(
+                "lockfile {} has requirements without hashes but the project policy requires \
+                 them (`tool.rye.policy.require-hashes`).\nRegenerate it with `rye lock`.",
+                lockfile.display()
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Checks that all requirements of a lockfile are pinned with hashes.
+///
+/// Editable installs and options are not hashed.  Hashes follow on
+/// continuation lines after a trailing backslash.
+fn lockfile_has_hashes(contents: &str) -> bool {
+    contents
+        .lines()
+        .filter(|line| {
+            !line.trim().is_empty()
+                && !line.starts_with(|c: char| c.is_whitespace() || c == '#' || c == '-')
+        })
+        .all(|line| line.ends_with('\\') || line.contains("--hash="))
+}
+
+#[cfg(test)]
+mod test {
+    use toml_edit::DocumentMut;
+
+    use super::{lockfile_has_hashes, Operation, Policy};
+
+    #[test]
+    fn test_policy_from_doc() {
+        let doc = "[tool.rye.policy]\nallow-lock = false\nrequire-hashes = true\n"
+            .parse::<DocumentMut>()
+            .unwrap();
+        let policy = Policy::from_doc(&doc);
+        assert!(!policy.allow_lock);
+        assert!(policy.allow_add);
+        assert!(policy.require_hashes);
+        assert!(!policy.allows(Operation::Lock));
+        assert_eq!(Policy::from_doc(&DocumentMut::new()), Policy::default());
+    }
+
+    #[test]
+    fn test_lockfile_has_hashes() {
+        assert!(lockfile_has_hashes(
+            "# generated by rye\n-e file:.\nflask==3.0.0 \\\n    --hash=sha256:abc\n"
+        ));
+        assert!(!lockfile_has_hashes("-e file:.\nflask==3.0.0\n"));
+    }
+}
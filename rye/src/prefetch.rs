@@ -0,0 +1,157 @@
+//! Downloads the toolchain pinned by a project in the background.
+//!
+//! With `behavior.prefetch-pinned` enabled, lightweight commands (`rye info`,
+//! `rye config`) notice if the pinned toolchain is not installed yet and
+//! spawn `rye internal prefetch` to download it, so that the eventual sync
+//! does not stall on the download.  Only one job runs at a time.  It is
+//! tracked in a job file in the rye home which the job touches while it runs
+//! and removes when it ends.  If a job dies the job file goes stale and the
+//! next command reschedules the download.
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{self, Command, Stdio};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use anyhow::{Context, Error};
+use serde::{Deserialize, Serialize};
+
+use crate::bootstrap::{fetch, FetchOptions};
+use crate::config::Config;
+use crate::platform::{get_app_dir, get_toolchain_python_bin};
+use crate::pyproject::PyProject;
+use crate::pyversion::PythonVersionSource;
+use crate::sources::py::PythonVersion;
+use crate::utils::{CommandOutput, IoPathContext};
+
+/// How often a running job touches its job file.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Jobs that did not touch their job file for this long are considered dead.
+const STALE_JOB_AFTER: Duration = Duration::from_secs(30);
+
+/// The job file of the running prefetch.
+#[derive(Serialize, Deserialize, Debug)]
+struct Job {
+    version: PythonVersion,
+    pid: u32,
+}
+
+fn get_job_path() -> PathBuf {
+    get_app_dir().join("prefetch.json")
+}
+
+/// Returns the running job, removing the job file of a dead one.
+fn get_running_job() -> Option<Job> {
+    let path = get_job_path();
+    let is_alive = fs::metadata(&path)
+        .and_then(|x| x.modified())
+        .ok()
+        .and_then(|x| SystemTime::now().duration_since(x).ok())
+        .map_or(false, |age| age < STALE_JOB_AFTER);
+    if !is_alive {
+        fs::remove_file(&path).ok();
+        return None;
+    }
+    // a job file that does not parse yet is still being written.
+    fs::read(&path)
+        .ok()
+        .and_then(|x| serde_json::from_slice(&x).ok())
+}
+
+/// Schedules a background download of the toolchain pinned by the project.
+///
+/// This is best effort and never fails the command that calls it.
+pub fn schedule_pinned_toolchain(project: &PyProject) {
+    if !Config::current().prefetch_pinned() {
+        return;
+    }
+    let Ok(resolution) = project.venv_python_version_resolution() else {
+        return;
+    };
+    if !matches!(resolution.source, PythonVersionSource::PythonVersionFile(_))
+        || get_toolchain_python_bin(&resolution.version).map_or(true, |x| x.is_file())
+        || get_running_job().is_some()
+    {
+        return;
+    }
+    let Ok(exe) = std::env::current_exe() else {
+        return;
+    };
+    Command::new(exe)
+        .arg("internal")
+        .arg("prefetch")
+        .arg(resolution.version.to_string())
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok();
+}
+
+/// Runs a prefetch job.  Exits silently if another job is running.
+pub fn run_job(version: &PythonVersion) -> Result<(), Error> {
+    if get_running_job().is_some() {
+        return Ok(());
+    }
+    let path = get_job_path();
+    let job = serde_json::to_vec(&Job {
+        version: version.clone(),
+        pid: process::id(),
+    })?;
+    // whoever creates the job file first runs the job.
+    let mut file = match fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&path)
+    {
+        Ok(file) => file,
+        Err(_) => return Ok(()),
+    };
+    file.write_all(&job)
+        .path_context(&path, "unable to write prefetch job")?;
+    drop(file);
+
+    // the heartbeat ends with the process or once the job file is removed.
+    // It only bumps the mtime so that readers never see a partial job file.
+    let heartbeat_path = path.clone();
+    thread::spawn(move || loop {
+        thread::sleep(HEARTBEAT_INTERVAL);
+        let touched = fs::OpenOptions::new()
+            .write(true)
+            .open(&heartbeat_path)
+            .and_then(|file| file.set_modified(SystemTime::now()));
+        if touched.is_err() {
+            break;
+        }
+    });
+
+    let rv = fetch(
+        &version.clone().into(),
+        FetchOptions {
+            output: CommandOutput::QUIET,
+            ..FetchOptions::default()
+        },
+    )
+    .with_context(|| format!("background download of {} failed", version));
+    fs::remove_file(&path).ok();
+    rv.map(|_| ())
+}
+
+/// Waits for a background download of the version to finish.
+///
+/// Does nothing if no such download is running or if called from the job itself.
+pub fn wait_for_job(version: &PythonVersion, output: CommandOutput) {
+    let mut announced = false;
+    while let Some(job) = get_running_job() {
+        if &job.version != version || job.pid == process::id() {
+            break;
+        }
+        if !announced {
+            echo!(if output, "Waiting for the background download of {}", version);
+            announced = true;
+        }
+        thread::sleep(Duration::from_millis(250));
+    }
+}
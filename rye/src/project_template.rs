@@ -0,0 +1,477 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use anyhow::{anyhow, bail, Context, Error};
+use minijinja::Environment;
+use tempfile::TempDir;
+
+use crate::bootstrap::ensure_self_venv;
+use crate::utils::{get_venv_python_bin, tui_theme, CommandOutput, IoPathContext, Verbosity};
+
+/// The manifest at the root of a project template.
+pub const MANIFEST_NAME: &str = "rye-template.toml";
+
+/// The folder within a template that holds the files to generate.
+const TEMPLATE_FOLDER: &str = "template";
+
+/// Environment variables passed through to post-generation hooks.
+const HOOK_ENV_PASSTHROUGH: &[&str] = &["PATH", "HOME", "SYSTEMROOT", "TEMP", "TMP", "TMPDIR"];
+
+/// Where a template is loaded from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TemplateSource {
+    /// A git repository with an optional branch or tag.
+    Git {
+        url: String,
+        reference: Option<String>,
+    },
+    /// A folder on the file system.
+    Local(PathBuf),
+}
+
+impl TemplateSource {
+    /// Parses a template reference.
+    ///
+    /// `gh:org/repo` refers to a GitHub repository, URLs and paths ending in
+    /// `.git` to other git repositories.  Everything else is a local path.
+    /// Git sources can pick a branch or tag with a `@ref` suffix.
+    pub fn parse(value: &str) -> TemplateSource {
+        let split_reference = |value: &str| match value.rsplit_once('@') {
+            Some((url, reference)) if !reference.contains('/') && !reference.is_empty() => {
+                (url.to_string(), Some(reference.to_string()))
+            }
+            _ => (value.to_string(), None),
+        };
+        if let Some(repo) = value.strip_prefix("gh:") {
+            let (repo, reference) = split_reference(repo);
+            return TemplateSource::Git {
+                url: format!("https://github.com/{}.git", repo.trim_end_matches(".git")),
+                reference,
+            };
+        }
+        if let Some(url) = value.strip_prefix("git+") {
+            let (url, reference) = split_reference(url);
+            return TemplateSource::Git { url, reference };
+        }
+        if value.starts_with("https://") || value.starts_with("git@") {
+            let (url, reference) = split_reference(value);
+            if url.ends_with(".git") || value.starts_with("git@") {
+                return TemplateSource::Git { url, reference };
+            }
+        }
+        TemplateSource::Local(PathBuf::from(value))
+    }
+}
+
+/// A variable the template asks for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TemplateVariable {
+    pub name: String,
+    pub prompt: Option<String>,
+    pub default: Option<String>,
+}
+
+/// A loaded project template.
+#[derive(Debug)]
+pub struct Template {
+    source: String,
+    root: PathBuf,
+    version: Option<String>,
+    variables: Vec<TemplateVariable>,
+    post_generate: Option<PathBuf>,
+    // keeps a cloned template alive until generation is done
+    _checkout: Option<TempDir>,
+}
+
+impl Template {
+    /// Loads a template from a path, git URL or `gh:org/repo` reference.
+    pub fn load(source: &str, output: CommandOutput) -> Result<Template, Error> {
+        let (root, checkout, commit) = match TemplateSource::parse(source) {
+            TemplateSource::Local(path) => {
+                let path = env::current_dir()?.join(path);
+                if !path.is_dir() {
+                    bail!("template folder {} does not exist", path.display());
+                }
+                (path, None, None)
+            }
+            TemplateSource::Git { url, reference } => {
+                echo!(if output, "Fetching template from {}", url);
+                let checkout = tempfile::tempdir()?;
+                let mut cmd = Command::new("git");
+                cmd.arg("clone").arg("--depth").arg("1").arg("--quiet");
+                if let Some(ref reference) = reference {
+                    cmd.arg("--branch").arg(reference);
+                }
+                let status = cmd
+                    .arg(&url)
+                    .arg(checkout.path())
+                    .stdout(Stdio::null())
+                    .status()
+                    .context("unable to run git to fetch the template")?;
+                if !status.success() {
+                    bail!("failed to fetch template from {}", url);
+                }
+                let commit = Command::new("git")
+                    .arg("rev-parse")
+                    .arg("HEAD")
+                    .current_dir(checkout.path())
+                    .output()
+                    .ok()
+                    .filter(|x| x.status.success())
+                    .map(|x| String::from_utf8_lossy(&x.stdout).trim().to_string());
+                (checkout.path().to_path_buf(), Some(checkout), commit)
+            }
+        };
+
+        let manifest_path = root.join(MANIFEST_NAME);
+        let manifest = fs::read_to_string(&manifest_path)
+            .path_context(&manifest_path, "template has no manifest")?
+            .parse::<toml_edit::DocumentMut>()
+            .path_context(&manifest_path, "failed to parse template manifest")?;
+        if !root.join(TEMPLATE_FOLDER).is_dir() {
+            bail!("template has no '{}' folder", TEMPLATE_FOLDER);
+        }
+
+        let section = manifest.get("template");
+        let get_str = |key: &str| {
+            section
+                .and_then(|x| x.get(key))
+                .and_then(|x| x.as_str())
+                .map(|x| x.to_string())
+        };
+        let post_generate = get_str("post-generate").map(|x| root.join(x));
+        if let Some(ref script) = post_generate {
+            if !script.is_file() {
+                bail!("post-generate hook {} does not exist", script.display());
+            }
+        }
+
+        Ok(Template {
+            source: source.to_string(),
+            version: get_str("version").or(commit),
+            variables: parse_variables(&manifest)?,
+            post_generate,
+            root,
+            _checkout: checkout,
+        })
+    }
+
+    /// Resolves the values of all variables.
+    ///
+    /// Values passed explicitly win, otherwise the user is prompted if a
+    /// terminal is attached.  `defaults` provides the values rye would use
+    /// without a template (name, description, license, author ...) which are
+    /// used when the manifest does not provide a default.
+    pub fn resolve_variables(
+        &self,
+        mut defaults: BTreeMap<String, String>,
+        explicit: &[(String, String)],
+    ) -> Result<BTreeMap<String, String>, Error> {
+        let interactive = console::user_attended_stderr();
+        let env = Environment::new();
+        for var in &self.variables {
+            if let Some((_, value)) = explicit.iter().rev().find(|(key, _)| *key == var.name) {
+                defaults.insert(var.name.clone(), value.clone());
+                continue;
+            }
+            let default = match var.default {
+                Some(ref default) => {
+                    Some(env.render_str(default, &defaults).with_context(|| {
+                        format!(
+                            "failed to render default of template variable '{}'",
+                            var.name
+                        )
+                    })?)
+                }
+                None => defaults.get(&var.name).cloned(),
+            };
+            let value = if interactive {
+                let mut input = dialoguer::Input::<String>::with_theme(tui_theme())
+                    .with_prompt(var.prompt.as_deref().unwrap_or(&var.name))
+                    .allow_empty(default.is_some());
+                if let Some(default) = default {
+                    input = input.default(default);
+                }
+                input.interact_text()?
+            } else {
+                default.ok_or_else(|| {
+                    anyhow!(
+                        "no value for template variable '{}'. Pass it with --template-var {}=VALUE",
+                        var.name,
+                        var.name
+                    )
+                })?
+            };
+            defaults.insert(var.name.clone(), value);
+        }
+        for (key, value) in explicit {
+            defaults.insert(key.clone(), value.clone());
+        }
+        Ok(defaults)
+    }
+
+    /// Renders the template into `dir`.
+    ///
+    /// File and folder names are rendered as well.  Files ending in `.j2` are
+    /// rendered and lose that suffix, everything else is copied as is.
+    /// Existing files are never overwritten and symlinks are skipped as they
+    /// could point anywhere on the machine.
+    pub fn generate(
+        &self,
+        dir: &Path,
+        variables: &BTreeMap<String, String>,
+        output: CommandOutput,
+    ) -> Result<(), Error> {
+        let mut env = Environment::new();
+        env.set_keep_trailing_newline(true);
+        let template_dir = self.root.join(TEMPLATE_FOLDER);
+
+        for entry in walkdir::WalkDir::new(&template_dir).min_depth(1) {
+            let entry = entry?;
+            let rel_path = entry.path().strip_prefix(&template_dir)?.to_string_lossy();
+            let mut target = env
+                .render_str(&rel_path, variables)
+                .with_context(|| format!("failed to render path '{}'", rel_path))?;
+            if !is_enclosed_path(&target) {
+                bail!(
+                    "template path '{}' renders to '{}' which is outside of the project",
+                    rel_path,
+                    target
+                );
+            }
+            if entry.file_type().is_dir() {
+                let target = dir.join(target);
+                fs::create_dir_all(&target).path_context(&target, "failed to create directory")?;
+                continue;
+            }
+            if entry.path_is_symlink() {
+                warn!("skipping symlink '{}' in template", rel_path);
+                continue;
+            }
+
+            let render = target.ends_with(".j2");
+            if render {
+                target.truncate(target.len() - 3);
+            }
+            let target = dir.join(target);
+            if target.exists() {
+                echo!(
+                    if verbose output,
+                    "skipping {} as it already exists",
+                    target.display()
+                );
+                continue;
+            }
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent).path_context(parent, "failed to create directory")?;
+            }
+            if render {
+                let source = fs::read_to_string(entry.path())
+                    .path_context(entry.path(), "failed to read template file")?;
+                let rv = env
+                    .render_named_str(&rel_path, &source, variables)
+                    .with_context(|| format!("failed to render '{}'", rel_path))?;
+                fs::write(&target, rv).path_context(&target, "failed to write file")?;
+            } else {
+                fs::copy(entry.path(), &target)
+                    .path_context(entry.path(), "failed to copy file")?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Runs the post-generation hook in the generated project.
+    ///
+    /// The hook runs with rye's internal Python in isolated mode and with an
+    /// environment that only contains the template variables and a few
+    /// system variables.
+    pub fn run_post_generate(
+        &self,
+        dir: &Path,
+        variables: &BTreeMap<String, String>,
+        output: CommandOutput,
+    ) -> Result<(), Error> {
+        let Some(ref script) = self.post_generate else {
+            return Ok(());
+        };
+        echo!(if output, "Running post-generate hook");
+        let self_venv = ensure_self_venv(output)?;
+        let mut cmd = Command::new(get_venv_python_bin(&self_venv));
+        cmd.arg("-I").arg(script).current_dir(dir).env_clear();
+        for key in HOOK_ENV_PASSTHROUGH {
+            if let Some(value) = env::var_os(key) {
+                cmd.env(key, value);
+            }
+        }
+        for (key, value) in variables {
+            cmd.env(
+                format!(
+                    "RYE_TEMPLATE_{}",
+                    key.to_ascii_uppercase().replace('-', "_")
+                ),
+                value,
+            );
+        }
+        if output.child() == Verbosity::Quiet {
+            cmd.stdout(Stdio::null());
+        }
+        let status = cmd.status().context("unable to run post-generate hook")?;
+        if !status.success() {
+            bail!("post-generate hook failed with {}", status);
+        }
+        Ok(())
+    }
+
+    /// Records the template in `[tool.rye.template]` of the pyproject.toml.
+    pub fn record(&self, pyproject_toml: &Path) -> Result<(), Error> {
+        let mut doc = fs::read_to_string(pyproject_toml)
+            .path_context(pyproject_toml, "template did not generate a pyproject.toml")?
+            .parse::<toml_edit::DocumentMut>()
+            .path_context(
+                pyproject_toml,
+                "template generated an invalid pyproject.toml",
+            )?;
+        let mut table = toml_edit::Table::new();
+        table.insert("source", toml_edit::value(&self.source));
+        if let Some(ref version) = self.version {
+            table.insert("version", toml_edit::value(version));
+        }
+        let tool = doc
+            .entry("tool")
+            .or_insert_with(|| {
+                let mut tool = toml_edit::Table::new();
+                tool.set_implicit(true);
+                toml_edit::Item::Table(tool)
+            })
+            .as_table_like_mut()
+            .ok_or_else(|| anyhow!("tool in pyproject.toml is not a table"))?;
+        let rye = tool
+            .entry("rye")
+            .or_insert_with(|| toml_edit::Item::Table(toml_edit::Table::new()))
+            .as_table_like_mut()
+            .ok_or_else(|| anyhow!("tool.rye in pyproject.toml is not a table"))?;
+        rye.insert("template", toml_edit::Item::Table(table));
+        fs::write(pyproject_toml, doc.to_string())
+            .path_context(pyproject_toml, "failed to write pyproject.toml")
+    }
+}
+
+/// Does the rendered path stay within the folder it is joined to?
+fn is_enclosed_path(path: &str) -> bool {
+    Path::new(path)
+        .components()
+        .all(|x| matches!(x, Component::Normal(_) | Component::CurDir))
+}
+
+fn parse_variables(manifest: &toml_edit::DocumentMut) -> Result<Vec<TemplateVariable>, Error> {
+    let Some(variables) = manifest.get("variables") else {
+        return Ok(Vec::new());
+    };
+    let variables = variables
+        .as_table_like()
+        .ok_or_else(|| anyhow!("variables in template manifest must be a table"))?;
+    let mut rv = Vec::new();
+    for (name, item) in variables.iter() {
+        let var = match item.as_str() {
+            // a plain string is the prompt
+            Some(prompt) => TemplateVariable {
+                name: name.to_string(),
+                prompt: Some(prompt.to_string()),
+                default: None,
+            },
+            None => {
+                let table = item
+                    .as_table_like()
+                    .ok_or_else(|| anyhow!("invalid template variable '{}'", name))?;
+                let get_str = |key: &str| {
+                    table
+                        .get(key)
+                        .and_then(|x| x.as_str())
+                        .map(|x| x.to_string())
+                };
+                TemplateVariable {
+                    name: name.to_string(),
+                    prompt: get_str("prompt"),
+                    default: get_str("default"),
+                }
+            }
+        };
+        rv.push(var);
+    }
+    Ok(rv)
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use super::{is_enclosed_path, parse_variables, TemplateSource, TemplateVariable};
+
+    #[test]
+    fn test_is_enclosed_path() {
+        assert!(is_enclosed_path("src/my_project/__init__.py"));
+        assert!(is_enclosed_path("./README.md"));
+        assert!(!is_enclosed_path("../escape.py"));
+        assert!(!is_enclosed_path("src/../../escape.py"));
+        assert!(!is_enclosed_path("/etc/passwd"));
+    }
+
+    #[test]
+    fn test_parse_template_source() {
+        assert_eq!(
+            TemplateSource::parse("gh:org/tmpl"),
+            TemplateSource::Git {
+                url: "https://github.com/org/tmpl.git".into(),
+                reference: None
+            }
+        );
+        assert_eq!(
+            TemplateSource::parse("gh:org/tmpl@v1.0"),
+            TemplateSource::Git {
+                url: "https://github.com/org/tmpl.git".into(),
+                reference: Some("v1.0".into())
+            }
+        );
+        assert_eq!(
+            TemplateSource::parse("https://example.com/tmpl.git"),
+            TemplateSource::Git {
+                url: "https://example.com/tmpl.git".into(),
+                reference: None
+            }
+        );
+        assert_eq!(
+            TemplateSource::parse("../templates/basic"),
+            TemplateSource::Local(PathBuf::from("../templates/basic"))
+        );
+    }
+
+    #[test]
+    fn test_parse_variables() {
+        let manifest = r#"
+[variables]
+description = "Project description"
+license = { prompt = "License", default = "MIT" }
+"#
+        .parse()
+        .unwrap();
+        assert_eq!(
+            parse_variables(&manifest).unwrap(),
+            vec![
+                TemplateVariable {
+                    name: "description".into(),
+                    prompt: Some("Project description".into()),
+                    default: None,
+                },
+                TemplateVariable {
+                    name: "license".into(),
+                    prompt: Some("License".into()),
+                    default: Some("MIT".into()),
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,307 @@
+//! Resolution reports that explain why the packages of a lockfile were pinned.
+//!
+//! For every locked package the report lists who requires it and with which
+//! specifier: the projects of the workspace for direct dependencies and the
+//! packages from the `via` annotations for transitive ones.  The requirements
+//! of locked packages come from their metadata, which is read from the
+//! virtualenv if the locked version is installed there and otherwise fetched
+//! from the index (PEP 658) when locking explicitly.  Requirers without
+//! available metadata are listed without specifier.
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::Error;
+use pep440_rs::Version;
+use pep508_rs::{Requirement, VersionOrUrl};
+
+use crate::bootstrap::{download_url_ignore_404, get_site_packages_dir};
+use crate::index::IndexClient;
+use crate::lock::{parse_locked_packages, LockedPackage};
+use crate::pyproject::{normalize_package_name, DependencyRef, ExpandedSources};
+use crate::sync::{find_installed_dists, InstalledDist};
+use crate::utils::{CommandOutput, IoPathContext};
+
+/// A requirement that constrains the pin of a package.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Constraint {
+    /// The project or locked package that declares the requirement.
+    pub requirer: String,
+    /// The requirement, `None` if the metadata of the requirer is unavailable.
+    pub requirement: Option<Requirement>,
+}
+
+/// Returns the path of the resolution report that accompanies a lockfile.
+pub fn get_report_path(lockfile: &Path) -> PathBuf {
+    let mut rv = lockfile.as_os_str().to_owned();
+    rv.push(".report");
+    PathBuf::from(rv)
+}
+
+/// Returns the key of the resolution a report was rendered for.
+pub fn get_report_resolution_key(report: &str) -> Option<&str> {
+    report
+        .lines()
+        .take_while(|x| x.starts_with('#'))
+        .find_map(|x| x.strip_prefix("# resolution key: "))
+}
+
+/// Adds the dependencies of a project to the direct requirements.
+pub fn collect_direct_requirements(
+    requirer: &str,
+    deps: impl Iterator<Item = DependencyRef>,
+    out: &mut Vec<(String, Requirement)>,
+) {
+    for dep in deps {
+        // only the specifier matters, not what variables expand to
+        if let Ok(req) = dep.expand(|_| Some("VARIABLE".into())) {
+            out.push((requirer.to_string(), req));
+        }
+    }
+}
+
+/// Reads the requirements (`Requires-Dist`) from a `METADATA` file.
+fn parse_requires_dist(metadata: &str) -> Vec<Requirement> {
+    metadata
+        .lines()
+        .take_while(|x| !x.is_empty())
+        .filter_map(|x| x.strip_prefix("Requires-Dist:"))
+        .filter_map(|x| x.trim().parse().ok())
+        .collect()
+}
+
+/// Looks up the requirements of locked packages.
+struct MetadataSource {
+    installed: HashMap<String, InstalledDist>,
+    index: Option<IndexClient>,
+    output: CommandOutput,
+}
+
+impl MetadataSource {
+    fn get_requirements(&self, package: &LockedPackage) -> Option<Vec<Requirement>> {
+        let version = package.version.as_ref()?;
+        if let Some(dist) = self
+            .installed
+            .get(&package.name)
+            .filter(|x| x.version == *version)
+        {
+            if let Ok(metadata) = fs::read_to_string(dist.dist_info.join("METADATA")) {
+                return Some(parse_requires_dist(&metadata));
+            }
+        }
+
+        let version = version.parse::<Version>().ok()?;
+        let project = self.index.as_ref()?.get_project(&package.name).ok()??;
+        let file = project
+            .files
+            .iter()
+            .find(|x| x.filename.ends_with(".whl") && x.version().as_ref() == Some(&version))?;
+        let url = file.url.split('#').next().unwrap_or(&file.url);
+        echo!(if verbose self.output, "fetching metadata of {}", file.filename);
+        let metadata =
+            download_url_ignore_404(&format!("{}.metadata", url), self.output.quieter()).ok()??;
+        Some(parse_requires_dist(&String::from_utf8_lossy(&metadata)))
+    }
+}
+
+/// Maps each locked package to the requirements that constrain it.
+///
+/// Editable packages are the projects of the workspace, their requirements
+/// are the direct ones.
+fn build_report<'a>(
+    locked: &'a [LockedPackage],
+    direct: &[(String, Requirement)],
+    mut get_requirements: impl FnMut(&LockedPackage) -> Option<Vec<Requirement>>,
+) -> Vec<(&'a LockedPackage, Vec<Constraint>)> {
+    let mut requirements = HashMap::new();
+    let mut rv = Vec::new();
+    for package in locked.iter().filter(|x| !x.editable) {
+        let mut constraints = direct
+            .iter()
+            .filter(|(_, req)| normalize_package_name(&req.name) == package.name)
+            .map(|(requirer, req)| Constraint {
+                requirer: requirer.clone(),
+                requirement: Some(req.clone()),
+            })
+            .collect::<Vec<_>>();
+        for parent in locked
+            .iter()
+            .filter(|x| !x.editable && x.dependencies.contains(&package.name))
+        {
+            let requirer = match parent.version {
+                Some(ref version) => format!("{}=={}", parent.name, version),
+                None => parent.name.clone(),
+            };
+            let matching = requirements
+                .entry(parent.name.clone())
+                .or_insert_with(|| get_requirements(parent))
+                .iter()
+                .flatten()
+                .filter(|req| normalize_package_name(&req.name) == package.name)
+                .cloned()
+                .collect::<Vec<_>>();
+            if matching.is_empty() {
+                constraints.push(Constraint {
+                    requirer,
+                    requirement: None,
+                });
+            }
+            constraints.extend(matching.into_iter().map(|req| Constraint {
+                requirer: requirer.clone(),
+                requirement: Some(req),
+            }));
+        }
+        rv.push((package, constraints));
+    }
+    rv
+}
+
+fn format_specifier(req: &Requirement) -> String {
+    let mut rv = match req.version_or_url {
+        Some(VersionOrUrl::VersionSpecifier(ref specs)) if !specs.is_empty() => specs.to_string(),
+        Some(VersionOrUrl::Url(ref url)) => format!("@ {}", url),
+        _ => "any version".to_string(),
+    };
+    if let Some(ref marker) = req.marker {
+        write!(rv, " ; {}", marker).unwrap();
+    }
+    rv
+}
+
+fn render_report(
+    lockfile: &str,
+    resolution_key: Option<&str>,
+    report: &[(&LockedPackage, Vec<Constraint>)],
+) -> String {
+    let mut rv = String::new();
+    writeln!(rv, "# resolution report for {}", lockfile).unwrap();
+    writeln!(rv, "# every pin with the requirements that constrain it").unwrap();
+    if let Some(key) = resolution_key {
+        writeln!(rv, "# resolution key: {}", key).unwrap();
+    }
+    for (package, constraints) in report {
+        writeln!(rv).unwrap();
+        match (&package.version, &package.url) {
+            (Some(version), _) => writeln!(rv, "{}=={}", package.name, version),
+            (None, Some(url)) => writeln!(rv, "{} @ {}", package.name, url),
+            (None, None) => writeln!(rv, "{}", package.name),
+        }
+        .unwrap();
+        if constraints.is_empty() {
+            writeln!(rv, "    (not required by anything)").unwrap();
+        }
+        for constraint in constraints {
+            match constraint.requirement {
+                Some(ref req) => {
+                    writeln!(rv, "    {}: {}", constraint.requirer, format_specifier(req))
+                }
+                None => writeln!(rv, "    {}: (unknown, no metadata)", constraint.requirer),
+            }
+            .unwrap();
+        }
+    }
+    rv
+}
+
+/// Renders the resolution report of a lockfile.
+///
+/// `direct` are the requirements of the workspace projects as `(requirer,
+/// requirement)`, `venv` the virtualenv whose metadata is used if possible.
+/// Without `sources` no metadata is fetched from the index.
+pub fn render_resolution_report(
+    lockfile: &Path,
+    workspace_root: &Path,
+    venv: &Path,
+    direct: &[(String, Requirement)],
+    sources: Option<&ExpandedSources>,
+    resolution_key: Option<&str>,
+    output: CommandOutput,
+) -> Result<String, Error> {
+    let contents =
+        fs::read_to_string(lockfile).path_context(lockfile, "unable to read lockfile")?;
+    let locked = parse_locked_packages(&contents, workspace_root);
+    let installed = get_site_packages_dir(venv)
+        .ok()
+        .filter(|x| x.is_dir())
+        .and_then(|x| find_installed_dists(&x).ok())
+        .unwrap_or_default()
+        .into_iter()
+        .map(|x| (x.name.clone(), x))
+        .collect();
+    let source = MetadataSource {
+        installed,
+        index: sources.map(|x| IndexClient::from_sources(x, output.quieter())),
+        output,
+    };
+    let report = build_report(&locked, direct, |x| source.get_requirements(x));
+    let name = lockfile
+        .file_name()
+        .map(|x| x.to_string_lossy().to_string())
+        .unwrap_or_default();
+    Ok(render_report(&name, resolution_key, &report))
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use pep508_rs::Requirement;
+
+    use super::{build_report, get_report_resolution_key, parse_requires_dist, render_report};
+    use crate::lock::parse_locked_packages;
+
+    #[test]
+    fn test_build_report() {
+        let lockfile = "\
+-e file:.
+certifi==2023.5.7
+    # via
+    #   httpx
+    #   requests
+httpx==0.26.0
+    # via my-project
+requests==2.31.0
+    # via my-project
+";
+        let locked = parse_locked_packages(lockfile, Path::new("/nonexistent"));
+        let direct = vec![
+            ("my-project".to_string(), "httpx>=0.25".parse().unwrap()),
+            ("my-project".to_string(), "requests".parse().unwrap()),
+            (
+                "my-project (dev)".to_string(),
+                "certifi<2024".parse().unwrap(),
+            ),
+        ];
+        let report = build_report(&locked, &direct, |package| match package.name.as_str() {
+            "httpx" => Some(parse_requires_dist(
+                "Metadata-Version: 2.1\nName: httpx\nRequires-Dist: certifi\n\
+                 Requires-Dist: anyio\n\nRequires-Dist: ignored\n",
+            )),
+            _ => None,
+        });
+        let rendered = render_report("requirements.lock", Some("abc123"), &report);
+        assert_eq!(get_report_resolution_key(&rendered), Some("abc123"));
+        assert!(rendered.contains(
+            "\ncertifi==2023.5.7\n    \
+             my-project (dev): <2024\n    \
+             httpx==0.26.0: any version\n    \
+             requests==2.31.0: (unknown, no metadata)\n"
+        ));
+        assert!(rendered.contains("\nhttpx==0.26.0\n    my-project: >=0.25\n"));
+    }
+
+    #[test]
+    fn test_parse_requires_dist() {
+        let reqs = parse_requires_dist(
+            "Name: foo\nRequires-Dist: idna (>=2.8)\nRequires-Dist: trio ; extra == 'trio'\n",
+        );
+        assert_eq!(
+            reqs,
+            vec![
+                "idna>=2.8".parse::<Requirement>().unwrap(),
+                "trio ; extra == 'trio'".parse::<Requirement>().unwrap(),
+            ]
+        );
+    }
+}
@@ -1,7 +1,7 @@
 use clap::ValueEnum;
 use core::fmt;
 use std::borrow::Cow;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::env;
 use std::ffi::OsStr;
 use std::ffi::OsString;
@@ -14,18 +14,28 @@ use std::sync::Arc;
 use crate::bootstrap::ensure_self_venv;
 use crate::config::Config;
 use crate::consts::VENV_BIN;
-use crate::platform::{get_python_version_request_from_pyenv_pin, list_known_toolchains};
+use crate::native::{get_native_deps, NativeDep};
+use crate::platform::list_known_toolchains;
+use crate::policy::Policy;
+use crate::pyversion::{
+    get_python_version_request_from_pyenv_pin, lower_bound_python_version, resolve_python_version,
+    PythonVersionResolution,
+};
+use crate::schema::validate_project;
 use crate::sources::py::{get_download_url, matches_version, PythonVersion, PythonVersionRequest};
 use crate::sync::VenvMarker;
+use crate::utils::atomic::{write_atomic, write_atomic_with_backup};
+use crate::utils::netrc::lookup_netrc_credentials;
 use crate::utils::{
-    escape_string, expand_env_vars, format_requirement, get_short_executable_name, is_executable,
-    toml,
+    escape_string, expand_env_vars, format_requirement, get_short_executable_name,
+    internal_command, is_executable, toml,
 };
 use crate::utils::{CommandOutput, IoPathContext};
+use crate::variants::{get_variants, Variant};
 use anyhow::{anyhow, bail, Context, Error};
 use globset::GlobBuilder;
 use once_cell::sync::Lazy;
-use pep440_rs::{Operator, Version, VersionSpecifiers};
+use pep440_rs::Version;
 use pep508_rs::Requirement;
 use python_pkginfo::Metadata;
 use regex::Regex;
@@ -125,6 +135,18 @@ impl fmt::Display for SourceRefType {
     }
 }
 
+/// A system dependency declared in `[tool.rye.system-deps]`.
+///
+/// Dependencies known to rye are declared with `true`, others say how to
+/// find them with `command` or `pkg-config`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeclaredSystemDep {
+    pub name: String,
+    pub command: Option<String>,
+    pub pkg_config: Option<String>,
+    pub hint: Option<String>,
+}
+
 /// Represents a source.
 pub struct SourceRef {
     pub name: String,
@@ -132,6 +154,7 @@ pub struct SourceRef {
     pub verify_ssl: bool,
     pub username: Option<String>,
     pub password: Option<String>,
+    pub credentials: Option<String>,
     pub ty: SourceRefType,
 }
 
@@ -143,6 +166,7 @@ impl SourceRef {
             verify_ssl: true,
             username: None,
             password: None,
+            credentials: None,
             ty,
         }
     }
@@ -170,6 +194,10 @@ impl SourceRef {
             .get("password")
             .and_then(|x| x.as_str())
             .map(|x| x.to_string());
+        let credentials = source
+            .get("credentials")
+            .and_then(|x| x.as_str())
+            .map(|x| x.to_string());
         let ty = source
             .get("type")
             .and_then(|x| x.as_str())
@@ -181,6 +209,7 @@ impl SourceRef {
             verify_ssl,
             username,
             password,
+            credentials,
             ty,
         })
     }
@@ -198,8 +227,49 @@ impl SourceRef {
         if let Some(ref password) = self.password {
             url.set_password(Some(password)).ok();
         }
+        if url.password().is_none() {
+            if let Some((username, password)) = self.resolve_credentials(&url)? {
+                if url.username().is_empty() {
+                    url.set_username(&username).ok();
+                }
+                url.set_password(Some(&password)).ok();
+            }
+        }
         Ok(url)
     }
+
+    /// Resolves credentials that are not embedded in the source.
+    ///
+    /// A `credentials = "env:VAR"` reference is looked up in the environment.  The
+    /// variable either holds `username:password` or just a token.  Otherwise the
+    /// user's netrc file is consulted for the host of the source.
+    fn resolve_credentials(&self, url: &Url) -> Result<Option<(String, String)>, Error> {
+        if let Some(ref credentials) = self.credentials {
+            let var = credentials.strip_prefix("env:").ok_or_else(|| {
+                anyhow!(
+                    "invalid credentials '{}' for source '{}' (expected env:VAR)",
+                    credentials,
+                    self.name
+                )
+            })?;
+            let value = std::env::var(var).with_context(|| {
+                format!(
+                    "environment variable {} for source '{}' is not set",
+                    var, self.name
+                )
+            })?;
+            return Ok(Some(match value.split_once(':') {
+                Some((username, password)) => (username.to_string(), password.to_string()),
+                None => (
+                    self.username
+                        .clone()
+                        .unwrap_or_else(|| "__token__".to_string()),
+                    value,
+                ),
+            }));
+        }
+        Ok(url.host_str().and_then(lookup_netrc_credentials))
+    }
 }
 
 type EnvVars = HashMap<String, String>;
@@ -212,12 +282,103 @@ pub enum Script {
     Call(String, EnvVars, EnvFile),
     /// A command alias
     Cmd(Vec<String>, EnvVars, EnvFile),
+    /// A command that is run by a shell
+    Shell(ShellCommand, EnvVars, EnvFile),
     /// A multi-script execution
     Chain(Vec<Vec<String>>),
     /// External script reference
     External(PathBuf),
 }
 
+/// A script command that is run by a shell instead of being executed directly.
+///
+/// This makes pipes, redirects and other shell syntax available.
+#[derive(Clone, Debug)]
+pub struct ShellCommand {
+    /// The shell program, eg: `sh` or `cmd`.
+    pub shell: String,
+    pub command: String,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum ShellFlavor {
+    Posix,
+    Cmd,
+    PowerShell,
+}
+
+impl ShellCommand {
+    /// The shell that `shell = true` selects on this platform.
+    fn default_shell() -> &'static str {
+        if cfg!(windows) {
+            "cmd"
+        } else {
+            "sh"
+        }
+    }
+
+    fn flavor(&self) -> ShellFlavor {
+        let name = Path::new(&self.shell)
+            .file_stem()
+            .and_then(|x| x.to_str())
+            .unwrap_or("")
+            .to_ascii_lowercase();
+        match name.as_str() {
+            "cmd" => ShellFlavor::Cmd,
+            "powershell" | "pwsh" => ShellFlavor::PowerShell,
+            _ => ShellFlavor::Posix,
+        }
+    }
+
+    /// Quotes an extra argument for the shell.
+    fn quote_arg<'a>(&self, arg: &'a str) -> Cow<'a, str> {
+        let plain = !arg.is_empty()
+            && arg
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "-_./:=@,+".contains(c));
+        if plain {
+            return Cow::Borrowed(arg);
+        }
+        match self.flavor() {
+            ShellFlavor::Posix => shlex_quote_unsafe(arg),
+            ShellFlavor::Cmd => Cow::Owned(quote_cmd_arg(arg)),
+            ShellFlavor::PowerShell => Cow::Owned(format!("'{}'", arg.replace('\'', "''"))),
+        }
+    }
+
+    /// Adds the arguments that make the shell run the command.
+    ///
+    /// Extra arguments are quoted for the shell and appended to the command.
+    pub fn add_args(&self, cmd: &mut Command, extra: &[OsString]) {
+        let mut command = self.command.clone();
+        for arg in extra {
+            command.push(' ');
+            command.push_str(&self.quote_arg(&arg.to_string_lossy()));
+        }
+        match self.flavor() {
+            ShellFlavor::Posix => {
+                cmd.arg("-c").arg(command);
+            }
+            ShellFlavor::PowerShell => {
+                cmd.arg("-NoProfile").arg("-Command").arg(command);
+            }
+            ShellFlavor::Cmd => {
+                // cmd does not follow the quoting rules of other programs, with
+                // `/S` it strips exactly the outer quotes of the command.
+                #[cfg(windows)]
+                {
+                    use std::os::windows::process::CommandExt;
+                    cmd.arg("/S").arg("/C").raw_arg(format!("\"{}\"", command));
+                }
+                #[cfg(not(windows))]
+                {
+                    cmd.arg("/C").arg(command);
+                }
+            }
+        }
+    }
+}
+
 fn toml_array_as_string_array(arr: &Array) -> Vec<String> {
     arr.iter()
         .map(|x| {
@@ -265,6 +426,32 @@ impl Script {
                 .map(PathBuf::from)
         }
 
+        // `Some(None)` means that the script does not use a shell, `None`
+        // that the `shell` key is invalid.
+        fn get_shell(detailed: &dyn TableLike) -> Option<Option<String>> {
+            let Some(shell) = detailed.get("shell") else {
+                return Some(None);
+            };
+            if let Some(enabled) = shell.as_bool() {
+                Some(enabled.then(|| ShellCommand::default_shell().to_string()))
+            } else if let Some(shell) = shell.as_str() {
+                Some(Some(shell.to_string()))
+            } else if let Some(by_platform) = shell.as_table_like() {
+                let mut keys = vec![std::env::consts::OS];
+                if cfg!(unix) {
+                    keys.push("unix");
+                }
+                for key in keys {
+                    if let Some(shell) = by_platform.get(key) {
+                        return Some(Some(shell.as_str()?.to_string()));
+                    }
+                }
+                Some(Some(ShellCommand::default_shell().to_string()))
+            } else {
+                None
+            }
+        }
+
         if let Some(detailed) = item.as_table_like() {
             if let Some(call) = detailed.get("call") {
                 let entry = call.as_str()?.to_string();
@@ -276,9 +463,24 @@ impl Script {
                     cmds.iter().flat_map(toml_value_as_command_args).collect(),
                 ))
             } else if let Some(cmd) = detailed.get("cmd") {
-                let cmd = toml_value_as_command_args(cmd.as_value()?)?;
                 let env_vars = get_env_vars(detailed);
                 let env_file = get_env_file(detailed);
+                if let Some(shell) = get_shell(detailed)? {
+                    let cmd = cmd.as_value()?;
+                    let command = match cmd.as_array() {
+                        Some(args) => {
+                            let args = toml_array_as_string_array(args);
+                            shlex::try_join(args.iter().map(|x| x.as_str())).ok()?
+                        }
+                        None => cmd.as_str()?.to_string(),
+                    };
+                    return Some(Script::Shell(
+                        ShellCommand { shell, command },
+                        env_vars,
+                        env_file,
+                    ));
+                }
+                let cmd = toml_value_as_command_args(cmd.as_value()?)?;
                 Some(Script::Cmd(cmd, env_vars, env_file))
             } else {
                 None
@@ -290,6 +492,42 @@ impl Script {
     }
 }
 
+/// Quotes an argument for a program started through cmd.
+///
+/// The argument is quoted the way programs split their command line (quotes
+/// are escaped with backslashes) and then every character cmd interprets is
+/// escaped with `^`, so that cmd passes the quoted argument on unchanged.
+fn quote_cmd_arg(arg: &str) -> String {
+    let mut quoted = String::from("\"");
+    let mut backslashes = 0;
+    for c in arg.chars() {
+        match c {
+            '\\' => backslashes += 1,
+            '"' => {
+                quoted.extend(std::iter::repeat('\\').take(backslashes * 2 + 1));
+                quoted.push('"');
+                backslashes = 0;
+            }
+            _ => {
+                quoted.extend(std::iter::repeat('\\').take(backslashes));
+                quoted.push(c);
+                backslashes = 0;
+            }
+        }
+    }
+    quoted.extend(std::iter::repeat('\\').take(backslashes * 2));
+    quoted.push('"');
+
+    let mut rv = String::with_capacity(quoted.len());
+    for c in quoted.chars() {
+        if "()%!^\"<>&|".contains(c) {
+            rv.push('^');
+        }
+        rv.push(c);
+    }
+    rv
+}
+
 /// Unsafe form of [`shlex::try_quote`] for display only.
 fn shlex_quote_unsafe(s: &str) -> Cow<'_, str> {
     shlex::Quoter::new().allow_nul(true).quote(s).unwrap()
@@ -346,6 +584,21 @@ impl fmt::Display for Script {
                 }
                 Ok(())
             }
+            Script::Shell(shell, env, env_file) => {
+                for (key, value) in env.iter() {
+                    write!(
+                        f,
+                        "{}={} ",
+                        shlex_quote_unsafe(key),
+                        shlex_quote_unsafe(value)
+                    )?;
+                }
+                write!(f, "{} (shell: {})", shell.command, shell.shell)?;
+                if let Some(ref env_file) = env_file {
+                    write!(f, " (env-file: {})", env_file.display())?;
+                }
+                Ok(())
+            }
             Script::Chain(cmds) => {
                 write!(f, "chain:")?;
                 for (idx, cmd) in cmds.iter().enumerate() {
@@ -525,6 +778,11 @@ impl Workspace {
         resolve_intended_venv_python_version(&self.doc, &self.root)
     }
 
+    /// Like [`Self::venv_python_version`] but also reports why that version was picked.
+    pub fn venv_python_version_resolution(&self) -> Result<PythonVersionResolution, Error> {
+        resolve_intended_venv_python_version_with_source(&self.doc, &self.root)
+    }
+
     /// Returns a list of index URLs that should be considered.
     pub fn sources(&self) -> Result<Vec<SourceRef>, Error> {
         get_sources(&self.doc)
@@ -535,10 +793,85 @@ impl Workspace {
         is_rye_managed(&self.doc)
     }
 
+    /// Returns the prompt of the workspace's virtualenv.
+    pub fn venv_prompt(&self) -> String {
+        get_venv_prompt(&self.doc, &self.root)
+    }
+
     /// Should requirements.txt based locking include a find-links reference?
     pub fn lock_with_sources(&self) -> bool {
         lock_with_sources(&self.doc)
     }
+
+    /// Should a structured lockfile be written next to the lockfiles?
+    pub fn structured_lock(&self) -> bool {
+        structured_lock(&self.doc)
+    }
+
+    /// Should a resolution report be written next to the lockfiles?
+    pub fn resolution_report(&self) -> bool {
+        resolution_report(&self.doc)
+    }
+
+    /// Returns the Pyodide release to lock for with `--platform emscripten`.
+    pub fn pyodide_version(&self) -> Option<String> {
+        pyodide_version(&self.doc)
+    }
+
+    /// Returns the versions of rye the workspace requires.
+    pub fn rye_version(&self) -> Option<String> {
+        rye_version(&self.doc)
+    }
+
+    /// Returns the folder the dependencies are vendored into.
+    pub fn vendor_dir(&self) -> Option<PathBuf> {
+        vendor_dir(&self.doc, &self.root)
+    }
+
+    /// Returns the policy of the workspace.
+    pub fn policy(&self) -> Policy {
+        Policy::from_doc(&self.doc)
+    }
+
+    /// Returns the names and patterns of the private packages of the workspace.
+    pub fn private_packages(&self) -> Vec<String> {
+        private_packages(&self.doc)
+    }
+
+    /// Returns the dependency variants of the workspace.
+    pub fn variants(&self) -> Result<Vec<Variant>, Error> {
+        get_variants(&self.doc).with_context(|| {
+            format!(
+                "invalid variants in {}",
+                self.path().join("pyproject.toml").display()
+            )
+        })
+    }
+
+    /// Returns the native dependencies of the workspace for this platform.
+    pub fn native_deps(&self) -> Result<Vec<NativeDep>, Error> {
+        get_native_deps(&self.doc).with_context(|| {
+            format!(
+                "invalid native dependencies in {}",
+                self.path().join("pyproject.toml").display()
+            )
+        })
+    }
+
+    /// Returns the Python versions of the test environments.
+    pub fn test_envs(&self) -> Vec<String> {
+        test_envs(&self.doc)
+    }
+
+    /// Returns the system dependencies declared in the workspace.
+    pub fn system_deps(&self) -> Vec<DeclaredSystemDep> {
+        system_deps(&self.doc)
+    }
+
+    /// Should dependencies be kept sorted?
+    pub fn sort_dependencies(&self) -> bool {
+        sort_dependencies(&self.doc)
+    }
 }
 
 /// Check if recurse should be skipped into directory with this name
@@ -592,10 +925,12 @@ impl PyProject {
     /// Loads a pyproject toml.
     pub fn load(filename: &Path) -> Result<PyProject, Error> {
         let root = filename.parent().unwrap_or(Path::new("."));
-        let doc = fs::read_to_string(filename)
-            .path_context(filename, "failed to read pyproject.toml")?
+        let contents =
+            fs::read_to_string(filename).path_context(filename, "failed to read pyproject.toml")?;
+        let doc = contents
             .parse::<DocumentMut>()
             .path_context(filename, "failed to parse pyproject.toml")?;
+        validate_project(&contents, filename)?;
         let mut workspace = Workspace::try_load_from_toml(&doc, root).map(Arc::new);
 
         if workspace.is_none() {
@@ -633,19 +968,19 @@ impl PyProject {
         workspace: Arc<Workspace>,
     ) -> Result<Option<PyProject>, Error> {
         let root = filename.parent().unwrap_or(Path::new("."));
-        let doc = fs::read_to_string(filename)?
-            .parse::<DocumentMut>()
-            .with_context(|| {
-                format!(
-                    "failed to parse pyproject.toml from '{}' in context of workspace {}",
-                    &filename.display(),
-                    workspace.path().display(),
-                )
-            })?;
+        let contents = fs::read_to_string(filename)?;
+        let doc = contents.parse::<DocumentMut>().with_context(|| {
+            format!(
+                "failed to parse pyproject.toml from '{}' in context of workspace {}",
+                &filename.display(),
+                workspace.path().display(),
+            )
+        })?;
 
         if !workspace.is_member(root) {
             return Ok(None);
         }
+        validate_project(&contents, filename)?;
 
         let basename = match filename.file_name() {
             Some(name) => name.to_os_string(),
@@ -726,6 +1061,15 @@ impl PyProject {
         }
     }
 
+    /// Like [`Self::venv_python_version`] but also reports why that version was picked.
+    pub fn venv_python_version_resolution(&self) -> Result<PythonVersionResolution, Error> {
+        if let Some(workspace) = self.workspace() {
+            workspace.venv_python_version_resolution()
+        } else {
+            resolve_intended_venv_python_version_with_source(&self.doc, &self.root)
+        }
+    }
+
     /// Set the target Python version.
     pub fn set_target_python_version(&mut self, version: &PythonVersionRequest) {
         let mut marker = format!(">= {}", version.major);
@@ -759,6 +1103,14 @@ impl PyProject {
             .and_then(|x| x.as_str())
     }
 
+    /// Returns the `requires-python` of the project.
+    pub fn requires_python(&self) -> Option<&str> {
+        self.doc
+            .get("project")
+            .and_then(|x| x.get("requires-python"))
+            .and_then(|x| x.as_str())
+    }
+
     /// Returns the normalized name.
     pub fn normalized_name(&self) -> Result<String, Error> {
         self.name()
@@ -782,6 +1134,16 @@ impl PyProject {
         Some(dv)
     }
 
+    /// Returns the version as declared in the `pyproject.toml`.
+    ///
+    /// Unlike [`Self::version`] this does not resolve dynamic versions.
+    pub fn declared_version(&self) -> Option<&str> {
+        self.doc
+            .get("project")
+            .and_then(|x| x.get("version"))
+            .and_then(|x| x.as_str())
+    }
+
     /// Returns the version.
     pub fn version(&mut self) -> Result<Version, Error> {
         let read_version = || {
@@ -839,6 +1201,22 @@ impl PyProject {
             build_system
         }
     }
+
+    /// Returns the requirements of the build backend.
+    pub fn build_requires(&self) -> Vec<String> {
+        self.doc
+            .get("build-system")
+            .and_then(|x| x.get("requires"))
+            .and_then(|x| x.as_array())
+            .map(|x| {
+                x.iter()
+                    .filter_map(|x| x.as_str())
+                    .map(|x| x.to_string())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
     /// Looks up a script
     pub fn get_script_cmd(&self, key: &str) -> Option<Script> {
         let external = self.venv_bin_path().join(key);
@@ -980,6 +1358,22 @@ impl PyProject {
             .map(DependencyRef::new)
     }
 
+    /// Returns the dependency groups with their includes resolved.
+    ///
+    /// The `dev` group always refers to `tool.rye.dev-dependencies`.
+    pub fn dependency_groups(&self) -> Result<BTreeMap<String, Vec<DependencyRef>>, Error> {
+        Ok(get_dependency_groups(&self.doc)
+            .with_context(|| {
+                format!(
+                    "invalid dependency groups in {}",
+                    self.toml_path().display()
+                )
+            })?
+            .into_iter()
+            .map(|(name, deps)| (name, deps.iter().map(|x| DependencyRef::new(x)).collect()))
+            .collect())
+    }
+
     /// Returns a list of sources that should be considered.
     pub fn sources(&self) -> Result<Vec<SourceRef>, Error> {
         match self.workspace {
@@ -996,6 +1390,16 @@ impl PyProject {
         }
     }
 
+    /// Returns the prompt of the project's virtualenv.
+    ///
+    /// This is `tool.rye.venv-prompt` or the project name.
+    pub fn venv_prompt(&self) -> String {
+        match self.workspace {
+            Some(ref workspace) => workspace.venv_prompt(),
+            None => get_venv_prompt(&self.doc, &self.root),
+        }
+    }
+
     /// Is this a virtual package (does not build)
     pub fn is_virtual(&self) -> bool {
         self.doc
@@ -1014,10 +1418,223 @@ impl PyProject {
         }
     }
 
+    /// Should a structured lockfile be written next to the lockfiles?
+    pub fn structured_lock(&self) -> bool {
+        match self.workspace {
+            Some(ref workspace) => workspace.structured_lock(),
+            None => structured_lock(&self.doc),
+        }
+    }
+
+    /// Should a resolution report be written next to the lockfiles?
+    pub fn resolution_report(&self) -> bool {
+        match self.workspace {
+            Some(ref workspace) => workspace.resolution_report(),
+            None => resolution_report(&self.doc),
+        }
+    }
+
+    /// Returns the Pyodide release to lock for with `--platform emscripten`.
+    pub fn pyodide_version(&self) -> Option<String> {
+        match self.workspace {
+            Some(ref workspace) => workspace.pyodide_version(),
+            None => pyodide_version(&self.doc),
+        }
+    }
+
+    /// Returns the versions of rye the project requires (`tool.rye.rye-version`).
+    ///
+    /// In a workspace this is configured in the workspace root.
+    pub fn rye_version(&self) -> Option<String> {
+        match self.workspace {
+            Some(ref workspace) => workspace.rye_version(),
+            None => rye_version(&self.doc),
+        }
+    }
+
+    /// Returns the policy for contributors.
+    ///
+    /// In a workspace this is configured in the workspace root.
+    pub fn policy(&self) -> Policy {
+        match self.workspace {
+            Some(ref workspace) => workspace.policy(),
+            None => Policy::from_doc(&self.doc),
+        }
+    }
+
+    /// Returns the names and patterns of private packages (`tool.rye.private-packages`).
+    ///
+    /// In a workspace these are configured in the workspace root.
+    pub fn private_packages(&self) -> Vec<String> {
+        match self.workspace {
+            Some(ref workspace) => workspace.private_packages(),
+            None => private_packages(&self.doc),
+        }
+    }
+
+    /// Returns the dependency variants (`tool.rye.variants`).
+    ///
+    /// In a workspace these are configured in the workspace root.
+    pub fn variants(&self) -> Result<Vec<Variant>, Error> {
+        match self.workspace {
+            Some(ref workspace) => workspace.variants(),
+            None => get_variants(&self.doc)
+                .with_context(|| format!("invalid variants in {}", self.toml_path().display())),
+        }
+    }
+
+    /// Returns the native dependencies for this platform (`tool.rye.native-deps`).
+    ///
+    /// In a workspace these are configured in the workspace root.
+    pub fn native_deps(&self) -> Result<Vec<NativeDep>, Error> {
+        match self.workspace {
+            Some(ref workspace) => workspace.native_deps(),
+            None => get_native_deps(&self.doc).with_context(|| {
+                format!(
+                    "invalid native dependencies in {}",
+                    self.toml_path().display()
+                )
+            }),
+        }
+    }
+
+    /// Returns the path of the pyproject.toml that configures the policy.
+    pub fn policy_path(&self) -> PathBuf {
+        self.workspace_path().join("pyproject.toml")
+    }
+
+    /// Returns the folder the dependencies are vendored into.
+    ///
+    /// In a workspace this is configured in the workspace root.  Syncs
+    /// install exclusively from this folder if it is set.
+    pub fn vendor_dir(&self) -> Option<PathBuf> {
+        match self.workspace {
+            Some(ref workspace) => workspace.vendor_dir(),
+            None => vendor_dir(&self.doc, &self.root),
+        }
+    }
+
+    /// Returns the Python versions of the test environments.
+    ///
+    /// In a workspace these are configured in the workspace root.
+    pub fn test_envs(&self) -> Vec<String> {
+        match self.workspace {
+            Some(ref workspace) => workspace.test_envs(),
+            None => test_envs(&self.doc),
+        }
+    }
+
+    /// Returns the system dependencies needed to build the dependencies.
+    ///
+    /// In a workspace these are configured in the workspace root.
+    pub fn system_deps(&self) -> Vec<DeclaredSystemDep> {
+        match self.workspace {
+            Some(ref workspace) => workspace.system_deps(),
+            None => system_deps(&self.doc),
+        }
+    }
+
+    /// Sets the folder the dependencies are vendored into.
+    pub fn set_vendor_dir(&mut self, dir: &str) {
+        self.doc["tool"]["rye"]["vendor-dir"] =
+            Item::Value(Value::String(Formatted::new(dir.to_string())));
+    }
+
+    /// Should dependencies be kept sorted on add and remove?
+    ///
+    /// This can be turned on for a single project or for the whole workspace.
+    pub fn sort_dependencies(&self) -> bool {
+        sort_dependencies(&self.doc)
+            || self
+                .workspace
+                .as_ref()
+                .map_or(false, |x| x.sort_dependencies())
+    }
+
+    /// Returns the paths that `rye run` and `rye test` add to `sys.path`.
+    ///
+    /// The paths of the workspace root come first.  Relative paths are
+    /// resolved against the project that declares them.
+    pub fn extra_python_paths(&self) -> Vec<PathBuf> {
+        let mut rv = match self.workspace {
+            Some(ref workspace) if !self.is_workspace_root() => {
+                extra_python_paths(&workspace.doc, &workspace.root)
+            }
+            _ => Vec::new(),
+        };
+        rv.extend(extra_python_paths(&self.doc, &self.root));
+        rv
+    }
+
+    /// Sorts and reformats the dependencies of the given kind.
+    pub fn normalize_dependencies(&mut self, kind: &DependencyKind) {
+        let dependencies = match kind {
+            DependencyKind::Normal => self
+                .doc
+                .get_mut("project")
+                .and_then(|x| x.get_mut("dependencies")),
+            DependencyKind::Dev => self
+                .doc
+                .get_mut("tool")
+                .and_then(|x| x.get_mut("rye"))
+                .and_then(|x| x.get_mut("dev-dependencies")),
+            DependencyKind::Excluded => self
+                .doc
+                .get_mut("tool")
+                .and_then(|x| x.get_mut("rye"))
+                .and_then(|x| x.get_mut("excluded-dependencies")),
+            DependencyKind::Optional(ref section) => self
+                .doc
+                .get_mut("project")
+                .and_then(|x| x.get_mut("optional-dependencies"))
+                .and_then(|x| x.get_mut(section as &str)),
+        };
+        if let Some(deps) = dependencies.and_then(|x| x.as_array_mut()) {
+            normalize_dependency_array(deps);
+        }
+    }
+
+    /// Sorts and reformats all dependency lists.
+    ///
+    /// Returns `true` if anything changed.
+    pub fn normalize_all_dependencies(&mut self) -> bool {
+        let before = self.doc.to_string();
+        let extras = self
+            .extras()
+            .into_iter()
+            .map(|x| x.to_string())
+            .collect::<Vec<_>>();
+        self.normalize_dependencies(&DependencyKind::Normal);
+        self.normalize_dependencies(&DependencyKind::Dev);
+        self.normalize_dependencies(&DependencyKind::Excluded);
+        for extra in extras {
+            self.normalize_dependencies(&DependencyKind::Optional(extra.into()));
+        }
+        if let Some(groups) = self
+            .doc
+            .get_mut("tool")
+            .and_then(|x| x.get_mut("rye"))
+            .and_then(|x| x.get_mut("dependency-groups"))
+            .and_then(|x| x.as_table_like_mut())
+        {
+            for (_, group) in groups.iter_mut() {
+                let deps = match group.as_table_like_mut() {
+                    Some(table) => table.get_mut("deps"),
+                    None => Some(group),
+                };
+                if let Some(deps) = deps.and_then(|x| x.as_array_mut()) {
+                    normalize_dependency_array(deps);
+                }
+            }
+        }
+        self.doc.to_string() != before
+    }
+
     /// Save back changes
     pub fn save(&self) -> Result<(), Error> {
         let path = self.toml_path();
-        fs::write(&path, self.doc.to_string()).path_context(&path, "unable to write changes")?;
+        write_atomic_with_backup(&path, self.doc.to_string())
+            .path_context(&path, "unable to write changes")?;
         Ok(())
     }
 }
@@ -1034,21 +1651,35 @@ pub fn normalize_package_name(x: &str) -> String {
         })
 }
 
+/// Adds or replaces a dependency.
+///
+/// A dependency can be listed multiple times with different markers, in
+/// which case the entry with the same marker is replaced.  If it is listed
+/// once, that entry is replaced and keeps its marker unless a new one is given.
 fn set_dependency(deps: &mut Array, req: &Requirement) {
-    let mut to_replace = None;
-    for (idx, dep) in deps.iter().enumerate() {
-        if let Some(dep) = dep.as_str() {
-            if let Ok(dep_req) = Requirement::from_str(dep) {
-                if dep_req.name.eq_ignore_ascii_case(&req.name) {
-                    to_replace = Some(idx);
-                    break;
-                }
-            }
+    let marker_of = |req: &Requirement| req.marker.as_ref().map(|x| x.to_string());
+    let candidates = deps
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, dep)| Some((idx, Requirement::from_str(dep.as_str()?).ok()?)))
+        .filter(|(_, dep_req)| dep_req.name.eq_ignore_ascii_case(&req.name))
+        .collect::<Vec<_>>();
+    let to_replace = candidates
+        .iter()
+        .find(|(_, dep_req)| marker_of(dep_req) == marker_of(req))
+        .or_else(|| match candidates.as_slice() {
+            [only] => Some(only),
+            _ => None,
+        });
+
+    let mut req = req.clone();
+    if let Some((_, dep_req)) = to_replace {
+        if req.marker.is_none() {
+            req.marker = dep_req.marker.clone();
         }
     }
-
-    let formatted = format_requirement(req).to_string();
-    if let Some(idx) = to_replace {
+    let formatted = format_requirement(&req).to_string();
+    if let Some(&(idx, _)) = to_replace {
         deps.replace(idx, formatted);
     } else {
         deps.push(formatted);
@@ -1056,6 +1687,32 @@ fn set_dependency(deps: &mut Array, req: &Requirement) {
     toml::reformat_array_multiline(deps);
 }
 
+/// Sorts a dependency array by package name and formats each requirement
+/// the same way `rye add` does.  Comments stay with their dependency.
+fn normalize_dependency_array(deps: &mut Array) {
+    let mut items = deps.iter().cloned().collect::<Vec<_>>();
+    for item in &mut items {
+        if let Some(req) = item.as_str().and_then(|x| Requirement::from_str(x).ok()) {
+            let decor = item.decor().clone();
+            *item = Value::from(format_requirement(&req).to_string());
+            *item.decor_mut() = decor;
+        }
+    }
+    items.sort_by_cached_key(|item| {
+        let dep = item.as_str().unwrap_or_default();
+        let name = dep
+            .split(|c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.'))
+            .next()
+            .unwrap_or_default();
+        (normalize_package_name(name), dep.to_string())
+    });
+    deps.clear();
+    for item in items {
+        deps.push_formatted(item);
+    }
+    toml::reformat_array_multiline(deps);
+}
+
 fn remove_dependency(deps: &mut Array, req: &Requirement) -> Option<Requirement> {
     let mut to_remove = None;
     for (idx, dep) in deps.iter().enumerate() {
@@ -1089,7 +1746,7 @@ pub fn read_venv_marker(venv_path: &Path) -> Option<VenvMarker> {
 
 pub fn write_venv_marker(venv_path: &Path, py_ver: &PythonVersion) -> Result<(), Error> {
     let marker = venv_path.join("rye-venv.json");
-    fs::write(
+    write_atomic(
         &marker,
         serde_json::to_string_pretty(&VenvMarker {
             python: py_ver.clone(),
@@ -1101,6 +1758,42 @@ pub fn write_venv_marker(venv_path: &Path, py_ver: &PythonVersion) -> Result<(),
     Ok(())
 }
 
+/// Reads the keys of the `pyvenv.cfg` of a virtualenv.
+pub fn read_pyvenv_cfg(venv_path: &Path) -> Option<HashMap<String, String>> {
+    let contents = fs::read_to_string(venv_path.join("pyvenv.cfg")).ok()?;
+    Some(
+        contents
+            .lines()
+            .filter_map(|line| line.split_once('='))
+            .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+            .collect(),
+    )
+}
+
+/// Sets keys in the `pyvenv.cfg` of a virtualenv.
+///
+/// Existing values for the keys are replaced, everything else is retained.
+pub fn update_pyvenv_cfg(venv_path: &Path, values: &[(&str, &str)]) -> Result<(), Error> {
+    let path = venv_path.join("pyvenv.cfg");
+    let contents = fs::read_to_string(&path).path_context(&path, "unable to read pyvenv.cfg")?;
+    let mut rv = String::new();
+    for line in contents.lines() {
+        let key = line.split_once('=').map(|(key, _)| key.trim());
+        if key.map_or(false, |key| values.iter().any(|(k, _)| *k == key)) {
+            continue;
+        }
+        rv.push_str(line);
+        rv.push('\n');
+    }
+    for (key, value) in values {
+        rv.push_str(&format!("{} = {}\n", key, value));
+    }
+    if rv == contents {
+        return Ok(());
+    }
+    write_atomic(&path, rv).path_context(&path, "unable to write pyvenv.cfg")
+}
+
 pub fn get_current_venv_python_version(venv_path: &Path) -> Option<PythonVersion> {
     read_venv_marker(venv_path).map(|x| x.python)
 }
@@ -1144,7 +1837,10 @@ fn resolve_target_python_version(
     root: &Path,
     venv_path: &Path,
 ) -> Option<PythonVersionRequest> {
-    resolve_lower_bound_python_version(doc)
+    doc.get("project")
+        .and_then(|x| x.get("requires-python"))
+        .and_then(|x| x.as_str())
+        .and_then(lower_bound_python_version)
         .or_else(|| get_current_venv_python_version(venv_path).map(Into::into))
         .or_else(|| get_python_version_request_from_pyenv_pin(root).map(Into::into))
         .or_else(|| Config::current().default_toolchain().ok())
@@ -1154,64 +1850,27 @@ fn resolve_intended_venv_python_version(
     doc: &DocumentMut,
     root: &Path,
 ) -> Result<PythonVersion, Error> {
-    let requested_version = get_python_version_request_from_pyenv_pin(root)
-        .or_else(|| resolve_lower_bound_python_version(doc))
-        .or_else(|| Config::current().default_toolchain().ok())
-        .ok_or_else(|| {
-            anyhow!(
-                "could not determine a target Python version.  Define requires-python in \
-                 pyproject.toml or use a .python-version file"
-            )
-        })?;
-
-    if let Ok(ver) = PythonVersion::try_from(requested_version.clone()) {
-        return Ok(ver);
-    }
-
-    if let Some(latest) = latest_available_python_version(&requested_version) {
-        Ok(latest)
-    } else {
-        Err(anyhow!(
-            "Unable to determine target virtualenv Python version"
-        ))
-    }
+    resolve_intended_venv_python_version_with_source(doc, root).map(|x| x.version)
 }
 
-fn resolve_lower_bound_python_version(doc: &DocumentMut) -> Option<PythonVersionRequest> {
-    doc.get("project")
+fn resolve_intended_venv_python_version_with_source(
+    doc: &DocumentMut,
+    root: &Path,
+) -> Result<PythonVersionResolution, Error> {
+    let requires_python = doc
+        .get("project")
         .and_then(|x| x.get("requires-python"))
-        .and_then(|x| x.as_str())
-        .and_then(|s| s.parse::<VersionSpecifiers>().ok())
-        .and_then(|versions| {
-            versions
-                .iter()
-                .filter(|x| {
-                    matches!(
-                        x.operator(),
-                        Operator::Equal
-                            | Operator::EqualStar
-                            | Operator::GreaterThanEqual
-                            | Operator::GreaterThan
-                    )
-                })
-                .map(|x| {
-                    let mut rv = PythonVersionRequest::from(x.version().clone());
-                    // this is pretty shitty, but probably good enough
-                    if matches!(x.operator(), Operator::GreaterThan) {
-                        if let Some(ref mut patch) = rv.patch {
-                            *patch += 1;
-                        } else if let Some(ref mut minor) = rv.minor {
-                            *minor += 1;
-                        }
-                    }
-                    rv
-                })
-                .min()
-        })
+        .and_then(|x| x.as_str());
+    resolve_python_version(None, root, requires_python)
 }
 
 pub fn find_project_root() -> Option<PathBuf> {
-    let mut here = env::current_dir().ok()?;
+    find_project_root_from(&env::current_dir().ok()?)
+}
+
+/// Finds the closest folder with a `pyproject.toml` starting at a folder.
+pub fn find_project_root_from(start: &Path) -> Option<PathBuf> {
+    let mut here = start.to_path_buf();
 
     loop {
         let project_file = here.join("pyproject.toml");
@@ -1269,6 +1928,114 @@ fn get_sources(doc: &DocumentMut) -> Result<Vec<SourceRef>, Error> {
     Ok(rv)
 }
 
+/// A dependency group as declared in `tool.rye.dependency-groups`.
+struct RawDependencyGroup<'a> {
+    includes: Vec<&'a str>,
+    deps: Vec<&'a str>,
+}
+
+fn get_str_array<'a>(item: Option<&'a Item>, path: &str) -> Result<Vec<&'a str>, Error> {
+    let Some(item) = item else {
+        return Ok(Vec::new());
+    };
+    item.as_array()
+        .and_then(|x| x.iter().map(|x| x.as_str()).collect::<Option<Vec<_>>>())
+        .ok_or_else(|| anyhow!("{} must be a list of strings", path))
+}
+
+/// Reads and flattens the dependency groups of a document.
+fn get_dependency_groups(doc: &DocumentMut) -> Result<BTreeMap<String, Vec<String>>, Error> {
+    let rye = doc.get("tool").and_then(|x| x.get("rye"));
+    let Some(groups) = rye.and_then(|x| x.get("dependency-groups")) else {
+        return Ok(BTreeMap::new());
+    };
+    let groups = groups
+        .as_table_like()
+        .ok_or_else(|| anyhow!("tool.rye.dependency-groups must be a table"))?;
+    let dev = get_str_array(
+        rye.and_then(|x| x.get("dev-dependencies")),
+        "tool.rye.dev-dependencies",
+    )?;
+
+    let mut raw = BTreeMap::new();
+    for (name, group) in groups.iter() {
+        let path = format!("tool.rye.dependency-groups.{}", name);
+        if name == "dev" {
+            bail!("{} is reserved for tool.rye.dev-dependencies", path);
+        }
+        if !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            bail!("{} is not a valid group name", path);
+        }
+        let group = if let Some(table) = group.as_table_like() {
+            RawDependencyGroup {
+                includes: get_str_array(table.get("includes"), &format!("{}.includes", path))?,
+                deps: get_str_array(table.get("deps"), &format!("{}.deps", path))?,
+            }
+        } else {
+            RawDependencyGroup {
+                includes: Vec::new(),
+                deps: get_str_array(Some(group), &path)?,
+            }
+        };
+        raw.insert(name, group);
+    }
+
+    let mut rv = BTreeMap::new();
+    for name in raw.keys() {
+        resolve_dependency_group(name, &raw, &dev, &mut rv, &mut Vec::new())?;
+    }
+    Ok(rv)
+}
+
+fn resolve_dependency_group(
+    name: &str,
+    raw: &BTreeMap<&str, RawDependencyGroup<'_>>,
+    dev: &[&str],
+    resolved: &mut BTreeMap<String, Vec<String>>,
+    stack: &mut Vec<String>,
+) -> Result<Vec<String>, Error> {
+    if name == "dev" {
+        return Ok(dev.iter().map(|x| x.to_string()).collect());
+    }
+    if let Some(deps) = resolved.get(name) {
+        return Ok(deps.clone());
+    }
+    if let Some(pos) = stack.iter().position(|x| x == name) {
+        bail!(
+            "dependency groups include each other: {} -> {}",
+            stack[pos..].join(" -> "),
+            name
+        );
+    }
+    let group = match (raw.get(name), stack.last()) {
+        (Some(group), _) => group,
+        (None, Some(parent)) => bail!(
+            "dependency group '{}' includes unknown group '{}'",
+            parent,
+            name
+        ),
+        (None, None) => bail!("unknown dependency group '{}'", name),
+    };
+
+    stack.push(name.to_string());
+    let mut deps = Vec::new();
+    for include in &group.includes {
+        deps.extend(resolve_dependency_group(
+            include, raw, dev, resolved, stack,
+        )?);
+    }
+    deps.extend(group.deps.iter().map(|x| x.to_string()));
+    stack.pop();
+
+    let mut seen = HashSet::new();
+    deps.retain(|x| seen.insert(x.clone()));
+    resolved.insert(name.to_string(), deps.clone());
+    Ok(deps)
+}
+
 fn is_rye_managed(doc: &DocumentMut) -> bool {
     if Config::current().force_rye_managed() {
         return true;
@@ -1280,6 +2047,21 @@ fn is_rye_managed(doc: &DocumentMut) -> bool {
         .unwrap_or(false)
 }
 
+fn get_venv_prompt(doc: &DocumentMut, root: &Path) -> String {
+    doc.get("tool")
+        .and_then(|x| x.get("rye"))
+        .and_then(|x| x.get("venv-prompt"))
+        .and_then(|x| x.as_str())
+        .or_else(|| {
+            doc.get("project")
+                .and_then(|x| x.get("name"))
+                .and_then(|x| x.as_str())
+        })
+        .map(|x| x.to_string())
+        .or_else(|| root.file_name().map(|x| x.to_string_lossy().into_owned()))
+        .unwrap_or_else(|| "venv".into())
+}
+
 fn lock_with_sources(doc: &DocumentMut) -> bool {
     doc.get("tool")
         .and_then(|x| x.get("rye"))
@@ -1288,9 +2070,130 @@ fn lock_with_sources(doc: &DocumentMut) -> bool {
         .unwrap_or(false)
 }
 
+fn structured_lock(doc: &DocumentMut) -> bool {
+    doc.get("tool")
+        .and_then(|x| x.get("rye"))
+        .and_then(|x| x.get("structured-lock"))
+        .and_then(|x| x.as_bool())
+        .unwrap_or(false)
+}
+
+fn resolution_report(doc: &DocumentMut) -> bool {
+    doc.get("tool")
+        .and_then(|x| x.get("rye"))
+        .and_then(|x| x.get("resolution-report"))
+        .and_then(|x| x.as_bool())
+        .unwrap_or(false)
+}
+
+fn pyodide_version(doc: &DocumentMut) -> Option<String> {
+    doc.get("tool")
+        .and_then(|x| x.get("rye"))
+        .and_then(|x| x.get("pyodide-version"))
+        .and_then(|x| x.as_str())
+        .map(|x| x.to_string())
+}
+
+fn private_packages(doc: &DocumentMut) -> Vec<String> {
+    doc.get("tool")
+        .and_then(|x| x.get("rye"))
+        .and_then(|x| x.get("private-packages"))
+        .and_then(|x| x.as_array())
+        .map(|x| {
+            x.iter()
+                .filter_map(|x| x.as_str())
+                .map(|x| x.to_string())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn rye_version(doc: &DocumentMut) -> Option<String> {
+    doc.get("tool")
+        .and_then(|x| x.get("rye"))
+        .and_then(|x| x.get("rye-version"))
+        .and_then(|x| x.as_str())
+        .map(|x| x.to_string())
+}
+
+fn vendor_dir(doc: &DocumentMut, root: &Path) -> Option<PathBuf> {
+    doc.get("tool")
+        .and_then(|x| x.get("rye"))
+        .and_then(|x| x.get("vendor-dir"))
+        .and_then(|x| x.as_str())
+        .map(|x| root.join(x))
+}
+
+fn test_envs(doc: &DocumentMut) -> Vec<String> {
+    doc.get("tool")
+        .and_then(|x| x.get("rye"))
+        .and_then(|x| x.get("test"))
+        .and_then(|x| x.get("envs"))
+        .and_then(|x| x.as_array())
+        .map(|x| {
+            x.iter()
+                .filter_map(|x| x.as_str())
+                .map(|x| x.to_string())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn system_deps(doc: &DocumentMut) -> Vec<DeclaredSystemDep> {
+    let Some(table) = doc
+        .get("tool")
+        .and_then(|x| x.get("rye"))
+        .and_then(|x| x.get("system-deps"))
+        .and_then(|x| x.as_table_like())
+    else {
+        return Vec::new();
+    };
+    table
+        .iter()
+        .filter(|(_, value)| value.as_bool() != Some(false))
+        .map(|(name, value)| {
+            let get = |key: &str| {
+                value
+                    .get(key)
+                    .and_then(|x| x.as_str())
+                    .map(|x| x.to_string())
+            };
+            DeclaredSystemDep {
+                name: name.to_string(),
+                command: get("command"),
+                pkg_config: get("pkg-config"),
+                hint: get("hint"),
+            }
+        })
+        .collect()
+}
+
+fn extra_python_paths(doc: &DocumentMut, root: &Path) -> Vec<PathBuf> {
+    doc.get("tool")
+        .and_then(|x| x.get("rye"))
+        .and_then(|x| x.get("run"))
+        .and_then(|x| x.get("extra-paths"))
+        .and_then(|x| x.as_array())
+        .map(|x| {
+            x.iter()
+                .filter_map(|x| x.as_str())
+                .map(|x| root.join(x))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn sort_dependencies(doc: &DocumentMut) -> bool {
+    doc.get("tool")
+        .and_then(|x| x.get("rye"))
+        .and_then(|x| x.get("sort-dependencies"))
+        .and_then(|x| x.as_bool())
+        .unwrap_or(false)
+}
+
 fn get_project_metadata(path: &Path) -> Result<Metadata, Error> {
-    let self_venv = ensure_self_venv(CommandOutput::Normal)?;
-    let mut metadata = Command::new(self_venv.join(VENV_BIN).join("python"));
+    let self_venv = ensure_self_venv(CommandOutput::NORMAL)?;
+    let mut metadata = internal_command(self_venv.join(VENV_BIN).join("python"));
     metadata.arg("-c").arg(PROJECT_METADATA_SCRIPT).arg(path);
     let metadata = metadata.stdout(Stdio::piped()).output()?;
     if !metadata.status.success() {
@@ -1305,6 +2208,7 @@ pub struct ExpandedSources {
     pub index_urls: Vec<(Url, bool)>,
     pub find_links: Vec<Url>,
     pub trusted_hosts: HashSet<String>,
+    pub no_index: bool,
 }
 
 impl ExpandedSources {
@@ -1313,9 +2217,22 @@ impl ExpandedSources {
             index_urls: Vec::new(),
             find_links: Vec::new(),
             trusted_hosts: HashSet::new(),
+            no_index: false,
         }
     }
 
+    /// Sources that only install from the distributions in a local folder.
+    pub fn offline(path: &Path) -> Result<ExpandedSources, Error> {
+        let url = Url::from_directory_path(path)
+            .map_err(|_| anyhow!("invalid find-links path '{}'", path.display()))?;
+        Ok(ExpandedSources {
+            index_urls: Vec::new(),
+            find_links: vec![url],
+            trusted_hosts: HashSet::new(),
+            no_index: true,
+        })
+    }
+
     /// Takes some sources and expands them.
     pub fn from_sources(sources: &[SourceRef]) -> Result<ExpandedSources, Error> {
         let mut index_urls = Vec::new();
@@ -1339,11 +2256,15 @@ impl ExpandedSources {
             index_urls,
             find_links,
             trusted_hosts,
+            no_index: false,
         })
     }
 
     /// Attach common pip args to a command.
     pub fn add_as_pip_args(&self, cmd: &mut Command) {
+        if self.no_index {
+            cmd.arg("--no-index");
+        }
         for (url, default) in self.index_urls.iter() {
             if *default {
                 cmd.arg("--index-url");
@@ -1363,16 +2284,18 @@ impl ExpandedSources {
     }
 
     /// Write the sources to a lockfile.
+    ///
+    /// Credentials are never written to the lockfile.
     pub fn add_to_lockfile(&self, out: &mut dyn std::io::Write) -> std::io::Result<()> {
         for (url, default) in self.index_urls.iter() {
             if *default {
-                writeln!(out, "--index-url {}", url)?;
+                writeln!(out, "--index-url {}", strip_credentials(url))?;
             } else {
-                writeln!(out, "--extra-index-url {}", url)?;
+                writeln!(out, "--extra-index-url {}", strip_credentials(url))?;
             }
         }
         for link in &self.find_links {
-            writeln!(out, "--find-links {}", link)?;
+            writeln!(out, "--find-links {}", strip_credentials(link))?;
         }
         for host in &self.trusted_hosts {
             writeln!(out, "--trusted-host {}", host)?;
@@ -1381,6 +2304,14 @@ impl ExpandedSources {
     }
 }
 
+/// Returns the URL without username and password.
+pub fn strip_credentials(url: &Url) -> Url {
+    let mut url = url.clone();
+    url.set_username("").ok();
+    url.set_password(None).ok();
+    url
+}
+
 #[derive(ValueEnum, Copy, Clone, Serialize, Debug, PartialEq)]
 #[value(rename_all = "snake_case")]
 #[serde(rename_all = "snake_case")]
@@ -1450,3 +2381,213 @@ pub fn locate_projects(
 
     Ok(projects)
 }
+
+#[cfg(test)]
+mod test {
+    use toml_edit::DocumentMut;
+
+    use std::str::FromStr;
+
+    use pep508_rs::Requirement;
+
+    use super::{
+        get_dependency_groups, normalize_dependency_array, quote_cmd_arg, set_dependency, Script,
+        SourceRef, SourceRefType,
+    };
+
+    fn groups(toml: &str) -> Result<Vec<(String, Vec<String>)>, String> {
+        let doc = toml.parse::<DocumentMut>().unwrap();
+        get_dependency_groups(&doc)
+            .map(|x| x.into_iter().collect())
+            .map_err(|x| x.to_string())
+    }
+
+    #[test]
+    fn test_normalize_dependency_array() {
+        let mut doc = r#"dependencies = [
+    "requests >= 2.0",
+    # web framework
+    "Flask>=3.0",
+    "attrs", "black[jupyter]"]
+"#
+        .parse::<DocumentMut>()
+        .unwrap();
+        normalize_dependency_array(doc["dependencies"].as_array_mut().unwrap());
+        assert_eq!(
+            doc.to_string(),
+            r#"dependencies = [
+    "attrs",
+    "black[jupyter]",
+    # web framework
+    "Flask>=3.0",
+    "requests>=2.0",
+]
+"#
+        );
+    }
+
+    #[test]
+    fn test_set_dependency_keeps_markers() {
+        let mut doc = r#"dependencies = [
+    "numpy>=1.26 ; python_version >= '3.12'",
+    "numpy<1.26 ; python_version < '3.12'",
+    "pywin32>=306 ; sys_platform == 'win32'",
+]
+"#
+        .parse::<DocumentMut>()
+        .unwrap();
+        let deps = doc["dependencies"].as_array_mut().unwrap();
+        let marker_of = |dep: &str| Requirement::from_str(dep).unwrap().marker;
+
+        // a single entry keeps its marker when updated without one
+        set_dependency(deps, &Requirement::from_str("pywin32>=307").unwrap());
+        assert_eq!(deps.len(), 3);
+        assert_eq!(
+            marker_of(deps.get(2).unwrap().as_str().unwrap()),
+            marker_of("pywin32 ; sys_platform == 'win32'")
+        );
+        assert!(deps.get(2).unwrap().as_str().unwrap().contains(">=307"));
+
+        // with multiple entries the one with the same marker is replaced
+        set_dependency(
+            deps,
+            &Requirement::from_str("numpy<1.25 ; python_version < '3.12'").unwrap(),
+        );
+        assert_eq!(deps.len(), 3);
+        assert!(deps.get(1).unwrap().as_str().unwrap().contains("<1.25"));
+        assert!(deps.get(0).unwrap().as_str().unwrap().contains(">=1.26"));
+    }
+
+    #[test]
+    fn test_dependency_groups() {
+        let rv = groups(
+            r#"
+[tool.rye]
+dev-dependencies = ["pytest"]
+
+[tool.rye.dependency-groups]
+docs = ["mkdocs"]
+test = { includes = ["dev"], deps = ["pytest-cov"] }
+ci = { includes = ["test", "docs"], deps = ["pytest"] }
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            rv,
+            vec![
+                (
+                    "ci".into(),
+                    vec!["pytest".into(), "pytest-cov".into(), "mkdocs".into()]
+                ),
+                ("docs".into(), vec!["mkdocs".into()]),
+                ("test".into(), vec!["pytest".into(), "pytest-cov".into()]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_dependency_group_errors() {
+        assert_eq!(
+            groups(
+                r#"
+[tool.rye.dependency-groups]
+a = { includes = ["b"] }
+b = { includes = ["c"] }
+c = { includes = ["a"] }
+"#
+            )
+            .unwrap_err(),
+            "dependency groups include each other: a -> b -> c -> a"
+        );
+        assert_eq!(
+            groups(
+                r#"
+[tool.rye.dependency-groups]
+a = { includes = ["missing"] }
+"#
+            )
+            .unwrap_err(),
+            "dependency group 'a' includes unknown group 'missing'"
+        );
+        assert!(groups("[tool.rye.dependency-groups]\ndev = []\n").is_err());
+    }
+
+    fn script(toml: &str) -> Option<Script> {
+        let doc = toml.parse::<DocumentMut>().unwrap();
+        Script::from_toml_item(&doc["script"])
+    }
+
+    #[test]
+    fn test_shell_scripts() {
+        let Some(Script::Shell(shell, _, _)) =
+            script(r#"script = { cmd = "a | b", shell = true }"#)
+        else {
+            panic!("expected a shell script");
+        };
+        assert_eq!(shell.command, "a | b");
+        assert_eq!(shell.shell, if cfg!(windows) { "cmd" } else { "sh" });
+
+        let Some(Script::Shell(shell, _, _)) =
+            script(r#"script = { cmd = ["echo", "a b"], shell = "bash" }"#)
+        else {
+            panic!("expected a shell script");
+        };
+        assert_eq!(shell.command, "echo 'a b'");
+        assert_eq!(shell.shell, "bash");
+
+        let Some(Script::Shell(shell, _, _)) = script(
+            r#"script = { cmd = "x", shell = { windows = "pwsh", linux = "bash", unix = "zsh" } }"#,
+        ) else {
+            panic!("expected a shell script");
+        };
+        let expected = match std::env::consts::OS {
+            "windows" => "pwsh",
+            "linux" => "bash",
+            _ => "zsh",
+        };
+        assert_eq!(shell.shell, expected);
+
+        assert!(matches!(
+            script(r#"script = { cmd = "a b", shell = false }"#),
+            Some(Script::Cmd(..))
+        ));
+        assert!(script(r#"script = { cmd = "a b", shell = 1 }"#).is_none());
+    }
+
+    #[test]
+    fn test_source_env_credentials() {
+        let mut source = SourceRef::from_url(
+            "company".into(),
+            "https://company.internal/simple/".into(),
+            SourceRefType::Index,
+        );
+        source.credentials = Some("env:RYE_TEST_SOURCE_CREDENTIALS".into());
+
+        std::env::set_var("RYE_TEST_SOURCE_CREDENTIALS", "john:s3cret");
+        let url = source.expand_url().unwrap();
+        assert_eq!(url.username(), "john");
+        assert_eq!(url.password(), Some("s3cret"));
+
+        // a plain token goes with the configured or the token username
+        std::env::set_var("RYE_TEST_SOURCE_CREDENTIALS", "pypi-token");
+        let url = source.expand_url().unwrap();
+        assert_eq!(url.username(), "__token__");
+        assert_eq!(url.password(), Some("pypi-token"));
+        source.username = Some("jane".into());
+        assert_eq!(source.expand_url().unwrap().username(), "jane");
+
+        std::env::remove_var("RYE_TEST_SOURCE_CREDENTIALS");
+        assert!(source.expand_url().is_err());
+
+        source.credentials = Some("RYE_TEST_SOURCE_CREDENTIALS".into());
+        assert!(source.expand_url().is_err());
+    }
+
+    #[test]
+    fn test_quote_cmd_arg() {
+        assert_eq!(quote_cmd_arg("a b"), r#"^"a b^""#);
+        assert_eq!(quote_cmd_arg(r#"say "hi""#), r#"^"say \^"hi\^"^""#);
+        assert_eq!(quote_cmd_arg(r"C:\dir\"), r#"^"C:\dir\\^""#);
+        assert_eq!(quote_cmd_arg("a&b"), r#"^"a^&b^""#);
+    }
+}
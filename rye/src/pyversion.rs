@@ -0,0 +1,283 @@
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Error};
+use pep440_rs::{Operator, VersionSpecifiers};
+
+use crate::config::Config;
+use crate::pyproject::latest_available_python_version;
+use crate::sources::py::{PythonVersion, PythonVersionRequest};
+
+/// Explains where a Python version was taken from.
+///
+/// The variants are listed in the order in which they are consulted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PythonVersionSource {
+    /// Passed explicitly on the command line.
+    CommandLine,
+    /// The `RYE_PYTHON` environment variable.
+    Environment,
+    /// A `.python-version` file in the project, the workspace root or any
+    /// of their parent folders.
+    PythonVersionFile(PathBuf),
+    /// The lower bound of `requires-python`.
+    RequiresPython(String),
+    /// The `default.toolchain` config (or the latest release).
+    DefaultToolchain,
+}
+
+impl fmt::Display for PythonVersionSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PythonVersionSource::CommandLine => write!(f, "command line"),
+            PythonVersionSource::Environment => write!(f, "RYE_PYTHON environment variable"),
+            PythonVersionSource::PythonVersionFile(path) => {
+                write!(f, "pinned in {}", path.display())
+            }
+            PythonVersionSource::RequiresPython(requires_python) => {
+                write!(f, "lower bound of requires-python ({})", requires_python)
+            }
+            PythonVersionSource::DefaultToolchain => write!(f, "default.toolchain config"),
+        }
+    }
+}
+
+/// The result of resolving the Python version to use.
+#[derive(Debug, Clone)]
+pub struct PythonVersionResolution {
+    pub request: PythonVersionRequest,
+    pub source: PythonVersionSource,
+    pub version: PythonVersion,
+}
+
+/// Resolves which Python version should be used for a folder.
+///
+/// This is the one place that decides on the interpreter for shims, sync and
+/// run.  The first match wins:
+///
+/// 1. the version passed on the command line (`explicit`)
+/// 2. the `RYE_PYTHON` environment variable
+/// 3. the closest `.python-version` file starting at `root` (for workspace
+///    members this is resolved from the workspace root)
+/// 4. the lower bound of `requires-python` if a project is involved
+/// 5. the `default.toolchain` config
+pub fn resolve_python_version_request(
+    explicit: Option<PythonVersionRequest>,
+    root: &Path,
+    requires_python: Option<&str>,
+) -> Result<(PythonVersionRequest, PythonVersionSource), Error> {
+    let from_env = env::var("RYE_PYTHON").ok().filter(|x| !x.is_empty());
+    resolve_request_from(explicit, from_env.as_deref(), root, requires_python, || {
+        Config::current().default_toolchain().ok()
+    })
+}
+
+/// Like [`resolve_python_version_request`] but also picks the concrete version.
+///
+/// If the request is not precise, the latest matching installed toolchain is
+/// used or, failing that, the latest one that can be downloaded.
+pub fn resolve_python_version(
+    explicit: Option<PythonVersionRequest>,
+    root: &Path,
+    requires_python: Option<&str>,
+) -> Result<PythonVersionResolution, Error> {
+    let (request, source) = resolve_python_version_request(explicit, root, requires_python)?;
+    let version = match PythonVersion::try_from(request.clone()) {
+        Ok(ver) => ver,
+        Err(_) => latest_available_python_version(&request)
+            .ok_or_else(|| anyhow!("Unable to determine target Python version"))?,
+    };
+    Ok(PythonVersionResolution {
+        request,
+        source,
+        version,
+    })
+}
+
+fn resolve_request_from<F: FnOnce() -> Option<PythonVersionRequest>>(
+    explicit: Option<PythonVersionRequest>,
+    from_env: Option<&str>,
+    root: &Path,
+    requires_python: Option<&str>,
+    default_toolchain: F,
+) -> Result<(PythonVersionRequest, PythonVersionSource), Error> {
+    if let Some(request) = explicit {
+        return Ok((request, PythonVersionSource::CommandLine));
+    }
+    if let Some(value) = from_env {
+        let request = value
+            .parse()
+            .with_context(|| format!("invalid Python version '{}' in RYE_PYTHON", value))?;
+        return Ok((request, PythonVersionSource::Environment));
+    }
+    if let Some((request, path)) = get_python_version_request_from_pyenv_pin_with_path(root) {
+        return Ok((request, PythonVersionSource::PythonVersionFile(path)));
+    }
+    if let Some(requires_python) = requires_python {
+        if let Some(request) = lower_bound_python_version(requires_python) {
+            return Ok((
+                request,
+                PythonVersionSource::RequiresPython(requires_python.to_string()),
+            ));
+        }
+    }
+    default_toolchain()
+        .map(|request| (request, PythonVersionSource::DefaultToolchain))
+        .ok_or_else(|| {
+            anyhow!(
+                "could not determine a target Python version.  Define requires-python in \
+                 pyproject.toml or use a .python-version file"
+            )
+        })
+}
+
+/// Reads the current `.python-version` file.
+pub fn get_python_version_request_from_pyenv_pin(root: &Path) -> Option<PythonVersionRequest> {
+    get_python_version_request_from_pyenv_pin_with_path(root).map(|x| x.0)
+}
+
+/// Reads the current `.python-version` file and also returns where it was found.
+pub fn get_python_version_request_from_pyenv_pin_with_path(
+    root: &Path,
+) -> Option<(PythonVersionRequest, PathBuf)> {
+    let mut here = root.to_owned();
+
+    loop {
+        here.push(".python-version");
+        if let Ok(contents) = fs::read_to_string(&here) {
+            return read_python_version(&contents).map(|ver| (ver, here));
+        }
+
+        // pop filename
+        here.pop();
+
+        // pop parent
+        if !here.pop() {
+            break;
+        }
+    }
+
+    None
+}
+
+/// Return the [`PythonVersionRequest`] from a `.python-version` file.
+fn read_python_version(contents: &str) -> Option<PythonVersionRequest> {
+    // Skip empty lines and comments.
+    let ver = contents.lines().find(|line| {
+        let trimmed = line.trim();
+        !(trimmed.is_empty() || trimmed.starts_with('#'))
+    })?;
+
+    // Parse the version.
+    let ver = ver.parse().ok()?;
+
+    Some(ver)
+}
+
+/// Returns the lowest Python version allowed by a `requires-python` value.
+pub fn lower_bound_python_version(requires_python: &str) -> Option<PythonVersionRequest> {
+    requires_python
+        .parse::<VersionSpecifiers>()
+        .ok()
+        .and_then(|versions| {
+            versions
+                .iter()
+                .filter(|x| {
+                    matches!(
+                        x.operator(),
+                        Operator::Equal
+                            | Operator::EqualStar
+                            | Operator::GreaterThanEqual
+                            | Operator::GreaterThan
+                    )
+                })
+                .map(|x| {
+                    let mut rv = PythonVersionRequest::from(x.version().clone());
+                    // this is pretty shitty, but probably good enough
+                    if matches!(x.operator(), Operator::GreaterThan) {
+                        if let Some(ref mut patch) = rv.patch {
+                            *patch += 1;
+                        } else if let Some(ref mut minor) = rv.minor {
+                            *minor += 1;
+                        }
+                    }
+                    rv
+                })
+                .min()
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use super::{resolve_request_from, PythonVersionSource};
+
+    #[test]
+    fn test_read_python_version() {
+        // Parse a simple version.
+        let ver = super::read_python_version("3.8.1\n");
+        assert_eq!(ver, Some("3.8.1".parse().unwrap()));
+
+        // Skip empty lines.
+        let ver = super::read_python_version("\n\n3.8.1\n");
+        assert_eq!(ver, Some("3.8.1".parse().unwrap()));
+
+        // Skip comments.
+        let ver = super::read_python_version("# comment\n3.8.1\n");
+        assert_eq!(ver, Some("3.8.1".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_resolution_chain() {
+        let dir = tempfile::tempdir().unwrap();
+        let workspace = dir.path();
+        let member = workspace.join("member");
+        fs::create_dir_all(&member).unwrap();
+        let default = || Some("3.12".parse().unwrap());
+
+        // nothing pinned uses requires-python and then the default
+        let (req, source) =
+            resolve_request_from(None, None, &member, Some(">= 3.9"), default).unwrap();
+        assert_eq!(req, "3.9".parse().unwrap());
+        assert_eq!(source, PythonVersionSource::RequiresPython(">= 3.9".into()));
+        let (req, source) = resolve_request_from(None, None, &member, None, default).unwrap();
+        assert_eq!(req, "3.12".parse().unwrap());
+        assert_eq!(source, PythonVersionSource::DefaultToolchain);
+
+        // the workspace root pin is found from the member
+        fs::write(workspace.join(".python-version"), "3.10\n").unwrap();
+        let (req, source) =
+            resolve_request_from(None, None, &member, Some(">= 3.9"), default).unwrap();
+        assert_eq!(req, "3.10".parse().unwrap());
+        assert_eq!(
+            source,
+            PythonVersionSource::PythonVersionFile(workspace.join(".python-version"))
+        );
+
+        // a pin in the member wins over the workspace root
+        fs::write(member.join(".python-version"), "3.11\n").unwrap();
+        let (req, _) = resolve_request_from(None, None, &member, None, default).unwrap();
+        assert_eq!(req, "3.11".parse().unwrap());
+
+        // the environment wins over pins and the command line over everything
+        let (req, source) =
+            resolve_request_from(None, Some("3.8"), &member, None, default).unwrap();
+        assert_eq!(req, "3.8".parse().unwrap());
+        assert_eq!(source, PythonVersionSource::Environment);
+        let (req, source) = resolve_request_from(
+            Some("pypy@3.10".parse().unwrap()),
+            Some("3.8"),
+            &member,
+            None,
+            default,
+        )
+        .unwrap();
+        assert_eq!(req, "pypy@3.10".parse().unwrap());
+        assert_eq!(source, PythonVersionSource::CommandLine);
+
+        assert!(resolve_request_from(None, Some("nope"), &member, None, default).is_err());
+    }
+}
@@ -0,0 +1,441 @@
+use std::collections::HashSet;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::Mutex;
+
+use anyhow::{bail, Error};
+use once_cell::sync::Lazy;
+use toml_edit::{ImDocument, Item, TableLike};
+
+/// Warnings shown by this process, projects are loaded more than once.
+static WARNED: Lazy<Mutex<HashSet<String>>> = Lazy::new(Default::default);
+
+/// The expected type of a config value.
+#[derive(Debug, Clone, Copy)]
+enum Kind {
+    String,
+    Bool,
+    Integer,
+    Array,
+    /// A table with known keys.
+    Table(&'static [Field]),
+    /// A table with arbitrary keys.
+    AnyTable,
+    /// An array of tables with known keys.
+    ArrayOfTables(&'static [Field]),
+}
+
+impl Kind {
+    fn describe(self) -> &'static str {
+        match self {
+            Kind::String => "a string",
+            Kind::Bool => "a boolean",
+            Kind::Integer => "an integer",
+            Kind::Array => "an array",
+            Kind::Table(_) | Kind::AnyTable => "a table",
+            Kind::ArrayOfTables(_) => "an array of tables",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Field {
+    name: &'static str,
+    kind: Kind,
+}
+
+const fn field(name: &'static str, kind: Kind) -> Field {
+    Field { name, kind }
+}
+
+const SOURCE_FIELDS: &[Field] = &[
+    field("name", Kind::String),
+    field("url", Kind::String),
+    field("type", Kind::String),
+    field("verify_ssl", Kind::Bool),
+    field("username", Kind::String),
+    field("password", Kind::String),
+    field("credentials", Kind::String),
+];
+
+/// The schema of the global `config.toml`.
+const CONFIG_FIELDS: &[Field] = &[
+    field(
+        "default",
+        Kind::Table(&[
+            field("requires-python", Kind::String),
+            field("toolchain", Kind::String),
+            field("build-system", Kind::String),
+            field("license", Kind::String),
+            field("author", Kind::String),
+            field("dependency-operator", Kind::String),
+            // legacy typo key
+            field("dependency_operator", Kind::String),
+        ]),
+    ),
+    field(
+        "proxy",
+        Kind::Table(&[field("http", Kind::String), field("https", Kind::String)]),
+    ),
+    field(
+        "network",
+        Kind::Table(&[
+            field("connect-timeout", Kind::Integer),
+            field("timeout", Kind::Integer),
+        ]),
+    ),
+    field(
+        "behavior",
+        Kind::Table(&[
+            field("force-rye-managed", Kind::Bool),
+            // legacy typo key
+            field("force_rye_managed", Kind::Bool),
+            field("global-python", Kind::Bool),
+            field("use-uv", Kind::Bool),
+            field("autosync", Kind::Bool),
+            field("venv-mark-sync-ignore", Kind::Bool),
+            field("fetch-with-build-info", Kind::Bool),
+            field("add-bound", Kind::String),
+            field("rye-version-check", Kind::String),
+            field("strict-sync", Kind::Bool),
+            field("sync-on-checkout", Kind::Bool),
+            field("prefetch-pinned", Kind::Bool),
+            field("check-path", Kind::Bool),
+            field("deprecation-warnings", Kind::Bool),
+            field("trace-shims", Kind::Bool),
+            field("record-commands", Kind::Bool),
+            field("compile-bytecode", Kind::Bool),
+            field("lock-cache", Kind::Bool),
+            field("venv-pip", Kind::Bool),
+            field("venv-backend", Kind::String),
+        ]),
+    ),
+    field(
+        "python-downloads",
+        Kind::Table(&[
+            field("checksum-url", Kind::String),
+            field("clear-quarantine", Kind::Bool),
+            field("codesign", Kind::Bool),
+            field(
+                "mirrors",
+                Kind::ArrayOfTables(&[
+                    field("url", Kind::String),
+                    field("timeout", Kind::Integer),
+                    field("layout", Kind::String),
+                ]),
+            ),
+            field("toolchain-mirror", Kind::String),
+        ]),
+    ),
+    field("sources", Kind::ArrayOfTables(SOURCE_FIELDS)),
+    field(
+        "toolchain",
+        Kind::Table(&[field(
+            "sources",
+            Kind::ArrayOfTables(&[
+                field("name", Kind::String),
+                field("version", Kind::String),
+                field("os", Kind::String),
+                field("arch", Kind::String),
+                field("url", Kind::String),
+                field("sha256", Kind::String),
+                field("index", Kind::String),
+            ]),
+        )]),
+    ),
+    field(
+        "tools",
+        Kind::Table(&[field("default-python", Kind::String)]),
+    ),
+];
+
+/// The schema of `[tool.rye]` in a `pyproject.toml`.
+const PROJECT_FIELDS: &[Field] = &[
+    field("managed", Kind::Bool),
+    field("rye-version", Kind::String),
+    field("virtual", Kind::Bool),
+    field("venv-prompt", Kind::String),
+    field("dev-dependencies", Kind::Array),
+    field("dependency-groups", Kind::AnyTable),
+    field("variants", Kind::AnyTable),
+    field("excluded-dependencies", Kind::Array),
+    field("private-packages", Kind::Array),
+    field("lock-with-sources", Kind::Bool),
+    field("structured-lock", Kind::Bool),
+    field("resolution-report", Kind::Bool),
+    field("pyodide-version", Kind::String),
+    field("sort-dependencies", Kind::Bool),
+    field("vendor-dir", Kind::String),
+    field("system-deps", Kind::AnyTable),
+    field("native-deps", Kind::AnyTable),
+    field(
+        "policy",
+        Kind::Table(&[
+            field("allow-lock", Kind::Bool),
+            field("allow-add", Kind::Bool),
+            field("allow-remove", Kind::Bool),
+            field("require-hashes", Kind::Bool),
+        ]),
+    ),
+    field("sources", Kind::ArrayOfTables(SOURCE_FIELDS)),
+    field("scripts", Kind::AnyTable),
+    field("run", Kind::Table(&[field("extra-paths", Kind::Array)])),
+    field("test", Kind::Table(&[field("envs", Kind::Array)])),
+    field("workspace", Kind::Table(&[field("members", Kind::Array)])),
+    field("template", Kind::AnyTable),
+];
+
+/// Validates a global config file.
+///
+/// Values of the wrong type are rejected.  Unknown keys are rejected too
+/// unless `warn_unknown` is set, which is used when loading the config so
+/// that a typo or a key of another rye version does not break every shim.
+pub fn validate_config(contents: &str, path: &Path, warn_unknown: bool) -> Result<(), Error> {
+    let Ok(doc) = ImDocument::parse(contents) else {
+        // parse errors are reported when the config is loaded
+        return Ok(());
+    };
+    let mut validator = Validator::new(contents, warn_unknown);
+    validator.check_table(doc.as_table(), CONFIG_FIELDS, "");
+    validator.finish(path)
+}
+
+/// Validates the `[tool.rye]` section of a `pyproject.toml`.
+///
+/// Values of the wrong type are rejected.  Unknown keys only warn, as the
+/// project might be meant for a newer version of rye.
+pub fn validate_project(contents: &str, path: &Path) -> Result<(), Error> {
+    let Ok(doc) = ImDocument::parse(contents) else {
+        return Ok(());
+    };
+    let mut validator = Validator::new(contents, true);
+    if let Some(rye) = doc.get("tool").and_then(|x| x.get("rye")) {
+        validator.check_item(rye, Kind::Table(PROJECT_FIELDS), "tool.rye", None);
+    }
+    validator.finish(path)
+}
+
+struct Validator<'a> {
+    contents: &'a str,
+    /// Report unknown keys as warnings instead of errors.
+    warn_unknown: bool,
+    errors: Vec<String>,
+    warnings: Vec<String>,
+}
+
+impl<'a> Validator<'a> {
+    fn new(contents: &'a str, warn_unknown: bool) -> Validator<'a> {
+        Validator {
+            contents,
+            warn_unknown,
+            errors: Vec::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    fn check_table(&mut self, table: &dyn TableLike, fields: &[Field], prefix: &str) {
+        for (key, item) in table.iter() {
+            let span = table.get_key_value(key).and_then(|x| x.0.span());
+            let dotted = if prefix.is_empty() {
+                key.to_string()
+            } else {
+                format!("{}.{}", prefix, key)
+            };
+            match fields.iter().find(|x| x.name == key) {
+                Some(field) => self.check_item(item, field.kind, &dotted, span),
+                None => {
+                    let suggestion = suggest(key, fields.iter().map(|x| x.name))
+                        .map(|x| format!(" (did you mean '{}'?)", x))
+                        .unwrap_or_default();
+                    let message = format!("unknown key '{}'{}", dotted, suggestion);
+                    if self.warn_unknown {
+                        let message = self.locate(span, message);
+                        self.warnings.push(message);
+                    } else {
+                        self.error(span, message);
+                    }
+                }
+            }
+        }
+    }
+
+    fn check_item(&mut self, item: &Item, kind: Kind, dotted: &str, span: Option<Range<usize>>) {
+        let ok = match kind {
+            Kind::String => item.as_str().is_some(),
+            Kind::Bool => item.as_bool().is_some(),
+            Kind::Integer => item.as_integer().is_some(),
+            Kind::Array => item.as_array().is_some(),
+            Kind::AnyTable => item.as_table_like().is_some(),
+            Kind::Table(fields) => match item.as_table_like() {
+                Some(table) => {
+                    self.check_table(table, fields, dotted);
+                    true
+                }
+                None => false,
+            },
+            Kind::ArrayOfTables(fields) => {
+                if let Some(tables) = item.as_array_of_tables() {
+                    for table in tables.iter() {
+                        self.check_table(table, fields, dotted);
+                    }
+                    true
+                } else if let Some(array) = item.as_array() {
+                    let tables = array
+                        .iter()
+                        .filter_map(|x| x.as_inline_table())
+                        .collect::<Vec<_>>();
+                    for table in &tables {
+                        self.check_table(*table, fields, dotted);
+                    }
+                    tables.len() == array.len()
+                } else {
+                    false
+                }
+            }
+        };
+        if !ok {
+            self.error(
+                item.span().or(span),
+                format!("expected {} for '{}'", kind.describe(), dotted),
+            );
+        }
+    }
+
+    fn error(&mut self, span: Option<Range<usize>>, message: String) {
+        let message = self.locate(span, message);
+        self.errors.push(message);
+    }
+
+    /// Prefixes a message with the line and column of a span.
+    fn locate(&self, span: Option<Range<usize>>, message: String) -> String {
+        match span {
+            Some(span) => {
+                let before = &self.contents[..span.start];
+                let line = before.matches('\n').count() + 1;
+                let column = before.len() - before.rfind('\n').map_or(0, |x| x + 1) + 1;
+                format!("{}:{}: {}", line, column, message)
+            }
+            None => message,
+        }
+    }
+
+    fn finish(self, path: &Path) -> Result<(), Error> {
+        for warning in &self.warnings {
+            let warning = format!("{}:{}", path.display(), warning);
+            if WARNED.lock().unwrap().insert(warning.clone()) {
+                warn!("{}", warning);
+            }
+        }
+        if self.errors.is_empty() {
+            return Ok(());
+        }
+        let errors = self
+            .errors
+            .iter()
+            .map(|x| format!("  {}:{}", path.display(), x))
+            .collect::<Vec<_>>();
+        bail!(
+            "invalid config in {}:\n{}",
+            path.display(),
+            errors.join("\n")
+        );
+    }
+}
+
+/// Finds the closest known key for a typo.
+fn suggest<'x, I: Iterator<Item = &'x str>>(key: &str, candidates: I) -> Option<&'x str> {
+    candidates
+        .map(|x| (edit_distance(key, x), x))
+        .filter(|(distance, _)| *distance <= 2.max(key.len() / 3))
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, x)| x)
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut row = (0..=b.len()).collect::<Vec<_>>();
+    for (i, ca) in a.chars().enumerate() {
+        let mut prev = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let current = row[j + 1];
+            row[j + 1] = if ca == *cb {
+                prev
+            } else {
+                1 + prev.min(row[j]).min(row[j + 1])
+            };
+            prev = current;
+        }
+    }
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use super::{suggest, validate_config, validate_project};
+
+    #[test]
+    fn test_suggest() {
+        let keys = ["autosync", "use-uv", "global-python"];
+        assert_eq!(suggest("autosyn", keys.into_iter()), Some("autosync"));
+        assert_eq!(suggest("use_uv", keys.into_iter()), Some("use-uv"));
+        assert_eq!(suggest("banana", keys.into_iter()), None);
+    }
+
+    #[test]
+    fn test_validate_config() {
+        let path = Path::new("config.toml");
+        assert!(validate_config(
+            "[behavior]\nuse-uv = true\n\n[[sources]]\nname = \"foo\"\nurl = \"https://x\"\n",
+            path,
+            false
+        )
+        .is_ok());
+
+        let err = validate_config(
+            "[default]\nlicense = \"MIT\"\n\n[behaviour]\nautosync = true\n",
+            path,
+            false,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid config in config.toml:\n  config.toml:4:2: unknown key 'behaviour' (did you mean 'behavior'?)"
+        );
+
+        let err = validate_config(
+            "[behavior]\nautosync = \"yes\"\n[network]\ntimout = 1\n",
+            path,
+            false,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid config in config.toml:\n  \
+             config.toml:2:12: expected a boolean for 'behavior.autosync'\n  \
+             config.toml:4:1: unknown key 'network.timout' (did you mean 'timeout'?)"
+        );
+
+        // when loading unknown keys only warn
+        assert!(validate_config("[behaviour]\nautosync = true\n", path, true).is_ok());
+        assert!(validate_config("[behavior]\nautosync = \"yes\"\n", path, true).is_err());
+    }
+
+    #[test]
+    fn test_validate_project() {
+        let path = Path::new("pyproject.toml");
+        assert!(validate_project(
+            "[project]\nname = \"foo\"\n\n[tool.rye]\nmanaged = true\n\n[tool.rye.scripts]\nhello = \"echo hi\"\n\n[tool.black]\nwhatever = 1\n",
+            path
+        )
+        .is_ok());
+        // unknown keys only warn, they might be from a newer rye
+        assert!(validate_project("[tool.rye]\nmanage = true\n", path).is_ok());
+        let err = validate_project("[tool.rye]\nmanaged = \"yes\"\n", path).unwrap_err();
+        assert!(err
+            .to_string()
+            .ends_with("pyproject.toml:2:11: expected a boolean for 'tool.rye.managed'"));
+    }
+}
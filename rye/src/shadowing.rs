@@ -0,0 +1,132 @@
+//! Guards locking against dependency confusion.
+//!
+//! Workspace members and the packages listed in `tool.rye.private-packages`
+//! are expected to come from the workspace or from a private index.  If the
+//! resolver pins such a package from an index while the public index also
+//! has a project of that name, the public one might have been picked up.
+//! Locking then fails unless the name is allowed with `--allow-shadowing`,
+//! which is recorded in the lockfile header.
+use std::collections::BTreeSet;
+
+use anyhow::{bail, Error};
+use globset::Glob;
+use pep508_rs::{Requirement, VersionOrUrl};
+use url::Url;
+
+use crate::index::get_project_metadata;
+use crate::pyproject::{normalize_package_name, ExpandedSources};
+use crate::utils::CommandOutput;
+
+/// The index that private packages must not be shadowed by.
+pub const PUBLIC_INDEX_URL: &str = "https://pypi.org/simple/";
+
+/// Does the name match one of the private names or patterns?
+fn is_private(name: &str, private: &[String]) -> bool {
+    let name = normalize_package_name(name);
+    private.iter().any(|pattern| {
+        Glob::new(&normalize_package_name(pattern))
+            .map_or(false, |x| x.compile_matcher().is_match(&name))
+    })
+}
+
+/// Returns the private packages the resolver pinned from an index.
+///
+/// Editable installs and direct references are local and skipped.
+fn find_pinned_private(resolved: &str, private: &[String]) -> BTreeSet<String> {
+    resolved
+        .lines()
+        .filter_map(|line| {
+            line.trim()
+                .trim_end_matches('\\')
+                .trim()
+                .parse::<Requirement>()
+                .ok()
+        })
+        .filter(|req| matches!(req.version_or_url, Some(VersionOrUrl::VersionSpecifier(_))))
+        .map(|req| normalize_package_name(&req.name))
+        .filter(|name| is_private(name, private))
+        .collect()
+}
+
+/// Fails if private packages were pinned and also exist on the public index.
+///
+/// Nothing is checked if the public index is not one of the sources.  If the
+/// public index cannot be reached the packages are not checked.
+pub fn check_shadowing(
+    resolved: &str,
+    private: &[String],
+    allowed: &[String],
+    sources: &ExpandedSources,
+    output: CommandOutput,
+) -> Result<(), Error> {
+    if private.is_empty()
+        || !sources
+            .index_urls
+            .iter()
+            .any(|(url, _)| url.as_str() == PUBLIC_INDEX_URL)
+    {
+        return Ok(());
+    }
+    let public_index = Url::parse(PUBLIC_INDEX_URL)?;
+    let mut shadowed = Vec::new();
+    for name in find_pinned_private(resolved, private) {
+        if allowed.iter().any(|x| normalize_package_name(x) == name) {
+            echo!(if verbose output, "{} is shadowed by the public index (allowed)", name);
+            continue;
+        }
+        match get_project_metadata(&public_index, &name, output.quieter()) {
+            Ok(Some(_)) => shadowed.push(name),
+            Ok(None) => {}
+            Err(err) => {
+                // the other lookups would only run into the same problem
+                warn!(
+                    "could not check private packages against the public index: {:#}",
+                    err
+                );
+                break;
+            }
+        }
+    }
+    if !shadowed.is_empty() {
+        bail!(
+            "the following private packages were resolved from an index but also exist on the \
+             public index {}:\n  {}\nThis can be a dependency confusion attack.  If the public \
+             packages are intended, lock again with `--allow-shadowing <name>`.",
+            PUBLIC_INDEX_URL,
+            shadowed.join("\n  ")
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeSet;
+
+    use super::{find_pinned_private, is_private};
+
+    #[test]
+    fn test_is_private() {
+        let private = vec!["acme-*".to_string(), "my_tool".to_string()];
+        assert!(is_private("Acme.Utils", &private));
+        assert!(is_private("my-tool", &private));
+        assert!(!is_private("flask", &private));
+    }
+
+    #[test]
+    fn test_find_pinned_private() {
+        let resolved = "\
+-e file:.
+-e file:packages/acme-core
+acme-utils==1.0.0 \\
+    --hash=sha256:abc
+    # via my-project
+acme-wheel @ file:///${PROJECT_ROOT}/wheels/acme_wheel-1.0-py3-none-any.whl
+flask==3.0.0
+";
+        assert_eq!(
+            find_pinned_private(resolved, &["acme-*".to_string()]),
+            BTreeSet::from(["acme-utils".to_string()])
+        );
+    }
+}
@@ -0,0 +1,642 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::env::consts::{EXE_EXTENSION, EXE_SUFFIX};
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::{env, fs};
+
+use anyhow::{Context, Error};
+use console::style;
+use same_file::is_same_file;
+
+use crate::consts::VENV_BIN;
+use crate::installer::{get_tool_lockfile_path, read_tool_lock};
+use crate::platform::{get_app_dir, get_system_package_manager, list_known_toolchains};
+use crate::pyproject::normalize_package_name;
+use crate::sources::py::{matches_version, PythonVersion, PythonVersionRequest};
+use crate::utils::{
+    get_short_executable_name, get_venv_python_bin, internal_command, is_executable, symlink_file,
+    CommandOutput, IoPathContext,
+};
+
+const FIND_SCRIPT_SCRIPT: &str = r#"
+import os
+import re
+import sys
+import json
+
+if sys.version_info >= (3, 8):
+    from importlib.metadata import distribution, PackageNotFoundError
+else:
+    from importlib_metadata import distribution, PackageNotFoundError
+
+_package_re = re.compile('(?i)^([a-z0-9._-]+)')
+
+result = {}
+
+def dump_all(dist, root=False):
+    rv = []
+    for file in dist.files or ():
+        rv.append(os.path.normpath(dist.locate_file(file)))
+    result["" if root else dist.name] = rv
+    req = []
+    for r in dist.requires or ():
+        name = _package_re.match(r)
+        if name is not None:
+            req.append(name.group())
+    return req
+
+root = sys.argv[1]
+to_resolve = [root]
+seen = set()
+while to_resolve:
+    try:
+        d = to_resolve.pop()
+        dist = distribution(d)
+    except Exception:
+        continue
+    if dist.name in seen:
+        continue
+    seen.add(dist.name)
+    to_resolve.extend(dump_all(dist, root=d==root))
+
+print(json.dumps(result))
+"#;
+
+/// Is this process a shim rather than a rye command?
+static RUNNING_AS_SHIM: AtomicBool = AtomicBool::new(false);
+
+/// Marks this process as a shim.
+pub fn mark_running_as_shim() {
+    RUNNING_AS_SHIM.store(true, Ordering::Relaxed);
+}
+
+/// Is this process a shim?  Shims must not print anything of their own.
+pub fn is_running_as_shim() -> bool {
+    RUNNING_AS_SHIM.load(Ordering::Relaxed)
+}
+
+/// Updates the core shims to point to the current rye executable.
+pub fn refresh_core_shims() -> Result<(), Error> {
+    let shims = get_app_dir().join("shims");
+    if !shims.is_dir() {
+        fs::create_dir_all(&shims).path_context(&shims, "tried to create shim folder")?;
+    }
+    update_core_shims(&shims, &get_shim_exe(&shims)?)
+}
+
+/// Refreshes the python shims if a package manager updated rye.
+///
+/// The shims of a packaged rye are hard links or copies of the binary, which
+/// go stale once the package manager replaces it.  Only the size and
+/// modification time of one shim are compared, so this is cheap enough to
+/// run on every command.
+pub fn refresh_packaged_core_shims() -> Result<(), Error> {
+    let py_shim = get_app_dir()
+        .join("shims")
+        .join("python")
+        .with_extension(EXE_EXTENSION);
+    let Ok(shim_meta) = fs::metadata(&py_shim) else {
+        return Ok(());
+    };
+    let this_meta = fs::metadata(env::current_exe()?)?;
+    let is_stale =
+        shim_meta.len() != this_meta.len() || shim_meta.modified()? < this_meta.modified()?;
+    // asking the package manager can be slow, so it is only done for stale shims
+    if is_stale && get_system_package_manager().is_some() {
+        refresh_core_shims()?;
+    }
+    Ok(())
+}
+
+/// Updates the versioned python shims after toolchains were added or removed.
+pub fn refresh_versioned_shims() -> Result<(), Error> {
+    let shims = get_app_dir().join("shims");
+    if !shims.join("python").with_extension(EXE_EXTENSION).is_file() {
+        return Ok(());
+    }
+    update_versioned_shims(&shims, &get_shim_exe(&shims)?)
+}
+
+/// Returns the executable the shims should point to.
+fn get_shim_exe(shims: &Path) -> Result<PathBuf, Error> {
+    // if rye is itself installed into the shims folder, we want to
+    // use that.  Otherwise we fall back to the current executable.  A
+    // packaged rye is only linked into the shims folder, so resolve that.
+    let mut this = shims.join("rye").with_extension(EXE_EXTENSION);
+    if !this.is_file() {
+        this = env::current_exe()?;
+    }
+    if this.is_symlink() {
+        this = this.canonicalize()?;
+    }
+    Ok(this)
+}
+
+pub fn update_core_shims(shims: &Path, this: &Path) -> Result<(), Error> {
+    #[cfg(unix)]
+    {
+        let py_shim = shims.join("python");
+        let py3_shim = shims.join("python3");
+
+        // on linux we cannot symlink at all, as this will misreport.  We will try to do
+        // hardlinks and if that fails, we fall back to copying the entire file over.  This
+        // for instance is needed when the rye executable is placed on a different volume
+        // than ~/.rye/shims
+        if cfg!(target_os = "linux") {
+            fs::remove_file(&py_shim).ok();
+            if fs::hard_link(this, &py_shim).is_err() {
+                fs::copy(this, &py_shim).path_context(&py_shim, "tried to copy python shim")?;
+            }
+            fs::remove_file(&py3_shim).ok();
+            if fs::hard_link(this, &py3_shim).is_err() {
+                fs::copy(this, &py3_shim).path_context(&py_shim, "tried to copy python3 shim")?;
+            }
+
+        // on other unices we always use symlinks
+        } else {
+            fs::remove_file(&py_shim).ok();
+            symlink_file(this, &py_shim).path_context(&py_shim, "tried to symlink python shim")?;
+            fs::remove_file(&py3_shim).ok();
+            symlink_file(this, &py3_shim)
+                .path_context(&py3_shim, "tried to symlink python3 shim")?;
+        }
+    }
+
+    #[cfg(windows)]
+    {
+        let py_shim = shims.join("python.exe");
+        let pyw_shim = shims.join("pythonw.exe");
+        let py3_shim = shims.join("python3.exe");
+
+        // on windows we need privileges to symlink.  Not everyone might have that, so we
+        // fall back to hardlinks.
+        fs::remove_file(&py_shim).ok();
+        if symlink_file(this, &py_shim).is_err() {
+            fs::hard_link(this, &py_shim).path_context(&py_shim, "tried to symlink python shim")?;
+        }
+        fs::remove_file(&py3_shim).ok();
+        if symlink_file(this, &py3_shim).is_err() {
+            fs::hard_link(this, &py3_shim)
+                .path_context(&py3_shim, "tried to symlink python3 shim")?;
+        }
+        fs::remove_file(&pyw_shim).ok();
+        if symlink_file(this, &pyw_shim).is_err() {
+            fs::hard_link(this, &pyw_shim)
+                .path_context(&pyw_shim, "tried to symlink pythonw shim")?;
+        }
+    }
+
+    update_versioned_shims(shims, this)
+}
+
+/// Creates a `python3.X` shim for every installed CPython minor version.
+///
+/// Shims of versions that are no longer installed are removed.
+pub fn update_versioned_shims(shims: &Path, this: &Path) -> Result<(), Error> {
+    let versions = list_known_toolchains()?
+        .into_iter()
+        .map(|(version, _)| version)
+        .collect::<Vec<_>>();
+    link_versioned_shims(shims, this, &versions)
+}
+
+fn link_versioned_shims(
+    shims: &Path,
+    this: &Path,
+    versions: &[PythonVersion],
+) -> Result<(), Error> {
+    let wanted = versions
+        .iter()
+        .filter(|version| {
+            matches_version(
+                &PythonVersionRequest {
+                    name: None,
+                    arch: None,
+                    os: None,
+                    major: version.major,
+                    minor: Some(version.minor),
+                    patch: None,
+                    suffix: None,
+                },
+                version,
+            )
+        })
+        .map(|version| format!("python{}.{}{}", version.major, version.minor, EXE_SUFFIX))
+        .collect::<BTreeSet<_>>();
+
+    for entry in fs::read_dir(shims).path_context(shims, "unable to enumerate shims")? {
+        let entry = entry?;
+        let name = entry.file_name();
+        if let Some(name) = name.to_str() {
+            if parse_versioned_shim_name(name).is_some() && !wanted.contains(name) {
+                fs::remove_file(entry.path()).ok();
+            }
+        }
+    }
+
+    for name in wanted {
+        let shim = shims.join(name);
+        fs::remove_file(&shim).ok();
+        link_shim(this, &shim).path_context(&shim, "tried to link versioned python shim")?;
+    }
+    Ok(())
+}
+
+/// Parses the name of a versioned python shim (`python3.12`) into major and minor.
+pub fn parse_versioned_shim_name(name: &str) -> Option<(u8, u8)> {
+    let name = match name.len().checked_sub(EXE_SUFFIX.len()) {
+        Some(idx)
+            if !EXE_SUFFIX.is_empty()
+                && name.is_char_boundary(idx)
+                && name[idx..].eq_ignore_ascii_case(EXE_SUFFIX) =>
+        {
+            &name[..idx]
+        }
+        _ => name,
+    };
+    let (major, minor) = name.strip_prefix("python")?.split_once('.')?;
+    if !(major.bytes().chain(minor.bytes())).all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    Some((major.parse().ok()?, minor.parse().ok()?))
+}
+
+/// Links a shim to the rye executable.
+///
+/// On linux symlinks misreport, so hard links with a copy as fallback are
+/// used.  On windows symlinks need privileges, so hard links are the fallback.
+fn link_shim(this: &Path, shim: &Path) -> std::io::Result<()> {
+    if cfg!(target_os = "linux") {
+        if fs::hard_link(this, shim).is_err() {
+            fs::copy(this, shim)?;
+        }
+        Ok(())
+    } else if cfg!(windows) {
+        symlink_file(this, shim).or_else(|_| fs::hard_link(this, shim))
+    } else {
+        symlink_file(this, shim)
+    }
+}
+
+/// Re-creates the shims of an installed tool.
+///
+/// This is useful after a tool gained new scripts or after `RYE_HOME`
+/// was moved.  Returns the names of the linked scripts.
+pub fn rehash_tool_shims(package: &str, output: CommandOutput) -> Result<Vec<String>, Error> {
+    let app_dir = get_app_dir();
+    let shim_dir = app_dir.join("shims");
+    let target_venv_path = app_dir.join("tools").join(normalize_package_name(package));
+
+    // the lockfile knows the name of the distribution and the dependencies
+    // that had their scripts installed.
+    let (name, include_deps) = match read_tool_lock(&get_tool_lockfile_path(package)) {
+        Ok((lock, _)) => {
+            let name = match lock.requirement.parse::<pep508_rs::Requirement>() {
+                Ok(req) => req.name,
+                Err(_) => package.to_string(),
+            };
+            (name, lock.include_deps)
+        }
+        Err(_) => (package.to_string(), Vec::new()),
+    };
+
+    remove_tool_shims(&target_venv_path, &shim_dir)?;
+    link_tool_scripts(&name, &target_venv_path, &include_deps, &shim_dir, output)
+}
+
+/// Removes the shims that point to scripts of a tool's virtualenv.
+///
+/// Dangling shims with the name of one of the scripts are removed too, as
+/// they are left behind when `RYE_HOME` was moved.
+pub fn remove_tool_shims(target_venv_path: &Path, shim_dir: &Path) -> Result<(), Error> {
+    let target_venv_bin_path = target_venv_path.join(VENV_BIN);
+    if !target_venv_bin_path.is_dir() {
+        return Ok(());
+    }
+
+    for script in fs::read_dir(&target_venv_bin_path)
+        .path_context(&target_venv_bin_path, "unable to enumerate scripts")?
+    {
+        let script = script?;
+        if let Some(base_name) = script.path().file_name() {
+            let shim_path = shim_dir.join(base_name);
+            let is_dangling = shim_path.is_symlink() && !shim_path.exists();
+            if is_dangling || is_same_file(&shim_path, script.path()).unwrap_or(false) {
+                fs::remove_file(&shim_path).ok();
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Links the scripts of an installed tool into the shims folder.
+pub fn link_tool_scripts(
+    name: &str,
+    target_venv_path: &Path,
+    include_deps: &[String],
+    shim_dir: &Path,
+    output: CommandOutput,
+) -> Result<Vec<String>, Error> {
+    let py = get_venv_python_bin(target_venv_path);
+    let target_venv_bin_path = target_venv_path.join(VENV_BIN);
+    let out = internal_command(py)
+        .arg("-c")
+        .arg(FIND_SCRIPT_SCRIPT)
+        .arg(name)
+        .stdout(Stdio::piped())
+        .output()
+        .context("unable to dump package manifest from installed package")?;
+    let all_files: BTreeMap<String, Vec<PathBuf>> = serde_json::from_slice(&out.stdout)
+        .with_context(|| {
+            format!(
+                "failed to resolve manifest\n{}",
+                String::from_utf8_lossy(&out.stderr)
+            )
+        })?;
+
+    let mut installed = Vec::new();
+    let mut scripts_found = Vec::new();
+    if let Some(files) = all_files.get("") {
+        installed.extend(install_scripts(files, &target_venv_bin_path, shim_dir)?);
+    }
+
+    for (package, files) in all_files.iter() {
+        if package.is_empty() {
+            continue;
+        }
+        if include_deps.contains(&normalize_package_name(package)) {
+            installed.extend(install_scripts(files, &target_venv_bin_path, shim_dir)?);
+        } else {
+            let scripts = find_scripts(files, &target_venv_bin_path);
+            if !scripts.is_empty() {
+                scripts_found.push((package, scripts));
+            }
+        }
+    }
+
+    if !scripts_found.is_empty()
+        && !output.is_quiet()
+        && (installed.is_empty() || output.is_verbose())
+    {
+        echo!(
+            "{}",
+            style("Found additional non installed scripts in dependencies:").yellow()
+        );
+        scripts_found.sort();
+        for (package, scripts) in scripts_found.iter() {
+            echo!("{}:", style(package).green());
+            for script in scripts {
+                echo!("  - {}", style(script).cyan());
+            }
+        }
+        echo!("To install scripts from these packages pass the appropriate --include-dep");
+    }
+
+    if !output.is_quiet() {
+        echo!();
+        if installed.is_empty() {
+            warn!("installed package did not expose any scripts")
+        } else {
+            echo!("Installed scripts:");
+            for script in &installed {
+                echo!("  - {}", style(script).cyan());
+            }
+            if !output.is_verbose() && !scripts_found.is_empty() {
+                echo!();
+                echo!(
+                    "note: {}",
+                    style("additional scripts were encountered in non-installed dependencies.")
+                        .dim()
+                );
+            }
+        }
+    }
+
+    Ok(installed)
+}
+
+fn find_scripts(files: &[PathBuf], target_venv_bin_path: &Path) -> Vec<String> {
+    let mut rv = Vec::new();
+    for file in files {
+        if let Ok(rest) = file.strip_prefix(target_venv_bin_path) {
+            rv.push(rest.to_string_lossy().to_string());
+        }
+    }
+    rv
+}
+
+fn install_scripts(
+    files: &[PathBuf],
+    target_venv_bin_path: &Path,
+    shim_dir: &Path,
+) -> Result<Vec<String>, Error> {
+    let mut rv = Vec::new();
+    for file in files {
+        if let Ok(rest) = file.strip_prefix(target_venv_bin_path) {
+            // In some cases we are given paths here which point to sub-folders of the
+            // script/bin folder.  For instance in some cases it has been shown that
+            // __pycache__/something.pyc shows up there.  These are obviously not good
+            // targets to link as they would never show up via PATH discovery.  Skip
+            // over these.
+            //
+            // Also do not try to link things which are not considered executables on
+            // this operating system.
+            if !rest.parent().map_or(true, |x| x == Path::new("")) || !is_executable(file) {
+                continue;
+            }
+
+            let shim_target = shim_dir.join(rest);
+
+            // shims left behind by this virtualenv are replaced, shims of
+            // other tools and virtualenvs are left alone.
+            if shim_target.symlink_metadata().is_ok() {
+                let is_dangling = shim_target.is_symlink() && !shim_target.exists();
+                if !is_dangling && !is_same_file(&shim_target, file).unwrap_or(false) {
+                    warn!(
+                        "not linking {}, {} already exists",
+                        get_short_executable_name(file),
+                        shim_target.display()
+                    );
+                    continue;
+                }
+                fs::remove_file(&shim_target)
+                    .path_context(&shim_target, "unable to remove shim")?;
+            }
+
+            // on windows we want to fall back to hardlinks.  That might be problematic in
+            // some cases, but it should work for most cases where setuptools or other
+            // systems created exe files.  Caveat: uninstallation currently does not work
+            // when hardlinks are used.
+            #[cfg(windows)]
+            {
+                if symlink_file(file, &shim_target).is_err() {
+                    fs::hard_link(file, &shim_target)
+                        .path_context(file, "unable to symlink tool")?;
+                }
+            }
+            #[cfg(unix)]
+            {
+                symlink_file(file, shim_target).path_context(file, "unable to symlink tool")?;
+            }
+            rv.push(get_short_executable_name(file));
+        }
+    }
+    Ok(rv)
+}
+
+/// Another system that puts its own `python` on `PATH`.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ShimConflict {
+    /// The shims of pyenv (or pyenv-win).
+    Pyenv(PathBuf),
+    /// The `bin` folder of a conda environment, usually the base environment.
+    Conda(PathBuf),
+}
+
+impl ShimConflict {
+    /// Detects if a folder on `PATH` belongs to a competing python setup.
+    pub fn detect(dir: &Path) -> Option<ShimConflict> {
+        if dir.file_name().map_or(false, |x| x == "shims")
+            && dir.ancestors().any(|x| {
+                x.file_name()
+                    .and_then(|x| x.to_str())
+                    .map_or(false, |x| x.to_ascii_lowercase().contains("pyenv"))
+            })
+        {
+            return Some(ShimConflict::Pyenv(dir.to_path_buf()));
+        }
+        // conda puts binaries into `bin` on unix and into the environment
+        // itself, `Scripts` or `Library\bin` on windows.
+        if dir
+            .ancestors()
+            .take(3)
+            .any(|x| x.join("conda-meta").is_dir())
+        {
+            return Some(ShimConflict::Conda(dir.to_path_buf()));
+        }
+        None
+    }
+
+    /// The name of the competing tool.
+    pub fn tool(&self) -> &'static str {
+        match self {
+            ShimConflict::Pyenv(_) => "pyenv",
+            ShimConflict::Conda(_) => "conda",
+        }
+    }
+
+    /// Explains how to resolve the conflict.
+    pub fn guidance(&self) -> String {
+        match self {
+            ShimConflict::Pyenv(dir) => format!(
+                "pyenv shims in {} come before the rye shims, remove `pyenv init` \
+                 from your shell profile or run `rye self fix-path` to put the rye \
+                 shims first",
+                dir.display()
+            ),
+            ShimConflict::Conda(dir) => format!(
+                "the conda environment in {} comes before the rye shims, run \
+                 `conda deactivate` and disable auto activation with \
+                 `conda config --set auto_activate_base false`",
+                dir.display()
+            ),
+        }
+    }
+}
+
+/// Finds competing python setups that come before the shims on `PATH`.
+///
+/// If the shims are not on `PATH` at all, every entry is considered.
+pub fn find_shim_conflicts(shims: &Path, path: &OsStr) -> Vec<ShimConflict> {
+    let mut rv = Vec::new();
+    for dir in env::split_paths(path) {
+        if is_same_file(&dir, shims).unwrap_or(false) {
+            break;
+        }
+        if !["python", "python3"]
+            .iter()
+            .any(|name| dir.join(name).with_extension(EXE_EXTENSION).is_file())
+        {
+            continue;
+        }
+        if let Some(conflict) = ShimConflict::detect(&dir) {
+            rv.push(conflict);
+        }
+    }
+    rv
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use super::{link_versioned_shims, parse_versioned_shim_name, ShimConflict};
+
+    #[test]
+    fn test_detect_shim_conflict() {
+        let tmp = tempfile::tempdir().unwrap();
+        let pyenv_shims = tmp.path().join(".pyenv").join("shims");
+        let conda_bin = tmp.path().join("miniconda3").join("bin");
+        let other = tmp.path().join("usr").join("bin");
+        fs::create_dir_all(&pyenv_shims).unwrap();
+        fs::create_dir_all(&conda_bin).unwrap();
+        fs::create_dir_all(tmp.path().join("miniconda3").join("conda-meta")).unwrap();
+        fs::create_dir_all(&other).unwrap();
+
+        assert_eq!(
+            ShimConflict::detect(&pyenv_shims),
+            Some(ShimConflict::Pyenv(pyenv_shims.clone()))
+        );
+        assert_eq!(
+            ShimConflict::detect(&conda_bin),
+            Some(ShimConflict::Conda(conda_bin.clone()))
+        );
+        assert_eq!(ShimConflict::detect(&other), None);
+    }
+
+    #[test]
+    fn test_parse_versioned_shim_name() {
+        assert_eq!(parse_versioned_shim_name("python3.12"), Some((3, 12)));
+        assert_eq!(parse_versioned_shim_name("python3"), None);
+        assert_eq!(parse_versioned_shim_name("python3.x"), None);
+        assert_eq!(parse_versioned_shim_name("pip3.12"), None);
+        #[cfg(windows)]
+        {
+            assert_eq!(parse_versioned_shim_name("python3.12.exe"), Some((3, 12)));
+        }
+    }
+
+    #[test]
+    fn test_link_versioned_shims() {
+        let tmp = tempfile::tempdir().unwrap();
+        let this = tmp.path().join("rye");
+        let shims = tmp.path().join("shims");
+        fs::write(&this, "").unwrap();
+        fs::create_dir_all(&shims).unwrap();
+        let stale = shims.join(format!("python3.8{}", std::env::consts::EXE_SUFFIX));
+        fs::write(&stale, "").unwrap();
+
+        let versions = [
+            "cpython@3.11.8".parse().unwrap(),
+            "cpython@3.12.1".parse().unwrap(),
+            "cpython@3.12.2".parse().unwrap(),
+            "pypy@3.10.13".parse().unwrap(),
+        ];
+        link_versioned_shims(&shims, &this, &versions).unwrap();
+        let mut names = fs::read_dir(&shims)
+            .unwrap()
+            .map(|x| x.unwrap().file_name().to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(
+            names,
+            [
+                format!("python3.11{}", std::env::consts::EXE_SUFFIX),
+                format!("python3.12{}", std::env::consts::EXE_SUFFIX)
+            ]
+        );
+    }
+}
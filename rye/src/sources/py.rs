@@ -1,11 +1,20 @@
 use std::borrow::Cow;
 use std::env::consts::{ARCH, OS};
-use std::fmt;
 use std::str::FromStr;
+use std::time::Duration;
+use std::{fmt, fs};
 
-use anyhow::{anyhow, Error};
+use anyhow::{anyhow, Context, Error};
+use once_cell::sync::Lazy;
 use pep440_rs::Version;
 use serde::{de, Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::bootstrap::{download_url, get_download_cache_dir};
+use crate::config::{Config, DownloadMirror, MirrorLayout, ToolchainSource};
+use crate::shims::is_running_as_shim;
+use crate::utils::atomic::write_atomic;
+use crate::utils::{CommandOutput, IoPathContext};
 
 mod downloads {
     use super::PythonVersion;
@@ -14,6 +23,10 @@ mod downloads {
 
 const DEFAULT_NAME: &str = "cpython";
 
+/// The release downloads of python-build-standalone that mirrors replace.
+const PBS_RELEASES_URL: &str =
+    "https://github.com/indygreg/python-build-standalone/releases/download";
+
 /// Internal descriptor for a python version.
 #[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone)]
 pub struct PythonVersion {
@@ -317,32 +330,227 @@ pub fn matches_version(req: &PythonVersionRequest, v: &PythonVersion) -> bool {
     true
 }
 
+/// A toolchain archive registered in `[[toolchain.sources]]` or an index.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CustomToolchain {
+    /// The implementation, defaults to `cpython`.
+    #[serde(default)]
+    pub name: Option<String>,
+    /// The full version (eg: `3.12.3`), optionally with name and platform.
+    pub version: String,
+    /// The operating system, defaults to the one of the version or this machine.
+    #[serde(default)]
+    pub os: Option<String>,
+    /// The architecture, defaults to the one of the version or this machine.
+    #[serde(default)]
+    pub arch: Option<String>,
+    pub url: String,
+    #[serde(default)]
+    pub sha256: Option<String>,
+}
+
+impl CustomToolchain {
+    /// Returns the toolchain version this archive provides.
+    pub fn python_version(&self) -> Result<PythonVersion, Error> {
+        let mut req: PythonVersionRequest = self
+            .version
+            .parse()
+            .with_context(|| format!("invalid toolchain version '{}'", self.version))?;
+        if let Some(ref name) = self.name {
+            req.name = Some(name.clone().into());
+        }
+        if let Some(ref os) = self.os {
+            req.os = Some(os.clone().into());
+        }
+        if let Some(ref arch) = self.arch {
+            req.arch = Some(arch.clone().into());
+        }
+        PythonVersion::try_from(req)
+            .with_context(|| format!("toolchain version '{}' is incomplete", self.version))
+    }
+}
+
+/// How long a fetched toolchain index is used before it is fetched again.
+const TOOLCHAIN_INDEX_MAX_AGE: Duration = Duration::from_secs(60 * 60);
+
+/// The toolchains of `[[toolchain.sources]]`, loaded once per process.
+static CUSTOM_DOWNLOADS: Lazy<Vec<(PythonVersion, String, Option<String>)>> =
+    Lazy::new(|| match load_custom_downloads() {
+        Ok(rv) => rv,
+        Err(err) => {
+            // shims have to stay silent, the next rye command reports it
+            if !is_running_as_shim() {
+                warn!("ignoring custom toolchain sources: {:#}", err);
+            }
+            Vec::new()
+        }
+    });
+
+/// Fetches a toolchain index over HTTPS.
+///
+/// The index is cached in the download cache for [`TOOLCHAIN_INDEX_MAX_AGE`]
+/// so that not every command goes to the network.  If the index cannot be
+/// fetched an outdated copy is used.
+fn fetch_toolchain_index(index: &str) -> Result<Vec<u8>, Error> {
+    let cache_path = get_download_cache_dir()
+        .join("toolchain-indexes")
+        .join(format!(
+            "{}.json",
+            &hex::encode(Sha256::digest(index.as_bytes()))[..16]
+        ));
+    let age = fs::metadata(&cache_path)
+        .and_then(|x| x.modified())
+        .ok()
+        .and_then(|x| x.elapsed().ok());
+    if age.map_or(false, |x| x < TOOLCHAIN_INDEX_MAX_AGE) {
+        if let Ok(contents) = fs::read(&cache_path) {
+            return Ok(contents);
+        }
+    }
+    match download_url(index, CommandOutput::QUIET) {
+        Ok(contents) => {
+            if let Some(parent) = cache_path.parent() {
+                fs::create_dir_all(parent).ok();
+            }
+            write_atomic(&cache_path, &contents).ok();
+            Ok(contents)
+        }
+        Err(err) => fs::read(&cache_path)
+            .map_err(|_| err.context(format!("failed to fetch toolchain index {}", index))),
+    }
+}
+
+fn load_custom_downloads() -> Result<Vec<(PythonVersion, String, Option<String>)>, Error> {
+    let Some(config) = Config::try_current() else {
+        return Ok(Vec::new());
+    };
+    let mut toolchains = Vec::new();
+    for source in config.toolchain_sources()? {
+        match source {
+            ToolchainSource::Toolchain(toolchain) => toolchains.push(toolchain),
+            ToolchainSource::Index(index) => {
+                let contents = if index.starts_with("https://") {
+                    fetch_toolchain_index(&index)?
+                } else {
+                    fs::read(&index).path_context(&index, "failed to read toolchain index")?
+                };
+                toolchains.extend(
+                    serde_json::from_slice::<Vec<CustomToolchain>>(&contents)
+                        .with_context(|| format!("invalid toolchain index {}", index))?,
+                );
+            }
+        }
+    }
+    toolchains
+        .into_iter()
+        .map(|x| Ok((x.python_version()?, x.url, x.sha256)))
+        .collect()
+}
+
+/// Finds the latest download matching a version among custom and builtin toolchains.
+///
+/// Custom toolchains win over builtin ones of the same version.
+fn find_download<'a>(
+    requested_version: &PythonVersionRequest,
+    custom: &'a [(PythonVersion, String, Option<String>)],
+) -> Option<(PythonVersion, &'a str, Option<&'a str>)> {
+    let mut rv: Option<(PythonVersion, &'a str, Option<&'a str>)> = None;
+    let custom = custom
+        .iter()
+        .map(|(version, url, sha256)| (version, url.as_str(), sha256.as_deref()));
+    let builtin = downloads::PYTHON_VERSIONS
+        .iter()
+        .map(|(version, url, sha256)| (version, *url, *sha256));
+    for (it_version, it_url, it_sha256) in custom.chain(builtin) {
+        if matches_version(requested_version, it_version)
+            && rv.as_ref().map_or(true, |(x, _, _)| {
+                (x.major, x.minor, x.patch) < (it_version.major, it_version.minor, it_version.patch)
+            })
+        {
+            rv = Some((it_version.clone(), it_url, it_sha256));
+        }
+    }
+    rv
+}
+
 /// Given a version, platform and architecture returns the download URL.
+///
+/// Toolchains of `[[toolchain.sources]]` are merged with the builtin ones.
 pub fn get_download_url(
     requested_version: &PythonVersionRequest,
 ) -> Option<(PythonVersion, &'static str, Option<&'static str>)> {
-    for (it_version, it_url, it_sha256) in downloads::PYTHON_VERSIONS {
-        if matches_version(requested_version, it_version) {
-            return Some((it_version.clone(), it_url, *it_sha256));
+    find_download(requested_version, &CUSTOM_DOWNLOADS)
+}
+
+/// Looks up the checksum of a download in a checksum manifest.
+///
+/// The manifest uses the format of `sha256sum` (`<hash>  <filename>` per
+/// line).  The archive is matched by the last path segment of its URL.
+pub fn find_checksum_in_manifest(manifest: &str, url: &str) -> Option<String> {
+    let filename = url.rsplit('/').next()?.replace("%2B", "+");
+    manifest.lines().find_map(|line| {
+        let (hash, name) = line.trim().split_once(char::is_whitespace)?;
+        let name = name.trim_start().trim_start_matches('*');
+        if name == filename || name.replace("%2B", "+") == filename {
+            Some(hash.to_ascii_lowercase())
+        } else {
+            None
+        }
+    })
+}
+
+/// Returns the URLs a download is tried from in order with their timeouts.
+///
+/// Mirrors with the releases layout only serve python-build-standalone
+/// archives (`<mirror>/<release>/<filename>`), flat mirrors serve any archive
+/// by file name (`<mirror>/<filename>`).  The original URL is tried last
+/// unless a mirror already points to it or an exclusive mirror is
+/// configured.  Local mirrors yield paths.
+pub fn get_mirror_urls(url: &str, mirrors: &[DownloadMirror]) -> Vec<(String, Option<Duration>)> {
+    let mut rv = Vec::new();
+    let filename = url.rsplit('/').next().unwrap_or(url);
+    for mirror in mirrors {
+        let mirror_url = match mirror.layout {
+            MirrorLayout::Releases => match url.strip_prefix(PBS_RELEASES_URL) {
+                Some(path) => format!("{}{}", mirror.url, path),
+                None => continue,
+            },
+            MirrorLayout::Flat => format!("{}/{}", mirror.url, filename),
+        };
+        if !rv.iter().any(|(x, _)| *x == mirror_url) {
+            rv.push((mirror_url, mirror.timeout));
         }
     }
-    None
+    if !mirrors.iter().any(|x| x.exclusive) && !rv.iter().any(|(x, _)| x == url) {
+        rv.push((url.to_string(), None));
+    }
+    rv
+}
+
+/// Returns the date the bundled index of Python downloads was generated.
+pub fn get_downloads_index_date() -> &'static str {
+    downloads::PYTHON_VERSIONS_DATE
 }
 
 /// Returns an iterator over downloadable installations.
+///
+/// This includes the toolchains of `[[toolchain.sources]]`.
 pub fn iter_downloadable<'s>(
     os: &'s str,
     arch: &'s str,
 ) -> impl Iterator<Item = PythonVersion> + 's {
-    downloads::PYTHON_VERSIONS
+    let custom = CUSTOM_DOWNLOADS.iter().map(|(version, _, _)| version);
+    let builtin = downloads::PYTHON_VERSIONS
         .iter()
-        .filter_map(move |(version, _, _)| {
-            if version.arch == arch && version.os == os {
-                Some(version.clone())
-            } else {
-                None
-            }
-        })
+        .map(|(version, _, _)| version)
+        .filter(|version| !CUSTOM_DOWNLOADS.iter().any(|(x, _, _)| x == *version));
+    custom.chain(builtin).filter_map(move |version| {
+        if version.arch == arch && version.os == os {
+            Some(version.clone())
+        } else {
+            None
+        }
+    })
 }
 
 #[test]
@@ -350,3 +558,145 @@ fn test_get_download_url() {
     let url = get_download_url(&"cpython-aarch64-macos@3.8.14".parse().unwrap());
     assert_eq!(url, Some((PythonVersion { name: "cpython".into(), arch: "aarch64".into(), os: "macos".into(), major: 3, minor: 8, patch: 14, suffix: None }, "https://github.com/indygreg/python-build-standalone/releases/download/20221002/cpython-3.8.14%2B20221002-aarch64-apple-darwin-pgo%2Blto-full.tar.zst", Some("d17a3fcc161345efa2ec0b4ab9c9ed6c139d29128f2e34bb636338a484aa7b72"))));
 }
+
+#[test]
+fn test_find_checksum_in_manifest() {
+    let manifest = "\
+d17a3fcc161345efa2ec0b4ab9c9ed6c139d29128f2e34bb636338a484aa7b72  cpython-3.8.14+20221002-aarch64-apple-darwin-pgo+lto-full.tar.zst
+FC720999BC5050E1D3706B3B6445E695CF42BFC71EBC7C88ED6BB88828B1D385 *pypy3.10-v7.3.16-aarch64.tar.bz2
+";
+    assert_eq!(
+        find_checksum_in_manifest(manifest, "https://mirror.invalid/20221002/cpython-3.8.14%2B20221002-aarch64-apple-darwin-pgo%2Blto-full.tar.zst").as_deref(),
+        Some("d17a3fcc161345efa2ec0b4ab9c9ed6c139d29128f2e34bb636338a484aa7b72")
+    );
+    assert_eq!(
+        find_checksum_in_manifest(
+            manifest,
+            "https://downloads.python.org/pypy/pypy3.10-v7.3.16-aarch64.tar.bz2"
+        )
+        .as_deref(),
+        Some("fc720999bc5050e1d3706b3b6445e695cf42bfc71ebc7c88ed6bb88828b1d385")
+    );
+    assert_eq!(
+        find_checksum_in_manifest(manifest, "https://mirror.invalid/other.tar.gz"),
+        None
+    );
+}
+
+#[test]
+fn test_get_mirror_urls() {
+    let mirrors = [
+        DownloadMirror {
+            url: "https://mirror.internal/pbs".into(),
+            timeout: Some(Duration::from_secs(3)),
+            layout: MirrorLayout::Releases,
+            exclusive: false,
+        },
+        DownloadMirror {
+            url: PBS_RELEASES_URL.into(),
+            timeout: Some(Duration::from_secs(10)),
+            layout: MirrorLayout::Releases,
+            exclusive: false,
+        },
+        DownloadMirror {
+            url: "/mnt/share/python-toolchains".into(),
+            timeout: None,
+            layout: MirrorLayout::Flat,
+            exclusive: false,
+        },
+    ];
+    let url = format!(
+        "{}/20240415/cpython-3.12.3%2B20240415-x86_64-unknown-linux-gnu-install_only.tar.gz",
+        PBS_RELEASES_URL
+    );
+    assert_eq!(
+        get_mirror_urls(&url, &mirrors),
+        vec![
+            (
+                "https://mirror.internal/pbs/20240415/cpython-3.12.3%2B20240415-x86_64-unknown-linux-gnu-install_only.tar.gz".to_string(),
+                Some(Duration::from_secs(3))
+            ),
+            (url.clone(), Some(Duration::from_secs(10))),
+            (
+                "/mnt/share/python-toolchains/cpython-3.12.3%2B20240415-x86_64-unknown-linux-gnu-install_only.tar.gz".to_string(),
+                None
+            ),
+        ]
+    );
+    assert_eq!(get_mirror_urls(&url, &mirrors[..1])[1], (url.clone(), None));
+    let pypy = "https://downloads.python.org/pypy/pypy3.10-v7.3.16-aarch64.tar.bz2";
+    assert_eq!(
+        get_mirror_urls(pypy, &mirrors),
+        vec![
+            (
+                "/mnt/share/python-toolchains/pypy3.10-v7.3.16-aarch64.tar.bz2".to_string(),
+                None
+            ),
+            (pypy.to_string(), None)
+        ]
+    );
+    let exclusive = [DownloadMirror {
+        exclusive: true,
+        ..mirrors[2].clone()
+    }];
+    assert_eq!(
+        get_mirror_urls(pypy, &exclusive),
+        vec![(
+            "/mnt/share/python-toolchains/pypy3.10-v7.3.16-aarch64.tar.bz2".to_string(),
+            None
+        )]
+    );
+}
+
+#[test]
+fn test_find_download_with_custom_toolchains() {
+    let custom = [
+        CustomToolchain {
+            name: None,
+            version: "cpython-aarch64-macos@3.8.14".into(),
+            os: None,
+            arch: None,
+            url: "https://toolchains.internal/cpython-3.8.14-patched.tar.zst".into(),
+            sha256: Some("abc".into()),
+        },
+        CustomToolchain {
+            name: None,
+            version: "3.8.99".into(),
+            os: Some("macos".into()),
+            arch: Some("aarch64".into()),
+            url: "https://toolchains.internal/cpython-3.8.99.tar.zst".into(),
+            sha256: None,
+        },
+    ]
+    .into_iter()
+    .map(|x| (x.python_version().unwrap(), x.url, x.sha256))
+    .collect::<Vec<_>>();
+
+    let find = |req: &str| {
+        find_download(&req.parse().unwrap(), &custom)
+            .map(|(version, url, _)| (version.to_string(), url))
+    };
+    assert_eq!(
+        find("cpython-aarch64-macos@3.8.14").unwrap().1,
+        "https://toolchains.internal/cpython-3.8.14-patched.tar.zst"
+    );
+    assert_eq!(
+        find("cpython-aarch64-macos@3.8").unwrap().1,
+        "https://toolchains.internal/cpython-3.8.99.tar.zst"
+    );
+    // builtin toolchains are still found
+    assert!(find("cpython-aarch64-macos@3.8.15")
+        .unwrap()
+        .1
+        .starts_with(PBS_RELEASES_URL));
+    assert!(CustomToolchain {
+        name: None,
+        version: "3.12".into(),
+        os: None,
+        arch: None,
+        url: "https://toolchains.internal/cpython-3.12.tar.zst".into(),
+        sha256: None,
+    }
+    .python_version()
+    .is_err());
+}
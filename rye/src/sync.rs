@@ -1,29 +1,44 @@
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
-use std::process::Command;
-use std::{env, fs};
+use std::process::Stdio;
+use std::str::FromStr;
+use std::time::Instant;
+use std::{env, fmt, fs};
 
 use anyhow::{bail, Context, Error};
 use console::style;
+use pep440_rs::Version;
 use same_file::is_same_file;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use tempfile::tempdir;
 
-use crate::bootstrap::{ensure_self_venv, fetch, get_pip_module, FetchOptions};
+use crate::backend::{Backend, InstallOptions};
+use crate::bootstrap::{ensure_self_venv, fetch, get_site_packages_dir, FetchOptions};
 use crate::config::Config;
-use crate::consts::VENV_BIN;
 use crate::lock::{
-    make_project_root_fragment, update_single_project_lockfile, update_workspace_lockfile,
-    KeyringProvider, LockMode, LockOptions,
+    get_dependency_group_names, parse_locked_packages, strip_project_installs,
+    update_single_project_lockfile, update_workspace_lockfile, KeyringProvider, LockMode,
+    LockOptions, LockedPackage,
 };
-use crate::piptools::{get_pip_sync, get_pip_tools_venv_path};
-use crate::platform::get_toolchain_python_bin;
-use crate::pyproject::{read_venv_marker, write_venv_marker, ExpandedSources, PyProject};
-use crate::sources::py::PythonVersion;
+use crate::native::{get_native_env, get_native_prefixes, sync_native_deps};
+use crate::platform::{get_toolchain_python_bin, record_toolchain_user};
+use crate::policy::Operation;
+use crate::pyproject::{
+    normalize_package_name, read_pyvenv_cfg, read_venv_marker, update_pyvenv_cfg,
+    write_venv_marker, ExpandedSources, PyProject,
+};
+use crate::pyversion::PythonVersionSource;
+use crate::sources::py::{PythonVersion, PythonVersionRequest};
+use crate::tui::Heartbeat;
+use crate::utils::atomic::write_atomic;
+use crate::utils::cleanup::{run_interruptible, RemoveOnInterrupt};
 use crate::utils::{
-    get_venv_python_bin, set_proxy_variables, symlink_dir, update_venv_sync_marker, CommandOutput,
-    IoPathContext,
+    get_venv_python_bin, internal_command, tui_theme, update_venv_sync_marker, CommandOutput,
+    IoPathContext, Verbosity,
 };
-use crate::uv::UvBuilder;
+use crate::variants::{select_variant, Accelerator};
+use crate::venv::VenvOptions;
 
 /// Controls the sync mode
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
@@ -50,6 +65,8 @@ pub struct SyncOptions {
     pub mode: SyncMode,
     /// Forces venv creation even when unsafe.
     pub force: bool,
+    /// Replace a virtualenv not created by rye without asking.
+    pub force_replace: bool,
     /// Do not lock.
     pub no_lock: bool,
     /// Controls locking.
@@ -58,6 +75,20 @@ pub struct SyncOptions {
     pub pyproject: Option<PathBuf>,
     /// Keyring provider to use for credential lookup.
     pub keyring_provider: KeyringProvider,
+    /// Install this dependency group instead of the dev dependencies.
+    pub group: Option<String>,
+    /// Byte-compile installed packages (`None` leaves it to the installer).
+    pub compile_bytecode: Option<bool>,
+    /// Write a JSON report of the changes to the virtualenv to this path.
+    pub report: Option<PathBuf>,
+    /// Only install from the distributions in this folder instead of the
+    /// configured sources.
+    pub offline_wheels: Option<PathBuf>,
+    /// Only install the dependencies, not the projects themselves.
+    pub no_install_project: bool,
+    /// Write a `.python-version` derived from `requires-python` after a
+    /// successful sync.  Only explicit syncs do this.
+    pub write_pin: bool,
 }
 
 impl SyncOptions {
@@ -88,14 +119,585 @@ impl VenvMarker {
     }
 }
 
+/// State written into the virtualenv after the dependencies were installed.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct SyncState {
+    /// The lockfile the virtualenv was synced from.
+    pub lockfile: PathBuf,
+    /// The sha256 of the lockfile at the time of the sync.
+    pub lock_hash: String,
+    /// The projects themselves were left out (`--no-install-project`).
+    #[serde(default)]
+    pub no_install_project: bool,
+}
+
+/// A distribution installed into a virtualenv.
+#[derive(Debug, Clone)]
+pub struct InstalledDist {
+    pub name: String,
+    pub version: String,
+    pub tags: Vec<String>,
+    pub purelib: bool,
+    /// Installed from a direct URL or as editable.
+    pub direct_url: bool,
+    /// The hash of the archive of a direct URL install (`sha256:...`).
+    pub archive_hash: Option<String>,
+    /// The `.dist-info` folder of the distribution.
+    pub dist_info: PathBuf,
+}
+
+impl InstalledDist {
+    /// Reconstructs the filename of the wheel this was installed from.
+    ///
+    /// Wheels with compressed tag sets expand into multiple tags, which are
+    /// compressed again here.  Direct URL installs have no known filename.
+    pub fn wheel_filename(&self) -> Option<String> {
+        if self.direct_url || self.tags.is_empty() {
+            return None;
+        }
+        let mut parts: [Vec<&str>; 3] = Default::default();
+        for tag in &self.tags {
+            for (part, value) in parts.iter_mut().zip(tag.splitn(3, '-')) {
+                if !part.contains(&value) {
+                    part.push(value);
+                }
+            }
+        }
+        Some(format!(
+            "{}-{}-{}-{}-{}.whl",
+            self.name.replace('-', "_"),
+            self.version,
+            parts[0].join("."),
+            parts[1].join("."),
+            parts[2].join(".")
+        ))
+    }
+}
+
+/// Reads all distributions from a site-packages folder.
+pub fn find_installed_dists(site_packages: &Path) -> Result<Vec<InstalledDist>, Error> {
+    let mut rv = Vec::new();
+    let entries =
+        fs::read_dir(site_packages).path_context(site_packages, "unable to read site-packages")?;
+    for entry in entries {
+        let dist_info = entry?.path();
+        if dist_info.extension().map_or(true, |x| x != "dist-info") {
+            continue;
+        }
+        let Ok(metadata) = fs::read_to_string(dist_info.join("METADATA")) else {
+            continue;
+        };
+        let wheel = fs::read_to_string(dist_info.join("WHEEL")).unwrap_or_default();
+        let header = |contents: &str, key: &str| {
+            contents
+                .lines()
+                .take_while(|x| !x.is_empty())
+                .filter_map(|x| x.split_once(':'))
+                .filter(|(k, _)| k.trim().eq_ignore_ascii_case(key))
+                .map(|(_, v)| v.trim().to_string())
+                .collect::<Vec<_>>()
+        };
+        let (Some(name), Some(version)) = (
+            header(&metadata, "Name").pop(),
+            header(&metadata, "Version").pop(),
+        ) else {
+            continue;
+        };
+        rv.push(InstalledDist {
+            name: normalize_package_name(&name),
+            version,
+            tags: header(&wheel, "Tag"),
+            purelib: header(&wheel, "Root-Is-Purelib")
+                .pop()
+                .map_or(true, |x| x.eq_ignore_ascii_case("true")),
+            direct_url: dist_info.join("direct_url.json").is_file(),
+            archive_hash: read_archive_hash(&dist_info),
+            dist_info,
+        });
+    }
+    rv.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(rv)
+}
+
+/// Reads the archive hash that installers record for direct URL installs.
+fn read_archive_hash(dist_info: &Path) -> Option<String> {
+    let contents = fs::read(dist_info.join("direct_url.json")).ok()?;
+    let direct_url: serde_json::Value = serde_json::from_slice(&contents).ok()?;
+    let archive_info = direct_url.get("archive_info")?;
+    if let Some(sha256) = archive_info
+        .get("hashes")
+        .and_then(|x| x.get("sha256"))
+        .and_then(|x| x.as_str())
+    {
+        return Some(format!("sha256:{}", sha256));
+    }
+    archive_info
+        .get("hash")
+        .and_then(|x| x.as_str())
+        .map(|x| x.replacen('=', ":", 1))
+}
+
+/// Output structure for `rye sync --report`
+// Reserves the right to expand with new fields.
+#[derive(Serialize, Debug)]
+struct SyncReport {
+    rye_version: &'static str,
+    python: ReportPython,
+    venv: PathBuf,
+    lockfile: PathBuf,
+    lock_hash: String,
+    installer: String,
+    timings: ReportTimings,
+    installed: Vec<ReportPackage>,
+    upgraded: Vec<ReportPackage>,
+    downgraded: Vec<ReportPackage>,
+    removed: Vec<ReportPackage>,
+}
+
+#[derive(Serialize, Debug)]
+struct ReportPython {
+    version: PythonVersion,
+    executable: PathBuf,
+}
+
+/// Durations in seconds.
+#[derive(Serialize, Debug)]
+struct ReportTimings {
+    lock: f64,
+    install: f64,
+}
+
+#[derive(Serialize, Debug)]
+struct ReportPackage {
+    name: String,
+    version: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    previous_version: Option<String>,
+    wheel: Option<String>,
+    url: Option<String>,
+    hashes: Vec<String>,
+}
+
+/// How a distribution changed during a sync.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Change {
+    Installed,
+    Upgraded,
+    Downgraded,
+    Removed,
+}
+
+/// Compares the distributions in a virtualenv before and after a sync.
+///
+/// Returns the change, the distribution and the version it had before.
+/// Reinstalls of the same version are not reported.
+fn diff_installed<'a>(
+    before: &'a [InstalledDist],
+    after: &'a [InstalledDist],
+) -> Vec<(Change, &'a InstalledDist, Option<&'a str>)> {
+    let mut rv = Vec::new();
+    for dist in after {
+        match before.iter().find(|x| x.name == dist.name) {
+            None => rv.push((Change::Installed, dist, None)),
+            Some(old) if old.version != dist.version => {
+                let change = match (
+                    Version::from_str(&old.version),
+                    Version::from_str(&dist.version),
+                ) {
+                    (Ok(old), Ok(new)) if new < old => Change::Downgraded,
+                    _ => Change::Upgraded,
+                };
+                rv.push((change, dist, Some(old.version.as_str())));
+            }
+            Some(_) => {}
+        }
+    }
+    for dist in before {
+        if !after.iter().any(|x| x.name == dist.name) {
+            rv.push((Change::Removed, dist, None));
+        }
+    }
+    rv
+}
+
+fn write_sync_report(
+    path: &Path,
+    pyproject: &PyProject,
+    py_ver: &PythonVersion,
+    venv: &Path,
+    lockfile: &Path,
+    before: &[InstalledDist],
+    timings: ReportTimings,
+) -> Result<(), Error> {
+    let after = find_installed_dists(&get_site_packages_dir(venv)?)?;
+    let contents =
+        fs::read_to_string(lockfile).path_context(lockfile, "unable to read lockfile")?;
+    let locked = parse_locked_packages(&contents, &pyproject.workspace_path())
+        .into_iter()
+        .map(|x| (x.name.clone(), x))
+        .collect::<HashMap<_, _>>();
+
+    let mut report = SyncReport {
+        rye_version: env!("CARGO_PKG_VERSION"),
+        python: ReportPython {
+            version: py_ver.clone(),
+            executable: get_venv_python_bin(venv),
+        },
+        venv: venv.to_path_buf(),
+        lockfile: lockfile.to_path_buf(),
+        lock_hash: hash_lockfile(lockfile)?,
+        installer: Backend::current().to_string(),
+        timings,
+        installed: Vec::new(),
+        upgraded: Vec::new(),
+        downgraded: Vec::new(),
+        removed: Vec::new(),
+    };
+    for (change, dist, previous_version) in diff_installed(before, &after) {
+        let locked = locked.get(&dist.name);
+        let package = ReportPackage {
+            name: dist.name.clone(),
+            version: dist.version.clone(),
+            previous_version: previous_version.map(|x| x.to_string()),
+            wheel: dist.wheel_filename(),
+            url: if change == Change::Removed {
+                None
+            } else {
+                locked.and_then(|x| x.url.clone())
+            },
+            hashes: if change == Change::Removed {
+                Vec::new()
+            } else {
+                locked.map(|x| x.hashes.clone()).unwrap_or_default()
+            },
+        };
+        match change {
+            Change::Installed => report.installed.push(package),
+            Change::Upgraded => report.upgraded.push(package),
+            Change::Downgraded => report.downgraded.push(package),
+            Change::Removed => report.removed.push(package),
+        }
+    }
+
+    fs::write(path, serde_json::to_string_pretty(&report)? + "\n")
+        .path_context(path, "unable to write sync report")?;
+    Ok(())
+}
+
+/// A difference between a virtualenv and the lockfile it should match.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Mismatch {
+    /// Locked but not installed.
+    Missing(String, Option<String>),
+    /// Installed but not locked.
+    Extra(String, String),
+    /// Installed in another version (name, locked, installed).
+    Version(String, String, String),
+    /// Locked as direct reference or editable but installed from an index.
+    NotDirect(String),
+    /// A direct reference installed from an archive with another hash.
+    Hash(String, String),
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Mismatch::Missing(name, Some(version)) => {
+                write!(f, "- {} {} (not installed)", name, version)
+            }
+            Mismatch::Missing(name, None) => write!(f, "- {} (not installed)", name),
+            Mismatch::Extra(name, version) => write!(f, "+ {} {} (not locked)", name, version),
+            Mismatch::Version(name, locked, installed) => {
+                write!(f, "~ {} {} (installed {})", name, locked, installed)
+            }
+            Mismatch::NotDirect(name) => {
+                write!(
+                    f,
+                    "~ {} (locked as direct reference, installed from index)",
+                    name
+                )
+            }
+            Mismatch::Hash(name, installed) => {
+                write!(
+                    f,
+                    "~ {} (installed archive {} is not locked)",
+                    name, installed
+                )
+            }
+        }
+    }
+}
+
+/// Compares the installed distributions with the locked packages.
+///
+/// Locked packages with an environment marker that are not installed are
+/// skipped as the marker most likely excludes this environment.  Names in
+/// `unlocked` may be installed without being locked.
+pub fn compare_with_lock(
+    locked: &[LockedPackage],
+    installed: &[InstalledDist],
+    unlocked: &[&str],
+) -> Vec<Mismatch> {
+    let mut rv = Vec::new();
+    for package in locked {
+        let Some(dist) = installed.iter().find(|x| x.name == package.name) else {
+            if package.marker.is_none() {
+                rv.push(Mismatch::Missing(
+                    package.name.clone(),
+                    package.version.clone(),
+                ));
+            }
+            continue;
+        };
+        if package.url.is_some() {
+            if !dist.direct_url {
+                rv.push(Mismatch::NotDirect(package.name.clone()));
+            } else if let Some(ref hash) = dist.archive_hash {
+                if !package.hashes.is_empty() && !package.hashes.contains(hash) {
+                    rv.push(Mismatch::Hash(package.name.clone(), hash.clone()));
+                }
+            }
+        } else if let Some(ref version) = package.version {
+            let same = match (Version::from_str(version), Version::from_str(&dist.version)) {
+                (Ok(a), Ok(b)) => a == b,
+                _ => *version == dist.version,
+            };
+            if !same {
+                rv.push(Mismatch::Version(
+                    package.name.clone(),
+                    version.clone(),
+                    dist.version.clone(),
+                ));
+            }
+        }
+    }
+    for dist in installed {
+        if !unlocked.contains(&dist.name.as_str()) && !locked.iter().any(|x| x.name == dist.name) {
+            rv.push(Mismatch::Extra(dist.name.clone(), dist.version.clone()));
+        }
+    }
+    rv
+}
+
+/// Returns the dependency variant to sync: the requested or the detected one.
+pub fn get_sync_variant(
+    pyproject: &PyProject,
+    requested: Option<&str>,
+) -> Result<Option<String>, Error> {
+    let variants = pyproject.variants()?;
+    Ok(select_variant(&variants, requested, Accelerator::is_available)?.map(|x| x.name))
+}
+
+/// Returns the path of the lockfile of a mode for a dependency variant.
+///
+/// Once a variant is selected sync only writes the lockfiles of that variant,
+/// so everything reading lockfiles needs to go through here.
+pub fn get_lockfile_path(pyproject: &PyProject, mode: &LockMode, variant: Option<&str>) -> PathBuf {
+    pyproject
+        .workspace_path()
+        .join(mode.variant_lockfile_name(variant).as_ref())
+}
+
+/// Verifies that the virtualenv matches the lockfile without changing anything.
+///
+/// Every difference is printed.  Returns `false` if the virtualenv needs a sync.
+pub fn check_sync(cmd: &SyncOptions) -> Result<bool, Error> {
+    let output = cmd.output;
+    let pyproject = PyProject::load_or_discover(cmd.pyproject.as_deref())?;
+    let venv = pyproject.venv_path();
+    let variant = get_sync_variant(&pyproject, cmd.lock_options.variant.as_deref())?;
+    let lockfile_path = |mode: LockMode| {
+        pyproject
+            .workspace_path()
+            .join(mode.variant_lockfile_name(variant.as_deref()).as_ref())
+    };
+    let lockfile = lockfile_path(LockMode::Production);
+    let dev_lockfile = lockfile_path(LockMode::Dev);
+    let target_lockfile = if let Some(ref group) = cmd.group {
+        lockfile_path(LockMode::Group(group.clone()))
+    } else if cmd.dev && dev_lockfile.is_file() {
+        dev_lockfile
+    } else {
+        lockfile
+    };
+    if !target_lockfile.is_file() {
+        bail!("lockfile '{}' does not exist", target_lockfile.display());
+    }
+
+    if !venv.is_dir() {
+        echo!(if output, "virtualenv {} does not exist", venv.display());
+        return Ok(false);
+    }
+    let Some(marker) = read_venv_marker(&venv) else {
+        echo!(if output, "virtualenv {} is not managed by rye", venv.display());
+        return Ok(false);
+    };
+    let mut in_sync = true;
+    let py_ver = pyproject.venv_python_version()?;
+    if marker.python != py_ver {
+        echo!(
+            if output,
+            "Python version mismatch (found {}, expected {})",
+            marker.python,
+            py_ver
+        );
+        in_sync = false;
+    }
+    match read_sync_state(&venv) {
+        None => {
+            echo!(if output, "virtualenv was never synced");
+            in_sync = false;
+        }
+        Some(state) => {
+            if !is_same_file(&state.lockfile, &target_lockfile).unwrap_or(false) {
+                echo!(
+                    if output,
+                    "virtualenv was synced from {}",
+                    state.lockfile.display()
+                );
+                in_sync = false;
+            } else if hash_lockfile(&target_lockfile)? != state.lock_hash {
+                echo!(if output, "lockfile changed since the last sync");
+                in_sync = false;
+            } else if state.no_install_project && !cmd.no_install_project {
+                echo!(if output, "virtualenv was synced without the project");
+                in_sync = false;
+            }
+        }
+    }
+
+    let mut contents = fs::read_to_string(&target_lockfile)
+        .path_context(&target_lockfile, "unable to read lockfile")?;
+    if cmd.no_install_project {
+        contents = strip_project_installs(
+            &contents,
+            &pyproject.workspace_path(),
+            &get_project_names(&pyproject)?,
+        );
+    }
+    let locked = parse_locked_packages(&contents, &pyproject.workspace_path());
+    let installed = find_installed_dists(&get_site_packages_dir(&venv)?)?;
+    let unlocked: &[&str] = if Config::current().venv_pip() {
+        &["pip"]
+    } else {
+        &[]
+    };
+    let mismatches = compare_with_lock(&locked, &installed, unlocked);
+    for mismatch in &mismatches {
+        echo!(if output, "{}", mismatch);
+    }
+    in_sync &= mismatches.is_empty();
+
+    if in_sync {
+        echo!(
+            if output,
+            "virtualenv matches {}",
+            style(target_lockfile.display()).cyan()
+        );
+    } else {
+        echo!(
+            if output,
+            "virtualenv does not match {}, run `rye sync` to update it",
+            style(target_lockfile.display()).cyan()
+        );
+    }
+    Ok(in_sync)
+}
+
+fn get_sync_state_path(venv: &Path) -> PathBuf {
+    venv.join("rye-sync-state.json")
+}
+
+/// Returns the sha256 of a lockfile.
+pub fn hash_lockfile(lockfile: &Path) -> Result<String, Error> {
+    let contents = fs::read(lockfile).path_context(lockfile, "unable to read lockfile")?;
+    Ok(hex::encode(Sha256::digest(contents)))
+}
+
+fn write_sync_state(venv: &Path, lockfile: &Path, no_install_project: bool) -> Result<(), Error> {
+    let state = SyncState {
+        lockfile: lockfile.to_path_buf(),
+        lock_hash: hash_lockfile(lockfile)?,
+        no_install_project,
+    };
+    let path = get_sync_state_path(venv);
+    write_atomic(&path, serde_json::to_string_pretty(&state)?)
+        .path_context(&path, "unable to write sync state")?;
+    update_pyvenv_cfg(venv, &[("rye-lock-hash", &state.lock_hash)])
+}
+
+/// Reads the sync state of a virtualenv.
+pub fn read_sync_state(venv: &Path) -> Option<SyncState> {
+    let contents = fs::read(get_sync_state_path(venv)).ok()?;
+    serde_json::from_slice(&contents).ok()
+}
+
+/// Checks if the virtualenv of a project drifted from its lockfile.
+///
+/// This warns once if the lockfile changed since the last sync, or fails
+/// if `behavior.strict-sync` is enabled.  Virtualenvs without a recorded
+/// sync state are not checked.
+pub fn check_venv_drift(pyproject: &PyProject) -> Result<(), Error> {
+    let lockfile = match get_venv_drift(&pyproject.venv_path()) {
+        Some(lockfile) => lockfile,
+        None => return Ok(()),
+    };
+    if Config::current().strict_sync() {
+        bail!(
+            "virtualenv is out of sync with {}. Run `rye sync` to update it.",
+            lockfile.display()
+        );
+    }
+    warn!(
+        "virtualenv is out of sync with {}. Run `rye sync` to update it.",
+        lockfile.display()
+    );
+    Ok(())
+}
+
+/// Returns the lockfile a virtualenv drifted from.
+///
+/// This is `None` if the virtualenv has no recorded sync state, the lockfile
+/// is gone or it did not change since the last sync.
+pub fn get_venv_drift(venv: &Path) -> Option<PathBuf> {
+    let state = read_sync_state(venv)?;
+    let lock_hash = hash_lockfile(&state.lockfile).ok()?;
+    if lock_hash == state.lock_hash {
+        None
+    } else {
+        Some(state.lockfile)
+    }
+}
+
 /// Synchronizes a project's virtualenv.
 pub fn sync(mut cmd: SyncOptions) -> Result<(), Error> {
     let pyproject = PyProject::load_or_discover(cmd.pyproject.as_deref())?;
-    let lockfile = pyproject.workspace_path().join("requirements.lock");
-    let dev_lockfile = pyproject.workspace_path().join("requirements-dev.lock");
+
+    // `rye lock` only locks a variant if asked to, syncs detect one.
+    if cmd.mode != SyncMode::PythonOnly
+        && (cmd.mode != SyncMode::LockOnly || cmd.lock_options.variant.is_some())
+    {
+        cmd.lock_options.variant =
+            get_sync_variant(&pyproject, cmd.lock_options.variant.as_deref())?;
+        if let Some(ref variant) = cmd.lock_options.variant {
+            echo!(if cmd.output, "Using dependency variant {}", style(variant).cyan());
+        }
+    }
+    let variant = cmd.lock_options.variant.clone();
+    let lockfile = pyproject.workspace_path().join(
+        LockMode::Production
+            .variant_lockfile_name(variant.as_deref())
+            .as_ref(),
+    );
+    let dev_lockfile = pyproject.workspace_path().join(
+        LockMode::Dev
+            .variant_lockfile_name(variant.as_deref())
+            .as_ref(),
+    );
     let venv = pyproject.venv_path();
     let py_ver = pyproject.venv_python_version()?;
     let output = cmd.output;
+    record_toolchain_user(&pyproject.workspace_path(), &py_ver);
 
     if cmd.pyproject.is_some()
         && cmd.mode != SyncMode::PythonOnly
@@ -109,6 +711,32 @@ pub fn sync(mut cmd: SyncOptions) -> Result<(), Error> {
     if pyproject.lock_with_sources() {
         cmd.lock_options.with_sources = true;
     }
+    if pyproject.structured_lock() {
+        cmd.lock_options.structured = true;
+    }
+    if pyproject.resolution_report() {
+        cmd.lock_options.report = true;
+    }
+    if cmd.lock_options.pyodide_version.is_none() {
+        cmd.lock_options.pyodide_version = pyproject.pyodide_version();
+    }
+
+    // contributors of projects that do not allow locking sync from the
+    // existing lockfiles, only `rye lock` fails.
+    let policy = pyproject.policy();
+    if policy.require_hashes {
+        cmd.lock_options.generate_hashes = true;
+    }
+    if !cmd.no_lock && cmd.mode != SyncMode::PythonOnly && !policy.allows(Operation::Lock) {
+        if cmd.mode == SyncMode::LockOnly {
+            policy.check(Operation::Lock, &pyproject.policy_path())?;
+        }
+        echo!(
+            if output,
+            "Locking is not allowed by the project policy, syncing from the existing lockfiles"
+        );
+        cmd.no_lock = true;
+    }
 
     // ensure we are bootstrapped
     let self_venv = ensure_self_venv(output).context("could not sync because bootstrap failed")?;
@@ -138,14 +766,18 @@ pub fn sync(mut cmd: SyncOptions) -> Result<(), Error> {
                     recreate = true;
                 }
             }
-        } else if cmd.force {
+        } else if cmd.force || cmd.force_replace {
             echo!(if cmd.output, "Forcing re-creation of non-rye managed virtualenv");
             recreate = true;
         } else if cmd.mode == SyncMode::PythonOnly {
             // in python-only sync mode, don't complain about foreign venvs
             return Ok(());
+        } else if ask_adopt_foreign_venv(&venv, &py_ver)? {
+            write_venv_marker(&venv, &py_ver)?;
+            echo!(if cmd.output, "Adopted existing virtualenv");
         } else {
-            bail!("virtualenv is not managed by rye. Run `rye sync -f` to force.");
+            echo!(if cmd.output, "Replacing non-rye managed virtualenv");
+            recreate = true;
         }
     }
 
@@ -170,19 +802,58 @@ pub fn sync(mut cmd: SyncOptions) -> Result<(), Error> {
             style(venv.display()).cyan()
         );
         echo!(if output, "Python version: {}", style(&py_ver).cyan());
-        let prompt = pyproject.name().unwrap_or("venv");
-        create_virtualenv(output, &self_venv, &py_ver, &venv, prompt)
+        create_virtualenv(output, &self_venv, &py_ver, &venv, &pyproject.venv_prompt())
             .context("failed creating virtualenv ahead of sync")?;
     }
 
+    // let other tools detect that this virtualenv is managed by rye
+    update_pyvenv_cfg(
+        &venv,
+        &[
+            ("rye-managed", "true"),
+            (
+                "rye-project",
+                &pyproject.workspace_path().display().to_string(),
+            ),
+        ],
+    )?;
+
     // prepare necessary utilities for pip-sync.  This is a super crude
     // hack to make this work for now.  We basically sym-link pip itself
     // into a folder all by itself and place a second file in there which we
     // can pass to pip-sync to install the local package.
     if recreate || cmd.mode != SyncMode::PythonOnly {
-        let sources = ExpandedSources::from_sources(&pyproject.sources()?)?;
+        // native libraries must be in place before locking builds sdists.
+        sync_native_deps(&pyproject.native_deps()?, &venv, output)?;
+        for (key, value) in get_native_env(&get_native_prefixes(&venv))? {
+            env::set_var(key, value);
+        }
+
+        let lock_start = Instant::now();
+        let sources = match cmd.offline_wheels {
+            Some(ref path) => ExpandedSources::offline(path)?,
+            None => ExpandedSources::from_sources(&pyproject.sources()?)?,
+        };
+        let groups = get_dependency_group_names(&pyproject)?;
+        let group_lockfile = match cmd.group {
+            Some(ref group) if groups.contains(group) => Some(
+                pyproject.workspace_path().join(
+                    LockMode::Group(group.clone())
+                        .variant_lockfile_name(variant.as_deref())
+                        .as_ref(),
+                ),
+            ),
+            Some(ref group) => bail!("unknown dependency group '{}'", group),
+            None => None,
+        };
         if cmd.no_lock {
-            let lockfile = if cmd.dev { &dev_lockfile } else { &lockfile };
+            let lockfile = if let Some(ref group_lockfile) = group_lockfile {
+                group_lockfile
+            } else if cmd.dev {
+                &dev_lockfile
+            } else {
+                &lockfile
+            };
             if !lockfile.is_file() {
                 bail!(
                     "Locking is disabled but lockfile '{}' does not exist",
@@ -239,75 +910,129 @@ pub fn sync(mut cmd: SyncOptions) -> Result<(), Error> {
             .context("could not write dev lockfile for project")?;
         }
 
+        // every dependency group gets a lockfile of its own
+        if !cmd.no_lock {
+            for group in groups {
+                let lock_mode = LockMode::Group(group);
+                let group_lockfile = pyproject
+                    .workspace_path()
+                    .join(lock_mode.variant_lockfile_name(variant.as_deref()).as_ref());
+                if let Some(workspace) = pyproject.workspace() {
+                    update_workspace_lockfile(
+                        &py_ver,
+                        workspace,
+                        lock_mode,
+                        &group_lockfile,
+                        cmd.output,
+                        &sources,
+                        &cmd.lock_options,
+                        cmd.keyring_provider,
+                    )
+                } else {
+                    update_single_project_lockfile(
+                        &py_ver,
+                        &pyproject,
+                        lock_mode,
+                        &group_lockfile,
+                        cmd.output,
+                        &sources,
+                        &cmd.lock_options,
+                        cmd.keyring_provider,
+                    )
+                }
+                .context("could not write lockfile for dependency group")?;
+            }
+        }
+
         // run pip install with the lockfile.
         if cmd.mode != SyncMode::LockOnly {
+            let lock_duration = lock_start.elapsed();
+            let install_start = Instant::now();
+            let before = if cmd.report.is_some() {
+                find_installed_dists(&get_site_packages_dir(&venv)?)?
+            } else {
+                Vec::new()
+            };
             echo!(if output, "Installing dependencies");
+            let _heartbeat = Heartbeat::start("installing dependencies");
 
-            let target_lockfile = if cmd.dev && dev_lockfile.is_file() {
+            let target_lockfile = if let Some(group_lockfile) = group_lockfile {
+                group_lockfile
+            } else if cmd.dev && dev_lockfile.is_file() {
                 dev_lockfile
             } else {
                 lockfile
             };
+            if policy.require_hashes {
+                let contents = fs::read_to_string(&target_lockfile)
+                    .path_context(&target_lockfile, "unable to read lockfile")?;
+                policy.check_lockfile_hashes(&target_lockfile, &contents)?;
+            }
+
+            let sources = match cmd.offline_wheels {
+                Some(_) => sources,
+                None => get_install_sources(&pyproject, sources)?,
+            };
 
+            let compile_bytecode = cmd
+                .compile_bytecode
+                .or_else(|| Config::current().compile_bytecode());
             let tempdir = tempdir()?;
-            let py_path = get_venv_python_bin(&venv);
-            if Config::current().use_uv() {
-                UvBuilder::new()
-                    .with_output(output.quieter())
-                    .with_workdir(&pyproject.workspace_path())
-                    .with_sources(sources)
-                    .ensure_exists()?
-                    .venv(&venv, &py_path, &py_ver, None)?
-                    .with_output(output)
-                    .sync(&target_lockfile)?;
-            } else {
-                let mut pip_sync_cmd = Command::new(get_pip_sync(&py_ver, output)?);
-                let root = pyproject.workspace_path();
-
-                // we need to run this after we have run the `get_pip_sync` command
-                // as this is what bootstraps or updates the pip tools installation.
-                // This is needed as on unix platforms we need to search the module path.
-                symlink_dir(
-                    get_pip_module(&get_pip_tools_venv_path(&py_ver))
-                        .context("could not locate pip")?,
-                    tempdir.path().join("pip"),
-                )
-                .context("failed linking pip module into for pip-sync")?;
-
-                pip_sync_cmd
-                    .env("PROJECT_ROOT", make_project_root_fragment(&root))
-                    .env("PYTHONPATH", tempdir.path())
-                    .current_dir(&root)
-                    .arg("--python-executable")
-                    .arg(&py_path)
-                    .arg("--pip-args")
-                    .arg("--no-deps");
-
-                if output != CommandOutput::Quiet {
-                    pip_sync_cmd.env("PYTHONWARNINGS", "ignore");
-                } else if output == CommandOutput::Verbose && env::var("PIP_VERBOSE").is_err() {
-                    pip_sync_cmd.env("PIP_VERBOSE", "2");
-                }
 
-                sources.add_as_pip_args(&mut pip_sync_cmd);
-
-                pip_sync_cmd.arg(&target_lockfile);
+            // the projects are left out by installing from a filtered copy
+            // of the lockfile.  The sync state still refers to the original
+            // but records that the projects are missing.
+            let filtered_lockfile = if cmd.no_install_project {
+                let contents = fs::read_to_string(&target_lockfile)
+                    .path_context(&target_lockfile, "unable to read lockfile")?;
+                let path = tempdir.path().join("requirements.lock");
+                fs::write(
+                    &path,
+                    strip_project_installs(
+                        &contents,
+                        &pyproject.workspace_path(),
+                        &get_project_names(&pyproject)?,
+                    ),
+                )
+                .path_context(&path, "unable to write filtered lockfile")?;
+                path
+            } else {
+                target_lockfile.clone()
+            };
+            install_lockfile(
+                &pyproject,
+                &py_ver,
+                &venv,
+                &filtered_lockfile,
+                sources,
+                compile_bytecode,
+                output,
+            )?;
 
-                if output == CommandOutput::Verbose {
-                    pip_sync_cmd.arg("--verbose");
-                } else if output == CommandOutput::Quiet {
-                    pip_sync_cmd.arg("-q");
-                }
-                set_proxy_variables(&mut pip_sync_cmd);
-                let status = pip_sync_cmd.status().context("unable to run pip-sync")?;
+            if let Some(ref report) = cmd.report {
+                write_sync_report(
+                    report,
+                    &pyproject,
+                    &py_ver,
+                    &venv,
+                    &target_lockfile,
+                    &before,
+                    ReportTimings {
+                        lock: lock_duration.as_secs_f64(),
+                        install: install_start.elapsed().as_secs_f64(),
+                    },
+                )?;
+                echo!(if verbose output, "wrote sync report to {}", report.display());
+            }
 
-                if !status.success() {
-                    bail!("Installation of dependencies failed");
-                }
-            };
+            write_sync_state(&venv, &target_lockfile, cmd.no_install_project)?;
         };
     }
 
+    if cmd.write_pin && !matches!(cmd.mode, SyncMode::PythonOnly | SyncMode::LockOnly) {
+        write_derived_pin(&pyproject, output)?;
+    }
+
     if cmd.mode != SyncMode::PythonOnly {
         echo!(if output, "Done!");
     }
@@ -315,6 +1040,293 @@ pub fn sync(mut cmd: SyncOptions) -> Result<(), Error> {
     Ok(())
 }
 
+/// Returns the normalized names of the projects a sync would install.
+pub fn get_project_names(pyproject: &PyProject) -> Result<Vec<String>, Error> {
+    match pyproject.workspace() {
+        Some(workspace) => workspace
+            .iter_projects()
+            .map(|project| project?.normalized_name())
+            .collect(),
+        None => Ok(vec![pyproject.normalized_name()?]),
+    }
+}
+
+/// Creates or updates the virtualenv of a test environment.
+///
+/// Test environments run the tests of the project on another Python
+/// version.  They are placed next to the virtualenv of the project and
+/// install the lockfile the project was last synced from (the dev lockfile
+/// by default).  Returns the path of the virtualenv.
+pub fn sync_test_env(
+    pyproject: &PyProject,
+    env: &str,
+    output: CommandOutput,
+) -> Result<PathBuf, Error> {
+    let request: PythonVersionRequest = env
+        .parse()
+        .with_context(|| format!("invalid test environment '{}'", env))?;
+    let root = pyproject.workspace_path();
+    let venv = root.join(format!(".venv-{}", test_env_slug(env)));
+    let (mode, variant) = match read_sync_state(&pyproject.venv_path()) {
+        Some(state) => LockMode::from_lockfile_name(
+            &state
+                .lockfile
+                .file_name()
+                .map(|x| x.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+        ),
+        None => (LockMode::Dev, get_sync_variant(pyproject, None)?),
+    };
+    let lockfile = get_lockfile_path(pyproject, &mode, variant.as_deref());
+    let lockfile = if lockfile.is_file() || mode != LockMode::Dev {
+        lockfile
+    } else {
+        get_lockfile_path(pyproject, &LockMode::Production, variant.as_deref())
+    };
+    if !lockfile.is_file() {
+        bail!(
+            "lockfile '{}' does not exist, run `rye sync` first",
+            lockfile.display()
+        );
+    }
+
+    let py_ver = fetch(&request, FetchOptions::with_output(output))
+        .with_context(|| format!("failed fetching toolchain for test environment '{}'", env))?;
+    if venv.is_dir() && read_venv_marker(&venv).map_or(true, |x| x.python != py_ver) {
+        fs::remove_dir_all(&venv).path_context(&venv, "failed to delete test environment")?;
+    }
+    if !venv.is_dir() {
+        echo!(
+            if output,
+            "Initializing test environment {} ({})",
+            style(env).cyan(),
+            style(&py_ver).cyan()
+        );
+        let self_venv = ensure_self_venv(output)?;
+        create_virtualenv(
+            output,
+            &self_venv,
+            &py_ver,
+            &venv,
+            &format!("{}-{}", pyproject.venv_prompt(), env),
+        )
+        .context("failed creating test environment")?;
+    }
+
+    let sources = ExpandedSources::from_sources(&pyproject.sources()?)?;
+    install_lockfile(
+        pyproject,
+        &py_ver,
+        &venv,
+        &lockfile,
+        get_install_sources(pyproject, sources)?,
+        Config::current().compile_bytecode(),
+        output,
+    )?;
+    write_sync_state(&venv, &lockfile, false)?;
+    Ok(venv)
+}
+
+/// Turns the name of a test environment into a folder name.
+///
+/// Names are Python version requests such as `3.11` or `pypy@3.10`.
+fn test_env_slug(env: &str) -> String {
+    env.chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '.' | '_' | '-' => c,
+            _ => '-',
+        })
+        .collect()
+}
+
+/// Pins the Python version derived from `requires-python`.
+///
+/// Projects without a `.python-version` would otherwise pick the interpreter
+/// anew on every machine.  Nothing is written if the version comes from
+/// anywhere else, such as `RYE_PYTHON` or an existing pin.
+fn write_derived_pin(pyproject: &PyProject, output: CommandOutput) -> Result<(), Error> {
+    let resolution = pyproject.venv_python_version_resolution()?;
+    let PythonVersionSource::RequiresPython(ref requires_python) = resolution.source else {
+        return Ok(());
+    };
+    let pin = format!("{}.{}", resolution.version.major, resolution.version.minor);
+    let version_file = pyproject.workspace_path().join(".python-version");
+    write_atomic(&version_file, format!("{}\n", pin))
+        .path_context(&version_file, "failed to write .python-version file")?;
+    echo!(
+        if output,
+        "Pinned Python {} in {} (derived from requires-python {}, skip with --no-write-pin)",
+        style(&pin).cyan(),
+        style(version_file.display()).cyan(),
+        requires_python
+    );
+    Ok(())
+}
+
+/// Vendored projects install exclusively from their vendor folder.
+fn get_install_sources(
+    pyproject: &PyProject,
+    sources: ExpandedSources,
+) -> Result<ExpandedSources, Error> {
+    match pyproject.vendor_dir() {
+        Some(dir) => {
+            if !dir.is_dir() {
+                bail!(
+                    "vendor folder '{}' does not exist, run `rye vendor` first",
+                    dir.display()
+                );
+            }
+            ExpandedSources::offline(&dir)
+        }
+        None => Ok(sources),
+    }
+}
+
+/// Installs a lockfile into a virtualenv.
+///
+/// Everything that is not in the lockfile is removed from the virtualenv.
+fn install_lockfile(
+    pyproject: &PyProject,
+    py_ver: &PythonVersion,
+    venv: &Path,
+    lockfile: &Path,
+    sources: ExpandedSources,
+    compile_bytecode: Option<bool>,
+    output: CommandOutput,
+) -> Result<(), Error> {
+    let py_path = get_venv_python_bin(venv);
+    Backend::current().sync(
+        venv,
+        py_ver,
+        lockfile,
+        InstallOptions {
+            workdir: Some(pyproject.workspace_path().into()),
+            compile_bytecode,
+            ..InstallOptions::new(sources)
+        },
+        output,
+    )?;
+
+    // the installers remove what is not in the lockfile, so pip is
+    // put back in afterwards.
+    if Config::current().venv_pip() {
+        ensure_venv_pip(venv, &py_path, output)?;
+    }
+
+    if compile_bytecode == Some(true) {
+        compile_site_packages(venv, &py_path, output)?;
+    }
+
+    Ok(())
+}
+
+/// Byte-compiles everything in the site-packages of the virtualenv.
+fn compile_site_packages(venv: &Path, py_path: &Path, output: CommandOutput) -> Result<(), Error> {
+    echo!(if output, "Compiling bytecode");
+    let _heartbeat = Heartbeat::start("compiling bytecode");
+    let site_packages = get_site_packages_dir(venv)?;
+    let mut compile_cmd = internal_command(py_path);
+    compile_cmd
+        .arg("-m")
+        .arg("compileall")
+        .arg("-j")
+        .arg("0")
+        .arg(if output.child() == Verbosity::Verbose {
+            "-q"
+        } else {
+            "-qq"
+        })
+        .arg(&site_packages);
+    let status = run_interruptible(&mut compile_cmd).context("unable to run compileall")?;
+    if !status.success() {
+        warn!(
+            "some files in {} could not be byte-compiled",
+            site_packages.display()
+        );
+    }
+    Ok(())
+}
+
+/// Installs pip into the virtualenv from the wheel bundled with Python.
+fn ensure_venv_pip(venv: &Path, py_path: &Path, output: CommandOutput) -> Result<(), Error> {
+    let site_packages = get_site_packages_dir(venv)?;
+    if find_installed_dists(&site_packages)?
+        .iter()
+        .any(|x| x.name == "pip")
+    {
+        return Ok(());
+    }
+    echo!(if verbose output, "installing pip into the virtualenv");
+    let mut ensurepip_cmd = internal_command(py_path);
+    ensurepip_cmd
+        .arg("-m")
+        .arg("ensurepip")
+        .arg("--default-pip");
+    if output.child() != Verbosity::Verbose {
+        ensurepip_cmd.stdout(Stdio::null());
+    }
+    let status = run_interruptible(&mut ensurepip_cmd).context("unable to run ensurepip")?;
+    if !status.success() {
+        bail!("failed to install pip into {}", venv.display());
+    }
+    Ok(())
+}
+
+/// Checks if a virtualenv that was not created by rye uses the given Python.
+fn foreign_venv_matches(venv: &Path, py_ver: &PythonVersion) -> bool {
+    let Some(cfg) = read_pyvenv_cfg(venv) else {
+        return false;
+    };
+    if !get_venv_python_bin(venv).is_file() {
+        return false;
+    }
+    // virtualenv and uv write `version_info`, the venv module writes `version`
+    let version = match cfg.get("version_info").or_else(|| cfg.get("version")) {
+        Some(version) => version.split('.').take(3).collect::<Vec<_>>().join("."),
+        None => return false,
+    };
+    let implementation_matches = cfg
+        .get("implementation")
+        .map_or(true, |x| x.eq_ignore_ascii_case(&py_ver.name));
+    implementation_matches
+        && version == format!("{}.{}.{}", py_ver.major, py_ver.minor, py_ver.patch)
+}
+
+/// Asks what to do with a virtualenv that was not created by rye.
+///
+/// Returns `true` if the virtualenv should be adopted and `false` if it
+/// should be replaced.  Adopting is only offered if the interpreter matches.
+fn ask_adopt_foreign_venv(venv: &Path, py_ver: &PythonVersion) -> Result<bool, Error> {
+    if !console::user_attended_stderr() {
+        bail!("virtualenv is not managed by rye. Run `rye sync --force-replace` to replace it.");
+    }
+
+    if !foreign_venv_matches(venv, py_ver) {
+        if !dialoguer::Confirm::with_theme(tui_theme())
+            .with_prompt(format!(
+                "The virtualenv in {} was not created by rye and does not use {}. Replace it?",
+                venv.display(),
+                py_ver
+            ))
+            .interact()?
+        {
+            bail!("virtualenv is not managed by rye");
+        }
+        return Ok(false);
+    }
+
+    Ok(dialoguer::Select::with_theme(tui_theme())
+        .with_prompt(format!(
+            "The virtualenv in {} was not created by rye",
+            venv.display()
+        ))
+        .item("Adopt it (the interpreter matches)")
+        .item("Replace it")
+        .default(0)
+        .interact()?
+        == 0)
+}
+
 /// Performs an autosync.
 pub fn autosync(
     pyproject: &PyProject,
@@ -326,10 +1338,17 @@ pub fn autosync(
         dev: true,
         mode: SyncMode::Regular,
         force: false,
+        force_replace: false,
         no_lock: false,
         lock_options: LockOptions::default(),
         pyproject: Some(pyproject.toml_path().to_path_buf()),
         keyring_provider,
+        group: None,
+        compile_bytecode: None,
+        report: None,
+        offline_wheels: None,
+        no_install_project: false,
+        write_pin: false,
     })
 }
 
@@ -341,44 +1360,27 @@ pub fn create_virtualenv(
     prompt: &str,
 ) -> Result<(), Error> {
     let py_bin = get_toolchain_python_bin(py_ver)?;
+    // a half created virtualenv would be picked up by the next sync
+    let _guard = RemoveOnInterrupt::new(venv);
 
-    if Config::current().use_uv() {
-        // try to kill the empty venv if there is one as uv can't work otherwise.
-        fs::remove_dir(venv).ok();
-        let uv = UvBuilder::new()
-            .with_output(output.quieter())
-            .ensure_exists()?
-            .venv(venv, &py_bin, py_ver, Some(prompt))
-            .context("failed to initialize virtualenv")?;
-        uv.write_marker()?;
-        uv.sync_marker();
-    } else {
-        // create the venv folder first so we can manipulate some flags on it.
-        fs::create_dir_all(venv).path_context(venv, "unable to create virtualenv folder")?;
+    let backend = Config::current().venv_backend()?;
+    echo!(if verbose output, "creating virtualenv with {}", backend);
 
-        update_venv_sync_marker(output, venv);
-        let mut venv_cmd = Command::new(self_venv.join(VENV_BIN).join("virtualenv"));
-        if output == CommandOutput::Verbose {
-            venv_cmd.arg("--verbose");
-        } else {
-            venv_cmd.arg("-q");
-            venv_cmd.env("PYTHONWARNINGS", "ignore");
-        }
-        venv_cmd.arg("-p");
-        venv_cmd.arg(&py_bin);
-        venv_cmd.arg("--no-seed");
-        venv_cmd.arg("--prompt");
-        venv_cmd.arg(prompt);
-        venv_cmd.arg("--").arg(venv);
-        let status = venv_cmd
-            .status()
-            .context("unable to invoke virtualenv command")?;
-        if !status.success() {
-            bail!("failed to initialize virtualenv");
-        }
-
-        write_venv_marker(venv, py_ver)?;
-    };
+    // some backends refuse to use an existing folder, even if it's empty.
+    fs::remove_dir(venv).ok();
+    backend.creator(self_venv).create(
+        venv,
+        &VenvOptions {
+            output,
+            py_bin: &py_bin,
+            py_ver,
+            prompt,
+            // windows only links with elevated permissions
+            symlinks: !cfg!(windows),
+        },
+    )?;
+    write_venv_marker(venv, py_ver)?;
+    update_venv_sync_marker(output, venv);
 
     // On UNIX systems Python is unable to find the tcl config that is placed
     // outside of the virtualenv.  It also sometimes is entirely unable to find
@@ -465,3 +1467,123 @@ fn get_site_packages(lib_dir: PathBuf) -> Result<Option<PathBuf>, Error> {
     }
     Ok(None)
 }
+
+#[cfg(test)]
+mod test {
+    use super::{
+        compare_with_lock, diff_installed, test_env_slug, Change, InstalledDist, Mismatch,
+    };
+    use crate::lock::LockedPackage;
+
+    fn dist(name: &str, version: &str, tags: &[&str]) -> InstalledDist {
+        InstalledDist {
+            name: name.into(),
+            version: version.into(),
+            tags: tags.iter().map(|x| x.to_string()).collect(),
+            purelib: true,
+            direct_url: false,
+            archive_hash: None,
+            dist_info: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_diff_installed() {
+        let before = [
+            dist("flask", "3.0.2", &[]),
+            dist("jinja2", "3.1.4", &[]),
+            dist("six", "1.16.0", &[]),
+            dist("urllib3", "2.2.1", &[]),
+        ];
+        let after = [
+            dist("flask", "3.0.3", &[]),
+            dist("idna", "3.7", &[]),
+            dist("jinja2", "3.1.4", &[]),
+            dist("urllib3", "1.26.18", &[]),
+        ];
+        let changes = diff_installed(&before, &after)
+            .into_iter()
+            .map(|(change, dist, old)| (change, dist.name.as_str(), old))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            changes,
+            vec![
+                (Change::Upgraded, "flask", Some("3.0.2")),
+                (Change::Installed, "idna", None),
+                (Change::Downgraded, "urllib3", Some("2.2.1")),
+                (Change::Removed, "six", None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_compare_with_lock() {
+        let locked = |name: &str, version: &str| LockedPackage {
+            name: name.into(),
+            version: Some(version.into()),
+            ..Default::default()
+        };
+        let mut direct = dist("local", "1.0", &[]);
+        direct.direct_url = true;
+        direct.archive_hash = Some("sha256:abc".into());
+        let installed = [
+            dist("flask", "3.0.3", &[]),
+            direct,
+            dist("pip", "24.0", &[]),
+            dist("six", "1.16.0", &[]),
+            dist("urllib3", "2.2.1", &[]),
+        ];
+        let packages = [
+            locked("colorama", "0.4.6"),
+            LockedPackage {
+                marker: Some("sys_platform == 'win32'".into()),
+                ..locked("pywin32", "306")
+            },
+            locked("flask", "3.0.3"),
+            LockedPackage {
+                name: "local".into(),
+                url: Some("file:///${PROJECT_ROOT}/local-1.0.tar.gz".into()),
+                hashes: vec!["sha256:def".into()],
+                ..Default::default()
+            },
+            locked("urllib3", "1.26.18"),
+        ];
+        assert_eq!(
+            compare_with_lock(&packages, &installed, &["pip"]),
+            vec![
+                Mismatch::Missing("colorama".into(), Some("0.4.6".into())),
+                Mismatch::Hash("local".into(), "sha256:abc".into()),
+                Mismatch::Version("urllib3".into(), "1.26.18".into(), "2.2.1".into()),
+                Mismatch::Extra("six".into(), "1.16.0".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_wheel_filename() {
+        assert_eq!(
+            dist("typing-extensions", "4.12.2", &["py3-none-any"]).wheel_filename(),
+            Some("typing_extensions-4.12.2-py3-none-any.whl".into())
+        );
+        assert_eq!(
+            dist(
+                "numpy",
+                "2.0.0",
+                &[
+                    "cp312-cp312-manylinux_2_17_x86_64",
+                    "cp312-cp312-manylinux2014_x86_64"
+                ]
+            )
+            .wheel_filename(),
+            Some("numpy-2.0.0-cp312-cp312-manylinux_2_17_x86_64.manylinux2014_x86_64.whl".into())
+        );
+        assert_eq!(dist("local", "1.0", &[]).wheel_filename(), None);
+    }
+
+    #[test]
+    fn test_test_env_slug() {
+        assert_eq!(test_env_slug("3.11"), "3.11");
+        assert_eq!(test_env_slug("pypy@3.10"), "pypy-3.10");
+        assert_eq!(test_env_slug("../3.12"), "..-3.12");
+    }
+}
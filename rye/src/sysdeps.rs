@@ -0,0 +1,374 @@
+//! Hints for system libraries and tools needed to build source distributions.
+//!
+//! Builds of source distributions fail with compiler errors if headers or
+//! tools of the system are missing.  These are recognized from the build
+//! output and turned into install instructions for the package manager of
+//! the machine.
+use std::fs;
+use std::process::{Command, Stdio};
+
+use console::style;
+
+use crate::pyproject::DeclaredSystemDep;
+
+/// A package manager of the operating system.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PackageManager {
+    Apt,
+    Dnf,
+    Zypper,
+    Apk,
+    Pacman,
+    Brew,
+}
+
+impl PackageManager {
+    /// Detects the package manager of this machine.
+    pub fn detect() -> Option<PackageManager> {
+        if cfg!(target_os = "macos") {
+            return Some(PackageManager::Brew);
+        } else if !cfg!(target_os = "linux") {
+            return None;
+        }
+        let os_release = fs::read_to_string("/etc/os-release").ok()?;
+        Self::from_os_release(&os_release)
+    }
+
+    /// Picks the package manager from the `ID` and `ID_LIKE` of `/etc/os-release`.
+    fn from_os_release(os_release: &str) -> Option<PackageManager> {
+        let ids = os_release
+            .lines()
+            .filter_map(|line| {
+                line.strip_prefix("ID=")
+                    .or_else(|| line.strip_prefix("ID_LIKE="))
+            })
+            .flat_map(|value| value.trim_matches('"').split_whitespace())
+            .collect::<Vec<_>>();
+        ids.iter().find_map(|id| match *id {
+            "debian" | "ubuntu" => Some(PackageManager::Apt),
+            "fedora" | "rhel" | "centos" | "rocky" | "almalinux" | "amzn" => {
+                Some(PackageManager::Dnf)
+            }
+            "suse" | "opensuse" | "opensuse-leap" | "opensuse-tumbleweed" => {
+                Some(PackageManager::Zypper)
+            }
+            "alpine" => Some(PackageManager::Apk),
+            "arch" | "manjaro" => Some(PackageManager::Pacman),
+            _ => None,
+        })
+    }
+
+    fn install_command(self, package: &str) -> String {
+        match self {
+            PackageManager::Apt => format!("sudo apt install {}", package),
+            PackageManager::Dnf => format!("sudo dnf install {}", package),
+            PackageManager::Zypper => format!("sudo zypper install {}", package),
+            PackageManager::Apk => format!("sudo apk add {}", package),
+            PackageManager::Pacman => format!("sudo pacman -S {}", package),
+            PackageManager::Brew => format!("brew install {}", package),
+        }
+    }
+}
+
+/// How to find out if a system dependency is installed.
+#[derive(Debug, Copy, Clone)]
+enum Check {
+    /// Any of these commands is on `PATH`.
+    Command(&'static [&'static str]),
+    /// `pkg-config` knows the module.
+    PkgConfig(&'static str),
+}
+
+/// The packages that provide a dependency, empty if there is none.
+#[derive(Debug, Copy, Clone)]
+struct Packages {
+    apt: &'static str,
+    dnf: &'static str,
+    zypper: &'static str,
+    apk: &'static str,
+    pacman: &'static str,
+    brew: &'static str,
+}
+
+impl Packages {
+    fn get(&self, package_manager: PackageManager) -> Option<&'static str> {
+        Some(match package_manager {
+            PackageManager::Apt => self.apt,
+            PackageManager::Dnf => self.dnf,
+            PackageManager::Zypper => self.zypper,
+            PackageManager::Apk => self.apk,
+            PackageManager::Pacman => self.pacman,
+            PackageManager::Brew => self.brew,
+        })
+        .filter(|x| !x.is_empty())
+    }
+}
+
+/// A system dependency rye knows about.
+#[derive(Debug)]
+pub struct KnownSystemDep {
+    pub name: &'static str,
+    pub description: &'static str,
+    /// Fragments of the build output when the dependency is missing.
+    patterns: &'static [&'static str],
+    check: Check,
+    packages: Packages,
+    /// Replaces the package manager instructions.
+    hint: Option<&'static str>,
+}
+
+const KNOWN_SYSTEM_DEPS: &[KnownSystemDep] = &[
+    KnownSystemDep {
+        name: "compiler",
+        description: "a C compiler",
+        patterns: &[
+            "command 'gcc' failed",
+            "command 'cc' failed",
+            "unable to execute 'gcc'",
+            "gcc: not found",
+            "error: no acceptable C compiler found",
+            "Microsoft Visual C++ 14.0 or greater is required",
+        ],
+        check: Check::Command(&["cc", "gcc", "clang", "cl"]),
+        packages: Packages {
+            apt: "build-essential",
+            dnf: "gcc",
+            zypper: "gcc",
+            apk: "build-base",
+            pacman: "base-devel",
+            brew: "",
+        },
+        hint: None,
+    },
+    KnownSystemDep {
+        name: "rust",
+        description: "the Rust toolchain",
+        patterns: &[
+            "can't find Rust compiler",
+            "Cargo, the Rust package manager, is not installed",
+            "error: cargo not found",
+        ],
+        check: Check::Command(&["cargo"]),
+        packages: Packages {
+            apt: "",
+            dnf: "",
+            zypper: "",
+            apk: "",
+            pacman: "",
+            brew: "",
+        },
+        hint: Some("install Rust with rustup: https://rustup.rs/"),
+    },
+    KnownSystemDep {
+        name: "libpq",
+        description: "the PostgreSQL client library (libpq)",
+        patterns: &["pg_config executable not found", "libpq-fe.h"],
+        check: Check::Command(&["pg_config"]),
+        packages: Packages {
+            apt: "libpq-dev",
+            dnf: "libpq-devel",
+            zypper: "postgresql-devel",
+            apk: "postgresql-dev",
+            pacman: "postgresql-libs",
+            brew: "libpq",
+        },
+        hint: None,
+    },
+    KnownSystemDep {
+        name: "openssl",
+        description: "the OpenSSL headers",
+        patterns: &[
+            "openssl/ssl.h",
+            "openssl/opensslv.h",
+            "openssl/err.h",
+            "Could not find directory of OpenSSL installation",
+        ],
+        check: Check::PkgConfig("openssl"),
+        packages: Packages {
+            apt: "libssl-dev",
+            dnf: "openssl-devel",
+            zypper: "libopenssl-devel",
+            apk: "openssl-dev",
+            pacman: "openssl",
+            brew: "openssl",
+        },
+        hint: None,
+    },
+    KnownSystemDep {
+        name: "libffi",
+        description: "the libffi headers",
+        patterns: &["ffi.h: No such file", "'ffi.h' file not found"],
+        check: Check::PkgConfig("libffi"),
+        packages: Packages {
+            apt: "libffi-dev",
+            dnf: "libffi-devel",
+            zypper: "libffi-devel",
+            apk: "libffi-dev",
+            pacman: "libffi",
+            brew: "libffi",
+        },
+        hint: None,
+    },
+    KnownSystemDep {
+        name: "mysql",
+        description: "the MySQL client library",
+        patterns: &["mysql_config not found", "mysql_config: not found"],
+        check: Check::Command(&["mysql_config", "mariadb_config"]),
+        packages: Packages {
+            apt: "default-libmysqlclient-dev",
+            dnf: "mysql-devel",
+            zypper: "libmariadb-devel",
+            apk: "mariadb-dev",
+            pacman: "mariadb-libs",
+            brew: "mysql-client",
+        },
+        hint: None,
+    },
+    KnownSystemDep {
+        name: "libxml2",
+        description: "the libxml2 and libxslt headers",
+        patterns: &["libxml/xmlversion.h", "xml2-config", "libxslt/xsltconfig.h"],
+        check: Check::PkgConfig("libxml-2.0"),
+        packages: Packages {
+            apt: "libxml2-dev libxslt1-dev",
+            dnf: "libxml2-devel libxslt-devel",
+            zypper: "libxml2-devel libxslt-devel",
+            apk: "libxml2-dev libxslt-dev",
+            pacman: "libxml2 libxslt",
+            brew: "libxml2 libxslt",
+        },
+        hint: None,
+    },
+];
+
+impl KnownSystemDep {
+    /// Looks up a system dependency by name.
+    pub fn find(name: &str) -> Option<&'static KnownSystemDep> {
+        KNOWN_SYSTEM_DEPS.iter().find(|x| x.name == name)
+    }
+
+    /// Returns how to install the dependency on this machine.
+    pub fn install_hint(&self) -> String {
+        if let Some(hint) = self.hint {
+            return hint.to_string();
+        }
+        match PackageManager::detect()
+            .and_then(|pm| Some(pm.install_command(self.packages.get(pm)?)))
+        {
+            Some(command) => format!("install it with `{}`", command),
+            None if cfg!(target_os = "macos") && self.name == "compiler" => {
+                "install it with `xcode-select --install`".into()
+            }
+            None if cfg!(windows) && self.name == "compiler" => {
+                "install the Microsoft C++ Build Tools".into()
+            }
+            None => "install it with the package manager of your system".into(),
+        }
+    }
+
+    /// Checks if the dependency is installed.
+    ///
+    /// Returns `None` if this cannot be checked on this machine.
+    pub fn is_installed(&self) -> Option<bool> {
+        match self.check {
+            Check::Command(commands) => Some(commands.iter().any(|x| which::which(x).is_ok())),
+            Check::PkgConfig(module) => is_pkg_config_module_installed(module),
+        }
+    }
+}
+
+/// Finds the system dependencies that a failed build was missing.
+pub fn find_missing_in_build_output(output: &str) -> Vec<&'static KnownSystemDep> {
+    KNOWN_SYSTEM_DEPS
+        .iter()
+        .filter(|dep| dep.patterns.iter().any(|x| output.contains(x)))
+        .collect()
+}
+
+/// Prints install instructions for system dependencies a build was missing.
+pub fn print_build_hints(output: &str) {
+    for dep in find_missing_in_build_output(output) {
+        warn!(
+            "building a dependency failed because {} is missing, {}",
+            style(dep.description).cyan(),
+            dep.install_hint()
+        );
+    }
+}
+
+/// Checks a system dependency declared by a project.
+///
+/// Returns `Err` with a description of the problem if it is missing.
+pub fn check_declared(dep: &DeclaredSystemDep) -> Result<(), String> {
+    let (installed, hint) = if let Some(ref command) = dep.command {
+        (Some(which::which(command).is_ok()), None)
+    } else if let Some(ref module) = dep.pkg_config {
+        let installed = is_pkg_config_module_installed(module);
+        if installed.is_none() {
+            warn!(
+                "cannot check system dependency {}, pkg-config is not installed",
+                dep.name
+            );
+        }
+        (installed, None)
+    } else if let Some(known) = KnownSystemDep::find(&dep.name) {
+        (known.is_installed(), Some(known.install_hint()))
+    } else {
+        return Err(format!(
+            "unknown system dependency '{}', declare a `command` or `pkg-config` to check for",
+            dep.name
+        ));
+    };
+    match installed {
+        Some(true) | None => Ok(()),
+        Some(false) => Err(format!(
+            "system dependency {} is missing, {}",
+            dep.name,
+            dep.hint
+                .clone()
+                .or(hint)
+                .unwrap_or_else(|| "install it before syncing".into())
+        )),
+    }
+}
+
+fn is_pkg_config_module_installed(module: &str) -> Option<bool> {
+    Command::new("pkg-config")
+        .arg("--exists")
+        .arg(module)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .ok()
+        .map(|status| status.success())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{find_missing_in_build_output, PackageManager};
+
+    #[test]
+    fn test_package_manager_from_os_release() {
+        let ubuntu = "NAME=\"Ubuntu\"\nID=ubuntu\nID_LIKE=debian\n";
+        assert_eq!(
+            PackageManager::from_os_release(ubuntu),
+            Some(PackageManager::Apt)
+        );
+        let rocky = "ID=\"rocky\"\nID_LIKE=\"rhel centos fedora\"\n";
+        assert_eq!(
+            PackageManager::from_os_release(rocky),
+            Some(PackageManager::Dnf)
+        );
+        assert_eq!(PackageManager::from_os_release("ID=nixos\n"), None);
+    }
+
+    #[test]
+    fn test_find_missing_in_build_output() {
+        let output = "      Error: pg_config executable not found.\n\
+                      \n      pg_config is required to build psycopg2 from source.";
+        let missing = find_missing_in_build_output(output);
+        assert_eq!(missing.len(), 1);
+        assert_eq!(missing[0].name, "libpq");
+        assert!(find_missing_in_build_output("error: resolution failed").is_empty());
+    }
+}
@@ -1,7 +1,15 @@
-use std::fmt;
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::{env, fmt};
 
 static ECHO_TO_STDERR: AtomicBool = AtomicBool::new(false);
+static CI_MODE: AtomicBool = AtomicBool::new(false);
+static ASCII_OUTPUT: AtomicBool = AtomicBool::new(false);
+
+/// How often progress lines are printed in CI mode.
+pub const CI_PROGRESS_INTERVAL: Duration = Duration::from_secs(10);
 
 #[doc(hidden)]
 pub fn _print(args: fmt::Arguments) {
@@ -29,25 +37,120 @@ impl Drop for RedirectGuard {
     }
 }
 
+/// Applies the color and character set preferences of the environment.
+///
+/// `NO_COLOR` turns colors off and `FORCE_COLOR` turns them on even if the
+/// output is not a terminal.  Terminals that cannot show UTF-8 get ASCII
+/// progress bars and prompt symbols.
+pub fn init_output() {
+    if let Some(enabled) = color_preference(
+        env::var("NO_COLOR").ok().as_deref(),
+        env::var("FORCE_COLOR").ok().as_deref(),
+    ) {
+        console::set_colors_enabled(enabled);
+        console::set_colors_enabled_stderr(enabled);
+    }
+    ASCII_OUTPUT.store(!is_unicode_terminal(), Ordering::Relaxed);
+}
+
+/// Returns whether colors were explicitly turned on or off.
+fn color_preference(no_color: Option<&str>, force_color: Option<&str>) -> Option<bool> {
+    if no_color.map_or(false, |x| !x.is_empty()) {
+        Some(false)
+    } else if force_color.map_or(false, |x| !x.is_empty() && x != "0" && x != "false") {
+        Some(true)
+    } else {
+        None
+    }
+}
+
+#[cfg(unix)]
+fn is_unicode_terminal() -> bool {
+    // the first locale variable that is set wins; without one we assume a
+    // modern terminal
+    ["LC_ALL", "LC_CTYPE", "LANG"]
+        .iter()
+        .filter_map(|key| env::var(key).ok())
+        .find(|x| !x.is_empty())
+        .map_or(true, |locale| is_unicode_locale(&locale))
+}
+
+#[cfg(windows)]
+fn is_unicode_terminal() -> bool {
+    console::Term::stderr().features().wants_emoji()
+}
+
+#[cfg(unix)]
+fn is_unicode_locale(locale: &str) -> bool {
+    let locale = locale.to_ascii_lowercase();
+    locale.contains("utf-8") || locale.contains("utf8")
+}
+
+/// Is the output restricted to ASCII?
+pub fn is_ascii_output() -> bool {
+    ASCII_OUTPUT.load(Ordering::Relaxed)
+}
+
+/// Returns the style of download progress bars.
+pub fn progress_style() -> indicatif::ProgressStyle {
+    let style =
+        indicatif::ProgressStyle::with_template("{wide_bar} {bytes:>7}/{total_bytes:7}").unwrap();
+    if is_ascii_output() {
+        style.progress_chars("=> ")
+    } else {
+        style
+    }
+}
+
+/// Checks if the `CI` environment variable indicates a CI system.
+pub fn is_ci_env() -> bool {
+    env::var("CI").map_or(false, |x| {
+        !x.is_empty() && x != "0" && !x.eq_ignore_ascii_case("false")
+    })
+}
+
+/// Enables the CI output preset.
+///
+/// This turns off colors and progress bars in favor of periodic single line
+/// progress updates with timestamps.  Internal commands inherit this through
+/// [`internal_command`](crate::utils::internal_command), user programs do not.
+pub fn enable_ci_mode() {
+    CI_MODE.store(true, Ordering::Relaxed);
+    console::set_colors_enabled(false);
+    console::set_colors_enabled_stderr(false);
+}
+
+/// Is the CI output preset enabled?
+pub fn is_ci_mode() -> bool {
+    CI_MODE.load(Ordering::Relaxed)
+}
+
+/// Returns the current UTC time of day for progress lines.
+pub fn ci_timestamp() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |x| x.as_secs());
+    format!(
+        "{:02}:{:02}:{:02}",
+        secs / 3600 % 24,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
 /// Echo a line to the output stream (usually stdout).
 macro_rules! echo {
     () => {
         $crate::tui::_print(format_args!(""))
     };
     (if verbose $out:expr, $($arg:tt)+) => {
-        match $out {
-            $crate::utils::CommandOutput::Verbose => {
-                $crate::tui::_print(format_args!($($arg)*))
-            }
-            _ => {}
+        if $out.is_verbose() {
+            $crate::tui::_print(format_args!($($arg)*))
         }
     };
     (if $out:expr, $($arg:tt)+) => {
-        match $out {
-            $crate::utils::CommandOutput::Normal | $crate::utils::CommandOutput::Verbose => {
-                $crate::tui::_print(format_args!($($arg)*))
-            }
-            _ => {}
+        if !$out.is_quiet() {
+            $crate::tui::_print(format_args!($($arg)*))
         }
     };
     ($($arg:tt)+) => {
@@ -67,7 +170,7 @@ macro_rules! warn {
     ($($arg:tt)+) => {
         elog!(
             "{} {}",
-            console::style("warning:").yellow().bold(),
+            console::style("warning:").for_stderr().yellow().bold(),
             format_args!($($arg)*)
         )
     }
@@ -78,8 +181,81 @@ macro_rules! error {
     ($($arg:tt)+) => {
         elog!(
             "{} {}",
-            console::style("error:").red().bold(),
+            console::style("error:").for_stderr().red().bold(),
             format_args!($($arg)*)
         )
     }
 }
+
+/// Periodically reports that a long running operation is still active.
+///
+/// This only prints in CI mode so that CI logs do not fall silent.  The
+/// reporting stops when the heartbeat is dropped.
+pub struct Heartbeat {
+    stop: Option<mpsc::Sender<()>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Heartbeat {
+    pub fn start(label: &str) -> Heartbeat {
+        if !is_ci_mode() {
+            return Heartbeat {
+                stop: None,
+                handle: None,
+            };
+        }
+        let label = label.to_string();
+        let started = Instant::now();
+        let (stop, stopped) = mpsc::channel::<()>();
+        let handle = thread::spawn(move || {
+            while let Err(mpsc::RecvTimeoutError::Timeout) =
+                stopped.recv_timeout(CI_PROGRESS_INTERVAL)
+            {
+                elog!(
+                    "[{}] {} ({}s elapsed)",
+                    ci_timestamp(),
+                    label,
+                    started.elapsed().as_secs()
+                );
+            }
+        });
+        Heartbeat {
+            stop: Some(stop),
+            handle: Some(handle),
+        }
+    }
+}
+
+impl Drop for Heartbeat {
+    fn drop(&mut self) {
+        // dropping the sender wakes up the thread
+        self.stop.take();
+        if let Some(handle) = self.handle.take() {
+            handle.join().ok();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::color_preference;
+
+    #[test]
+    fn test_color_preference() {
+        assert_eq!(color_preference(None, None), None);
+        assert_eq!(color_preference(Some(""), None), None);
+        assert_eq!(color_preference(Some("1"), Some("1")), Some(false));
+        assert_eq!(color_preference(None, Some("1")), Some(true));
+        assert_eq!(color_preference(None, Some("0")), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_is_unicode_locale() {
+        use super::is_unicode_locale;
+        assert!(is_unicode_locale("en_US.UTF-8"));
+        assert!(is_unicode_locale("de_DE.utf8"));
+        assert!(!is_unicode_locale("C"));
+        assert!(!is_unicode_locale("en_US.ISO-8859-1"));
+    }
+}
@@ -0,0 +1,154 @@
+//! Crash safe writes of the files rye mutates.
+//!
+//! Files are written to a temporary file in the same folder, flushed to disk
+//! and renamed over the target.  A crash or ctrl-c in the middle of a write
+//! thus leaves either the old or the new file behind, never a truncated one.
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use tempfile::Builder;
+
+use crate::utils::is_tracked_by_git;
+
+/// Atomically replaces the contents of a file.
+///
+/// The permissions of an existing file are kept, new files get the usual
+/// permissions of the umask.  Symlinks are followed so that the file they
+/// point to is replaced rather than the link.
+pub fn write_atomic<P: AsRef<Path>, C: AsRef<[u8]>>(path: P, contents: C) -> io::Result<()> {
+    let path = fs::canonicalize(path.as_ref()).unwrap_or_else(|_| path.as_ref().to_path_buf());
+    let path = path.as_path();
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    // temporary files are private by default, the umask applies on top
+    #[cfg(unix)]
+    let tmp = {
+        use std::os::unix::fs::PermissionsExt;
+        Builder::new()
+            .permissions(fs::Permissions::from_mode(0o666))
+            .tempfile_in(dir)
+    };
+    #[cfg(not(unix))]
+    let tmp = Builder::new().tempfile_in(dir);
+    let mut tmp = tmp?;
+    tmp.write_all(contents.as_ref())?;
+    if let Ok(metadata) = fs::metadata(path) {
+        fs::set_permissions(tmp.path(), metadata.permissions())?;
+    }
+    tmp.as_file().sync_all()?;
+    tmp.persist(path).map_err(|err| err.error)?;
+    // make the rename itself durable.  Folders cannot be opened on windows.
+    #[cfg(unix)]
+    {
+        if let Ok(dir) = fs::File::open(dir) {
+            dir.sync_all().ok();
+        }
+    }
+    Ok(())
+}
+
+/// Like [`write_atomic`] but keeps the previous contents in a `.bak` file.
+///
+/// Only one backup is kept and it is only replaced if the contents change,
+/// so that writing the same contents twice does not lose the backup.  Files
+/// tracked by git get no backup as git already has the previous contents.
+pub fn write_atomic_with_backup<P: AsRef<Path>, C: AsRef<[u8]>>(
+    path: P,
+    contents: C,
+) -> io::Result<()> {
+    let path = path.as_ref();
+    match fs::read(path) {
+        Ok(old) if old == contents.as_ref() => return Ok(()),
+        Ok(_) if is_tracked_by_git(path) => {}
+        Ok(old) => write_atomic(get_backup_path(path), old)?,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+        Err(err) => return Err(err),
+    }
+    write_atomic(path, contents)
+}
+
+/// Returns the path of the backup of a file (`pyproject.toml.bak`).
+pub fn get_backup_path(path: &Path) -> PathBuf {
+    let mut rv = OsString::from(path.as_os_str());
+    rv.push(".bak");
+    PathBuf::from(rv)
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use super::{get_backup_path, write_atomic, write_atomic_with_backup};
+
+    #[test]
+    fn test_write_atomic_with_backup() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("pyproject.toml");
+        let backup = get_backup_path(&path);
+        assert_eq!(backup, dir.path().join("pyproject.toml.bak"));
+
+        write_atomic_with_backup(&path, "a = 1\n").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "a = 1\n");
+        assert!(!backup.exists());
+
+        write_atomic_with_backup(&path, "a = 2\n").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "a = 2\n");
+        assert_eq!(fs::read_to_string(&backup).unwrap(), "a = 1\n");
+
+        // unchanged contents keep the backup
+        write_atomic_with_backup(&path, "a = 2\n").unwrap();
+        assert_eq!(fs::read_to_string(&backup).unwrap(), "a = 1\n");
+
+        // no temporary files are left behind
+        write_atomic(&path, "a = 3\n").unwrap();
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_atomic_keeps_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("hook");
+        fs::write(&path, "old").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        write_atomic(&path, "new").unwrap();
+        assert_eq!(
+            fs::metadata(&path).unwrap().permissions().mode() & 0o777,
+            0o755
+        );
+
+        // new files get the same permissions as with a regular write
+        let reference = dir.path().join("reference");
+        fs::write(&reference, "").unwrap();
+        let path = dir.path().join("config.toml");
+        write_atomic(&path, "new").unwrap();
+        assert_eq!(
+            fs::metadata(&path).unwrap().permissions().mode() & 0o777,
+            fs::metadata(&reference).unwrap().permissions().mode() & 0o777
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_atomic_follows_symlinks() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("dotfiles").join("profile");
+        fs::create_dir(target.parent().unwrap()).unwrap();
+        fs::write(&target, "old").unwrap();
+        let link = dir.path().join(".profile");
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        write_atomic(&link, "new").unwrap();
+        assert!(fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(fs::read_to_string(&target).unwrap(), "new");
+    }
+}
@@ -0,0 +1,354 @@
+//! Keeps the app dir and projects consistent when rye is interrupted.
+//!
+//! When ctrl-c is pressed while no subprocess runs, the signal handler
+//! removes all registered paths and exits right away.  While a subprocess
+//! runs, the handler only records the interruption: the waiting thread then
+//! kills the child and returns [`Interrupted`] so that the regular error path
+//! unwinds and the guards clean up after themselves.
+//!
+//! Processes that are killed outright still leave temporary files behind.
+//! These are collected later by [`collect_orphaned_temp_files`].
+use std::fmt;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use anyhow::Error;
+use walkdir::WalkDir;
+
+use crate::platform::get_app_dir;
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+static RUNNING_CHILDREN: AtomicUsize = AtomicUsize::new(0);
+static NEXT_GUARD_ID: AtomicUsize = AtomicUsize::new(0);
+static PATHS: Mutex<Vec<(usize, PathBuf)>> = Mutex::new(Vec::new());
+
+/// How often a waiting thread checks for an interruption.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Temporary files older than this are collected automatically.
+const ORPHAN_MIN_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// How often orphaned temporary files are collected automatically.
+const ORPHAN_GC_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Returned when an operation was cancelled with ctrl-c.
+#[derive(Debug)]
+pub struct Interrupted;
+
+impl std::error::Error for Interrupted {}
+
+impl fmt::Display for Interrupted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "interrupted")
+    }
+}
+
+/// Checks if an error was caused by an interruption.
+pub fn is_interrupted_error(err: &Error) -> bool {
+    err.chain().any(|x| x.is::<Interrupted>())
+}
+
+/// Called from the ctrl-c handler.
+///
+/// Returns `true` if the process should exit right away.  This is the case
+/// if no subprocess is waited on or if the user pressed ctrl-c twice.
+pub fn handle_interrupt() -> bool {
+    let again = INTERRUPTED.swap(true, Ordering::SeqCst);
+    if !again && RUNNING_CHILDREN.load(Ordering::SeqCst) > 0 {
+        return false;
+    }
+    remove_registered_paths();
+    true
+}
+
+fn remove_registered_paths() {
+    // the lock can be poisoned or held by the interrupted thread, in which
+    // case there is nothing sensible left to do.
+    if let Ok(paths) = PATHS.try_lock() {
+        for (_, path) in paths.iter() {
+            remove_path(path);
+        }
+    }
+}
+
+fn remove_path(path: &Path) {
+    if path.is_dir() {
+        fs::remove_dir_all(path).ok();
+    } else {
+        fs::remove_file(path).ok();
+    }
+}
+
+/// Removes a path if rye is interrupted while the guard is alive.
+///
+/// Dropping the guard after a normal run leaves the path alone.
+#[must_use]
+pub struct RemoveOnInterrupt {
+    id: usize,
+    path: PathBuf,
+}
+
+impl RemoveOnInterrupt {
+    pub fn new<P: AsRef<Path>>(path: P) -> RemoveOnInterrupt {
+        let id = NEXT_GUARD_ID.fetch_add(1, Ordering::SeqCst);
+        let path = path.as_ref().to_path_buf();
+        PATHS.lock().unwrap().push((id, path.clone()));
+        RemoveOnInterrupt { id, path }
+    }
+}
+
+impl Drop for RemoveOnInterrupt {
+    fn drop(&mut self) {
+        if let Ok(mut paths) = PATHS.lock() {
+            paths.retain(|(id, _)| *id != self.id);
+        }
+        if INTERRUPTED.load(Ordering::SeqCst) {
+            remove_path(&self.path);
+        }
+    }
+}
+
+/// Runs a command to completion like [`Command::status`].
+///
+/// If ctrl-c is pressed in the meantime the child is killed and
+/// [`Interrupted`] is returned.
+pub fn run_interruptible(cmd: &mut Command) -> Result<ExitStatus, Error> {
+    wait_interruptible(cmd.spawn()?)
+}
+
+/// Like [`run_interruptible`] but also returns what the child wrote to stderr
+/// if it failed.
+///
+/// When stderr is a terminal the child writes to it directly so that its
+/// progress bars keep working, and the command is run a second time without
+/// output to capture it after a failure.  This is only suitable for commands
+/// that can be repeated, like installs.  Otherwise the output is passed
+/// through to stderr as it comes in.
+pub fn run_interruptible_capture_stderr(cmd: &mut Command) -> Result<(ExitStatus, String), Error> {
+    if !console::user_attended_stderr() {
+        return capture_stderr(cmd, true);
+    }
+    let status = run_interruptible(cmd)?;
+    if status.success() {
+        return Ok((status, String::new()));
+    }
+    let (_, log) = capture_stderr(cmd.stdout(Stdio::null()), false)?;
+    Ok((status, log))
+}
+
+fn capture_stderr(cmd: &mut Command, passthrough: bool) -> Result<(ExitStatus, String), Error> {
+    let mut child = cmd.stderr(Stdio::piped()).spawn()?;
+    let mut child_stderr = child.stderr.take().expect("stderr is piped");
+    let reader = thread::spawn(move || {
+        let mut captured = Vec::new();
+        let mut buf = [0; 4096];
+        while let Ok(n) = child_stderr.read(&mut buf) {
+            if n == 0 {
+                break;
+            }
+            if passthrough {
+                io::stderr().write_all(&buf[..n]).ok();
+            }
+            captured.extend_from_slice(&buf[..n]);
+        }
+        String::from_utf8_lossy(&captured).into_owned()
+    });
+    let status = wait_interruptible(child)?;
+    Ok((status, reader.join().unwrap_or_default()))
+}
+
+fn wait_interruptible(mut child: Child) -> Result<ExitStatus, Error> {
+    RUNNING_CHILDREN.fetch_add(1, Ordering::SeqCst);
+    let rv = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break Ok(status),
+            Ok(None) => {}
+            Err(err) => break Err(err.into()),
+        }
+        if INTERRUPTED.load(Ordering::SeqCst) {
+            child.kill().ok();
+            child.wait().ok();
+            break Err(Interrupted.into());
+        }
+        thread::sleep(POLL_INTERVAL);
+    };
+    RUNNING_CHILDREN.fetch_sub(1, Ordering::SeqCst);
+    // the child might have exited because it got the signal too
+    if rv.is_ok() && INTERRUPTED.load(Ordering::SeqCst) {
+        return Err(Interrupted.into());
+    }
+    rv
+}
+
+/// Finds the temporary files and folders left behind in the app dir.
+///
+/// Temporary files are created with a `.tmp` prefix next to their
+/// destination (atomic writes, unpacked toolchains), so only the folders
+/// rye writes into are looked at.  Virtualenvs are not descended into.
+pub fn find_orphaned_temp_files(app_dir: &Path, min_age: Duration) -> Vec<PathBuf> {
+    let now = SystemTime::now();
+    let mut rv = Vec::new();
+    let folders = [
+        (app_dir.to_path_buf(), 1),
+        (app_dir.join("py"), 1),
+        (app_dir.join("tools"), 1),
+        (app_dir.join("shims"), 1),
+        (app_dir.join("cache"), usize::MAX),
+    ];
+    for (folder, max_depth) in folders {
+        let mut it = WalkDir::new(folder)
+            .min_depth(1)
+            .max_depth(max_depth)
+            .into_iter();
+        loop {
+            let entry = match it.next() {
+                Some(Ok(entry)) => entry,
+                Some(Err(_)) => continue,
+                None => break,
+            };
+            if !entry
+                .file_name()
+                .to_str()
+                .map_or(false, |x| x.starts_with(".tmp"))
+            {
+                continue;
+            }
+            if entry.file_type().is_dir() {
+                it.skip_current_dir();
+            }
+            let age = entry
+                .metadata()
+                .ok()
+                .and_then(|x| x.modified().ok())
+                .and_then(|x| now.duration_since(x).ok());
+            if age.map_or(false, |age| age >= min_age) {
+                rv.push(entry.into_path());
+            }
+        }
+    }
+    rv
+}
+
+/// Removes orphaned temporary files from the app dir.
+///
+/// Returns the number of removed entries and the bytes freed.
+pub fn remove_orphaned_temp_files(app_dir: &Path, min_age: Duration) -> (usize, u64) {
+    let mut count = 0;
+    let mut size = 0;
+    for path in find_orphaned_temp_files(app_dir, min_age) {
+        let entry_size = WalkDir::new(&path)
+            .into_iter()
+            .filter_map(|x| x.ok())
+            .filter_map(|x| x.metadata().ok())
+            .filter(|x| x.is_file())
+            .map(|x| x.len())
+            .sum::<u64>();
+        let removed = if path.is_dir() {
+            fs::remove_dir_all(&path)
+        } else {
+            fs::remove_file(&path)
+        };
+        if removed.is_ok() {
+            count += 1;
+            size += entry_size;
+        }
+    }
+    (count, size)
+}
+
+/// Collects orphaned temporary files at most once a day.
+///
+/// This runs on startup and never fails.
+pub fn collect_orphaned_temp_files() {
+    let app_dir = get_app_dir();
+    let stamp = app_dir.join("cache").join("last-temp-gc");
+    let is_due = fs::metadata(&stamp)
+        .and_then(|x| x.modified())
+        .ok()
+        .and_then(|x| SystemTime::now().duration_since(x).ok())
+        .map_or(true, |age| age >= ORPHAN_GC_INTERVAL);
+    if !is_due || !app_dir.is_dir() {
+        return;
+    }
+    if let Some(parent) = stamp.parent() {
+        fs::create_dir_all(parent).ok();
+    }
+    if fs::write(&stamp, b"").is_ok() {
+        remove_orphaned_temp_files(app_dir, ORPHAN_MIN_AGE);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+    use std::time::Duration;
+
+    use anyhow::Context;
+
+    use super::{
+        find_orphaned_temp_files, is_interrupted_error, remove_orphaned_temp_files, Interrupted,
+    };
+
+    #[test]
+    fn test_is_interrupted_error() {
+        let err = Err::<(), _>(anyhow::Error::new(Interrupted))
+            .context("unable to run pip-sync")
+            .unwrap_err();
+        assert!(is_interrupted_error(&err));
+        assert!(!is_interrupted_error(&anyhow::anyhow!("failed")));
+    }
+
+    #[test]
+    fn test_remove_orphaned_temp_files() {
+        let app_dir = tempfile::tempdir().unwrap();
+        let app_dir = app_dir.path();
+        fs::create_dir_all(app_dir.join("py").join(".tmpAbC").join("install")).unwrap();
+        fs::write(
+            app_dir.join("py").join(".tmpAbC").join("install").join("x"),
+            "12",
+        )
+        .unwrap();
+        fs::create_dir_all(app_dir.join("cache").join("metadata").join("abc")).unwrap();
+        fs::write(
+            app_dir
+                .join("cache")
+                .join("metadata")
+                .join("abc")
+                .join(".tmp123"),
+            "",
+        )
+        .unwrap();
+        fs::write(app_dir.join(".tmpXyZ"), "1").unwrap();
+        fs::write(app_dir.join("config.toml"), "").unwrap();
+        // temporary files in virtualenvs are none of our business
+        fs::create_dir_all(app_dir.join("self").join("lib")).unwrap();
+        fs::write(app_dir.join("self").join("lib").join(".tmp1"), "").unwrap();
+
+        let mut found = find_orphaned_temp_files(app_dir, Duration::ZERO);
+        found.sort();
+        assert_eq!(
+            found,
+            [
+                app_dir.join(".tmpXyZ"),
+                app_dir
+                    .join("cache")
+                    .join("metadata")
+                    .join("abc")
+                    .join(".tmp123"),
+                app_dir.join("py").join(".tmpAbC"),
+            ]
+        );
+        assert!(find_orphaned_temp_files(app_dir, Duration::from_secs(3600)).is_empty());
+
+        assert_eq!(remove_orphaned_temp_files(app_dir, Duration::ZERO), (3, 3));
+        assert!(!app_dir.join("py").join(".tmpAbC").exists());
+        assert!(app_dir.join("config.toml").exists());
+        assert!(app_dir.join("self").join("lib").join(".tmp1").exists());
+    }
+}
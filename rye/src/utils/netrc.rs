@@ -0,0 +1,105 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+/// Returns the location of the netrc file.
+///
+/// This honors the `NETRC` environment variable like curl and pip do.
+pub fn get_netrc_path() -> Option<PathBuf> {
+    if let Some(path) = env::var_os("NETRC") {
+        return Some(PathBuf::from(path));
+    }
+    let home = home::home_dir()?;
+    #[cfg(windows)]
+    {
+        let path = home.join("_netrc");
+        if path.is_file() {
+            return Some(path);
+        }
+    }
+    Some(home.join(".netrc"))
+}
+
+/// Looks up the login and password for a host in the user's netrc file.
+pub fn lookup_netrc_credentials(host: &str) -> Option<(String, String)> {
+    let contents = fs::read_to_string(get_netrc_path()?).ok()?;
+    find_credentials(&contents, host)
+}
+
+/// Finds the credentials for a host in the contents of a netrc file.
+///
+/// An entry for the host wins over the `default` entry.
+fn find_credentials(contents: &str, host: &str) -> Option<(String, String)> {
+    let mut tokens = contents
+        .lines()
+        .filter(|line| !line.trim_start().starts_with('#'))
+        .flat_map(|line| line.split_whitespace());
+
+    // (machine, login, password) where a missing machine is the default entry
+    let mut entries: Vec<(Option<&str>, Option<&str>, Option<&str>)> = Vec::new();
+    while let Some(token) = tokens.next() {
+        match token {
+            "machine" => entries.push((Some(tokens.next()?), None, None)),
+            "default" => entries.push((None, None, None)),
+            "login" => {
+                let login = tokens.next();
+                if let Some(entry) = entries.last_mut() {
+                    entry.1 = login;
+                }
+            }
+            "password" => {
+                let password = tokens.next();
+                if let Some(entry) = entries.last_mut() {
+                    entry.2 = password;
+                }
+            }
+            "account" => {
+                tokens.next();
+            }
+            _ => {}
+        }
+    }
+
+    let find = |machine: Option<&str>| {
+        entries
+            .iter()
+            .find_map(|(m, login, password)| match password {
+                Some(password) if *m == machine => {
+                    Some((login.unwrap_or("").to_string(), password.to_string()))
+                }
+                _ => None,
+            })
+    };
+    find(Some(host)).or_else(|| find(None))
+}
+
+#[cfg(test)]
+mod test {
+    use super::find_credentials;
+
+    #[test]
+    fn test_find_credentials() {
+        let contents = "\
+# company index
+machine company.internal
+  login john
+  password s3cret
+
+machine other.internal login jane password hunter2
+default login anonymous password guest
+";
+        assert_eq!(
+            find_credentials(contents, "company.internal"),
+            Some(("john".into(), "s3cret".into()))
+        );
+        assert_eq!(
+            find_credentials(contents, "other.internal"),
+            Some(("jane".into(), "hunter2".into()))
+        );
+        assert_eq!(
+            find_credentials(contents, "pypi.org"),
+            Some(("anonymous".into(), "guest".into()))
+        );
+        assert_eq!(find_credentials("machine a login b", "a"), None);
+    }
+}
@@ -9,7 +9,7 @@ use clap::Parser;
 use crate::bootstrap::ensure_self_venv;
 use crate::consts::VENV_BIN;
 use crate::pyproject::{locate_projects, PyProject};
-use crate::utils::{CommandOutput, QuietExit};
+use crate::utils::{CommandOutput, QuietExit, Verbosity};
 
 #[derive(Parser, Debug)]
 pub struct RuffArgs {
@@ -50,12 +50,12 @@ pub fn execute_ruff(args: RuffArgs, extra_args: &[&str]) -> Result<(), Error> {
     }
     ruff_cmd.args(extra_args);
 
-    match output {
-        CommandOutput::Normal => {}
-        CommandOutput::Verbose => {
+    match output.child() {
+        Verbosity::Normal => {}
+        Verbosity::Verbose => {
             ruff_cmd.arg("--verbose");
         }
-        CommandOutput::Quiet => {
+        Verbosity::Quiet => {
             ruff_cmd.arg("--quiet");
         }
     }
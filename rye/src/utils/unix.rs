@@ -3,6 +3,7 @@ use std::{env, fs};
 
 use anyhow::{Context, Error};
 
+use crate::utils::atomic::write_atomic;
 use crate::utils::IoPathContext;
 
 pub(crate) fn add_to_path(rye_home: &Path) -> Result<(), Error> {
@@ -19,7 +20,34 @@ pub(crate) fn add_to_path(rye_home: &Path) -> Result<(), Error> {
     Ok(())
 }
 
-fn add_source_line_to_profile(profile_path: &Path, source_line: &str) -> Result<(), Error> {
+/// Returns the shell config file and the line that puts the shims first on `PATH`.
+///
+/// The shell is detected from the `SHELL` environment variable and falls back
+/// to `.profile` for unknown shells.
+pub(crate) fn get_fix_path_line(rye_home: &Path) -> Result<(PathBuf, String), Error> {
+    let home = home::home_dir().context("could not find home dir")?;
+    let shims = reverse_resolve_env_home(rye_home.join("shims"));
+    let shell = env::var("SHELL").unwrap_or_default();
+    let export_line = format!("export PATH=\"{}:$PATH\"", shims.display());
+    Ok(match shell.rsplit('/').next().unwrap_or("") {
+        "fish" => (
+            home.join(".config/fish/conf.d/rye.fish"),
+            format!("set -gx PATH \"{}\" $PATH", shims.display()),
+        ),
+        "zsh" => (home.join(".zshrc"), export_line),
+        "bash" => (home.join(".bashrc"), export_line),
+        _ => (home.join(".profile"), export_line),
+    })
+}
+
+pub(crate) fn add_source_line_to_profile(
+    profile_path: &Path,
+    source_line: &str,
+) -> Result<(), Error> {
+    if let Some(parent) = profile_path.parent() {
+        fs::create_dir_all(parent).path_context(parent, "failed to create profile folder")?;
+    }
+
     let mut profile = if profile_path.is_file() {
         fs::read_to_string(profile_path)
             .path_context(profile_path, "failed to read profile file")?
@@ -30,7 +58,7 @@ fn add_source_line_to_profile(profile_path: &Path, source_line: &str) -> Result<
     if !profile.lines().any(|x| x.trim() == source_line) {
         profile.push_str(source_line);
         profile.push('\n');
-        fs::write(profile_path, profile)
+        write_atomic(profile_path, profile)
             .path_context(profile_path, "failed to write updated .profile")?;
     }
 
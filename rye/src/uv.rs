@@ -4,13 +4,15 @@ use crate::platform::get_app_dir;
 use crate::pyproject::{read_venv_marker, write_venv_marker, ExpandedSources};
 use crate::sources::py::PythonVersion;
 use crate::sources::uv::{UvDownload, UvRequest};
+use crate::sysdeps::print_build_hints;
+use crate::utils::cleanup::{run_interruptible, run_interruptible_capture_stderr};
 use crate::utils::{
-    check_checksum, set_proxy_variables, unpack_archive, update_venv_sync_marker, CommandOutput,
-    IoPathContext,
+    check_checksum, internal_command, set_network_timeouts, set_proxy_variables, unpack_archive,
+    update_venv_sync_marker, CommandOutput, Verbosity,
 };
 use anyhow::{anyhow, Context, Error};
 use pep508_rs::Requirement;
-use std::fs::{self, remove_dir_all};
+use std::fs::remove_dir_all;
 use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
@@ -40,6 +42,8 @@ struct UvCompileOptions {
     pub no_header: bool,
     pub keyring_provider: KeyringProvider,
     pub generate_hashes: bool,
+    pub python_platform: Option<String>,
+    pub constraints: Option<PathBuf>,
 }
 
 impl UvCompileOptions {
@@ -64,6 +68,14 @@ impl UvCompileOptions {
             cmd.arg("--exclude-newer").arg(dt);
         }
 
+        if let Some(platform) = self.python_platform {
+            cmd.arg("--python-platform").arg(platform);
+        }
+
+        if let Some(constraints) = self.constraints {
+            cmd.arg("--constraint").arg(constraints);
+        }
+
         match self.upgrade {
             UvPackageUpgrade::All => {
                 cmd.arg("--upgrade");
@@ -95,6 +107,8 @@ impl Default for UvCompileOptions {
             no_header: false,
             generate_hashes: false,
             keyring_provider: KeyringProvider::Disabled,
+            python_platform: None,
+            constraints: None,
         }
     }
 }
@@ -110,7 +124,7 @@ impl UvBuilder {
         Self {
             workdir: None,
             sources: None,
-            output: CommandOutput::Normal,
+            output: CommandOutput::NORMAL,
         }
     }
 
@@ -152,7 +166,7 @@ pub struct Uv {
 impl Default for Uv {
     fn default() -> Self {
         Uv {
-            output: CommandOutput::Normal,
+            output: CommandOutput::NORMAL,
             uv_bin: PathBuf::new(),
             workdir: std::env::current_dir().unwrap_or_default(),
             sources: ExpandedSources::empty(),
@@ -251,22 +265,23 @@ impl Uv {
     /// Returns a new command with the uv binary as the command to run.
     /// The command will have the correct proxy settings and verbosity level based on CommandOutput.
     pub fn cmd(&self) -> Command {
-        let mut cmd = Command::new(&self.uv_bin);
+        let mut cmd = internal_command(&self.uv_bin);
         cmd.current_dir(&self.workdir);
         cmd.env("PROJECT_ROOT", make_project_root_fragment(&self.workdir));
 
-        match self.output {
-            CommandOutput::Verbose => {
+        match self.output.child() {
+            Verbosity::Verbose => {
                 cmd.arg("--verbose");
             }
-            CommandOutput::Quiet => {
+            Verbosity::Quiet => {
                 cmd.arg("--quiet");
                 cmd.env("PYTHONWARNINGS", "ignore");
             }
-            CommandOutput::Normal => {}
+            Verbosity::Normal => {}
         }
 
         set_proxy_variables(&mut cmd);
+        set_network_timeouts(&mut cmd);
         cmd
     }
 
@@ -307,7 +322,7 @@ impl Uv {
             cmd.arg("--prompt").arg(prompt);
         }
         cmd.arg(venv_dir);
-        let status = cmd.status().with_context(|| {
+        let status = run_interruptible(&mut cmd).with_context(|| {
             format!(
                 "unable to create self venv using {}. It might be that \
                       the used Python build is incompatible with this machine. \
@@ -337,6 +352,8 @@ impl Uv {
         upgrade: UvPackageUpgrade,
         keyring_provider: KeyringProvider,
         generate_hashes: bool,
+        python_platform: Option<String>,
+        constraints: Option<&Path>,
     ) -> Result<(), Error> {
         let options = UvCompileOptions {
             allow_prerelease,
@@ -346,6 +363,8 @@ impl Uv {
             no_header: true,
             generate_hashes,
             keyring_provider,
+            python_platform,
+            constraints: constraints.map(|x| x.to_path_buf()),
         };
 
         let mut cmd = self.cmd();
@@ -361,7 +380,7 @@ impl Uv {
 
         cmd.arg(source);
 
-        let status = cmd.status().with_context(|| {
+        let status = run_interruptible(&mut cmd).with_context(|| {
             format!(
                 "Unable to run uv pip compile and generate {}",
                 target.to_str().unwrap_or("<unknown>")
@@ -427,7 +446,8 @@ impl UvWithVenv {
 
     /// Updates the pip version in the venv.
     pub fn update_pip(&self, pip_version: &str) -> Result<(), Error> {
-        self.venv_cmd()
+        let status = self
+            .venv_cmd()
             .arg("pip")
             .arg("install")
             .arg("--upgrade")
@@ -440,6 +460,14 @@ impl UvWithVenv {
                 )
             })?;
 
+        if !status.success() {
+            return Err(anyhow!(
+                "Failed to update pip in venv at {}. uv exited with status: {}",
+                self.venv_path.display(),
+                status
+            ));
+        }
+
         Ok(())
     }
 
@@ -448,7 +476,8 @@ impl UvWithVenv {
         let mut req_file = NamedTempFile::new()?;
         writeln!(req_file, "{}", requirements)?;
 
-        self.venv_cmd()
+        let status = self
+            .venv_cmd()
             .arg("pip")
             .arg("install")
             .arg("--upgrade")
@@ -462,6 +491,14 @@ impl UvWithVenv {
                 )
             })?;
 
+        if !status.success() {
+            return Err(anyhow!(
+                "Failed to update requirements in venv at {}. uv exited with status: {}",
+                self.venv_path.display(),
+                status
+            ));
+        }
+
         Ok(())
     }
 
@@ -516,7 +553,7 @@ impl UvWithVenv {
             cmd.arg("importlib-metadata==6.6.0");
         }
 
-        let status = cmd.status().with_context(|| {
+        let status = run_interruptible(&mut cmd).with_context(|| {
             format!(
                 "unable to install {} in venv at {}",
                 requirement,
@@ -543,12 +580,12 @@ impl UvWithVenv {
 
         self.uv.sources.add_as_pip_args(&mut cmd);
 
-        let status = cmd
-            .arg(lockfile)
-            .status()
+        cmd.arg(lockfile);
+        let (status, log) = run_interruptible_capture_stderr(&mut cmd)
             .with_context(|| format!("unable to run sync {}", self.venv_path.display()))?;
 
         if !status.success() {
+            print_build_hints(&log);
             return Err(anyhow!(
                 "Installation of dependencies failed in venv at {}. uv exited with status: {}",
                 self.venv_path.display(),
@@ -558,14 +595,6 @@ impl UvWithVenv {
         Ok(())
     }
 
-    /// Writes the tool version to the venv.
-    pub fn write_tool_version(&self, version: u64) -> Result<(), Error> {
-        let tool_version_path = self.venv_path.join("tool-version.txt");
-        fs::write(&tool_version_path, version.to_string())
-            .path_context(&tool_version_path, "could not write tool version")?;
-        Ok(())
-    }
-
     /// Update the cloud synchronization marker for the given path
     pub fn sync_marker(&self) {
         update_venv_sync_marker(self.uv.output, &self.venv_path)
@@ -591,6 +620,8 @@ impl UvWithVenv {
             no_header: true,
             generate_hashes: false,
             keyring_provider,
+            python_platform: None,
+            constraints: None,
         };
 
         cmd.arg("pip").arg("compile");
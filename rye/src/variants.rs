@@ -0,0 +1,231 @@
+//! Hardware dependent dependency variants (`[tool.rye.variants]`).
+//!
+//! A variant is an additional set of dependencies, for instance the CUDA or
+//! the CPU build of torch.  Every variant is locked into lockfiles of its own
+//! (`requirements.gpu.lock`, `requirements-dev.gpu.lock`, ...).  `rye sync`
+//! installs the variant passed with `--variant` or else the first one whose
+//! `detect` condition matches the machine.
+use std::fmt;
+use std::path::Path;
+use std::process::Stdio;
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Error};
+use toml_edit::DocumentMut;
+
+use crate::pyproject::DependencyRef;
+
+/// The hardware a variant can be detected with.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Accelerator {
+    /// An NVIDIA GPU with a driver.
+    Cuda,
+    /// An AMD GPU with the ROCm kernel driver.
+    Rocm,
+    /// Apple silicon.
+    Mps,
+    /// Always available.
+    Cpu,
+}
+
+impl Accelerator {
+    /// Is the accelerator available on this machine?
+    pub fn is_available(self) -> bool {
+        match self {
+            Accelerator::Cuda => {
+                Path::new("/proc/driver/nvidia/version").is_file()
+                    || which::which("nvidia-smi").map_or(false, |bin| {
+                        std::process::Command::new(bin)
+                            .arg("-L")
+                            .stdout(Stdio::null())
+                            .stderr(Stdio::null())
+                            .status()
+                            .map_or(false, |x| x.success())
+                    })
+            }
+            Accelerator::Rocm => Path::new("/dev/kfd").exists(),
+            Accelerator::Mps => cfg!(all(target_os = "macos", target_arch = "aarch64")),
+            Accelerator::Cpu => true,
+        }
+    }
+}
+
+impl FromStr for Accelerator {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Accelerator, Error> {
+        match s {
+            "cuda" => Ok(Accelerator::Cuda),
+            "rocm" => Ok(Accelerator::Rocm),
+            "mps" => Ok(Accelerator::Mps),
+            "cpu" => Ok(Accelerator::Cpu),
+            _ => bail!("unknown accelerator '{}'", s),
+        }
+    }
+}
+
+impl fmt::Display for Accelerator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Accelerator::Cuda => "cuda",
+            Accelerator::Rocm => "rocm",
+            Accelerator::Mps => "mps",
+            Accelerator::Cpu => "cpu",
+        })
+    }
+}
+
+/// A dependency variant of a project.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Variant {
+    pub name: String,
+    /// The dependencies installed in addition to the regular ones.
+    pub dependencies: Vec<String>,
+    /// Selects the variant automatically if this hardware is available.
+    pub detect: Option<Accelerator>,
+}
+
+impl Variant {
+    /// Iterates over the dependencies of the variant.
+    pub fn iter_dependencies(&self) -> impl Iterator<Item = DependencyRef> + '_ {
+        self.dependencies.iter().map(|x| DependencyRef::new(x))
+    }
+}
+
+/// Reads the variants from `[tool.rye.variants]` in declaration order.
+pub fn get_variants(doc: &DocumentMut) -> Result<Vec<Variant>, Error> {
+    let Some(variants) = doc
+        .get("tool")
+        .and_then(|x| x.get("rye"))
+        .and_then(|x| x.get("variants"))
+    else {
+        return Ok(Vec::new());
+    };
+    let variants = variants
+        .as_table_like()
+        .ok_or_else(|| anyhow!("tool.rye.variants must be a table"))?;
+
+    let mut rv = Vec::new();
+    for (name, variant) in variants.iter() {
+        let path = format!("tool.rye.variants.{}", name);
+        if !is_valid_variant_name(name) {
+            bail!("{} is not a valid variant name", path);
+        }
+        let variant = variant
+            .as_table_like()
+            .ok_or_else(|| anyhow!("{} must be a table", path))?;
+        let dependencies = match variant.get("dependencies") {
+            Some(deps) => deps
+                .as_array()
+                .and_then(|x| {
+                    x.iter()
+                        .map(|x| x.as_str().map(|x| x.to_string()))
+                        .collect::<Option<Vec<_>>>()
+                })
+                .ok_or_else(|| anyhow!("{}.dependencies must be a list of strings", path))?,
+            None => Vec::new(),
+        };
+        let detect = match variant.get("detect") {
+            Some(detect) => Some(
+                detect
+                    .as_str()
+                    .ok_or_else(|| anyhow!("{}.detect must be a string", path))?
+                    .parse::<Accelerator>()
+                    .map_err(|err| anyhow!("{}.detect: {}", path, err))?,
+            ),
+            None => None,
+        };
+        rv.push(Variant {
+            name: name.to_string(),
+            dependencies,
+            detect,
+        });
+    }
+    Ok(rv)
+}
+
+/// Variant names end up in lockfile names.
+fn is_valid_variant_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Selects the variant to sync.
+///
+/// An explicitly requested variant has to exist.  Otherwise the first
+/// variant whose hardware is available is picked, if any.
+pub fn select_variant(
+    variants: &[Variant],
+    requested: Option<&str>,
+    is_available: impl Fn(Accelerator) -> bool,
+) -> Result<Option<Variant>, Error> {
+    if let Some(requested) = requested {
+        return match variants.iter().find(|x| x.name == requested) {
+            Some(variant) => Ok(Some(variant.clone())),
+            None => bail!("unknown variant '{}'", requested),
+        };
+    }
+    Ok(variants
+        .iter()
+        .find(|x| x.detect.map_or(false, &is_available))
+        .cloned())
+}
+
+#[cfg(test)]
+mod test {
+    use toml_edit::DocumentMut;
+
+    use super::{get_variants, select_variant, Accelerator};
+
+    #[test]
+    fn test_select_variant() {
+        let doc = r#"
+            [tool.rye.variants.gpu]
+            dependencies = ["torch==2.3.1+cu121"]
+            detect = "cuda"
+
+            [tool.rye.variants.cpu]
+            dependencies = ["torch==2.3.1+cpu"]
+            detect = "cpu"
+
+            [tool.rye.variants.minimal]
+        "#
+        .parse::<DocumentMut>()
+        .unwrap();
+        let variants = get_variants(&doc).unwrap();
+        assert_eq!(
+            variants.iter().map(|x| x.name.as_str()).collect::<Vec<_>>(),
+            ["gpu", "cpu", "minimal"]
+        );
+        assert_eq!(variants[0].detect, Some(Accelerator::Cuda));
+        assert!(variants[2].dependencies.is_empty());
+
+        let pick = |requested, cuda| {
+            select_variant(&variants, requested, |x| {
+                x == Accelerator::Cpu || (cuda && x == Accelerator::Cuda)
+            })
+            .map(|x| x.map(|x| x.name))
+        };
+        assert_eq!(pick(None, true).unwrap().as_deref(), Some("gpu"));
+        assert_eq!(pick(None, false).unwrap().as_deref(), Some("cpu"));
+        assert_eq!(
+            pick(Some("minimal"), true).unwrap().as_deref(),
+            Some("minimal")
+        );
+        assert!(pick(Some("tpu"), true).is_err());
+    }
+
+    #[test]
+    fn test_invalid_variants() {
+        let doc = "[tool.rye.variants.gpu]\ndetect = \"tpu\"\n"
+            .parse::<DocumentMut>()
+            .unwrap();
+        assert!(get_variants(&doc).is_err());
+        let doc = "[tool.rye.variants.\"a.b\"]\n"
+            .parse::<DocumentMut>()
+            .unwrap();
+        assert!(get_variants(&doc).is_err());
+    }
+}
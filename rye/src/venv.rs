@@ -0,0 +1,162 @@
+//! Creation of virtualenvs.
+//!
+//! Virtualenvs can be created with the `venv` module of the standard library,
+//! with `virtualenv` or with `uv`.  The backends only create the bare
+//! environment, everything rye adds on top is handled by the caller so that
+//! the result looks the same no matter which backend was used.
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Context, Error};
+
+use crate::consts::VENV_BIN;
+use crate::platform::get_app_dir;
+use crate::sources::py::PythonVersion;
+use crate::utils::cleanup::run_interruptible;
+use crate::utils::{internal_command, CommandOutput, Verbosity};
+use crate::uv::UvBuilder;
+
+/// Which tool creates virtualenvs.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum VenvBackend {
+    /// The `venv` module of the standard library.
+    Venv,
+    /// `virtualenv` from the rye self environment.
+    Virtualenv,
+    /// `uv venv`.
+    Uv,
+}
+
+impl FromStr for VenvBackend {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "venv" => Ok(VenvBackend::Venv),
+            "virtualenv" => Ok(VenvBackend::Virtualenv),
+            "uv" => Ok(VenvBackend::Uv),
+            _ => Err(anyhow!("unknown venv backend")),
+        }
+    }
+}
+
+impl fmt::Display for VenvBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            VenvBackend::Venv => "venv",
+            VenvBackend::Virtualenv => "virtualenv",
+            VenvBackend::Uv => "uv",
+        })
+    }
+}
+
+impl VenvBackend {
+    /// Returns the creator for this backend.
+    pub fn creator(self, self_venv: &Path) -> Box<dyn CreateVenv> {
+        match self {
+            VenvBackend::Venv => Box::new(StdlibVenv),
+            VenvBackend::Virtualenv => Box::new(Virtualenv {
+                self_venv: self_venv.to_path_buf(),
+            }),
+            VenvBackend::Uv => Box::new(UvVenv),
+        }
+    }
+}
+
+/// The options a virtualenv is created with.
+pub struct VenvOptions<'a> {
+    pub output: CommandOutput,
+    pub py_bin: &'a Path,
+    pub py_ver: &'a PythonVersion,
+    pub prompt: &'a str,
+    /// Link the interpreter instead of copying it.
+    pub symlinks: bool,
+}
+
+/// Creates bare virtualenvs without pip or other seed packages.
+pub trait CreateVenv {
+    /// Creates the virtualenv.  The folder must not exist or be empty.
+    fn create(&self, venv: &Path, options: &VenvOptions) -> Result<(), Error>;
+}
+
+/// Creates virtualenvs with `python -m venv`.
+struct StdlibVenv;
+
+impl CreateVenv for StdlibVenv {
+    fn create(&self, venv: &Path, options: &VenvOptions) -> Result<(), Error> {
+        let mut cmd = internal_command(options.py_bin);
+        cmd.arg("-m")
+            .arg("venv")
+            .arg("--without-pip")
+            .arg(if options.symlinks {
+                "--symlinks"
+            } else {
+                "--copies"
+            })
+            .arg("--prompt")
+            .arg(options.prompt)
+            .arg(venv);
+        let status = run_interruptible(&mut cmd).context("unable to invoke venv module")?;
+        if !status.success() {
+            bail!("failed to initialize virtualenv");
+        }
+        Ok(())
+    }
+}
+
+/// Creates virtualenvs with the `virtualenv` of the rye self environment.
+struct Virtualenv {
+    self_venv: PathBuf,
+}
+
+impl CreateVenv for Virtualenv {
+    fn create(&self, venv: &Path, options: &VenvOptions) -> Result<(), Error> {
+        let mut cmd = internal_command(self.self_venv.join(VENV_BIN).join("virtualenv"));
+        if options.output.child() == Verbosity::Verbose {
+            cmd.arg("--verbose");
+        } else {
+            cmd.arg("-q");
+            cmd.env("PYTHONWARNINGS", "ignore");
+        }
+        // keep the app-data of virtualenv with the other rye caches
+        cmd.env(
+            "VIRTUALENV_OVERRIDE_APP_DATA",
+            get_app_dir().join("cache").join("virtualenv"),
+        );
+        cmd.arg("-p")
+            .arg(options.py_bin)
+            .arg("--no-seed")
+            .arg(if options.symlinks {
+                "--symlinks"
+            } else {
+                "--copies"
+            })
+            .arg("--prompt")
+            .arg(options.prompt)
+            .arg("--")
+            .arg(venv);
+        let status = run_interruptible(&mut cmd).context("unable to invoke virtualenv command")?;
+        if !status.success() {
+            bail!("failed to initialize virtualenv");
+        }
+        Ok(())
+    }
+}
+
+/// Creates virtualenvs with `uv venv`.
+///
+/// uv always links the interpreter where the platform supports it, which
+/// matches the default of the other backends.
+struct UvVenv;
+
+impl CreateVenv for UvVenv {
+    fn create(&self, venv: &Path, options: &VenvOptions) -> Result<(), Error> {
+        UvBuilder::new()
+            .with_output(options.output.quieter())
+            .ensure_exists()?
+            .venv(venv, options.py_bin, options.py_ver, Some(options.prompt))
+            .context("failed to initialize virtualenv")?;
+        Ok(())
+    }
+}
@@ -0,0 +1,330 @@
+//! Locking for WebAssembly targets (Pyodide / Emscripten and WASI).
+//!
+//! Neither target can build or install binary wheels from PyPI.  Pyodide
+//! ships a distribution of packages built for Emscripten and can install
+//! pure Python wheels on top, WASI only the latter.  Resolution happens for
+//! Linux with the versions of the Pyodide distribution as constraints and
+//! the result is checked against these rules afterwards.
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Error};
+use pep508_rs::{Requirement, VersionOrUrl};
+use serde::{Deserialize, Serialize};
+
+use crate::bootstrap::download_url;
+use crate::index::{IndexClient, ProjectFile};
+use crate::lock::LockedPackage;
+use crate::platform::get_app_dir;
+use crate::pyproject::{normalize_package_name, ExpandedSources};
+use crate::sources::py::PythonVersion;
+use crate::utils::atomic::write_atomic;
+use crate::utils::{CommandOutput, IoPathContext};
+
+/// The Pyodide distribution used if a project does not configure one.
+pub const DEFAULT_PYODIDE_VERSION: &str = "0.26.1";
+
+/// Resolving for the WebAssembly targets happens as if on this platform.
+pub const RESOLVE_PLATFORM: &str = "linux";
+
+/// A WebAssembly platform that can be locked for.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum WasmTarget {
+    /// Pyodide in the browser or node.
+    Emscripten,
+    /// WASI runtimes such as wasmtime.
+    Wasi,
+}
+
+impl WasmTarget {
+    /// Recognizes a WebAssembly platform passed to `--platform`.
+    pub fn from_platform(platform: &str) -> Option<WasmTarget> {
+        match platform.to_ascii_lowercase().as_str() {
+            "emscripten" | "pyodide" | "wasm32-emscripten" => Some(WasmTarget::Emscripten),
+            "wasi" | "wasm32-wasi" => Some(WasmTarget::Wasi),
+            _ => None,
+        }
+    }
+}
+
+/// The `pyodide-lock.json` of a Pyodide distribution.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PyodideLock {
+    pub info: PyodideInfo,
+    pub packages: BTreeMap<String, PyodidePackage>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PyodideInfo {
+    pub arch: String,
+    pub platform: String,
+    pub version: String,
+    pub python: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PyodidePackage {
+    pub name: String,
+    pub version: String,
+    pub file_name: String,
+    pub install_dir: String,
+    pub sha256: String,
+    pub package_type: String,
+    #[serde(default)]
+    pub imports: Vec<String>,
+    #[serde(default)]
+    pub depends: Vec<String>,
+}
+
+impl PyodideLock {
+    /// Looks up a package of the distribution by its normalized name.
+    pub fn get(&self, name: &str) -> Option<(&str, &PyodidePackage)> {
+        let name = normalize_package_name(name);
+        self.packages
+            .iter()
+            .find(|(key, pkg)| {
+                normalize_package_name(key) == name || normalize_package_name(&pkg.name) == name
+            })
+            .map(|(key, pkg)| (key.as_str(), pkg))
+    }
+
+    /// The Python version of the distribution.
+    pub fn python_version(&self) -> Result<PythonVersion, Error> {
+        format!("cpython@{}", self.info.python)
+            .parse()
+            .with_context(|| {
+                format!(
+                    "invalid Python version '{}' in Pyodide lock",
+                    self.info.python
+                )
+            })
+    }
+}
+
+fn get_pyodide_lock_url(version: &str) -> String {
+    format!(
+        "https://cdn.jsdelivr.net/pyodide/v{}/full/pyodide-lock.json",
+        version
+    )
+}
+
+fn get_pyodide_cache_path(version: &str) -> PathBuf {
+    get_app_dir()
+        .join("cache")
+        .join("pyodide")
+        .join(format!("pyodide-lock-{}.json", version))
+}
+
+/// Returns the `pyodide-lock.json` of a Pyodide release.
+///
+/// Releases never change, so the file is cached forever.
+pub fn fetch_pyodide_lock(version: &str, output: CommandOutput) -> Result<PyodideLock, Error> {
+    let path = get_pyodide_cache_path(version);
+    let contents = match fs::read(&path) {
+        Ok(contents) => contents,
+        Err(_) => {
+            echo!(if output, "Downloading Pyodide {} package list", version);
+            let contents = download_url(&get_pyodide_lock_url(version), output.quieter())
+                .with_context(|| {
+                    format!("failed to download package list of Pyodide {}", version)
+                })?;
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent).path_context(parent, "unable to create cache folder")?;
+            }
+            write_atomic(&path, &contents).path_context(&path, "unable to cache Pyodide lock")?;
+            contents
+        }
+    };
+    serde_json::from_slice(&contents)
+        .with_context(|| format!("invalid package list of Pyodide {}", version))
+}
+
+/// Writes the versions of the packages of a Pyodide distribution as constraints.
+pub fn write_pyodide_constraints(lock: &PyodideLock, path: &Path) -> Result<(), Error> {
+    let mut rv = Vec::new();
+    for pkg in lock.packages.values() {
+        if pkg.package_type == "package" {
+            writeln!(rv, "{}=={}", pkg.name, pkg.version)?;
+        }
+    }
+    fs::write(path, rv).path_context(path, "unable to write Pyodide constraints")
+}
+
+/// Finds the pure Python wheel of a version of a project.
+pub fn find_pure_wheel(
+    client: &IndexClient,
+    name: &str,
+    version: &str,
+) -> Result<Option<ProjectFile>, Error> {
+    let Some(metadata) = client.get_project(name)? else {
+        return Ok(None);
+    };
+    Ok(metadata.files.into_iter().find(|file| {
+        !file.is_yanked()
+            && file.filename.ends_with("-none-any.whl")
+            && file.version().map_or(false, |x| x.to_string() == version)
+    }))
+}
+
+/// Checks that every resolved package can be installed on the target.
+///
+/// `resolved` is the output of the resolver.  All offending packages are
+/// reported at once.
+pub fn check_resolution(
+    target: WasmTarget,
+    resolved: &str,
+    pyodide: Option<&PyodideLock>,
+    sources: &ExpandedSources,
+    output: CommandOutput,
+) -> Result<(), Error> {
+    let client = IndexClient::from_sources(sources, output.quieter());
+    let mut offenders = BTreeSet::new();
+    for line in resolved.lines() {
+        let Ok(req) = line
+            .trim()
+            .trim_end_matches('\\')
+            .trim()
+            .parse::<Requirement>()
+        else {
+            continue;
+        };
+        let Some(VersionOrUrl::VersionSpecifier(ref specs)) = req.version_or_url else {
+            continue;
+        };
+        let Some(version) = specs.iter().next().map(|x| x.version().to_string()) else {
+            continue;
+        };
+        if pyodide.map_or(false, |lock| lock.get(&req.name).is_some()) {
+            continue;
+        }
+        if find_pure_wheel(&client, &req.name, &version)?.is_none() {
+            offenders.insert(format!("{}=={}", req.name, version));
+        }
+    }
+    if !offenders.is_empty() {
+        bail!(
+            "the following packages have no pure Python wheel{} and cannot be installed on {}:\n  {}",
+            if pyodide.is_some() { " and are not part of Pyodide" } else { "" },
+            match target {
+                WasmTarget::Emscripten => "emscripten",
+                WasmTarget::Wasi => "wasi",
+            },
+            offenders.into_iter().collect::<Vec<_>>().join("\n  ")
+        );
+    }
+    Ok(())
+}
+
+/// Creates a `pyodide-lock.json` for the locked packages.
+///
+/// Packages of the Pyodide distribution are taken over together with their
+/// dependencies, all others point to their pure Python wheel on the index.
+pub fn make_pyodide_lock(
+    locked: &[LockedPackage],
+    pyodide: &PyodideLock,
+    sources: &ExpandedSources,
+    output: CommandOutput,
+) -> Result<PyodideLock, Error> {
+    let client = IndexClient::from_sources(sources, output.quieter());
+    let mut packages = BTreeMap::new();
+    let mut pending = Vec::new();
+
+    for pkg in locked.iter().filter(|x| !x.editable) {
+        if let Some((key, _)) = pyodide.get(&pkg.name) {
+            pending.push(key.to_string());
+            continue;
+        }
+        let Some(ref version) = pkg.version else {
+            bail!(
+                "{} is not pinned to a version and cannot be exported",
+                pkg.name
+            );
+        };
+        let Some(file) = find_pure_wheel(&client, &pkg.name, version)? else {
+            bail!(
+                "{}=={} has no pure Python wheel, lock with `rye lock --platform emscripten`",
+                pkg.name,
+                version
+            );
+        };
+        let name = normalize_package_name(&pkg.name);
+        packages.insert(
+            name.clone(),
+            PyodidePackage {
+                name,
+                version: version.clone(),
+                file_name: file.url,
+                install_dir: "site".into(),
+                sha256: file.hashes.get("sha256").cloned().unwrap_or_default(),
+                package_type: "package".into(),
+                imports: Vec::new(),
+                depends: pkg
+                    .dependencies
+                    .iter()
+                    .map(|x| normalize_package_name(x))
+                    .collect(),
+            },
+        );
+    }
+
+    // packages of the distribution can depend on shared libraries of it.
+    while let Some(key) = pending.pop() {
+        if packages.contains_key(&key) {
+            continue;
+        }
+        if let Some(pkg) = pyodide.packages.get(&key) {
+            pending.extend(pkg.depends.iter().cloned());
+            packages.insert(key, pkg.clone());
+        }
+    }
+
+    Ok(PyodideLock {
+        info: PyodideInfo {
+            arch: pyodide.info.arch.clone(),
+            platform: pyodide.info.platform.clone(),
+            version: pyodide.info.version.clone(),
+            python: pyodide.info.python.clone(),
+        },
+        packages,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{PyodideLock, WasmTarget};
+
+    #[test]
+    fn test_wasm_target_from_platform() {
+        assert_eq!(
+            WasmTarget::from_platform("emscripten"),
+            Some(WasmTarget::Emscripten)
+        );
+        assert_eq!(
+            WasmTarget::from_platform("Pyodide"),
+            Some(WasmTarget::Emscripten)
+        );
+        assert_eq!(
+            WasmTarget::from_platform("wasm32-wasi"),
+            Some(WasmTarget::Wasi)
+        );
+        assert_eq!(WasmTarget::from_platform("linux"), None);
+    }
+
+    #[test]
+    fn test_pyodide_lock_lookup() {
+        let lock: PyodideLock = serde_json::from_str(
+            r#"{
+                "info": {"arch": "wasm32", "platform": "emscripten_3_1_58", "version": "0.26.1", "python": "3.12.1"},
+                "packages": {
+                    "scikit-learn": {"name": "scikit-learn", "version": "1.4.2", "file_name": "scikit_learn-1.4.2-cp312-cp312-pyodide_2024_0_wasm32.whl", "install_dir": "site", "sha256": "abc", "package_type": "package", "imports": ["sklearn"], "depends": ["numpy"], "unvendored_tests": true}
+                }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(lock.get("Scikit_Learn").unwrap().0, "scikit-learn");
+        assert!(lock.get("numpy").is_none());
+        assert_eq!(lock.python_version().unwrap().minor, 12);
+    }
+}
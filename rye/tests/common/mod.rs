@@ -145,6 +145,8 @@ impl Space {
         rv.env("RYE_HOME", self.rye_home().as_os_str());
         rv.env("UV_CACHE_DIR", self.tempdir.path().join("uv-cache"));
         rv.env("__RYE_UV_EXCLUDE_NEWER", EXCLUDE_NEWER);
+        // the CI output preset would otherwise change the snapshots
+        rv.env_remove("CI");
         rv.current_dir(self.project_path());
         rv
     }
@@ -214,3 +214,63 @@ fn test_add_explicit_version_or_url() {
      + pip==1.3.1 (from https://github.com/pypa/pip/archive/1.3.1.zip#sha1=da9234ee9982d4bbb3c72346a6de940a148ea686)
     "###);
 }
+
+#[test]
+fn test_add_platform_and_marker() {
+    let space = Space::new();
+    space.init("my-project");
+    let status = space
+        .rye_cmd()
+        .arg("add")
+        .arg("colorama")
+        .arg("--platform")
+        .arg("windows")
+        .arg("--platform")
+        .arg("macos")
+        .arg("--marker")
+        .arg("python_version >= '3.8'")
+        .arg("--no-sync")
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let dependency = space.load_toml("pyproject.toml", |doc| {
+        doc["project"]["dependencies"][0]
+            .as_str()
+            .unwrap()
+            .to_string()
+    });
+    assert!(dependency.starts_with("colorama>="));
+    assert!(dependency.contains("python_version >= '3.8'"));
+    assert!(dependency.contains("(sys_platform == 'win32' or sys_platform == 'darwin')"));
+
+    // updating the dependency keeps the marker
+    let status = space
+        .rye_cmd()
+        .arg("add")
+        .arg("colorama==0.4.6")
+        .arg("--no-sync")
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let dependency = space.load_toml("pyproject.toml", |doc| {
+        doc["project"]["dependencies"][0]
+            .as_str()
+            .unwrap()
+            .to_string()
+    });
+    assert!(dependency.starts_with("colorama==0.4.6"));
+    assert!(dependency.contains("sys_platform == 'win32'"));
+
+    // invalid markers are rejected
+    let status = space
+        .rye_cmd()
+        .arg("add")
+        .arg("flask")
+        .arg("--marker")
+        .arg("python_version >>> '3'")
+        .arg("--no-sync")
+        .status()
+        .unwrap();
+    assert!(!status.success());
+}
@@ -0,0 +1,51 @@
+use crate::common::Space;
+
+mod common;
+
+#[test]
+fn test_export_import() {
+    let space = Space::new();
+    space.init("my-project");
+    let status = space.rye_cmd().arg("lock").status().unwrap();
+    assert!(status.success());
+
+    let archive = space
+        .project_path()
+        .parent()
+        .unwrap()
+        .join("bundle.tar.zst");
+    let status = space
+        .rye_cmd()
+        .arg("export")
+        .arg("--archive")
+        .arg(&archive)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert!(archive.is_file());
+
+    let restored = space.project_path().parent().unwrap().join("restored");
+    let status = space
+        .rye_cmd()
+        .arg("import")
+        .arg("--archive")
+        .arg(&archive)
+        .arg(&restored)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert!(restored.join("pyproject.toml").is_file());
+    assert!(restored.join("requirements.lock").is_file());
+    assert!(restored.join(".venv").is_dir());
+
+    // restoring over an existing project is refused
+    let status = space
+        .rye_cmd()
+        .arg("import")
+        .arg("--archive")
+        .arg(&archive)
+        .arg(&restored)
+        .status()
+        .unwrap();
+    assert!(!status.success());
+}
@@ -0,0 +1,32 @@
+use std::fs;
+
+use crate::common::{rye_cmd_snapshot, Space};
+
+mod common;
+
+#[test]
+fn test_cache_clean_metadata() {
+    let space = Space::new();
+    let metadata_dir = space.rye_home().join("cache").join("metadata");
+    fs::create_dir_all(metadata_dir.join("0123456789abcdef")).unwrap();
+    fs::write(metadata_dir.join("0123456789abcdef/flask.json"), "{}").unwrap();
+
+    rye_cmd_snapshot!(space.rye_cmd().arg("cache").arg("clean").arg("--metadata"), @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    Removed metadata cache
+
+    ----- stderr -----
+    "###);
+    assert!(!metadata_dir.exists());
+
+    rye_cmd_snapshot!(space.rye_cmd().arg("cache").arg("clean").arg("--metadata"), @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    Nothing to remove
+
+    ----- stderr -----
+    "###);
+}
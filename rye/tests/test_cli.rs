@@ -49,3 +49,94 @@ fn test_dotenv() {
     ----- stderr -----
     "###);
 }
+
+#[cfg(unix)]
+#[test]
+fn test_plugin() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let space = Space::new();
+    space.init("my-project");
+    let bin_dir = space.project_path().join("plugins");
+    fs::create_dir_all(&bin_dir).unwrap();
+    let plugin = bin_dir.join("rye-hello");
+    fs::write(
+        &plugin,
+        "#!/bin/sh\necho \"hello $1 from $(basename \"$RYE_PROJECT_ROOT\")\"\n",
+    )
+    .unwrap();
+    fs::set_permissions(&plugin, fs::Permissions::from_mode(0o755)).unwrap();
+    let path = std::env::join_paths(
+        std::iter::once(bin_dir).chain(std::env::split_paths(&std::env::var_os("PATH").unwrap())),
+    )
+    .unwrap();
+
+    rye_cmd_snapshot!(space.rye_cmd().env("PATH", &path).arg("hello").arg("world"), @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    hello world from project
+
+    ----- stderr -----
+    "###);
+    rye_cmd_snapshot!(space.rye_cmd().env("PATH", &path).arg("nope"), @r###"
+    success: false
+    exit_code: 1
+    ----- stdout -----
+
+    ----- stderr -----
+    error: unknown command 'nope'. No rye built-in or plugin (rye-nope on PATH) has this name.
+    "###);
+}
+
+#[test]
+fn test_required_rye_version() {
+    let space = Space::new();
+    space.init("my-project");
+    space.edit_toml("pyproject.toml", |doc| {
+        doc["tool"]["rye"]["rye-version"] = value(">=999");
+    });
+
+    let output = space.rye_cmd().arg("version").output().unwrap();
+    assert!(!output.status.success());
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("error: this project requires rye >=999")
+    );
+
+    // `rye config` keeps working to relax the check
+    let status = space
+        .rye_cmd()
+        .arg("config")
+        .arg("--set")
+        .arg("behavior.rye-version-check=warn")
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let output = space.rye_cmd().arg("version").output().unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("warning: this project requires rye >=999"));
+
+    // an invalid requirement only warns in warn mode
+    space.edit_toml("pyproject.toml", |doc| {
+        doc["tool"]["rye"]["rye-version"] = value("not a version");
+    });
+    let output = space.rye_cmd().arg("version").output().unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("warning: invalid tool.rye.rye-version 'not a version'"));
+
+    // unknown modes are rejected
+    let status = space
+        .rye_cmd()
+        .arg("config")
+        .arg("--set")
+        .arg("behavior.rye-version-check=ignore")
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let output = space.rye_cmd().arg("version").output().unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("invalid behavior.rye-version-check 'ignore'"));
+}
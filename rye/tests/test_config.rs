@@ -28,6 +28,13 @@ fn test_config_empty() {
             --set-int <SET_INT>    Sets a config key to an integer
             --set-bool <SET_BOOL>  Sets a config key to a bool
             --unset <UNSET>        Remove a config key
+            --app-dir <PATH>       Use this folder as the rye home (same as `RYE_HOME`)
+            --override-policy      Ignore the `tool.rye.policy` of the project (also
+                                   `RYE_OVERRIDE_POLICY=1`)
+            --offline              Fail instead of downloading toolchains or bootstrapping rye internals
+                                   (also `RYE_OFFLINE=1`)
+            --quiet-pip            Hide the output of pip and other tools rye runs
+            --show-pip             Show the full output of pip and other tools rye runs
         -h, --help                 Print help (see more with '--help')
     "###);
 }
@@ -134,3 +141,42 @@ fn test_config_save_missing_folder() {
     toolchain = "cpython@3.12"
     "###);
 }
+
+#[test]
+fn test_config_rejects_unknown_keys() {
+    let space = Space::new();
+    rye_cmd_snapshot!(space.rye_cmd()
+        .arg("config")
+        .arg("--set-bool")
+        .arg("behaviour.autosync=true"), @r###"
+    success: false
+    exit_code: 1
+    ----- stdout -----
+
+    ----- stderr -----
+    error: invalid config in [RYE_HOME]/config.toml:
+      [RYE_HOME]/config.toml:1:2: unknown key 'behaviour' (did you mean 'behavior'?)
+    "###);
+}
+
+#[test]
+fn test_config_load_warns_about_unknown_keys() {
+    let space = Space::new();
+    let fake_home = space.project_path().join("fake-home");
+    fs::create_dir_all(&fake_home).unwrap();
+    fs::write(
+        fake_home.join("config.toml"),
+        "[behaviour]\nautosync = true\n",
+    )
+    .unwrap();
+    let output = space
+        .rye_cmd()
+        .arg("config")
+        .arg("--show-path")
+        .env("RYE_HOME", fake_home.as_os_str())
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("unknown key 'behaviour' (did you mean 'behavior'?)"));
+}
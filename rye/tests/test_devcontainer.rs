@@ -0,0 +1,50 @@
+use crate::common::Space;
+
+mod common;
+
+#[test]
+fn test_devcontainer_init() {
+    let space = Space::new();
+    space.init("my-project");
+
+    let status = space
+        .rye_cmd()
+        .arg("devcontainer")
+        .arg("init")
+        .arg("--dockerfile")
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let config: serde_json::Value =
+        serde_json::from_str(&space.read_string(".devcontainer/devcontainer.json")).unwrap();
+    assert_eq!(config["name"], "my-project");
+    assert_eq!(config["build"]["dockerfile"], "Dockerfile");
+    assert_eq!(config["postCreateCommand"], "rye sync --no-lock");
+    assert_eq!(
+        config["customizations"]["vscode"]["settings"]["python.defaultInterpreterPath"],
+        "${containerWorkspaceFolder}/.venv/bin/python"
+    );
+    let dockerfile = space.read_string(".devcontainer/Dockerfile");
+    assert!(dockerfile.contains("RUN rye fetch cpython@3.12.3"));
+
+    // existing files are not overwritten without --force
+    let status = space
+        .rye_cmd()
+        .arg("devcontainer")
+        .arg("init")
+        .status()
+        .unwrap();
+    assert!(!status.success());
+    let status = space
+        .rye_cmd()
+        .arg("devcontainer")
+        .arg("init")
+        .arg("--force")
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let config: serde_json::Value =
+        serde_json::from_str(&space.read_string(".devcontainer/devcontainer.json")).unwrap();
+    assert!(config["image"].is_string());
+}
@@ -0,0 +1,81 @@
+use std::fs;
+use std::process::Command;
+
+use crate::common::Space;
+
+mod common;
+
+#[test]
+fn test_hooks_install_and_remove() {
+    let space = Space::new();
+    space.init("my-project");
+    let status = Command::new("git")
+        .arg("init")
+        .arg("-q")
+        .current_dir(space.project_path())
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let hooks_dir = space.project_path().join(".git").join("hooks");
+    let hooks = |args: &[&str]| space.rye_cmd().arg("hooks").args(args).status().unwrap();
+
+    assert!(hooks(&["install", "--post-checkout"]).success());
+    let script = space.read_string(".git/hooks/post-checkout");
+    assert!(script.contains("rye hooks run --pyproject"));
+    assert!(script.contains("pyproject.toml' post-checkout"));
+    assert!(!hooks_dir.join("post-merge").exists());
+
+    // hooks not written by rye are only replaced with --force
+    fs::write(hooks_dir.join("post-merge"), "#!/bin/sh\nexit 0\n").unwrap();
+    assert!(!hooks(&["install"]).success());
+    assert!(hooks(&["install", "--force"]).success());
+    assert!(space
+        .read_string(".git/hooks/post-merge")
+        .contains("pyproject.toml' post-merge"));
+
+    assert!(hooks(&["remove"]).success());
+    assert!(!hooks_dir.join("post-checkout").exists());
+    assert!(!hooks_dir.join("post-merge").exists());
+}
+
+#[test]
+fn test_hooks_run_detects_lockfile_change() {
+    let space = Space::new();
+    space.init("my-project");
+    let status = space.rye_cmd().arg("sync").status().unwrap();
+    assert!(status.success());
+
+    let run = || {
+        let output = space
+            .rye_cmd()
+            .arg("hooks")
+            .arg("run")
+            .arg("post-merge")
+            .arg("0")
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8_lossy(&output.stderr).into_owned()
+    };
+
+    assert!(!run().contains("rye sync"));
+    let lockfile = space.read_string("requirements-dev.lock");
+    space.write("requirements-dev.lock", lockfile + "\n# changed\n");
+    assert!(run().contains("run `rye sync`"));
+
+    // the installed hooks point to the project from anywhere in the repository
+    let output = space
+        .rye_cmd()
+        .arg("hooks")
+        .arg("run")
+        .arg("--pyproject")
+        .arg(space.project_path().join("pyproject.toml"))
+        .arg("post-merge")
+        .arg("0")
+        .current_dir(space.project_path().parent().unwrap())
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("run `rye sync`"));
+}
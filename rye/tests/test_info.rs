@@ -0,0 +1,65 @@
+use crate::common::Space;
+
+mod common;
+
+fn info_json(space: &Space) -> serde_json::Value {
+    let output = space.rye_cmd().arg("info").arg("--json").output().unwrap();
+    assert!(output.status.success());
+    serde_json::from_slice(&output.stdout).unwrap()
+}
+
+#[test]
+fn test_info_json() {
+    let space = Space::new();
+    space.init("my-project");
+
+    let info = info_json(&space);
+    assert_eq!(info["name"], "my-project");
+    assert_eq!(info["version"], "0.1.0");
+    assert_eq!(info["venv"]["status"], "missing");
+    assert!(info["locked_dependencies"]["production"].is_null());
+    assert!(info["workspace"].is_null());
+    assert_eq!(info["sources"][0]["name"], "default");
+
+    space.rye_cmd().arg("sync").status().unwrap();
+    let info = info_json(&space);
+    assert_eq!(info["venv"]["status"], "up-to-date");
+    assert_eq!(info["locked_dependencies"]["production"], 0);
+    assert_eq!(info["python"]["version"], "cpython@3.12.3");
+
+    let lockfile = space.read_string("requirements-dev.lock");
+    space.write("requirements-dev.lock", format!("{}# changed\n", lockfile));
+    let info = info_json(&space);
+    assert_eq!(info["venv"]["status"], "out-of-sync");
+}
+
+#[test]
+fn test_info_and_export_with_variant() {
+    let space = Space::new();
+    space.init("my-project");
+    space.edit_toml("pyproject.toml", |doc| {
+        let mut deps = toml_edit::Array::new();
+        deps.push("colorama==0.4.6");
+        doc["tool"]["rye"]["variants"]["cpu"]["dependencies"] = toml_edit::value(deps);
+        doc["tool"]["rye"]["variants"]["cpu"]["detect"] = toml_edit::value("cpu");
+    });
+    let status = space.rye_cmd().arg("sync").status().unwrap();
+    assert!(status.success());
+    assert!(space.project_path().join("requirements.cpu.lock").is_file());
+
+    // the lockfiles of the detected variant are read
+    let info = info_json(&space);
+    assert_eq!(info["locked_dependencies"]["production"], 1);
+
+    let status = space
+        .rye_cmd()
+        .arg("export")
+        .arg("--format")
+        .arg("third-party-notices")
+        .arg("-o")
+        .arg("NOTICES")
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert!(space.read_string("NOTICES").contains("colorama"));
+}
@@ -1,3 +1,5 @@
+use std::fs;
+
 use crate::common::{get_bin, rye_cmd_snapshot, Space};
 
 mod common;
@@ -163,3 +165,148 @@ fn test_init_lib_and_script_incompatible() {
         error: an argument cannot be used with one or more of the other specified arguments
     "###);
 }
+
+// Test that init --namespace creates a PEP 420 layout
+#[test]
+fn test_init_namespace() {
+    let space = Space::new();
+    space
+        .cmd(get_bin())
+        .arg("init")
+        .arg("--namespace")
+        .arg("company.tools.myproj")
+        .arg("-q")
+        .current_dir(space.project_path())
+        .status()
+        .expect("initialization successful");
+
+    let src = space.project_path().join("src");
+    assert!(src.join("company/tools/myproj/__init__.py").is_file());
+    assert!(!src.join("company/__init__.py").exists());
+    assert!(!src.join("company/tools/__init__.py").exists());
+    let toml = space.read_toml("pyproject.toml");
+    assert_eq!(
+        toml["project"]["name"].as_str(),
+        Some("company-tools-myproj")
+    );
+    assert_eq!(
+        toml["tool"]["hatch"]["build"]["targets"]["wheel"]["only-include"][0].as_str(),
+        Some("src/company/tools/myproj")
+    );
+
+    rye_cmd_snapshot!(space.rye_cmd().arg("sync"), @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    Initializing new virtualenv in [TEMP_PATH]/project/.venv
+    Python version: cpython@3.12.3
+    Generating production lockfile: [TEMP_PATH]/project/requirements.lock
+    Generating dev lockfile: [TEMP_PATH]/project/requirements-dev.lock
+    Installing dependencies
+    Done!
+
+    ----- stderr -----
+    Built 1 editable in [EXECUTION_TIME]
+    Installed 1 package in [EXECUTION_TIME]
+     + company-tools-myproj==0.1.0 (from file:[TEMP_PATH]/project)
+    "###);
+
+    rye_cmd_snapshot!(space.rye_cmd().arg("run").arg("python").arg("-c").arg("import company.tools.myproj as m; print(m.hello())"), @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    Hello from company-tools-myproj!
+
+    ----- stderr -----
+    "###);
+}
+
+// Test that init --ci github writes a workflow with a matrix from requires-python
+#[test]
+fn test_init_ci_github() {
+    let space = Space::new();
+    space
+        .cmd(get_bin())
+        .arg("init")
+        .arg("--name")
+        .arg("my-project")
+        .arg("-q")
+        .arg("--min-py")
+        .arg("3.10")
+        .arg("--ci")
+        .arg("github")
+        .current_dir(space.project_path())
+        .status()
+        .expect("initialization successful");
+
+    let workflow = space.read_string(".github/workflows/ci.yml");
+    assert!(workflow.contains(r#"python-version: ["3.10", "3.11", "3.12"]"#));
+    assert!(workflow.contains("run: rye pin ${{ matrix.python-version }}"));
+    assert!(workflow.contains("run: rye test"));
+}
+
+// Test that init --template renders a local template and records it
+#[test]
+fn test_init_template() {
+    let space = Space::new();
+    let template = space.project_path().parent().unwrap().join("my-template");
+    fs::create_dir_all(template.join("template/src/{{ name_safe }}")).unwrap();
+    fs::write(
+        template.join("rye-template.toml"),
+        r#"[template]
+version = "1.2.0"
+
+[variables]
+description = { prompt = "Project description", default = "A {{ name }} project" }
+flavor = "Flavor"
+"#,
+    )
+    .unwrap();
+    fs::write(
+        template.join("template/pyproject.toml.j2"),
+        r#"[project]
+name = "{{ name }}"
+version = "{{ version }}"
+description = "{{ description }}"
+requires-python = "{{ requires_python }}"
+"#,
+    )
+    .unwrap();
+    fs::write(
+        template.join("template/src/{{ name_safe }}/__init__.py.j2"),
+        "FLAVOR = \"{{ flavor }}\"\n",
+    )
+    .unwrap();
+    // symlinks could point outside of the template and are not copied
+    #[cfg(unix)]
+    std::os::unix::fs::symlink("/etc/hostname", template.join("template/hostname")).unwrap();
+
+    space
+        .cmd(get_bin())
+        .arg("init")
+        .arg("--name")
+        .arg("my-project")
+        .arg("-q")
+        .arg("--template")
+        .arg(&template)
+        .arg("--template-var")
+        .arg("flavor=vanilla")
+        .current_dir(space.project_path())
+        .status()
+        .expect("initialization successful");
+
+    let doc = space.read_toml("pyproject.toml");
+    assert_eq!(
+        doc["project"]["description"].as_str(),
+        Some("A my-project project")
+    );
+    assert_eq!(
+        doc["tool"]["rye"]["template"]["version"].as_str(),
+        Some("1.2.0")
+    );
+    assert_eq!(
+        space.read_string("src/my_project/__init__.py"),
+        "FLAVOR = \"vanilla\"\n"
+    );
+    assert!(!space.project_path().join("hostname").exists());
+}
@@ -0,0 +1,41 @@
+use crate::common::Space;
+
+mod common;
+
+#[test]
+fn test_kernel_install_and_remove() {
+    let space = Space::new();
+    space.init("my-project");
+    let status = space.rye_cmd().arg("sync").status().unwrap();
+    assert!(status.success());
+
+    let data_dir = space.project_path().join("jupyter");
+    let kernel = |args: &[&str]| {
+        space
+            .rye_cmd()
+            .env("JUPYTER_DATA_DIR", &data_dir)
+            .arg("kernel")
+            .args(args)
+            .status()
+            .unwrap()
+    };
+
+    assert!(kernel(&["install"]).success());
+    let spec: serde_json::Value =
+        serde_json::from_str(&space.read_string("jupyter/kernels/my-project/kernel.json")).unwrap();
+    assert_eq!(spec["display_name"], "Python (my-project)");
+    assert_eq!(spec["argv"][1], "-m");
+    assert_eq!(spec["argv"][2], "ipykernel_launcher");
+    assert!(spec["argv"][0]
+        .as_str()
+        .unwrap()
+        .starts_with(space.venv_path().to_str().unwrap()));
+
+    // an existing kernel is only replaced with --force
+    assert!(!kernel(&["install"]).success());
+    assert!(kernel(&["install", "--force", "--display-name", "Analysis"]).success());
+
+    assert!(kernel(&["remove"]).success());
+    assert!(!data_dir.join("kernels/my-project").exists());
+    assert!(!kernel(&["remove"]).success());
+}
@@ -0,0 +1,25 @@
+use crate::common::{rye_cmd_snapshot, Space};
+
+mod common;
+
+#[test]
+fn test_pin_auto() {
+    let space = Space::new();
+    space.init("my-project");
+    let pyproject = space.read_string("pyproject.toml").replace(
+        r#"requires-python = ">= 3.8""#,
+        r#"requires-python = ">= 3.12""#,
+    );
+    space.write("pyproject.toml", pyproject);
+
+    // the installed 3.12 toolchain wins over newer downloadable ones
+    rye_cmd_snapshot!(space.rye_cmd().arg("pin").arg("--auto"), @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    pinned 3.12.3 in [TEMP_PATH]/project/.python-version
+
+    ----- stderr -----
+    "###);
+    assert_eq!(space.read_string(".python-version"), "3.12.3\n");
+}
@@ -0,0 +1,47 @@
+use crate::common::{rye_cmd_snapshot, Space};
+
+mod common;
+
+#[test]
+fn test_run_with_python() {
+    let space = Space::new();
+    rye_cmd_snapshot!(space.rye_cmd()
+        .arg("run")
+        .arg("--python")
+        .arg("3.12.3")
+        .arg("--")
+        .arg("python")
+        .arg("-c")
+        .arg("import sys; print(sys.version_info[:3])"), @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    (3, 12, 3)
+
+    ----- stderr -----
+    "###);
+}
+
+#[test]
+fn test_run_extra_paths() {
+    let space = Space::new();
+    space.init("my-project");
+    space.write("libs/shared/src/shared_helper.py", "VALUE = 42\n");
+    space.edit_toml("pyproject.toml", |doc| {
+        doc["tool"]["rye"]["run"]["extra-paths"] =
+            toml_edit::value(toml_edit::Array::from_iter(["libs/shared/src"]));
+        doc["tool"]["rye"]["scripts"]["show-value"] =
+            toml_edit::value("python -c 'import shared_helper; print(shared_helper.VALUE)'");
+    });
+    let status = space.rye_cmd().arg("sync").status().unwrap();
+    assert!(status.success());
+
+    let output = space
+        .rye_cmd()
+        .arg("run")
+        .arg("show-value")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "42");
+}
@@ -70,3 +70,34 @@ fn test_version() {
     ----- stderr -----
     "###);
 }
+
+#[test]
+fn test_version_json() {
+    let space = Space::new();
+    let _guard = space.lock_rye_home();
+
+    let output = space
+        .rye_cmd()
+        .arg("self")
+        .arg("version")
+        .arg("--json")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let info: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(info["version"], env!("CARGO_PKG_VERSION"));
+    assert_eq!(info["app_dir"], space.rye_home().to_str().unwrap());
+    assert_eq!(info["self_python"], "cpython@3.12.3");
+    assert_eq!(info["features"]["uv_enabled"], true);
+    assert_eq!(
+        info["features"]["python_downloads_date"]
+            .as_str()
+            .unwrap()
+            .len(),
+        10
+    );
+    assert!(info["features"]["archive_formats"]
+        .as_array()
+        .unwrap()
+        .contains(&"tar.zst".into()));
+}
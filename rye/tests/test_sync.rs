@@ -40,6 +40,12 @@ fn test_empty_sync() {
         let contents = fs::read_to_string(script).unwrap();
         assert!(contents.contains("@set \"VIRTUAL_ENV_PROMPT=my-project\""));
     }
+
+    // is the virtualenv marked as rye managed?
+    let cfg = fs::read_to_string(space.venv_path().join("pyvenv.cfg")).unwrap();
+    assert!(cfg.contains("rye-managed = true\n"));
+    assert!(cfg.contains("rye-project = "));
+    assert!(cfg.contains("rye-lock-hash = "));
 }
 
 #[test]
@@ -263,3 +269,293 @@ fn test_autosync_remember() {
     werkzeug==3.0.1
     "###);
 }
+
+#[test]
+fn test_venv_drift_warning() {
+    let space = Space::new();
+    space.init("my-project");
+    space.rye_cmd().arg("sync").status().unwrap();
+
+    // the lockfile changing after the sync means the venv drifted
+    let lockfile = space.read_string("requirements-dev.lock");
+    space.write("requirements-dev.lock", format!("{}# changed\n", lockfile));
+    rye_cmd_snapshot!(space.rye_cmd().arg("run").arg("python").arg("-c").arg("print('hello')"), @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    hello
+
+    ----- stderr -----
+    warning: virtualenv is out of sync with [TEMP_PATH]/project/requirements-dev.lock. Run `rye sync` to update it.
+    "###);
+}
+
+#[test]
+fn test_lock_for_other_platform() {
+    let space = Space::new();
+    space.init("my-project");
+    space.edit_toml("pyproject.toml", |doc| {
+        let mut deps = toml_edit::Array::new();
+        deps.push("colorama==0.4.6; sys_platform == 'win32'");
+        doc["project"]["dependencies"] = toml_edit::value(deps);
+    });
+
+    let status = space
+        .rye_cmd()
+        .arg("lock")
+        .arg("--platform")
+        .arg("windows")
+        .arg("--python-version")
+        .arg("3.9")
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let lockfile = space.read_string("requirements.lock");
+    assert!(lockfile.contains("#   python-version: \"3.9\"\n#   platform: \"windows\"\n"));
+    assert!(lockfile.contains("colorama==0.4.6"));
+}
+
+#[test]
+fn test_foreign_venv_replace() {
+    let space = Space::new();
+    space.init("my-project");
+    space.write(".venv/pyvenv.cfg", "home = /usr/bin\nversion = 3.8.10\n");
+
+    // without a terminal rye refuses to touch the virtualenv
+    rye_cmd_snapshot!(space.rye_cmd().arg("sync"), @r###"
+    success: false
+    exit_code: 1
+    ----- stdout -----
+
+    ----- stderr -----
+    error: virtualenv is not managed by rye. Run `rye sync --force-replace` to replace it.
+    "###);
+
+    rye_cmd_snapshot!(space.rye_cmd().arg("sync").arg("--force-replace"), @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    Forcing re-creation of non-rye managed virtualenv
+    Initializing new virtualenv in [TEMP_PATH]/project/.venv
+    Python version: cpython@3.12.3
+    Generating production lockfile: [TEMP_PATH]/project/requirements.lock
+    Generating dev lockfile: [TEMP_PATH]/project/requirements-dev.lock
+    Installing dependencies
+    Done!
+
+    ----- stderr -----
+    Built 1 editable in [EXECUTION_TIME]
+    Installed 1 package in [EXECUTION_TIME]
+     + my-project==0.1.0 (from file:[TEMP_PATH]/project)
+    "###);
+}
+
+#[test]
+fn test_dependency_groups() {
+    let space = Space::new();
+    space.init("my-project");
+    space.edit_toml("pyproject.toml", |doc| {
+        let groups: toml_edit::DocumentMut = r#"
+docs = ["markupsafe==2.1.3"]
+test = { includes = ["docs", "dev"], deps = ["colorama==0.4.6"] }
+"#
+        .parse()
+        .unwrap();
+        doc["tool"]["rye"]["dependency-groups"] = groups.as_item().clone();
+    });
+
+    let status = space.rye_cmd().arg("lock").status().unwrap();
+    assert!(status.success());
+    assert!(!space
+        .read_string("requirements.lock")
+        .contains("markupsafe"));
+    assert!(space
+        .read_string("requirements-docs.lock")
+        .contains("markupsafe==2.1.3"));
+    let test_lock = space.read_string("requirements-test.lock");
+    assert!(test_lock.contains("markupsafe==2.1.3"));
+    assert!(test_lock.contains("colorama==0.4.6"));
+
+    // includes must not form a cycle
+    space.edit_toml("pyproject.toml", |doc| {
+        doc["tool"]["rye"]["dependency-groups"]["docs"] =
+            toml_edit::value(toml_edit::InlineTable::from_iter([(
+                "includes",
+                toml_edit::Value::from_iter(["test"]),
+            )]));
+    });
+    let output = space.rye_cmd().arg("lock").output().unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("dependency groups include each other: docs -> test -> docs"));
+}
+
+#[test]
+fn test_sync_ignores_hostile_python_env() {
+    let space = Space::new();
+    space.init("my-project");
+
+    // a user environment that breaks every python started with it
+    let hostile = space.project_path().join("hostile");
+    fs::create_dir_all(&hostile).unwrap();
+    fs::write(
+        hostile.join("sitecustomize.py"),
+        "raise SystemExit('hostile sitecustomize')\n",
+    )
+    .unwrap();
+
+    let status = space
+        .rye_cmd()
+        .arg("sync")
+        .env("PYTHONPATH", &hostile)
+        .env("PYTHONHOME", hostile.join("missing"))
+        .env("PIP_REQUIRE_VIRTUALENV", "1")
+        .env("PIP_USER", "1")
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert!(space.venv_path().join("pyvenv.cfg").is_file());
+}
+
+#[test]
+fn test_sync_check() {
+    let space = Space::new();
+    space.init("my-project");
+    let status = space.rye_cmd().arg("lock").status().unwrap();
+    assert!(status.success());
+
+    // locked but nothing was synced yet
+    let output = space.rye_cmd().arg("sync").arg("--check").output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("does not exist"));
+    assert!(!space.venv_path().exists());
+
+    let status = space.rye_cmd().arg("sync").status().unwrap();
+    assert!(status.success());
+    let status = space.rye_cmd().arg("sync").arg("--check").status().unwrap();
+    assert!(status.success());
+
+    // a package locked after the sync is reported as missing and not installed
+    let status = space
+        .rye_cmd()
+        .arg("add")
+        .arg("colorama==0.4.6")
+        .arg("--no-sync")
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let status = space.rye_cmd().arg("lock").status().unwrap();
+    assert!(status.success());
+    let lockfile = space.read_string("requirements-dev.lock");
+    let output = space.rye_cmd().arg("sync").arg("--check").output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("- colorama 0.4.6 (not installed)"));
+    assert_eq!(space.read_string("requirements-dev.lock"), lockfile);
+
+    // with an unchanged lockfile the installed packages are compared
+    let status = space.rye_cmd().arg("sync").status().unwrap();
+    assert!(status.success());
+    let site_packages = space.venv_path().join("lib/python3.12/site-packages");
+    let dist_info = fs::read_dir(&site_packages)
+        .unwrap()
+        .map(|x| x.unwrap().path())
+        .find(|x| x.to_string_lossy().contains("colorama-0.4.6.dist-info"))
+        .unwrap();
+    fs::remove_dir_all(dist_info).unwrap();
+    let output = space.rye_cmd().arg("sync").arg("--check").output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("- colorama 0.4.6 (not installed)"));
+    assert!(!stdout.contains("lockfile changed"));
+}
+
+#[test]
+fn test_sync_writes_derived_pin() {
+    let space = Space::new();
+    space.init("my-project");
+    fs::remove_file(space.project_path().join(".python-version")).unwrap();
+    space.edit_toml("pyproject.toml", |doc| {
+        doc["project"]["requires-python"] = toml_edit::value("==3.12.*");
+    });
+
+    // the derived version is used but not written
+    let status = space
+        .rye_cmd()
+        .arg("sync")
+        .arg("--no-write-pin")
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert!(!space.project_path().join(".python-version").exists());
+
+    // the automatic sync of other commands does not write it either
+    let status = space
+        .rye_cmd()
+        .arg("add")
+        .arg("colorama==0.4.6")
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert!(!space.project_path().join(".python-version").exists());
+
+    let status = space.rye_cmd().arg("sync").status().unwrap();
+    assert!(status.success());
+    assert_eq!(space.read_string(".python-version"), "3.12\n");
+}
+
+#[test]
+fn test_sync_no_install_project() {
+    let space = Space::new();
+    space.init("my-project");
+
+    let status = space
+        .rye_cmd()
+        .arg("sync")
+        .arg("--no-install-project")
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert!(space.read_string("requirements.lock").contains("-e file:."));
+
+    // the project itself is missing from the virtualenv
+    let status = space.rye_cmd().arg("sync").arg("--check").status().unwrap();
+    assert_eq!(status.code(), Some(1));
+    let status = space
+        .rye_cmd()
+        .arg("sync")
+        .arg("--check")
+        .arg("--no-install-project")
+        .status()
+        .unwrap();
+    assert!(status.success());
+}
+
+#[test]
+fn test_policy_forbids_lock() {
+    let space = Space::new();
+    space.init("my-project");
+    space.edit_toml("pyproject.toml", |doc| {
+        doc["tool"]["rye"]["policy"]["allow-lock"] = toml_edit::value(false);
+    });
+
+    // there is no lockfile to sync from yet
+    let status = space.rye_cmd().arg("lock").status().unwrap();
+    assert!(!status.success());
+    let status = space
+        .rye_cmd()
+        .arg("--override-policy")
+        .arg("lock")
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    // sync installs from the existing lockfile without touching it
+    let lockfile = format!(
+        "{}# unchanged\n",
+        space.read_string("requirements-dev.lock")
+    );
+    space.write("requirements-dev.lock", &lockfile);
+    let status = space.rye_cmd().arg("sync").status().unwrap();
+    assert!(status.success());
+    assert_eq!(space.read_string("requirements-dev.lock"), lockfile);
+}
@@ -150,3 +150,40 @@ fn test_basic_tool_behavior() {
     ----- stderr -----
     "###);
 }
+
+#[test]
+fn test_coverage() {
+    let space = Space::new();
+    space.init("foo");
+    space.edit_toml("pyproject.toml", |doc| {
+        let mut deps = Array::new();
+        deps.push("pytest>=7.0.0");
+        deps.push("coverage>=7.0.0");
+        doc["tool"]["rye"]["dev-dependencies"] = value(deps);
+    });
+    space.write(
+        "tests/test_foo.py",
+        "def test_okay():\n    import foo\n    assert foo.hello()\n",
+    );
+    let status = space.rye_cmd().arg("sync").status().unwrap();
+    assert!(status.success());
+
+    // without configured environments there is nothing to run on
+    let status = space
+        .rye_cmd()
+        .arg("test")
+        .arg("--all-envs")
+        .status()
+        .unwrap();
+    assert!(!status.success());
+
+    let output = space
+        .rye_cmd()
+        .arg("test")
+        .arg("--coverage")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Combined coverage"));
+    assert!(space.project_path().join(".coverage").is_file());
+}
@@ -36,3 +36,72 @@ fn test_fetch() {
     ----- stderr -----
     "###);
 }
+
+#[test]
+#[cfg(unix)]
+fn test_which() {
+    let space = Space::new();
+    space.init("my-project");
+    space.write(".python-version", "3.12.3\n");
+
+    rye_cmd_snapshot!(space.rye_cmd().arg("toolchain").arg("which"), @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    [RYE_HOME]/py/cpython@3.12.3/bin/python3
+      project: [TEMP_PATH]/project/pyproject.toml
+      requested: 3.12.3 (pinned in [TEMP_PATH]/project/.python-version)
+      resolved: cpython@3.12.3
+      virtualenv: not created yet
+
+    ----- stderr -----
+    "###);
+}
+
+#[test]
+fn test_fetch_list_urls() {
+    let space = Space::new();
+    let output = space
+        .rye_cmd()
+        .arg("fetch")
+        .arg("--list-urls")
+        .arg("pypy-x86_64-linux@3.10.14")
+        .arg("pypy-x86_64-linux@3.9.19")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines = stdout.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].ends_with(
+        " https://downloads.python.org/pypy/pypy3.10-v7.3.16-linux64.tar.bz2 \
+         404e6180d6caf9258eaab0c02c72018e9aa8eb03ab9094a0ff17ee5e3b265ac1"
+    ));
+    assert!(lines[1].contains("pypy3.9-v7.3.16-linux64.tar.bz2"));
+}
+
+#[test]
+fn test_sync_check() {
+    let space = Space::new();
+    space.write("toolchains.toml", "toolchains = [\"cpython@3.12\"]\n");
+    let status = space
+        .rye_cmd()
+        .arg("toolchain")
+        .arg("sync")
+        .arg("--check")
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    // a version that is never installed by the tests is reported as missing
+    space.write("toolchains.toml", "toolchains = [\"cpython@3.7.1\"]\n");
+    let output = space
+        .rye_cmd()
+        .arg("toolchain")
+        .arg("sync")
+        .arg("--check")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("missing: cpython@3.7.1"));
+}
@@ -66,6 +66,70 @@ fn test_basic_tool_behavior() {
     ----- stderr -----
     "###);
 
+    rye_cmd_snapshot!(
+        space.rye_cmd()
+            .arg("tools")
+            .arg("list")
+            .arg("--include-python"), @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    pycowsay (cpython@3.11.9)
+
+    ----- stderr -----
+    "###);
+
+    // the lockfile allows restoring the tool
+    let lockfile = space.rye_home().join("tools").join("pycowsay.lock");
+    assert!(lockfile.is_file());
+    fs::remove_dir_all(space.rye_home().join("tools").join("pycowsay")).unwrap();
+
+    rye_cmd_snapshot!(
+        space.rye_cmd()
+            .arg("tools")
+            .arg("sync"), @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    Syncing pycowsay
+    Done!
+
+    ----- stderr -----
+    "###);
+
+    rye_cmd_snapshot!(
+        space.rye_cmd()
+            .arg("tools")
+            .arg("sync"), @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    Done!
+
+    ----- stderr -----
+    "###);
+
+    // rehashing brings back missing shims
+    let shim = space
+        .rye_home()
+        .join("shims")
+        .join("pycowsay")
+        .with_extension(EXE_EXTENSION);
+    fs::remove_file(&shim).unwrap();
+    rye_cmd_snapshot!(
+        space.rye_cmd()
+            .arg("self")
+            .arg("rehash"), @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    Updated python shims
+    Updated shims of pycowsay (pycowsay)
+
+    ----- stderr -----
+    "###);
+    assert!(shim.is_file());
+
     rye_cmd_snapshot!(
         space.rye_cmd()
             .arg("toolchain")
@@ -93,4 +157,5 @@ fn test_basic_tool_behavior() {
     "###);
 
     assert!(!space.rye_home().join("tools").join("pycowsay").is_dir());
+    assert!(!lockfile.is_file());
 }
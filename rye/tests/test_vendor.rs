@@ -0,0 +1,55 @@
+use std::fs;
+
+use crate::common::Space;
+
+mod common;
+
+#[test]
+fn test_vendor() {
+    let space = Space::new();
+    space.init("my-project");
+
+    // vendoring needs a lockfile
+    let status = space.rye_cmd().arg("vendor").status().unwrap();
+    assert!(!status.success());
+
+    let status = space
+        .rye_cmd()
+        .arg("add")
+        .arg("sniffio==1.3.1")
+        .arg("--no-sync")
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let status = space.rye_cmd().arg("lock").status().unwrap();
+    assert!(status.success());
+
+    let status = space.rye_cmd().arg("vendor").status().unwrap();
+    assert!(status.success());
+    let doc = space.read_toml("pyproject.toml");
+    assert_eq!(doc["tool"]["rye"]["vendor-dir"].as_str(), Some("vendor"));
+    assert!(fs::read_dir(space.project_path().join("vendor"))
+        .unwrap()
+        .any(|x| x
+            .unwrap()
+            .file_name()
+            .to_string_lossy()
+            .starts_with("sniffio-1.3.1")));
+
+    // syncs install from the vendor folder only
+    let status = space
+        .rye_cmd()
+        .arg("sync")
+        .arg("--no-lock")
+        .status()
+        .unwrap();
+    assert!(status.success());
+    fs::remove_dir_all(space.project_path().join("vendor")).unwrap();
+    let status = space
+        .rye_cmd()
+        .arg("sync")
+        .arg("--no-lock")
+        .status()
+        .unwrap();
+    assert!(!status.success());
+}
@@ -0,0 +1,97 @@
+use std::env::consts::EXE_EXTENSION;
+use std::fs;
+
+use crate::common::Space;
+
+mod common;
+
+#[test]
+fn test_venv_create_add_run() {
+    let space = Space::new();
+    let shim = space
+        .rye_home()
+        .join("shims")
+        .join("tqdm")
+        .with_extension(EXE_EXTENSION);
+
+    // in case we left things behind from last run.
+    fs::remove_dir_all(space.rye_home().join("venvs").join("test-venv")).ok();
+    fs::remove_file(&shim).ok();
+
+    let status = space
+        .rye_cmd()
+        .arg("venv")
+        .arg("create")
+        .arg("test-venv")
+        .arg("-p")
+        .arg("cpython@3.11")
+        .arg("--packages")
+        .arg("tqdm==4.66.1")
+        .arg("--shims")
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert!(shim.symlink_metadata().is_ok());
+
+    // adding a package again replaces its shims
+    let status = space
+        .rye_cmd()
+        .arg("venv")
+        .arg("add")
+        .arg("test-venv")
+        .arg("tqdm==4.66.1")
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert!(shim.symlink_metadata().is_ok());
+
+    let output = space
+        .rye_cmd()
+        .arg("venv")
+        .arg("run")
+        .arg("test-venv")
+        .arg("python")
+        .arg("-c")
+        .arg("import tqdm; print(tqdm.__version__)")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "4.66.1");
+
+    // a failed recreate keeps the previous virtualenv
+    let status = space
+        .rye_cmd()
+        .arg("venv")
+        .arg("create")
+        .arg("test-venv")
+        .arg("-p")
+        .arg("cpython@3.11")
+        .arg("--packages")
+        .arg("this-package-does-not-exist-rye==1.0")
+        .arg("--force")
+        .status()
+        .unwrap();
+    assert!(!status.success());
+    let status = space
+        .rye_cmd()
+        .arg("venv")
+        .arg("run")
+        .arg("test-venv")
+        .arg("python")
+        .arg("-c")
+        .arg("import tqdm")
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert!(shim.symlink_metadata().is_ok());
+
+    let status = space
+        .rye_cmd()
+        .arg("venv")
+        .arg("remove")
+        .arg("test-venv")
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert!(shim.symlink_metadata().is_err());
+}
@@ -0,0 +1,32 @@
+use std::fs;
+
+use crate::common::Space;
+
+mod common;
+
+#[test]
+fn test_verify_env() {
+    let space = Space::new();
+    space.init("my-project");
+    let status = space.rye_cmd().arg("sync").status().unwrap();
+    assert!(status.success());
+
+    let status = space.rye_cmd().arg("verify-env").status().unwrap();
+    assert!(status.success());
+
+    // tamper with the editable install of the project
+    let site_packages = space.venv_path().join("lib/python3.12/site-packages");
+    let pth = fs::read_dir(&site_packages)
+        .unwrap()
+        .map(|x| x.unwrap().path())
+        .find(|x| {
+            x.extension().map_or(false, |x| x == "pth")
+                && x.to_string_lossy().contains("my_project")
+        })
+        .unwrap();
+    fs::write(&pth, "/tmp/somewhere-else\n").unwrap();
+
+    let output = space.rye_cmd().arg("verify-env").output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("modified"));
+}